@@ -0,0 +1,288 @@
+//! `rdm import-curl` — turns a browser's "Copy as cURL" command into a
+//! download request.
+//!
+//! Only the shape browsers emit is understood:
+//! `curl 'URL' -H 'Name: value' -b 'cookies' -X METHOD --data-raw 'body'`,
+//! with single, double or `$'...'` quoting and `\` line continuations.
+//! Options that would change what gets downloaded but have no equivalent
+//! here are rejected rather than dropped.
+
+use std::collections::HashMap;
+
+/// The parts of a curl command rdm can replay.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CurlRequest {
+    pub url: String,
+    pub headers: HashMap<String, Vec<String>>,
+    pub cookies: Option<String>,
+    /// `-X`, or POST when a body is given without one.
+    pub method: Option<String>,
+    pub body: Option<Vec<u8>>,
+}
+
+/// Options that make no difference to the download and are skipped.
+const IGNORED_FLAGS: &[&str] = &[
+    "--compressed",
+    "-L",
+    "--location",
+    "-s",
+    "--silent",
+    "-S",
+    "--show-error",
+    "-g",
+    "--globoff",
+    "--http1.1",
+    "--http2",
+];
+
+/// Headers rdm manages itself; a copied value would conflict with it.
+fn is_managed_header(name: &str) -> bool {
+    matches!(
+        name.to_ascii_lowercase().as_str(),
+        "host" | "connection" | "content-length" | "range"
+            // The client does not decompress; the file would be stored encoded.
+            | "accept-encoding"
+    )
+}
+
+/// Parse a curl command line (with or without the leading `curl`).
+pub fn parse_curl(command: &str) -> Result<CurlRequest, String> {
+    let mut words = split_words(command)?.into_iter().peekable();
+    if words.peek().map(String::as_str) == Some("curl") {
+        words.next();
+    }
+
+    let mut request = CurlRequest::default();
+    let mut url = None;
+    let mut data: Vec<String> = Vec::new();
+
+    while let Some(word) = words.next() {
+        if !word.starts_with('-') || word == "-" {
+            if url.replace(word).is_some() {
+                return Err("more than one URL in the curl command".to_string());
+            }
+            continue;
+        }
+        if IGNORED_FLAGS.contains(&word.as_str()) {
+            continue;
+        }
+        // `--name=value` is accepted as well as `--name value`.
+        let (flag, inline) = match word.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (word, None),
+        };
+        let mut value = || {
+            inline
+                .clone()
+                .or_else(|| words.next())
+                .ok_or_else(|| format!("curl option {} needs a value", flag))
+        };
+        match flag.as_str() {
+            "--url" => {
+                if url.replace(value()?).is_some() {
+                    return Err("more than one URL in the curl command".to_string());
+                }
+            }
+            "-H" | "--header" => {
+                let header = value()?;
+                let (name, val) = header
+                    .split_once(':')
+                    .ok_or_else(|| format!("malformed header {:?}; expected \"Name: value\"", header))?;
+                let (name, val) = (name.trim(), val.trim());
+                if name.eq_ignore_ascii_case("cookie") {
+                    request.cookies = Some(val.to_string());
+                } else if !is_managed_header(name) {
+                    request.headers.entry(name.to_string()).or_default().push(val.to_string());
+                }
+            }
+            "-b" | "--cookie" => {
+                let cookies = value()?;
+                if !cookies.contains('=') {
+                    return Err(format!(
+                        "cookie files are not supported (-b {:?}); pass the cookies themselves",
+                        cookies
+                    ));
+                }
+                request.cookies = Some(cookies);
+            }
+            "-A" | "--user-agent" => {
+                request.headers.insert("User-Agent".to_string(), vec![value()?]);
+            }
+            "-e" | "--referer" => {
+                request.headers.insert("Referer".to_string(), vec![value()?]);
+            }
+            "-X" | "--request" => request.method = Some(value()?.to_ascii_uppercase()),
+            "--data-raw" => data.push(value()?),
+            "-d" | "--data" | "--data-ascii" | "--data-binary" => {
+                let body = value()?;
+                if body.starts_with('@') {
+                    return Err(format!("reading the body from a file ({} {:?}) is not supported", flag, body));
+                }
+                data.push(body);
+            }
+            other => return Err(format!("unsupported curl option {}", other)),
+        }
+    }
+
+    request.url = url.ok_or_else(|| "no URL in the curl command".to_string())?;
+    if !data.is_empty() {
+        request.body = Some(data.join("&").into_bytes());
+        request.method.get_or_insert_with(|| "POST".to_string());
+    }
+    Ok(request)
+}
+
+/// Split a POSIX-shell-style command line into words.
+fn split_words(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                // Line continuation
+                Some('\n') => {}
+                Some('\r') if chars.peek() == Some(&'\n') => {
+                    chars.next();
+                }
+                Some(escaped) => current.get_or_insert_with(String::new).push(escaped),
+                None => return Err("command ends with a lone backslash".to_string()),
+            },
+            '\'' => {
+                let word = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(e @ ('"' | '\\' | '$' | '`')) => word.push(e),
+                            Some('\n') => {}
+                            Some(other) => {
+                                word.push('\\');
+                                word.push(other);
+                            }
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                let word = current.get_or_insert_with(String::new);
+                ansi_c_quoted(&mut chars, word)?;
+            }
+            c if c.is_whitespace() => {
+                if let Some(word) = current.take() {
+                    words.push(word);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(current);
+    Ok(words)
+}
+
+/// The rest of a `$'...'` string (bash ANSI-C quoting, which Chrome uses for
+/// values containing quotes or control characters).
+fn ansi_c_quoted(chars: &mut std::iter::Peekable<std::str::Chars>, word: &mut String) -> Result<(), String> {
+    loop {
+        match chars.next() {
+            Some('\'') => return Ok(()),
+            Some('\\') => {
+                let escaped = match chars.next() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('0') => '\0',
+                    Some(e @ ('\\' | '\'' | '"' | '?')) => e,
+                    Some(kind @ ('x' | 'u' | 'U')) => {
+                        let max = match kind {
+                            'x' => 2,
+                            'u' => 4,
+                            _ => 8,
+                        };
+                        let mut hex = String::new();
+                        while hex.len() < max && chars.peek().is_some_and(char::is_ascii_hexdigit) {
+                            hex.extend(chars.next());
+                        }
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| format!("invalid escape \\{}{} in $'...' string", kind, hex))?
+                    }
+                    Some(other) => {
+                        word.push('\\');
+                        other
+                    }
+                    None => return Err("unterminated $'...' string".to_string()),
+                };
+                word.push(escaped);
+            }
+            Some(c) => word.push(c),
+            None => return Err("unterminated $'...' string".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_chrome_copy_as_curl() {
+        let command = "curl 'https://example.com/files/report.pdf?id=7' \\\n  \
+            -H 'accept: application/pdf' \\\n  \
+            -H 'accept-encoding: gzip, deflate, br' \\\n  \
+            -b 'session=abc; theme=dark' \\\n  \
+            -H $'x-note: it\\'s here' \\\n  \
+            --compressed";
+        let request = parse_curl(command).unwrap();
+        assert_eq!(request.url, "https://example.com/files/report.pdf?id=7");
+        assert_eq!(request.headers["accept"], vec!["application/pdf"]);
+        assert_eq!(request.headers["x-note"], vec!["it's here"]);
+        assert!(!request.headers.contains_key("accept-encoding"));
+        assert_eq!(request.cookies.as_deref(), Some("session=abc; theme=dark"));
+        assert_eq!(request.method, None);
+        assert_eq!(request.body, None);
+    }
+
+    #[test]
+    fn data_implies_post_unless_method_given() {
+        let request = parse_curl(r#"curl "https://example.com/export" -H "Cookie: a=1" --data-raw '{"q":"x"}'"#).unwrap();
+        assert_eq!(request.method.as_deref(), Some("POST"));
+        assert_eq!(request.body.as_deref(), Some(br#"{"q":"x"}"#.as_slice()));
+        assert_eq!(request.cookies.as_deref(), Some("a=1"));
+
+        let request = parse_curl("curl -X put --data=a=1 -d b=2 https://example.com/").unwrap();
+        assert_eq!(request.method.as_deref(), Some("PUT"));
+        assert_eq!(request.body.as_deref(), Some(b"a=1&b=2".as_slice()));
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_replay() {
+        for (command, expected) in [
+            ("curl https://example.com/ -k", "unsupported curl option -k"),
+            ("curl https://example.com/ -b cookies.txt", "cookie files"),
+            ("curl https://example.com/ --data-binary @body.bin", "from a file"),
+            ("curl -H 'Accept: */*'", "no URL"),
+            ("curl 'https://example.com/", "unterminated single quote"),
+            ("curl https://example.com/ -H", "needs a value"),
+        ] {
+            let err = parse_curl(command).unwrap_err();
+            assert!(err.contains(expected), "{:?}: {}", command, err);
+        }
+    }
+}
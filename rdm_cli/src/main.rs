@@ -1,14 +1,27 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::Duration;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
+use rdm_core::downloader::cache;
+use rdm_core::downloader::dash_manifest::is_dash_manifest;
 use rdm_core::downloader::http_downloader::HttpDownloader;
+use rdm_core::downloader::naming::naming_strategy;
+use rdm_core::downloader::strategy::common_options::CommonOptions;
+use rdm_core::downloader::strategy::dash_download_strategy::DashDownloadStrategy;
+use rdm_core::downloader::strategy::download_strategy::DownloadStrategy;
 use rdm_core::downloader::strategy::multipart_download_strategy::MultipartDownloadStrategy;
+use rdm_core::downloader::rate_limiter::parse_rate;
+use rdm_core::progress::snapshot::{format_bytes, format_speed, SpeedUnit};
+use rdm_core::types::types::{AuthenticationInfo, ChecksumAlgo, DownloadError, HttpVersion, OutputTarget, SpeedLimit};
 
+mod curl_command;
+mod remote;
 mod terminal_observer;
-use terminal_observer::TerminalProgressObserver;
+use curl_command::{parse_curl, CurlRequest};
+use remote::{server_url, RemoteClient};
+use terminal_observer::TerminalProgress;
 
 #[derive(Parser)]
 #[command(name = "rdm", about = "Rust Download Manager")]
@@ -17,35 +30,449 @@ struct Args {
     #[arg(short, long, default_value = "https://proof.ovh.net/files/1Mb.dat")]
     url: String,
 
-    /// Output file path
-    #[arg(short, long, default_value = "downloaded_file")]
-    output: PathBuf,
+    /// Output file path; `-` writes the download to stdout (status goes to
+    /// stderr). Without it the file is named by `RDM_NAMING` (`safe`, `raw`
+    /// or `template`) when set, else `downloaded_file`
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Build the output path from the probed URL instead of --output, e.g.
+    /// "{host}/{date}/{name}.{ext}"; missing directories are created
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "output")]
+    output_template: Option<String>,
     #[arg(short, long, default_value = "8")]
     connections: Option<usize>,
+
+    /// Separate audio track to download and mux into the output (requires ffmpeg)
+    #[arg(long)]
+    audio_url: Option<String>,
+
+    /// Mirror serving the same file as --url; segments fail over to it (repeatable)
+    #[arg(long = "mirror", value_name = "URL")]
+    mirrors: Vec<String>,
+
+    /// Replace the output file if it already exists
+    #[arg(long, conflicts_with_all = ["no_clobber", "continue_partial"])]
+    overwrite: bool,
+
+    /// Skip the download (and exit successfully) if the output file already exists
+    #[arg(long, conflicts_with = "continue_partial")]
+    no_clobber: bool,
+
+    /// Resume into an existing partial output file (resumable servers only)
+    #[arg(long = "continue")]
+    continue_partial: bool,
+
+    /// Download in a single stream without range requests, even if the
+    /// server claims to support them
+    #[arg(long, conflicts_with = "continue_partial")]
+    no_resume: bool,
+
+    /// Use one connection at a time, resuming with a range request when it
+    /// drops, for servers that refuse parallel connections; also what
+    /// --connections 1 does
+    #[arg(long, conflicts_with_all = ["connections", "no_resume"])]
+    sequential: bool,
+
+    /// HTTP version: auto (HTTP/2 where TLS negotiates it), 1.1, or 2 (also
+    /// over plain http://). Under HTTP/2, --connections sets concurrent
+    /// streams over a single connection
+    #[arg(long, value_name = "VERSION", default_value = "auto")]
+    http_version: HttpVersion,
+
+    /// Give the output file the server's Last-Modified time instead of the
+    /// time the download finished
+    #[arg(long)]
+    preserve_mtime: bool,
+
+    /// Segments copied into the output at once when assembling it (default
+    /// 1, serial); more may help fast disks on large files
+    #[arg(long, value_name = "N", default_value = "1", value_parser = clap::value_parser!(u64).range(1..))]
+    assembly_threads: u64,
+
+    /// Remember the server's ETag/Last-Modified beside the output; run again
+    /// with the same URL and output, download only if the file changed
+    #[arg(long, conflicts_with = "continue_partial")]
+    if_changed: bool,
+
+    /// Send Cache-Control: no-cache and Pragma: no-cache so caching proxies
+    /// revalidate with the origin, for resources that change; every request
+    /// then reaches the origin
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Give up if the whole download (probe, transfer and assembly) takes
+    /// longer than this many seconds; unlike the idle timeout, which retries a
+    /// stalled connection, this ends the download
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    max_time: Option<u64>,
+
+    /// Cap the aggregate download speed, in bytes/s (K, M and G suffixes accepted)
+    #[arg(long, value_name = "RATE", value_parser = parse_rate, conflicts_with = "limit_rate_per_connection")]
+    max_speed: Option<u64>,
+
+    /// Cap each connection's speed instead of the aggregate, in bytes/s; with
+    /// N connections the total reaches up to N times this
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    limit_rate_per_connection: Option<u64>,
+
+    /// Compute and print a checksum of the downloaded file (md5, sha256 or sha512)
+    #[arg(long, value_name = "ALGO")]
+    checksum: Option<ChecksumAlgo>,
+
+    /// Compare the --checksum digest with this hex value
+    #[arg(long, value_name = "HEX", requires = "checksum")]
+    expected_checksum: Option<String>,
+
+    /// Verify the output against a published checksum file such as
+    /// file.iso.sha256 or SHA256SUMS (md5, sha256 or sha512)
+    #[arg(long, value_name = "URL")]
+    checksum_url: Option<String>,
+
+    /// How speeds are shown: binary (MB/s in powers of 1024, the default),
+    /// decimal (powers of 1000) or bits (Mbps, as ISPs quote them)
+    #[arg(long, value_name = "UNIT", default_value = "binary")]
+    speed_unit: SpeedUnit,
+
+    /// PEM client certificate for servers that require mutual TLS
+    #[arg(long, value_name = "PEM", requires = "key")]
+    cert: Option<String>,
+
+    /// PEM private key of --cert
+    #[arg(long, value_name = "PEM", requires = "cert")]
+    key: Option<String>,
+
+    /// PEM CA certificate(s) to trust in addition to the system roots
+    #[arg(long, value_name = "PEM")]
+    cacert: Option<String>,
+
+    /// Credentials for servers that ask for HTTP Basic authentication
+    #[arg(long, value_name = "USER:PASSWORD", value_parser = parse_credentials)]
+    user: Option<AuthenticationInfo>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Download the request of a browser "Copy as cURL" command, with its
+    /// headers, cookies, method and body; --url is ignored
+    ImportCurl {
+        /// The whole curl command, quoted
+        curl: String,
+    },
+    /// List the downloads of a running rdmd (at RDM_SERVER_URL)
+    List,
+    /// Show one rdmd download and its progress
+    Status {
+        /// Download id, as shown by `rdm list`
+        id: String,
+    },
+    /// Cancel an rdmd download
+    Cancel {
+        /// Download id, as shown by `rdm list`
+        id: String,
+    },
+}
+
+/// Runs a subcommand that controls a running rdmd; `false` if `command`
+/// is not one. Exits on failure.
+async fn run_remote(command: Option<&Command>) -> bool {
+    let client = RemoteClient::new(server_url());
+    let result = match command {
+        Some(Command::List) => client.list().await,
+        Some(Command::Status { id }) => client.status(id).await,
+        Some(Command::Cancel { id }) => client.cancel(id).await,
+        _ => return false,
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    true
+}
+
+/// `--user USER:PASSWORD`; the password may contain `:`.
+fn parse_credentials(s: &str) -> Result<AuthenticationInfo, String> {
+    let (username, password) = s
+        .split_once(':')
+        .ok_or_else(|| "expected USER:PASSWORD".to_string())?;
+    Ok(AuthenticationInfo { username: username.to_string(), password: password.to_string() })
+}
+
+/// Prints a status line: to stdout, or to stderr when the download itself
+/// goes to stdout (`--output -`) so the two never mix.
+macro_rules! status {
+    ($to_stdout:expr, $($arg:tt)*) => {
+        if $to_stdout {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+impl Args {
+    /// `--output -`: the download is written to stdout.
+    fn to_stdout(&self) -> bool {
+        self.output.as_deref() == Some(Path::new("-"))
+    }
+}
+
+/// Exits when `--output -` is combined with an option that needs an output
+/// file.
+fn check_stdout_options(args: &Args) {
+    if !args.to_stdout() {
+        return;
+    }
+    let conflicting = [
+        ("--continue", args.continue_partial),
+        ("--if-changed", args.if_changed),
+        ("--preserve-mtime", args.preserve_mtime),
+        ("--audio-url", args.audio_url.is_some()),
+    ];
+    if let Some((flag, _)) = conflicting.iter().find(|(_, set)| *set) {
+        eprintln!("{} needs an output file and cannot be used with --output -", flag);
+        std::process::exit(1);
+    }
+}
+
+/// What to do when the output file already exists. With `--if-changed`, a
+/// file this URL was downloaded to before is left for the server to judge.
+fn check_existing_output(args: &Args, url: &str, output: &Path) {
+    if args.to_stdout() || !output.exists() || args.overwrite || args.continue_partial {
+        return;
+    }
+    if args.if_changed && cache::load(output).is_some_and(|record| record.matches(url, output)) {
+        return;
+    }
+    if args.no_clobber {
+        println!("{} already exists, skipping (--no-clobber)", output.display());
+        std::process::exit(0);
+    }
+    eprintln!(
+        "{} already exists; pass --overwrite to replace it, --continue to resume it, or --no-clobber to skip",
+        output.display()
+    );
+    std::process::exit(1);
+}
+
+/// The request to download: `--url`, or the one `import-curl` was given.
+/// Exits if the curl command cannot be parsed.
+fn resolve_request(args: &Args) -> CurlRequest {
+    match &args.command {
+        Some(Command::ImportCurl { curl }) => parse_curl(curl).unwrap_or_else(|e| {
+            eprintln!("Could not import the curl command: {}", e);
+            std::process::exit(1);
+        }),
+        _ => CurlRequest {
+            url: args.url.clone(),
+            ..CurlRequest::default()
+        },
+    }
+}
+
+/// Output path when neither `--output`, `--output-template` nor
+/// `RDM_NAMING` says otherwise.
+const DEFAULT_OUTPUT: &str = "downloaded_file";
+
+/// The output path: `--output`; else the file named by a naming strategy
+/// against a probe of the URL, the template of `--output-template` or the
+/// strategy `RDM_NAMING` picks; else [`DEFAULT_OUTPUT`]. The probe goes
+/// through `strategy`, with the proxy, TLS, HTTP version and headers of the
+/// download, and the name it picks is handed to `strategy`. Exits on a bad
+/// naming strategy or a probe failure.
+async fn resolve_output(args: &Args, url: &str, strategy: &dyn DownloadStrategy) -> PathBuf {
+    if let Some(output) = &args.output {
+        return output.clone();
+    }
+    let template = args.output_template.as_deref();
+    let (name, source) = match (template, std::env::var("RDM_NAMING")) {
+        (Some(_), _) => ("template".to_string(), "--output-template"),
+        (None, Ok(name)) => (name, "RDM_NAMING"),
+        (None, Err(_)) => return PathBuf::from(DEFAULT_OUTPUT),
+    };
+    let naming = naming_strategy(&name, PathBuf::new(), template).unwrap_or_else(|e| {
+        eprintln!("Invalid {}: {}", source, e);
+        std::process::exit(1);
+    });
+    let mut probe = match strategy.probe().await {
+        Ok(probe) => probe,
+        Err(e) => {
+            eprintln!("Could not probe {} for {}: {}", url, source, e);
+            std::process::exit(1);
+        }
+    };
+    if is_dash_manifest(url, probe.content_type.as_deref()) {
+        // The manifest's own type says nothing about the muxed output.
+        probe.content_type = Some("video/mp4".to_string());
+    }
+    let output = naming.resolve(&probe, &probe.final_uri, "");
+    if let Err(e) = strategy.set_output_path(output.to_string_lossy().into_owned()) {
+        eprintln!("Could not set the output path: {}", e);
+        std::process::exit(1);
+    }
+    output
 }
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
     let args = Args::parse();
-    let url = args.url;
-    let output_path = args.output;
+    if run_remote(args.command.as_ref()).await {
+        return;
+    }
+    let request = resolve_request(&args);
+    check_stdout_options(&args);
+    let url = request.url.clone();
+    let output_path = args.output.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_OUTPUT));
+    let method = match request.method.as_deref().map(str::parse::<reqwest::Method>) {
+        Some(Ok(method)) => Some(method),
+        Some(Err(_)) => {
+            eprintln!("Invalid request method {:?}", request.method.as_deref().unwrap_or_default());
+            std::process::exit(1);
+        }
+        None => None,
+    };
     let connections = args.connections.unwrap_or(8);
+    let sequential = args.sequential || connections == 1;
+    let speed_limit = match (args.max_speed, args.limit_rate_per_connection) {
+        (Some(rate), _) => Some(SpeedLimit::Global(rate)),
+        (None, Some(rate)) => Some(SpeedLimit::PerConnection(rate)),
+        (None, None) => None,
+    };
 
-    let strategy = Arc::new(MultipartDownloadStrategy::builder(url.clone(), output_path).with_connection_size(connections).build());
+    let to_stdout = args.to_stdout();
+    let strategy: Arc<dyn DownloadStrategy> = if is_dash_manifest(&url, None) && method.is_none() {
+        if to_stdout {
+            eprintln!("DASH manifests are muxed into a file and cannot be written to --output -");
+            std::process::exit(1);
+        }
+        let builder = DashDownloadStrategy::builder(url.clone(), output_path)
+            .with_connection_size(if sequential { 1 } else { connections })
+            .with_headers(request.headers);
+        let builder = match request.cookies {
+            Some(cookies) => builder.with_cookies(cookies),
+            None => builder,
+        };
+        let builder = match args.user.clone() {
+            Some(auth) => builder.with_authentication(auth),
+            None => builder,
+        };
+        let builder = match (args.cert.clone(), args.key.clone()) {
+            (Some(cert), Some(key)) => builder.with_client_cert(cert, key),
+            _ => builder,
+        };
+        let builder = match args.cacert.clone() {
+            Some(ca) => builder.with_root_cert(ca),
+            None => builder,
+        };
+        let builder = match speed_limit {
+            Some(limit) => builder.with_speed_limit(limit),
+            None => builder,
+        };
+        let builder = match (args.checksum, args.expected_checksum.clone()) {
+            (Some(algo), Some(hex)) => builder.with_expected_checksum(algo, hex),
+            (Some(algo), None) => builder.with_compute_checksum(algo),
+            _ => builder,
+        };
+        Arc::new(builder.build())
+    } else {
+        let builder = MultipartDownloadStrategy::builder(url.clone(), output_path)
+            .with_connection_size(connections)
+            .with_continue(args.continue_partial)
+            .with_force_single_stream(args.no_resume)
+            .with_sequential(sequential)
+            .with_http_version(args.http_version)
+            .with_preserve_mtime(args.preserve_mtime)
+            .with_assembly_threads(args.assembly_threads as usize)
+            .with_conditional_cache(args.if_changed)
+            .with_no_cache(args.no_cache)
+            .with_output_target(if to_stdout { OutputTarget::Stdout } else { OutputTarget::File })
+            .with_mirrors(args.mirrors.clone())
+            .with_headers(request.headers);
+        let builder = match request.cookies {
+            Some(cookies) => builder.with_cookies(cookies),
+            None => builder,
+        };
+        let builder = match method {
+            Some(method) => builder.with_method(method),
+            None => builder,
+        };
+        let builder = match request.body {
+            Some(body) => builder.with_body(body),
+            None => builder,
+        };
+        let builder = match args.user.clone() {
+            Some(auth) => builder.with_authentication(auth),
+            None => builder,
+        };
+        let builder = match (args.cert.clone(), args.key.clone()) {
+            (Some(cert), Some(key)) => builder.with_client_cert(cert, key),
+            _ => builder,
+        };
+        let builder = match args.cacert.clone() {
+            Some(ca) => builder.with_root_cert(ca),
+            None => builder,
+        };
+        let builder = match args.audio_url.clone() {
+            Some(audio_url) => builder.with_audio_url(audio_url),
+            None => builder,
+        };
+        let builder = match speed_limit {
+            Some(limit) => builder.with_speed_limit(limit),
+            None => builder,
+        };
+        let builder = match (args.checksum, args.expected_checksum.clone()) {
+            (Some(algo), Some(hex)) => builder.with_expected_checksum(algo, hex),
+            (Some(algo), None) => builder.with_compute_checksum(algo),
+            _ => builder,
+        };
+        let builder = match args.checksum_url.clone() {
+            Some(checksum_url) => builder.with_checksum_url(checksum_url),
+            None => builder,
+        };
+        Arc::new(builder.build())
+    };
+    let output_path = resolve_output(&args, &url, strategy.as_ref()).await;
+    check_existing_output(&args, &url, &output_path);
     let mut downloader = HttpDownloader::new(strategy);
-    downloader.add_observer(Box::new(TerminalProgressObserver::new()));
+    let progress = TerminalProgress::new().with_speed_unit(args.speed_unit);
+    downloader.add_observer(Box::new(progress.observer()));
+    if let Some(secs) = args.max_time {
+        downloader.set_deadline(Duration::from_secs(secs));
+    }
 
-    println!("Starting download: {}", url);
-    let start = Instant::now();
+    status!(to_stdout, "Starting download: {}", url);
 
     match downloader.download().await {
-        Ok(()) => {
-            let elapsed = start.elapsed();
-            println!("Download completed in {:.2}s", elapsed.as_secs_f64());
+        Ok(summary) if summary.skipped => {
+            status!(to_stdout, "{} is unchanged on the server; nothing downloaded", summary.path);
+        }
+        Ok(summary) => {
+            status!(to_stdout, "Download completed in {:.2}s", summary.duration.as_secs_f64());
+            status!(to_stdout, "  path       {}", summary.path);
+            status!(to_stdout, "  size       {} ({} bytes)", format_bytes(summary.bytes), summary.bytes);
+            status!(to_stdout, "  avg speed  {}", format_speed(summary.avg_speed, args.speed_unit));
+            status!(to_stdout, "  segments   {}", summary.segments);
+            status!(to_stdout, "  resumable  {}", if summary.resumable { "yes" } else { "no" });
+            if let (Some(algo), Some(digest)) = (args.checksum, &summary.checksum) {
+                status!(to_stdout, "  {:<10} {}", algo, digest);
+            }
+            if let Some(ok) = summary.checksum_ok {
+                status!(to_stdout, "  checksum   {}", if ok { "ok" } else { "MISMATCH" });
+            }
         }
         Err(e) => {
             eprintln!("Download failed: {}", e);
+            if let DownloadError::AuthRequired { .. } = e {
+                if args.user.is_some() {
+                    eprintln!("The server rejected the --user credentials.");
+                } else {
+                    eprintln!("Pass --user USER:PASSWORD to log in.");
+                }
+            }
         }
     }
 }
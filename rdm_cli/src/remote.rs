@@ -0,0 +1,241 @@
+//! `rdm list`, `rdm status <id>` and `rdm cancel <id>` — a control client
+//! for a running `rdmd`, over its REST endpoints.
+//!
+//! The daemon is found at `RDM_SERVER_URL` (default
+//! `http://127.0.0.1:8597`, rdmd's own default address).
+
+use serde::Deserialize;
+
+use rdm_core::progress::snapshot::format_bytes;
+use rdm_core::types::types::{DownloadSummary, Segment};
+
+const DEFAULT_SERVER_URL: &str = "http://127.0.0.1:8597";
+
+/// Base URL of the daemon, without a trailing slash.
+pub fn server_url() -> String {
+    std::env::var("RDM_SERVER_URL")
+        .ok()
+        .filter(|url| !url.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_SERVER_URL.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// One row of `GET /downloads`.
+#[derive(Debug, Deserialize)]
+pub struct DownloadRow {
+    pub id: String,
+    pub url: String,
+    pub output_path: String,
+    pub status: String,
+    pub created_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DownloadList {
+    total: usize,
+    downloads: Vec<DownloadRow>,
+}
+
+/// `GET /status/{id}`; only `id` and `status` are set for an unknown id.
+#[derive(Debug, Deserialize)]
+struct DownloadStatus {
+    status: String,
+    url: Option<String>,
+    output_path: Option<String>,
+    summary: Option<DownloadSummary>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelResponse {
+    status: String,
+    detail: Option<String>,
+}
+
+pub struct RemoteClient {
+    client: reqwest::Client,
+    base: String,
+}
+
+impl RemoteClient {
+    pub fn new(base: String) -> Self {
+        Self { client: reqwest::Client::new(), base }
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.send(self.client.get(format!("{}{}", self.base, path))).await
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T, String> {
+        self.send(self.client.post(format!("{}{}", self.base, path))).await
+    }
+
+    async fn send<T: serde::de::DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<T, String> {
+        let response = request.send().await.map_err(|e| {
+            if e.is_connect() {
+                format!(
+                    "could not connect to {}; is rdmd running? (set RDM_SERVER_URL to point elsewhere)",
+                    self.base
+                )
+            } else {
+                format!("request to {} failed: {}", self.base, e)
+            }
+        })?;
+        let status = response.status();
+        let body = response.bytes().await.map_err(|e| format!("reading the response failed: {}", e))?;
+        if !status.is_success() {
+            return Err(format!("rdmd responded {}: {}", status, String::from_utf8_lossy(&body).trim()));
+        }
+        serde_json::from_slice(&body).map_err(|e| format!("unexpected response from rdmd: {}", e))
+    }
+
+    /// Prints every download, newest first, as a table.
+    pub async fn list(&self) -> Result<(), String> {
+        let list: DownloadList = self.get("/downloads").await?;
+        if list.downloads.is_empty() {
+            println!("No downloads.");
+            return Ok(());
+        }
+        print!("{}", format_table(&list.downloads, unix_now()));
+        if list.total > list.downloads.len() {
+            println!("({} of {} shown)", list.downloads.len(), list.total);
+        }
+        Ok(())
+    }
+
+    /// Prints one download and a progress line.
+    pub async fn status(&self, id: &str) -> Result<(), String> {
+        let status: DownloadStatus = self.get(&format!("/status/{}", id)).await?;
+        if status.status == "not_found" {
+            return Err(format!("no download with id {}", id));
+        }
+        println!("id      {}", id);
+        println!("url     {}", status.url.unwrap_or_default());
+        println!("output  {}", status.output_path.unwrap_or_default());
+        let progress = match &status.summary {
+            Some(summary) => format!(
+                "{} — {} in {:.1}s",
+                status.status,
+                format_bytes(summary.bytes),
+                summary.duration.as_secs_f64()
+            ),
+            None => {
+                let segments: Vec<Segment> = self.get(&format!("/downloads/{}/segments", id)).await?;
+                progress_line(&status.status, &segments)
+            }
+        };
+        println!("status  {}", progress);
+        Ok(())
+    }
+
+    pub async fn cancel(&self, id: &str) -> Result<(), String> {
+        let response: CancelResponse = self.post(&format!("/cancel/{}", id)).await?;
+        match response.status.as_str() {
+            "cancelled" => {
+                println!("Cancelled {}", id);
+                Ok(())
+            }
+            "not_found" => Err(format!("no download with id {}", id)),
+            _ => Err(format!(
+                "could not cancel {}: {}",
+                id,
+                response.detail.unwrap_or(response.status)
+            )),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// `rows` as aligned columns under a header line; `now` dates the AGE column.
+pub fn format_table(rows: &[DownloadRow], now: u64) -> String {
+    let status_width = rows.iter().map(|r| r.status.len()).max().unwrap_or(0).max("STATUS".len());
+    let mut out = format!("{:<36}  {:<status_width$}  {:>5}  OUTPUT\n", "ID", "STATUS", "AGE");
+    for row in rows {
+        let output = if row.output_path.is_empty() { &row.url } else { &row.output_path };
+        out.push_str(&format!(
+            "{:<36}  {:<status_width$}  {:>5}  {}\n",
+            row.id,
+            row.status,
+            format_age(now.saturating_sub(row.created_at)),
+            output
+        ));
+    }
+    out
+}
+
+/// `secs` as the largest whole unit: `42s`, `5m`, `3h`, `2d`.
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// `status` with how far the segments have got; the percentage only when
+/// every segment's length is known.
+pub fn progress_line(status: &str, segments: &[Segment]) -> String {
+    let downloaded: u64 = segments.iter().map(|s| s.downloaded.max(0) as u64).sum();
+    if segments.is_empty() || segments.iter().any(|s| s.length < 0) {
+        return format!("{} — {} downloaded", status, format_bytes(downloaded));
+    }
+    let total: u64 = segments.iter().map(|s| s.length as u64).sum();
+    let percent = if total == 0 { 100.0 } else { downloaded as f64 * 100.0 / total as f64 };
+    format!(
+        "{} — {:.1}% ({} of {}, {} segments)",
+        status,
+        percent,
+        format_bytes(downloaded),
+        format_bytes(total),
+        segments.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: &str, status: &str, created_at: u64, output_path: &str) -> DownloadRow {
+        DownloadRow {
+            id: id.to_string(),
+            url: "https://example.com/a.iso".to_string(),
+            output_path: output_path.to_string(),
+            status: status.to_string(),
+            created_at,
+        }
+    }
+
+    #[test]
+    fn formats_downloads_as_table() {
+        let rows = [
+            row("2f1c3b9e-0000-4000-8000-000000000001", "running", 9_970, "/tmp/a.iso"),
+            row("2f1c3b9e-0000-4000-8000-000000000002", "cancelled", 2_800, ""),
+        ];
+        let table = format_table(&rows, 10_000);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], format!("{:<36}  STATUS       AGE  OUTPUT", "ID"));
+        assert_eq!(lines[1], "2f1c3b9e-0000-4000-8000-000000000001  running      30s  /tmp/a.iso");
+        assert_eq!(lines[2], "2f1c3b9e-0000-4000-8000-000000000002  cancelled     2h  https://example.com/a.iso");
+    }
+
+    #[test]
+    fn progress_line_needs_known_lengths_for_a_percentage() {
+        let mut first = Segment::new("a".to_string(), 0, 1024 * 1024);
+        first.downloaded = 1024 * 1024;
+        let second = Segment::new("b".to_string(), 1024 * 1024, 1024 * 1024);
+        assert_eq!(
+            progress_line("running", &[first.clone(), second]),
+            "running — 50.0% (1.00 MB of 2.00 MB, 2 segments)"
+        );
+
+        let unknown = Segment::new("c".to_string(), 0, -1);
+        assert_eq!(progress_line("running", &[first, unknown]), "running — 1.00 MB downloaded");
+    }
+}
@@ -1,123 +1,284 @@
 use async_trait::async_trait;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressState, ProgressStyle};
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use rdm_core::progress::observer::ProgressObserver;
-use rdm_core::progress::snapshot::{format_bytes, ProgressSnapshot};
+use rdm_core::progress::snapshot::{format_bytes, format_speed, ProgressSnapshot, SpeedUnit};
+use rdm_core::types::types::PreprocessInfo;
 
-/// Renders download progress as indicatif terminal bars.
+/// Terminal progress for every download the CLI runs at once.
 ///
-/// One `ProgressBar` is created per segment, plus a total bar.
-/// All bars live under a shared `MultiProgress` so they render cleanly.
-pub struct TerminalProgressObserver {
+/// All bars live under one `MultiProgress` so they render cleanly: each
+/// download's segment bars are stacked together, above a single total bar
+/// across all downloads. Each download reports through its own
+/// [`TerminalProgressObserver`], from [`TerminalProgress::observer`].
+#[derive(Clone)]
+pub struct TerminalProgress {
     multi: MultiProgress,
-    /// segment_id → ProgressBar (lazily initialised on first `on_progress` call)
-    bars: Mutex<HashMap<String, ProgressBar>>,
+    shared: Arc<Mutex<Bars>>,
+    speed_unit: SpeedUnit,
+}
+
+/// The bars of all downloads, behind one lock so a download's bars and the
+/// total bar are always updated together.
+#[derive(Default)]
+struct Bars {
+    /// (download_index, segment_id) → ProgressBar (created on first sight)
+    segments: HashMap<(usize, String), ProgressBar>,
+    /// Most recently placed segment bar of each download; the next one goes
+    /// right after it.
+    last: HashMap<usize, ProgressBar>,
     /// The aggregate total bar
-    total_bar: Mutex<Option<ProgressBar>>,
+    total: Option<ProgressBar>,
+    /// download_index → latest `(bytes_downloaded, total_bytes)`
+    totals: HashMap<usize, (u64, u64)>,
+    /// Observers handed out, and how many of them have finished or failed.
+    downloads: usize,
+    finished: usize,
+    failed: usize,
 }
 
-impl TerminalProgressObserver {
+impl TerminalProgress {
     pub fn new() -> Self {
         Self {
             multi: MultiProgress::new(),
-            bars: Mutex::new(HashMap::new()),
-            total_bar: Mutex::new(None),
+            shared: Arc::new(Mutex::new(Bars::default())),
+            speed_unit: SpeedUnit::default(),
         }
     }
 
+    /// Unit the bars and the completion message show speeds in.
+    pub fn with_speed_unit(mut self, unit: SpeedUnit) -> Self {
+        self.speed_unit = unit;
+        self
+    }
+
+    /// The observer for one more download; its bars are keyed by the order
+    /// the observers were handed out in.
+    pub fn observer(&self) -> TerminalProgressObserver {
+        let mut bars = self.shared.lock().unwrap();
+        let index = bars.downloads;
+        bars.downloads += 1;
+        TerminalProgressObserver { progress: self.clone(), index }
+    }
+
+    /// Bar style for `template`, whose `{speed}` key is rendered in `speed_unit`.
+    fn style(&self, template: &str) -> ProgressStyle {
+        let unit = self.speed_unit;
+        ProgressStyle::with_template(template)
+            .unwrap()
+            .with_key("speed", move |state: &ProgressState, w: &mut dyn std::fmt::Write| {
+                let _ = w.write_str(&format_speed(state.per_sec(), unit));
+            })
+            .progress_chars("=>-")
+    }
+}
+
+/// Renders one download's progress as indicatif bars: one `ProgressBar`
+/// per segment, plus its share of the total bar of the [`TerminalProgress`]
+/// it came from.
+pub struct TerminalProgressObserver {
+    progress: TerminalProgress,
+    index: usize,
+}
+
+impl TerminalProgressObserver {
+    /// What a line or bar of this download is labelled with: nothing extra
+    /// while it is the only download.
+    fn label(&self, bars: &Bars, text: &str) -> String {
+        if bars.downloads > 1 {
+            format!("[{}] {}", self.index + 1, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn println(&self, text: &str) {
+        let line = self.label(&self.progress.shared.lock().unwrap(), text);
+        let _ = self.progress.multi.println(line);
+    }
+
     /// Ensure all per-segment bars and the total bar exist for the given snapshot.
-    fn ensure_bars(&self, snapshot: &ProgressSnapshot) {
-        let mut bars = self.bars.lock().unwrap();
-        let mut total_bar = self.total_bar.lock().unwrap();
+    fn ensure_bars(&self, bars: &mut Bars, snapshot: &ProgressSnapshot) {
+        let multi = &self.progress.multi;
 
-        // Per-segment bars
+        // Per-segment bars, kept together below this download's others
         for segment in &snapshot.segments {
-            if !bars.contains_key(&segment.segment_id) {
-                let style = ProgressStyle::with_template(
-                    "[{bar:30.cyan/blue}] {bytes}/{total_bytes} ({binary_bytes_per_sec}) ETA {eta} — {msg}",
-                )
-                .unwrap()
-                .progress_chars("=>-");
-
-                let pb = self.multi.add(ProgressBar::new(segment.total_bytes.max(1)));
+            let key = (self.index, segment.segment_id.clone());
+            if !bars.segments.contains_key(&key) {
+                let style = self.progress.style("[{bar:30.cyan/blue}] {bytes}/{total_bytes} ({speed}) ETA {eta} — {msg}");
+
+                let pb = ProgressBar::new(segment.total_bytes.max(1));
+                let pb = match (bars.last.get(&self.index), &bars.total) {
+                    (Some(last), _) => multi.insert_after(last, pb),
+                    (None, Some(total)) => multi.insert_before(total, pb),
+                    (None, None) => multi.add(pb),
+                };
                 pb.set_style(style);
-                pb.set_message(segment.segment_id.clone());
-                bars.insert(segment.segment_id.clone(), pb);
+                pb.set_message(self.label(bars, &segment.segment_id));
+                bars.last.insert(self.index, pb.clone());
+                bars.segments.insert(key, pb);
             }
         }
 
-        // Total bar (created once)
-        if total_bar.is_none() && snapshot.total_bytes > 0 {
-            let style = ProgressStyle::with_template(
-                "Total [{bar:30.green/white}] {bytes}/{total_bytes} ({binary_bytes_per_sec}) ETA {eta}",
-            )
-            .unwrap()
-            .progress_chars("=>-");
+        // Total bar (created once, for all downloads)
+        if bars.total.is_none() && snapshot.total_bytes > 0 {
+            let style = self.progress.style("Total [{bar:30.green/white}] {bytes}/{total_bytes} ({speed}) ETA {eta}");
 
-            let pb = self.multi.add(ProgressBar::new(snapshot.total_bytes.max(1)));
+            let pb = multi.add(ProgressBar::new(snapshot.total_bytes.max(1)));
             pb.set_style(style);
-            *total_bar = Some(pb);
+            bars.total = Some(pb);
         }
     }
 
-    fn update_bars(&self, snapshot: &ProgressSnapshot) {
-        let bars = self.bars.lock().unwrap();
-        let total_bar = self.total_bar.lock().unwrap();
-
+    fn update_bars(&self, bars: &mut Bars, snapshot: &ProgressSnapshot) {
         for segment in &snapshot.segments {
-            if let Some(pb) = bars.get(&segment.segment_id) {
+            if let Some(pb) = bars.segments.get(&(self.index, segment.segment_id.clone())) {
                 pb.set_length(segment.total_bytes.max(1));
                 pb.set_position(segment.bytes_downloaded);
+                if segment.retry_count > 0 {
+                    let retry = format!("{} (retry {})", segment.segment_id, segment.retry_count);
+                    pb.set_message(self.label(bars, &retry));
+                }
             }
         }
 
-        if let Some(pb) = total_bar.as_ref() {
-            pb.set_length(snapshot.total_bytes.max(1));
-            pb.set_position(snapshot.total_bytes_downloaded);
+        bars.totals.insert(self.index, (snapshot.total_bytes_downloaded, snapshot.total_bytes));
+        let (downloaded, total) = bars.totals.values().fold((0, 0), |(d, t), &(dd, tt)| (d + dd, t + tt));
+        if let Some(pb) = bars.total.as_ref() {
+            pb.set_length(total.max(1));
+            pb.set_position(downloaded);
         }
     }
 
-    fn finish_bars(&self, snapshot: &ProgressSnapshot) {
-        let bars = self.bars.lock().unwrap();
-        let total_bar = self.total_bar.lock().unwrap();
-
+    fn finish_bars(&self, bars: &mut Bars, snapshot: &ProgressSnapshot) {
         for segment in &snapshot.segments {
-            if let Some(pb) = bars.get(&segment.segment_id) {
-                pb.finish_with_message(format!("{} done", segment.segment_id));
+            if let Some(pb) = bars.segments.get(&(self.index, segment.segment_id.clone())) {
+                let done = format!("{} done", segment.segment_id);
+                pb.finish_with_message(self.label(bars, &done));
             }
         }
 
-        if let Some(pb) = total_bar.as_ref() {
-            let speed = format_bytes(snapshot.speed as u64);
-            let total = format_bytes(snapshot.total_bytes_downloaded);
-            pb.finish_with_message(format!("Complete — {} at {}/s", total, speed));
+        bars.finished += 1;
+        if bars.finished < bars.downloads {
+            return;
+        }
+        if let Some(pb) = bars.total.as_ref() {
+            let downloaded: u64 = bars.totals.values().map(|&(d, _)| d).sum();
+            // One download's own average; across several, the wall-clock one.
+            let speed = if bars.downloads == 1 {
+                snapshot.speed
+            } else {
+                downloaded as f64 / pb.elapsed().as_secs_f64().max(f64::EPSILON)
+            };
+            let mut message = format!("Complete — {} at {}", format_bytes(downloaded), format_speed(speed, self.progress.speed_unit));
+            if bars.failed > 0 {
+                message.push_str(&format!(" ({} of {} failed)", bars.failed, bars.downloads));
+            }
+            pb.finish_with_message(message);
         }
     }
 }
 
 #[async_trait]
 impl ProgressObserver for TerminalProgressObserver {
+    async fn on_start(&self, info: &PreprocessInfo) {
+        let size = info.file_size.map(format_bytes).unwrap_or_else(|| "unknown size".to_string());
+        self.println(&format!("Downloading {} in {} segment(s)", size, info.segment_count));
+        if !info.resumable {
+            self.println(
+                "Warning: the server does not support ranges; downloading in one stream, \
+                 which cannot be continued if interrupted",
+            );
+        }
+    }
+
+    async fn on_phase(&self, phase: &str) {
+        self.println(phase);
+    }
+
+    async fn on_warning(&self, message: &str) {
+        self.println(&format!("Warning: {}", message));
+    }
+
     async fn on_progress(&self, snapshot: &ProgressSnapshot) {
-        self.ensure_bars(snapshot);
-        self.update_bars(snapshot);
+        let mut bars = self.progress.shared.lock().unwrap();
+        self.ensure_bars(&mut bars, snapshot);
+        self.update_bars(&mut bars, snapshot);
     }
 
     async fn on_complete(&self, snapshot: &ProgressSnapshot) {
-        self.ensure_bars(snapshot);
-        self.finish_bars(snapshot);
+        let mut bars = self.progress.shared.lock().unwrap();
+        self.ensure_bars(&mut bars, snapshot);
+        bars.totals.insert(self.index, (snapshot.total_bytes_downloaded, snapshot.total_bytes));
+        self.finish_bars(&mut bars, snapshot);
     }
 
     async fn on_error(&self, error: &str) {
-        // Abandon all open bars with the error message.
-        let bars = self.bars.lock().unwrap();
-        let total_bar = self.total_bar.lock().unwrap();
-
-        for pb in bars.values() {
-            pb.abandon_with_message(format!("Error: {}", error));
+        // Abandon this download's open bars with the error message.
+        let mut bars = self.progress.shared.lock().unwrap();
+        for ((index, _), pb) in &bars.segments {
+            if *index == self.index {
+                pb.abandon_with_message(self.label(&bars, &format!("Error: {}", error)));
+            }
         }
-        if let Some(pb) = total_bar.as_ref() {
+        if bars.downloads > 1 {
+            bars.failed += 1;
+            self.finish_bars(&mut bars, &ProgressSnapshot::empty());
+        } else if let Some(pb) = bars.total.as_ref() {
             pb.abandon_with_message(format!("Failed: {}", error));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indicatif::ProgressDrawTarget;
+    use rdm_core::progress::snapshot::SegmentSnapshot;
+
+    fn snapshot(segments: &[(&str, u64, u64)]) -> ProgressSnapshot {
+        let mut snapshot = ProgressSnapshot::empty();
+        for &(id, downloaded, total) in segments {
+            snapshot.segments.push(SegmentSnapshot {
+                segment_id: id.to_string(),
+                bytes_downloaded: downloaded,
+                total_bytes: total,
+                speed: 0.0,
+                eta_secs: 0.0,
+                retry_count: 0,
+            });
+            snapshot.total_bytes_downloaded += downloaded;
+            snapshot.total_bytes += total;
+        }
+        snapshot
+    }
+
+    #[tokio::test]
+    async fn downloads_share_one_total_bar() {
+        let progress = TerminalProgress::new();
+        progress.multi.set_draw_target(ProgressDrawTarget::hidden());
+        let first = progress.observer();
+        let second = progress.observer();
+
+        // Both downloads may use the same segment ids.
+        first.on_progress(&snapshot(&[("a", 10, 100), ("b", 0, 100)])).await;
+        second.on_progress(&snapshot(&[("a", 50, 300)])).await;
+        first.on_progress(&snapshot(&[("a", 100, 100), ("b", 40, 100)])).await;
+        {
+            let bars = progress.shared.lock().unwrap();
+            assert_eq!(bars.segments.len(), 3);
+            let total = bars.total.as_ref().unwrap();
+            assert_eq!((total.position(), total.length()), (190, Some(500)));
+            assert_eq!(bars.segments[&(1, "a".to_string())].message(), "[2] a");
+        }
+
+        first.on_complete(&snapshot(&[("a", 100, 100), ("b", 100, 100)])).await;
+        assert!(!progress.shared.lock().unwrap().total.as_ref().unwrap().is_finished());
+        second.on_error("connection refused").await;
+        let bars = progress.shared.lock().unwrap();
+        let total = bars.total.as_ref().unwrap();
+        assert!(total.is_finished());
+        assert!(total.message().ends_with("(1 of 2 failed)"), "{}", total.message());
+    }
+}
@@ -0,0 +1,46 @@
+//! `rdm --assembly-threads`: serial and parallel assembly write the same file.
+
+use std::process::Command;
+
+use rdm_test_support::RangeSlice;
+use wiremock::{Mock, MockServer};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serial_and_parallel_assembly_write_the_same_file() {
+    let body: Vec<u8> = (0..6 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+    let server = MockServer::start().await;
+    Mock::given(wiremock::matchers::method("GET"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    for threads in ["1", "4"] {
+        let output = dir.path().join(format!("assembled_{}.bin", threads));
+        let args = [
+            "--url".to_string(),
+            format!("{}/data.bin", server.uri()),
+            "--output".to_string(),
+            output.to_string_lossy().into_owned(),
+            "--connections".to_string(),
+            "4".to_string(),
+            "--assembly-threads".to_string(),
+            threads.to_string(),
+        ];
+        let run = tokio::task::spawn_blocking(move || Command::new(env!("CARGO_BIN_EXE_rdm")).args(args).output().unwrap())
+            .await
+            .unwrap();
+        assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+        assert!(std::fs::read(&output).unwrap() == body, "--assembly-threads {} wrote the wrong bytes", threads);
+    }
+}
+
+#[test]
+fn zero_assembly_threads_are_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rdm"))
+        .args(["--url", "http://127.0.0.1:1/x", "--assembly-threads", "0"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--assembly-threads"));
+}
@@ -0,0 +1,45 @@
+//! `rdm --output-template`: the probe that names the file is sent like the
+//! download itself.
+
+use std::process::Command;
+
+use wiremock::matchers::{header_regex, method};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn the_naming_probe_sends_the_download_user_agent() {
+    let body = b"named by the template".to_vec();
+    let server = MockServer::start().await;
+    // Only requests carrying rdm's User-Agent learn the attachment name.
+    Mock::given(method("GET"))
+        .and(header_regex("user-agent", "^rdm/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(body.clone())
+                .insert_header("Content-Disposition", "attachment; filename=\"report.bin\""),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET")).respond_with(ResponseTemplate::new(403)).mount(&server).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let cwd = dir.path().to_path_buf();
+    let url = format!("{}/clip.bin", server.uri());
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_rdm"))
+            .args(["--url", &url, "--output-template", "saved-{name}"])
+            .current_dir(cwd)
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().path()).collect();
+    assert_eq!(files.len(), 1, "{:?}", files);
+    let name = files[0].file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.starts_with("saved-report"), "{}", name);
+    assert_eq!(std::fs::read(&files[0]).unwrap(), body);
+}
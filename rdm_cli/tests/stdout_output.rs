@@ -0,0 +1,50 @@
+//! `rdm --output -` end to end: stdout carries exactly the downloaded bytes.
+
+use std::process::Command;
+
+use rdm_test_support::RangeSlice;
+use wiremock::{Mock, MockServer};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn output_dash_writes_the_download_to_stdout() {
+    // Large enough to be split, so the segments have to come out in order.
+    let body: Vec<u8> = (0..6 * 1024 * 1024 + 17).map(|i| (i % 251) as u8).collect();
+    let server = MockServer::start().await;
+    Mock::given(wiremock::matchers::method("GET"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let cwd = dir.path().to_path_buf();
+    let url = format!("{}/data.bin", server.uri());
+    let output = tokio::task::spawn_blocking(move || {
+        Command::new(env!("CARGO_BIN_EXE_rdm"))
+            .args(["--url", &url, "--output", "-", "--connections", "4"])
+            .current_dir(cwd)
+            .output()
+            .unwrap()
+    })
+    .await
+    .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{}", stderr);
+    assert!(output.stdout == body, "stdout holds {} bytes, not the {} of the file", output.stdout.len(), body.len());
+    assert!(stderr.contains("Download completed"), "{}", stderr);
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0, "no file is written");
+}
+
+#[test]
+fn output_dash_rejects_options_that_need_a_file() {
+    let output = Command::new(env!("CARGO_BIN_EXE_rdm"))
+        .args(["--url", "http://127.0.0.1:1/x", "--output", "-", "--continue"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr).trim(),
+        "--continue needs an output file and cannot be used with --output -"
+    );
+}
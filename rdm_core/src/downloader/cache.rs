@@ -0,0 +1,96 @@
+//! Validators of a finished download, kept in a sidecar file next to the
+//! output, so downloading the same URL to the same path again can ask the
+//! server whether anything changed (`If-None-Match` / `If-Modified-Since`)
+//! and skip the transfer on `304 Not Modified`; see
+//! `MultipartDownloadStrategyBuilder::with_conditional_cache`.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Suffix appended to the output file name for its sidecar.
+pub const SIDECAR_SUFFIX: &str = ".rdm-cache";
+
+/// What the server said about the output file when it was downloaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheRecord {
+    /// The URL as requested, before any redirect.
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Size of the output file when it was recorded.
+    pub size: u64,
+}
+
+impl CacheRecord {
+    /// Whether the record was made for `url` and the file at `output` is
+    /// still there with the recorded size, so a `304` means it is current.
+    pub fn matches(&self, url: &str, output: &Path) -> bool {
+        self.url == url
+            && (self.etag.is_some() || self.last_modified.is_some())
+            && std::fs::metadata(output).is_ok_and(|m| m.is_file() && m.len() == self.size)
+    }
+
+    /// Conditional request headers for this record: `If-None-Match` when
+    /// there is an ETag, `If-Modified-Since` when there is a date.
+    pub fn conditional_headers(&self) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        if let Some(etag) = &self.etag {
+            headers.push(("If-None-Match".to_string(), etag.clone()));
+        }
+        if let Some(last_modified) = &self.last_modified {
+            headers.push(("If-Modified-Since".to_string(), last_modified.clone()));
+        }
+        headers
+    }
+}
+
+/// The sidecar path for `output`: `clip.mp4` → `clip.mp4.rdm-cache`.
+pub fn sidecar_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// The record kept for `output`, if there is a readable one.
+pub fn load(output: &Path) -> Option<CacheRecord> {
+    let bytes = std::fs::read(sidecar_path(output)).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// The record of the file a download of `url` to `output` was saved as,
+/// with that file's path, if it is still there unchanged. That is
+/// `output` itself, or a file in it when it is a directory, or `output`
+/// with an extension added when saving gave it one.
+pub fn find(output: &Path, url: &str) -> Option<(PathBuf, CacheRecord)> {
+    let current = |file: PathBuf| load(&file).filter(|record| record.matches(url, &file)).map(|record| (file, record));
+    let (dir, prefix) = if output.is_dir() {
+        (output.to_path_buf(), None)
+    } else {
+        if let Some(found) = current(output.to_path_buf()) {
+            return Some(found);
+        }
+        let dir = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        (dir.to_path_buf(), Some(format!("{}.", output.file_name()?.to_string_lossy())))
+    };
+    std::fs::read_dir(&dir).ok()?.filter_map(Result::ok).find_map(|entry| {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let file_name = name.strip_suffix(SIDECAR_SUFFIX)?;
+        if prefix.as_deref().is_some_and(|prefix| !file_name.starts_with(prefix)) {
+            return None;
+        }
+        current(dir.join(file_name))
+    })
+}
+
+/// Write `record` as the sidecar of `output`.
+pub fn store(output: &Path, record: &CacheRecord) -> io::Result<()> {
+    let json = serde_json::to_vec_pretty(record).map_err(io::Error::other)?;
+    std::fs::write(sidecar_path(output), json)
+}
+
+/// Remove the sidecar of `output`, if any.
+pub fn remove(output: &Path) {
+    let _ = std::fs::remove_file(sidecar_path(output));
+}
@@ -0,0 +1,133 @@
+//! Streaming digests of the assembled output file, reported in
+//! `DownloadSummary::checksum`.
+
+use std::io::{self, Write};
+use std::path::Path;
+
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::types::types::ChecksumAlgo;
+
+/// A running digest of one of the supported [`ChecksumAlgo`]s.
+#[derive(Clone)]
+pub enum Checksum {
+    Md5(Md5),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Checksum {
+    pub fn new(algo: ChecksumAlgo) -> Self {
+        match algo {
+            ChecksumAlgo::Md5 => Self::Md5(Md5::new()),
+            ChecksumAlgo::Sha256 => Self::Sha256(Sha256::new()),
+            ChecksumAlgo::Sha512 => Self::Sha512(Sha512::new()),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(h) => h.update(data),
+            Self::Sha256(h) => h.update(data),
+            Self::Sha512(h) => h.update(data),
+        }
+    }
+
+    /// Lowercase hex of the digest.
+    pub fn finalize_hex(self) -> String {
+        let digest = match self {
+            Self::Md5(h) => h.finalize().to_vec(),
+            Self::Sha256(h) => h.finalize().to_vec(),
+            Self::Sha512(h) => h.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    /// Feed the whole file at `path` into the digest.
+    pub fn update_from_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = std::fs::File::open(path)?;
+        io::copy(&mut file, &mut HashingWriter { inner: io::sink(), checksum: self })?;
+        Ok(())
+    }
+
+    /// Feed the first `len` bytes of the file at `path` into the digest.
+    /// Fails with `UnexpectedEof` if the file is shorter.
+    pub fn update_from_file_prefix(&mut self, path: &Path, len: u64) -> io::Result<()> {
+        let file = std::fs::File::open(path)?;
+        let read = io::copy(&mut io::Read::take(file, len), &mut HashingWriter { inner: io::sink(), checksum: self })?;
+        if read < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("{} holds {} of {} bytes", path.display(), read, len),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Hex digest of the file at `path`.
+pub fn hash_file(path: &Path, algo: ChecksumAlgo) -> io::Result<String> {
+    let mut checksum = Checksum::new(algo);
+    checksum.update_from_file(path)?;
+    Ok(checksum.finalize_hex())
+}
+
+/// The supported algorithm that makes hex digests of `hex`'s length.
+pub fn algo_for_digest(hex: &str) -> Option<ChecksumAlgo> {
+    match hex.len() {
+        32 => Some(ChecksumAlgo::Md5),
+        64 => Some(ChecksumAlgo::Sha256),
+        128 => Some(ChecksumAlgo::Sha512),
+        _ => None,
+    }
+}
+
+/// The digest for `file_name` in a published checksum file: `sha256sum`
+/// output (`<hex>  <name>` or `<hex> *<name>`), the BSD form
+/// (`SHA256 (<name>) = <hex>`) or a bare digest. A file with one entry is
+/// taken whatever name it gives; otherwise the entry naming `file_name`.
+pub fn parse_checksum_file(text: &str, file_name: &str) -> Option<String> {
+    let entries: Vec<(&str, Option<&str>)> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            if let Some((head, hex)) = line.rsplit_once(" = ") {
+                // BSD: ALGO (name) = hex
+                let name = head.split_once(" (")?.1.strip_suffix(')')?;
+                return Some((hex.trim(), Some(name)));
+            }
+            match line.split_once(char::is_whitespace) {
+                Some((hex, name)) => Some((hex, Some(name.trim().trim_start_matches('*')))),
+                None => Some((line, None)),
+            }
+        })
+        .filter(|(hex, _)| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .collect();
+
+    let matches_name = |name: &str| name.rsplit('/').next() == Some(file_name);
+    let hex = match entries.as_slice() {
+        [(hex, _)] => hex,
+        _ => entries.iter().find(|(_, name)| name.is_some_and(matches_name))?.0,
+    };
+    Some(hex.to_ascii_lowercase())
+}
+
+/// Passes writes through to `inner`, hashing what was written.
+pub(crate) struct HashingWriter<'a, W> {
+    pub inner: W,
+    pub checksum: &'a mut Checksum,
+}
+
+impl<W: Write> Write for HashingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.checksum.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::types::types::Codec;
+
+/// zstd level used for stored files: the library default, a good
+/// speed/ratio balance for logs and text.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Streams `path` through `codec` into `<path>.<ext>` and removes `path`.
+/// Returns the new path and its size. On failure the uncompressed file is
+/// kept and any partial output removed.
+pub fn compress_file(path: &Path, codec: Codec) -> io::Result<(String, u64)> {
+    let mut target = path.as_os_str().to_owned();
+    target.push(".");
+    target.push(codec.extension());
+    let target = Path::new(&target).to_path_buf();
+
+    let result = (|| {
+        let mut input = BufReader::new(File::open(path)?);
+        let output = BufWriter::new(File::create(&target)?);
+        match codec {
+            Codec::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.flush()?;
+            }
+            Codec::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(output, ZSTD_LEVEL)?;
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.flush()?;
+            }
+        }
+        std::fs::metadata(&target).map(|m| m.len())
+    })();
+
+    match result {
+        Ok(size) => {
+            std::fs::remove_file(path)?;
+            log::info!("[postprocess] stored {} as {} ({} bytes)", path.display(), target.display(), size);
+            Ok((target.to_string_lossy().to_string(), size))
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&target);
+            Err(e)
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Connect-time host checks.
+//!
+//! An embedder that fetches URLs on behalf of others (rdmd) must not be
+//! steered at internal services. Checking a URL once before the download is
+//! not enough: the client resolves the name again when it connects (DNS
+//! rebinding), follows redirects, and a download also reaches mirrors, an
+//! audio URL or DASH segment URLs. A [`ConnectGuard`] set with
+//! `CommonOptions::with_connect_guard` is consulted on all of those:
+//!
+//! - every host name is resolved through the guard, so each address a
+//!   connection may use is checked when it is used;
+//! - every redirect hop is checked before it is followed;
+//! - mirror, audio and DASH segment URLs are checked before the download
+//!   starts (an IP-literal host is never resolved, so this is where its
+//!   address is checked).
+//!
+//! Through a proxy only the proxy's own name is resolved locally; the
+//! target's addresses are up to the proxy.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+
+/// Decides which hosts and addresses a download may connect to.
+pub trait ConnectGuard: Send + Sync {
+    /// `Err(reason)` to refuse `host` (a lowercase name) before it is
+    /// resolved.
+    fn check_host(&self, host: &str) -> Result<(), String>;
+
+    /// `Err(reason)` to refuse connecting to `ip`, an address `host`
+    /// resolved to (or `host` itself, for an IP-literal URL).
+    fn check_addr(&self, host: &str, ip: IpAddr) -> Result<(), String>;
+}
+
+/// Why a [`ConnectGuard`] refused a connection; surfaces as
+/// `DownloadError::HostRefused`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostRefused(pub String);
+
+impl std::fmt::Display for HostRefused {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for HostRefused {}
+
+/// Checks `url` without resolving it: the host name with
+/// [`ConnectGuard::check_host`], an IP-literal host with
+/// [`ConnectGuard::check_addr`].
+pub fn check_url(guard: &dyn ConnectGuard, url: &str) -> Result<(), HostRefused> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| HostRefused(format!("invalid URL {:?}: {}", url, e)))?;
+    let Some(host) = parsed.host_str() else {
+        return Err(HostRefused(format!("URL {:?} has no host", url)));
+    };
+    // IPv6 literals come bracketed.
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_ascii_lowercase();
+    let result = match host.parse::<IpAddr>() {
+        Ok(ip) => guard.check_addr(&host, ip),
+        Err(_) => guard.check_host(&host),
+    };
+    result.map_err(HostRefused)
+}
+
+/// The client's resolver when a guard is set: resolves with the system
+/// resolver, then refuses the host unless the guard accepts every address.
+pub(crate) struct GuardedResolver {
+    pub(crate) guard: Arc<dyn ConnectGuard>,
+}
+
+impl Resolve for GuardedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let guard = Arc::clone(&self.guard);
+        Box::pin(async move {
+            let host = name.as_str().to_ascii_lowercase();
+            guard.check_host(&host).map_err(HostRefused)?;
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            for addr in &addrs {
+                guard.check_addr(&host, addr.ip()).map_err(HostRefused)?;
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// The [`HostRefused`] somewhere in `err`'s chain of sources, if a guard
+/// caused it.
+pub(crate) fn find_refusal<'a>(err: &'a (dyn std::error::Error + 'static)) -> Option<&'a HostRefused> {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(refused) = err.downcast_ref::<HostRefused>() {
+            return Some(refused);
+        }
+        source = err.source();
+    }
+    None
+}
@@ -0,0 +1,382 @@
+//! Minimal MPEG-DASH (`.mpd`) manifest parser.
+//!
+//! Only what `DashDownloadStrategy` needs for static, non-DRM manifests:
+//! 1. Picks the first `Period`.
+//! 2. Selects the highest-bandwidth video `Representation` and, when the
+//!    audio lives in its own `AdaptationSet`, the highest-bandwidth audio one.
+//! 3. Resolves `SegmentTemplate` (`$Number$` or `SegmentTimeline`/`$Time$`),
+//!    `SegmentList`, or a bare `BaseURL` into absolute init + media URLs.
+
+use reqwest::Url;
+use roxmltree::{Document, Node};
+
+use crate::types::types::DownloadError;
+
+/// A fully resolved track: the optional init segment followed by the media
+/// segments, all as absolute URLs in playback order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashTrack {
+    pub representation_id: String,
+    pub bandwidth: u64,
+    pub mime_type: Option<String>,
+    pub init_url: Option<String>,
+    pub media_urls: Vec<String>,
+}
+
+/// The tracks selected from a manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashManifest {
+    pub video: Option<DashTrack>,
+    /// Present only when audio is served as a separate adaptation set.
+    pub audio: Option<DashTrack>,
+}
+
+/// Returns `true` when the URL path or content type indicates a DASH manifest.
+pub fn is_dash_manifest(url: &str, content_type: Option<&str>) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    path.to_lowercase().ends_with(".mpd")
+        || content_type.is_some_and(|ct| ct.to_lowercase().contains("dash+xml"))
+}
+
+/// Parse an MPD document fetched from `manifest_url`.
+///
+/// Relative `BaseURL`s and segment URLs are resolved against `manifest_url`.
+pub fn parse_manifest(xml: &str, manifest_url: &str) -> Result<DashManifest, DownloadError> {
+    let doc = Document::parse(xml)
+        .map_err(|e| DownloadError::Manifest(format!("malformed MPD: {}", e)))?;
+    let mpd = doc.root_element();
+    if mpd.tag_name().name() != "MPD" {
+        return Err(DownloadError::Manifest("root element is not <MPD>".into()));
+    }
+    if mpd.attribute("type") == Some("dynamic") {
+        return Err(DownloadError::Manifest("live (dynamic) manifests are not supported".into()));
+    }
+
+    let manifest_base = Url::parse(manifest_url)
+        .map_err(|e| DownloadError::Manifest(format!("invalid manifest URL: {}", e)))?;
+    let mpd_base = resolve_base(&manifest_base, mpd)?;
+
+    let period = child(mpd, "Period")
+        .ok_or_else(|| DownloadError::Manifest("no <Period> in manifest".into()))?;
+    let period_base = resolve_base(&mpd_base, period)?;
+
+    // Total duration: Period@duration, else MPD@mediaPresentationDuration.
+    let total_secs = period
+        .attribute("duration")
+        .or_else(|| mpd.attribute("mediaPresentationDuration"))
+        .and_then(parse_iso8601_duration);
+
+    let mut best_video: Option<(Node, Node)> = None;
+    let mut best_audio: Option<(Node, Node)> = None;
+
+    for set in children(period, "AdaptationSet") {
+        for rep in children(set, "Representation") {
+            let kind = content_kind(set, rep);
+            let slot = match kind {
+                Some("video") => &mut best_video,
+                Some("audio") => &mut best_audio,
+                _ => continue,
+            };
+            let better = match slot {
+                Some((_, current)) => bandwidth(rep) > bandwidth(*current),
+                None => true,
+            };
+            if better {
+                *slot = Some((set, rep));
+            }
+        }
+    }
+
+    let resolve = |pair: Option<(Node, Node)>| -> Result<Option<DashTrack>, DownloadError> {
+        match pair {
+            Some((set, rep)) => {
+                if child(set, "ContentProtection").is_some() || child(rep, "ContentProtection").is_some() {
+                    return Err(DownloadError::Manifest("DRM-protected streams are not supported".into()));
+                }
+                let set_base = resolve_base(&period_base, set)?;
+                let rep_base = resolve_base(&set_base, rep)?;
+                resolve_track(period, set, rep, &rep_base, total_secs).map(Some)
+            }
+            None => Ok(None),
+        }
+    };
+
+    let manifest = DashManifest {
+        video: resolve(best_video)?,
+        audio: resolve(best_audio)?,
+    };
+
+    if manifest.video.is_none() && manifest.audio.is_none() {
+        return Err(DownloadError::Manifest("no audio or video representation found".into()));
+    }
+    Ok(manifest)
+}
+
+// ---------------------------------------------------------------------------
+// Track resolution
+// ---------------------------------------------------------------------------
+
+fn resolve_track(
+    period: Node,
+    set: Node,
+    rep: Node,
+    base: &Url,
+    total_secs: Option<f64>,
+) -> Result<DashTrack, DownloadError> {
+    let rep_id = rep.attribute("id").unwrap_or_default().to_string();
+    let bw = bandwidth(rep);
+    let mime_type = rep
+        .attribute("mimeType")
+        .or_else(|| set.attribute("mimeType"))
+        .map(str::to_string);
+
+    // Most specific first: Representation → AdaptationSet → Period.
+    let templates: Vec<Node> = [rep, set, period]
+        .iter()
+        .filter_map(|n| child(*n, "SegmentTemplate"))
+        .collect();
+
+    let (init_url, media_urls) = if !templates.is_empty() {
+        resolve_template(&templates, &rep_id, bw, base, total_secs)?
+    } else if let Some(list) = child(rep, "SegmentList").or_else(|| child(set, "SegmentList")) {
+        let init = child(list, "Initialization")
+            .and_then(|n| n.attribute("sourceURL"))
+            .map(|u| join(base, u))
+            .transpose()?;
+        let media = children(list, "SegmentURL")
+            .filter_map(|n| n.attribute("media"))
+            .map(|u| join(base, u))
+            .collect::<Result<Vec<_>, _>>()?;
+        (init, media)
+    } else {
+        // SegmentBase / bare BaseURL: the whole representation is one file.
+        (None, vec![base.to_string()])
+    };
+
+    if media_urls.is_empty() {
+        return Err(DownloadError::Manifest(format!(
+            "representation {} has no media segments",
+            rep_id
+        )));
+    }
+
+    Ok(DashTrack {
+        representation_id: rep_id,
+        bandwidth: bw,
+        mime_type,
+        init_url,
+        media_urls,
+    })
+}
+
+fn resolve_template(
+    templates: &[Node],
+    rep_id: &str,
+    bandwidth: u64,
+    base: &Url,
+    total_secs: Option<f64>,
+) -> Result<(Option<String>, Vec<String>), DownloadError> {
+    // Attributes inherit from the less specific templates when absent.
+    let attr = |name: &str| templates.iter().find_map(|t| t.attribute(name));
+
+    let media = attr("media")
+        .ok_or_else(|| DownloadError::Manifest("SegmentTemplate without @media".into()))?;
+    let start_number: u64 = attr("startNumber").and_then(|s| s.parse().ok()).unwrap_or(1);
+    let timescale: u64 = attr("timescale").and_then(|s| s.parse().ok()).unwrap_or(1).max(1);
+
+    let init_url = attr("initialization")
+        .map(|tpl| join(base, &expand_template(tpl, rep_id, bandwidth, None, None)))
+        .transpose()?;
+
+    let mut media_urls = Vec::new();
+
+    if let Some(timeline) = templates.iter().find_map(|t| child(*t, "SegmentTimeline")) {
+        let end_time = total_secs.map(|s| (s * timescale as f64).round() as u64);
+        let mut time: u64 = 0;
+        let mut number = start_number;
+        for s in children(timeline, "S") {
+            if let Some(t) = s.attribute("t").and_then(|v| v.parse().ok()) {
+                time = t;
+            }
+            let d: u64 = s
+                .attribute("d")
+                .and_then(|v| v.parse().ok())
+                .filter(|d| *d > 0)
+                .ok_or_else(|| DownloadError::Manifest("SegmentTimeline <S> without @d".into()))?;
+            let r: i64 = s.attribute("r").and_then(|v| v.parse().ok()).unwrap_or(0);
+            let repeats = if r < 0 {
+                // r = -1: repeat until the end of the period.
+                let end = end_time.ok_or_else(|| {
+                    DownloadError::Manifest("open-ended SegmentTimeline without a known duration".into())
+                })?;
+                end.saturating_sub(time).div_ceil(d).saturating_sub(1)
+            } else {
+                r as u64
+            };
+            for _ in 0..=repeats {
+                let url = expand_template(media, rep_id, bandwidth, Some(number), Some(time));
+                media_urls.push(join(base, &url)?);
+                time += d;
+                number += 1;
+            }
+        }
+    } else {
+        let duration: u64 = attr("duration")
+            .and_then(|s| s.parse().ok())
+            .filter(|d| *d > 0)
+            .ok_or_else(|| DownloadError::Manifest("SegmentTemplate needs @duration or a SegmentTimeline".into()))?;
+        let total = total_secs
+            .ok_or_else(|| DownloadError::Manifest("manifest has no mediaPresentationDuration".into()))?;
+        let segment_secs = duration as f64 / timescale as f64;
+        let count = (total / segment_secs).ceil() as u64;
+        for i in 0..count {
+            let number = start_number + i;
+            let time = i * duration;
+            let url = expand_template(media, rep_id, bandwidth, Some(number), Some(time));
+            media_urls.push(join(base, &url)?);
+        }
+    }
+
+    Ok((init_url, media_urls))
+}
+
+/// Expand `$RepresentationID$`, `$Bandwidth$`, `$Number$`, `$Time$` (each
+/// optionally with a `%0Nd` width) and the `$$` escape.
+pub fn expand_template(
+    template: &str,
+    rep_id: &str,
+    bandwidth: u64,
+    number: Option<u64>,
+    time: Option<u64>,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            // Unterminated identifier — emit literally.
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let ident = &after[..end];
+        rest = &after[end + 1..];
+
+        if ident.is_empty() {
+            out.push('$');
+            continue;
+        }
+
+        let (name, width) = match ident.split_once('%') {
+            Some((name, fmt)) => (
+                name,
+                fmt.trim_start_matches('0').trim_end_matches('d').parse::<usize>().unwrap_or(0),
+            ),
+            None => (ident, 0),
+        };
+        let value = match name {
+            "RepresentationID" => Some(rep_id.to_string()),
+            "Bandwidth" => Some(format!("{:0width$}", bandwidth, width = width)),
+            "Number" => number.map(|n| format!("{:0width$}", n, width = width)),
+            "Time" => time.map(|t| format!("{:0width$}", t, width = width)),
+            _ => None,
+        };
+        match value {
+            Some(v) => out.push_str(&v),
+            None => {
+                out.push('$');
+                out.push_str(ident);
+                out.push('$');
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parse an ISO 8601 duration such as `PT1H2M3.5S` into seconds.
+/// Years and months are approximated as 365 and 30 days.
+pub fn parse_iso8601_duration(s: &str) -> Option<f64> {
+    let s = s.trim().strip_prefix('P')?;
+    let mut total = 0.0;
+    let mut in_time = false;
+    let mut num = String::new();
+
+    for c in s.chars() {
+        match c {
+            'T' => in_time = true,
+            '0'..='9' | '.' => num.push(c),
+            unit => {
+                let value: f64 = num.parse().ok()?;
+                num.clear();
+                total += value
+                    * match (unit, in_time) {
+                        ('Y', false) => 365.0 * 86400.0,
+                        ('M', false) => 30.0 * 86400.0,
+                        ('W', false) => 7.0 * 86400.0,
+                        ('D', false) => 86400.0,
+                        ('H', true) => 3600.0,
+                        ('M', true) => 60.0,
+                        ('S', true) => 1.0,
+                        _ => return None,
+                    };
+            }
+        }
+    }
+    if !num.is_empty() {
+        return None;
+    }
+    Some(total)
+}
+
+// ---------------------------------------------------------------------------
+// XML helpers
+// ---------------------------------------------------------------------------
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children().filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn bandwidth(rep: Node) -> u64 {
+    rep.attribute("bandwidth").and_then(|b| b.parse().ok()).unwrap_or(0)
+}
+
+/// Classify a representation as `"video"` or `"audio"` from `contentType`
+/// or the `mimeType` prefix on the representation or its adaptation set.
+fn content_kind(set: Node, rep: Node) -> Option<&'static str> {
+    let hint = set
+        .attribute("contentType")
+        .or_else(|| rep.attribute("mimeType"))
+        .or_else(|| set.attribute("mimeType"))?;
+    if hint.starts_with("video") {
+        Some("video")
+    } else if hint.starts_with("audio") {
+        Some("audio")
+    } else {
+        None
+    }
+}
+
+/// Apply a node's `<BaseURL>` child (if any) on top of `parent`.
+fn resolve_base(parent: &Url, node: Node) -> Result<Url, DownloadError> {
+    match child(node, "BaseURL").and_then(|n| n.text()).map(str::trim) {
+        Some(base) if !base.is_empty() => parent
+            .join(base)
+            .map_err(|e| DownloadError::Manifest(format!("invalid BaseURL {:?}: {}", base, e))),
+        _ => Ok(parent.clone()),
+    }
+}
+
+fn join(base: &Url, relative: &str) -> Result<String, DownloadError> {
+    base.join(relative)
+        .map(|u| u.to_string())
+        .map_err(|e| DownloadError::Manifest(format!("invalid segment URL {:?}: {}", relative, e)))
+}
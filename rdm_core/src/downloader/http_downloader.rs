@@ -1,15 +1,19 @@
+use std::collections::HashMap;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc;
 
 use crate::downloader::strategy::download_strategy::DownloadStrategy;
 use crate::progress::notifier::ProgressNotifier;
 use crate::progress::observer::ProgressObserver;
-use crate::types::types::DownloadError;
+use crate::types::types::{DownloadError, DownloadSummary};
 
 pub struct HttpDownloader {
     download_strategy: Arc<dyn DownloadStrategy>,
     notifier: ProgressNotifier,
+    deadline: Option<Duration>,
 }
 
 impl HttpDownloader {
@@ -17,6 +21,7 @@ impl HttpDownloader {
         Self {
             download_strategy: strategy,
             notifier: ProgressNotifier::new(),
+            deadline: None,
         }
     }
 
@@ -25,34 +30,84 @@ impl HttpDownloader {
         self.notifier.add_observer(observer);
     }
 
+    /// Limit the whole run — probe, transfer and assembly — to `deadline`.
+    /// When it passes, `download()` stops the strategy and fails with
+    /// [`DownloadError::Deadline`]; the temp directory is removed unless the
+    /// strategy keeps its temp files.
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+    }
+
     /// Run the full download lifecycle (preprocess → download → postprocess).
     ///
     /// Internally creates the progress channel, injects the sender into the
     /// strategy, runs the `ProgressNotifier` as a background task, then awaits
     /// it after the download completes.  Callers only need `add_observer`.
-    pub async fn download(&mut self) -> Result<(), DownloadError> {
+    ///
+    /// On success returns a [`DownloadSummary`] built from the strategy's
+    /// final state.
+    pub async fn download(&mut self) -> Result<DownloadSummary, DownloadError> {
+        let started = Instant::now();
+        let deadline = self.deadline.map(|limit| (tokio::time::Instant::now() + limit, limit));
+
         // Create the internal progress channel.
-        let (progress_tx, progress_rx) = mpsc::channel(256);
+        let (progress_tx, mut progress_rx) = mpsc::channel(256);
 
-        // Inject the sender into the strategy.
-        self.download_strategy.set_progress_tx(progress_tx);
+        // Inject the sender into the strategy; keep a clone to report a
+        // failure of the download as a whole.
+        self.download_strategy.set_progress_tx(progress_tx.clone());
+
+        // Preprocessing sends only phase events and warnings. They are
+        // relayed here, before the notifier is moved into its task, so
+        // observers then hear about the plan ahead of any progress.
+        let preprocessed = {
+            let preprocess = within(deadline, self.download_strategy.preprocess());
+            tokio::pin!(preprocess);
+            loop {
+                tokio::select! {
+                    result = &mut preprocess => break result,
+                    Some(msg) = progress_rx.recv() => {
+                        self.notifier.dispatch(msg).await;
+                    }
+                }
+            }
+        };
+        while let Ok(msg) = progress_rx.try_recv() {
+            self.notifier.dispatch(msg).await;
+        }
+        if let Ok(info) = &preprocessed {
+            self.notifier.start(info).await;
+        }
 
         // Take the notifier out so we can move it into the background task.
         // A fresh empty notifier is left in place so the field stays valid.
-        let notifier = std::mem::replace(&mut self.notifier, ProgressNotifier::new());
+        let notifier = std::mem::take(&mut self.notifier);
 
         // Spawn the notifier — it drains until all senders are dropped.
         let notifier_handle = tokio::spawn(async move {
             notifier.run(progress_rx).await;
         });
 
-        // Run the three-phase download.
-        let result = async {
-            self.download_strategy.preprocess().await?;
+        // Run the rest of the three-phase download.
+        let result = within(deadline, async {
+            preprocessed?;
             self.download_strategy.download().await?;
             self.download_strategy.postprocess().await
-        }
+        })
         .await;
+        if let Err(DownloadError::Deadline(_)) = &result {
+            // Dropping the run aborted its segment tasks; cancel whatever
+            // the strategy still has in flight. Nothing is retried after a
+            // deadline, so the partial files go too.
+            let _ = self.download_strategy.stop().await;
+            self.download_strategy.discard_temp_files();
+        }
+
+        // Observers get on_error instead of on_complete for a failed download.
+        if let Err(e) = &result {
+            let _ = progress_tx.send(Err(e.to_string())).await;
+        }
+        drop(progress_tx);
 
         // Clear the sender held by the strategy so the channel closes and the
         // notifier task can call on_complete / on_error and exit cleanly.
@@ -61,7 +116,53 @@ impl HttpDownloader {
         // Wait for the notifier to finish before returning to the caller.
         let _ = notifier_handle.await;
 
-        result
+        result?;
+        Ok(self.summarize(started).await)
+    }
+
+    /// Aggregate the strategy's final state and segments into a summary.
+    async fn summarize(&self, started: Instant) -> DownloadSummary {
+        let state = self.download_strategy.state_snapshot();
+        let segments = self.download_strategy.segments_snapshot().await;
+        let bytes: u64 = segments.iter().map(|s| s.downloaded.max(0) as u64).sum();
+        let duration = started.elapsed();
+        let secs = duration.as_secs_f64();
+
+        DownloadSummary {
+            path: state.output_path.unwrap_or_default(),
+            bytes,
+            duration,
+            avg_speed: if secs > 0.0 { bytes as f64 / secs } else { 0.0 },
+            segments: segments.len(),
+            resumable: state.resumable,
+            checksum_ok: match (&state.expected_checksum, &state.checksum) {
+                (Some(expected), Some(actual)) => Some(expected.trim().eq_ignore_ascii_case(actual)),
+                _ => None,
+            },
+            checksum: state.checksum,
+            compressed_bytes: state.compressed_size,
+            skipped: state.skipped,
+        }
+    }
+
+    /// Change the output path of a download that has not started yet.
+    pub fn set_output_path(&self, output_path: String) -> Result<(), DownloadError> {
+        self.download_strategy.set_output_path(output_path)
+    }
+
+    /// Change the connection count of a download that has not started yet.
+    pub fn set_connection_size(&self, connections: usize) -> Result<(), DownloadError> {
+        self.download_strategy.set_connection_size(connections)
+    }
+
+    /// Replace the request headers and cookies of a download that has not
+    /// started yet.
+    pub fn set_request_headers(
+        &self,
+        headers: HashMap<String, Vec<String>>,
+        cookies: Option<String>,
+    ) -> Result<(), DownloadError> {
+        self.download_strategy.set_request_headers(headers, cookies)
     }
 
     pub async fn stop(&self) -> Result<(), DownloadError> {
@@ -71,4 +172,26 @@ impl HttpDownloader {
     pub async fn pause(&self) -> Result<(), DownloadError> {
         self.download_strategy.pause().await
     }
+
+    pub async fn hold(&self) -> Result<(), DownloadError> {
+        self.download_strategy.hold().await
+    }
+
+    pub async fn resume(&self) -> Result<(), DownloadError> {
+        self.download_strategy.resume().await
+    }
+}
+
+/// Run `fut` until `deadline` (the instant and the limit it came from), if
+/// any, failing with [`DownloadError::Deadline`] once it passes.
+async fn within<T>(
+    deadline: Option<(tokio::time::Instant, Duration)>,
+    fut: impl Future<Output = Result<T, DownloadError>>,
+) -> Result<T, DownloadError> {
+    match deadline {
+        Some((at, limit)) => tokio::time::timeout_at(at, fut)
+            .await
+            .unwrap_or(Err(DownloadError::Deadline(limit))),
+        None => fut.await,
+    }
 }
@@ -1,3 +1,13 @@
 pub mod segment_grabber;
 pub mod http_downloader;
 pub mod strategy;
+pub mod dash_manifest;
+pub mod muxer;
+pub mod pause_token;
+pub mod rate_limiter;
+pub mod checksum;
+pub mod naming;
+pub mod cache;
+pub mod connect_guard;
+#[cfg(feature = "compression")]
+pub mod compression;
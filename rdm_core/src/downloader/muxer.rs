@@ -0,0 +1,57 @@
+//! ffmpeg-based muxing of separately downloaded audio and video tracks.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::types::types::DownloadError;
+
+/// Returns the ffmpeg binary to invoke: `explicit`, else `$RDM_FFMPEG`,
+/// else `ffmpeg` on PATH.
+fn ffmpeg_binary(explicit: Option<&Path>) -> PathBuf {
+    match explicit {
+        Some(path) => path.to_path_buf(),
+        None => std::env::var_os("RDM_FFMPEG").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("ffmpeg")),
+    }
+}
+
+/// Mux `video` and `audio` into `output` without re-encoding.
+///
+/// Blocking — call from `spawn_blocking`. The container is chosen by ffmpeg
+/// from `output`'s extension; `ffmpeg` overrides the binary (see
+/// `CommonOptions::with_ffmpeg`).
+pub fn mux_audio_video(
+    ffmpeg: Option<&Path>,
+    video: &Path,
+    audio: &Path,
+    output: &Path,
+) -> Result<(), DownloadError> {
+    let ffmpeg = ffmpeg_binary(ffmpeg);
+    log::info!(
+        "[mux] {} + {} -> {} (using {})",
+        video.display(),
+        audio.display(),
+        output.display(),
+        ffmpeg.display()
+    );
+
+    let result = Command::new(&ffmpeg)
+        .args(["-y", "-loglevel", "error", "-i"])
+        .arg(video)
+        .arg("-i")
+        .arg(audio)
+        .args(["-map", "0:v:0", "-map", "1:a:0", "-c", "copy"])
+        .arg(output)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| DownloadError::Mux(format!("failed to run {}: {}", ffmpeg.display(), e)))?;
+
+    if !result.status.success() {
+        return Err(DownloadError::Mux(format!(
+            "{} exited with {}: {}",
+            ffmpeg.display(),
+            result.status,
+            String::from_utf8_lossy(&result.stderr).trim()
+        )));
+    }
+    Ok(())
+}
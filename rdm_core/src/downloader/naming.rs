@@ -0,0 +1,493 @@
+//! Output naming — filename sanitising, `--output-template` expansion and
+//! the [`NamingStrategy`]s built on them.
+//!
+//! A template is a path whose components may contain `{token}`s, e.g.
+//! `{host}/{date}/{name}.{ext}`. Tokens are filled in from the probe of the
+//! download URL; each expanded component is sanitised so that no value can
+//! introduce a path separator, traversal, or characters that are illegal on
+//! some platform. Literal `/` in the template creates directories.
+//!
+//! A [`NamingStrategy`] turns a probed download into its output path; the
+//! server and CLI pick one by name (`RDM_NAMING`, see [`naming_strategy`]).
+
+use std::path::{Path, PathBuf};
+
+use crate::types::types::ProbeResult;
+
+/// Tokens accepted by [`expand_template`].
+pub const TEMPLATE_TOKENS: &[&str] = &["host", "date", "name", "ext"];
+
+/// Characters that are always safe in a filename on macOS / Linux / Windows.
+/// Anything outside this set is replaced with `_`.
+/// Note: space is intentionally excluded — spaces are normalised to `_` so
+/// that filenames never contain whitespace.
+fn is_safe_char(c: char) -> bool {
+    c.is_alphanumeric()
+        || matches!(
+            c,
+            '-' | '_' | '.' | '(' | ')' | '[' | ']' | '+' | ',' | '@' | '~'
+        )
+}
+
+/// Sanitise one path component: unsafe characters (including space and
+/// path separators) become `_`, runs of `_` collapse, leading/trailing `_`
+/// and `.` are trimmed and the result is cut to 180 bytes. Empty or
+/// dots-only input yields `"download"`.
+pub fn sanitise_component(raw: &str) -> String {
+    let cleaned: String = raw
+        .chars()
+        .map(|c| if is_safe_char(c) { c } else { '_' })
+        .collect();
+    let cleaned = collapse_runs(&cleaned);
+    let cleaned = cleaned.trim_matches(|c| c == '_' || c == '.');
+    if cleaned.is_empty() {
+        return "download".to_string();
+    }
+    truncate_to_bytes(cleaned, 180)
+}
+
+/// Collapse consecutive `_` characters to a single `_`.
+fn collapse_runs(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut last: Option<char> = None;
+    for c in s.chars() {
+        if c == '_' && last == Some('_') {
+            continue; // skip duplicate underscore
+        }
+        out.push(c);
+        last = Some(c);
+    }
+    out
+}
+
+/// Truncate `s` to at most `max_bytes` UTF-8 bytes, respecting char boundaries.
+fn truncate_to_bytes(s: &str, max_bytes: usize) -> String {
+    if s.len() <= max_bytes {
+        return s.to_string();
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s[..end].to_string()
+}
+
+/// Map a MIME type string to a file extension.
+pub fn ext_from_mime(content_type: Option<&str>) -> Option<String> {
+    let mime = content_type?
+        .split(';')
+        .next()?
+        .trim()
+        .to_lowercase();
+
+    let ext = match mime.as_str() {
+        "video/mp4" | "video/x-m4v"                        => "mp4",
+        "video/x-matroska"                                  => "mkv",
+        "video/webm"                                        => "webm",
+        "video/x-msvideo"                                   => "avi",
+        "video/quicktime"                                   => "mov",
+        "video/x-ms-wmv"                                    => "wmv",
+        "video/3gpp"                                        => "3gp",
+        "video/x-flv"                                       => "flv",
+        "video/mpeg"                                        => "mpg",
+        "audio/mpeg"                                        => "mp3",
+        "audio/flac"                                        => "flac",
+        "audio/ogg"                                         => "ogg",
+        "audio/wav" | "audio/x-wav"                        => "wav",
+        "audio/aac"                                         => "aac",
+        "audio/x-m4a" | "audio/mp4"                        => "m4a",
+        "audio/opus"                                        => "opus",
+        "application/zip"                                   => "zip",
+        "application/x-tar"                                 => "tar",
+        "application/gzip" | "application/x-gzip"          => "gz",
+        "application/x-bzip2"                               => "bz2",
+        "application/x-7z-compressed"                       => "7z",
+        "application/x-rar-compressed" | "application/vnd.rar" => "rar",
+        "application/x-xz"                                  => "xz",
+        "application/x-ms-installer" | "application/x-msi" => "msi",
+        "application/vnd.debian.binary-package"             => "deb",
+        "application/x-rpm"                                 => "rpm",
+        "application/x-apple-diskimage"                     => "dmg",
+        "application/x-newton-compatible-pkg"               => "pkg",
+        "application/pdf"                                   => "pdf",
+        "application/msword"                                => "doc",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => "docx",
+        "application/vnd.ms-excel"                          => "xls",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => "xlsx",
+        "application/vnd.ms-powerpoint"                     => "ppt",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation" => "pptx",
+        "image/jpeg"                                        => "jpg",
+        "image/png"                                         => "png",
+        "image/gif"                                         => "gif",
+        "image/webp"                                        => "webp",
+        "image/svg+xml"                                     => "svg",
+        _ => return None,
+    };
+    Some(ext.to_string())
+}
+
+/// Values substituted into an output template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TemplateContext {
+    /// Host of the URL, lowercased.
+    pub host: String,
+    /// Date of the download, `YYYY-MM-DD` (UTC).
+    pub date: String,
+    /// File name without extension.
+    pub name: String,
+    /// Extension without the dot.
+    pub ext: String,
+}
+
+impl TemplateContext {
+    /// Context for downloading `url`, given its probe: `name` comes from the
+    /// `Content-Disposition` filename or else the last URL path segment;
+    /// `ext` from the `Content-Type`, then either of those names, then `bin`.
+    pub fn from_probe(url: &str, probe: &ProbeResult) -> Self {
+        let parsed = reqwest::Url::parse(url).ok();
+        let host = parsed
+            .as_ref()
+            .and_then(|u| u.host_str().map(str::to_lowercase))
+            .unwrap_or_else(|| "unknown-host".to_string());
+        let url_name = parsed
+            .as_ref()
+            .and_then(|u| u.path_segments())
+            .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+            .map(str::to_string);
+        let named = probe.attachment_name.clone().or(url_name);
+        let (name, named_ext) = match &named {
+            Some(n) => {
+                let p = PathBuf::from(n);
+                let stem = p.file_stem().map(|s| s.to_string_lossy().into_owned());
+                let ext = p.extension().map(|e| e.to_string_lossy().to_lowercase());
+                (stem.unwrap_or_else(|| n.clone()), ext)
+            }
+            None => ("download".to_string(), None),
+        };
+        let ext = ext_from_mime(probe.content_type.as_deref())
+            .or(named_ext)
+            .unwrap_or_else(|| "bin".to_string());
+        Self { host, date: today_utc(), name, ext }
+    }
+
+    fn get(&self, token: &str) -> Option<&str> {
+        match token {
+            "host" => Some(&self.host),
+            "date" => Some(&self.date),
+            "name" => Some(&self.name),
+            "ext" => Some(&self.ext),
+            _ => None,
+        }
+    }
+}
+
+/// Expand `template` against `ctx` into an output path.
+///
+/// Every `/`-separated component is expanded and then sanitised with
+/// [`sanitise_component`]; empty components are dropped and a leading `/`
+/// keeps the path absolute. Unknown tokens, unbalanced braces and `..`
+/// components are errors.
+pub fn expand_template(template: &str, ctx: &TemplateContext) -> Result<PathBuf, String> {
+    if template.trim().is_empty() {
+        return Err("output template is empty".to_string());
+    }
+    let mut path = if template.starts_with('/') {
+        PathBuf::from("/")
+    } else {
+        PathBuf::new()
+    };
+    for part in template.split('/').filter(|p| !p.is_empty()) {
+        if part == ".." {
+            return Err(format!("output template {:?} contains '..'", template));
+        }
+        if part == "." {
+            continue;
+        }
+        path.push(sanitise_component(&expand_component(part, ctx)?));
+    }
+    if path.file_name().is_none() {
+        return Err(format!("output template {:?} has no file name", template));
+    }
+    Ok(path)
+}
+
+/// Substitute the tokens of a single path component.
+fn expand_component(part: &str, ctx: &TemplateContext) -> Result<String, String> {
+    let mut out = String::with_capacity(part.len());
+    let mut rest = part;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(format!("unmatched '}}' in output template component {:?}", part));
+        }
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let close = after
+            .find('}')
+            .ok_or_else(|| format!("unclosed '{{' in output template component {:?}", part))?;
+        let token = &after[..close];
+        let value = ctx.get(token).ok_or_else(|| {
+            format!(
+                "unknown output template token {{{}}}; expected one of {}",
+                token,
+                TEMPLATE_TOKENS.iter().map(|t| format!("{{{}}}", t)).collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        // Sanitise values on their own too, so a `/` in a title can't split
+        // the component.
+        out.push_str(&value.replace(['/', '\\'], "_"));
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Names the output of a download from its probe, its URL and a `hint`
+/// (a tab title, say; may be empty). Only picks the path: nothing is
+/// created on disk.
+pub trait NamingStrategy: Send + Sync {
+    fn resolve(&self, probe: &ProbeResult, url: &str, hint: &str) -> PathBuf;
+}
+
+/// Names accepted by [`naming_strategy`].
+pub const NAMING_STRATEGIES: &[&str] = &["safe", "raw", "template"];
+
+/// The strategy called `name` (one of [`NAMING_STRATEGIES`]), naming files
+/// in `dir`. `template` is the output template, required by `template`.
+pub fn naming_strategy(
+    name: &str,
+    dir: PathBuf,
+    template: Option<&str>,
+) -> Result<Box<dyn NamingStrategy>, String> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "safe" => Ok(Box::new(SafeNamingStrategy::new(dir))),
+        "raw" => Ok(Box::new(RawNamingStrategy::new(dir))),
+        "template" => {
+            let template = template.ok_or("the template naming strategy needs an output template")?;
+            Ok(Box::new(TemplateNamingStrategy::new(dir, template)?))
+        }
+        other => Err(format!(
+            "unknown naming strategy `{}`; expected one of {}",
+            other,
+            NAMING_STRATEGIES.join(", ")
+        )),
+    }
+}
+
+/// The default: the hint (else the last URL path segment) made safe on
+/// every platform, with an extension from the `Content-Type` or the URL
+/// when it has none, numbered `_2`, `_3`, … rather than replace a file
+/// already in `dir`.
+#[derive(Debug, Clone)]
+pub struct SafeNamingStrategy {
+    dir: PathBuf,
+}
+
+impl SafeNamingStrategy {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl NamingStrategy for SafeNamingStrategy {
+    fn resolve(&self, probe: &ProbeResult, url: &str, hint: &str) -> PathBuf {
+        let name = sanitise_filename(hint, url, probe.content_type.as_deref());
+        unique_path(&self.dir, &name)
+    }
+}
+
+/// The hint, else the `Content-Disposition` filename, else the last URL
+/// path segment, as it is. Only its last path component is kept, so it
+/// stays in `dir`; nothing is replaced or added, and an existing file of
+/// that name is not avoided.
+#[derive(Debug, Clone)]
+pub struct RawNamingStrategy {
+    dir: PathBuf,
+}
+
+impl RawNamingStrategy {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+impl NamingStrategy for RawNamingStrategy {
+    fn resolve(&self, probe: &ProbeResult, url: &str, hint: &str) -> PathBuf {
+        let name = [Some(hint), probe.attachment_name.as_deref(), Some(filename_from_url(url).as_str())]
+            .into_iter()
+            .flatten()
+            .filter_map(|raw| Path::new(raw.trim()).file_name())
+            .find(|name| !name.is_empty())
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| "download".into());
+        self.dir.join(name)
+    }
+}
+
+/// An output template (`{host}/{date}/{name}.{ext}`, see
+/// [`expand_template`]) expanded against the probe, under `dir` unless the
+/// template is absolute. The hint is not used.
+#[derive(Debug, Clone)]
+pub struct TemplateNamingStrategy {
+    dir: PathBuf,
+    template: String,
+}
+
+impl TemplateNamingStrategy {
+    /// Fails if `template` does not expand, whatever the probe.
+    pub fn new(dir: PathBuf, template: &str) -> Result<Self, String> {
+        let sample = TemplateContext {
+            host: "host".to_string(),
+            date: today_utc(),
+            name: "name".to_string(),
+            ext: "bin".to_string(),
+        };
+        expand_template(template, &sample)?;
+        Ok(Self { dir, template: template.to_string() })
+    }
+}
+
+impl NamingStrategy for TemplateNamingStrategy {
+    fn resolve(&self, probe: &ProbeResult, url: &str, _hint: &str) -> PathBuf {
+        let ctx = TemplateContext::from_probe(url, probe);
+        // Expansion depends on the template alone, which `new` checked.
+        let path = expand_template(&self.template, &ctx).expect("template checked in new");
+        self.dir.join(path)
+    }
+}
+
+/// Sanitise `suggested`, falling back to the last segment of `url`.
+/// Returns a filename **with** extension when one can be found, e.g.
+/// `"My_Video_HD.mp4"`: from the name itself, else `content_type`, else the
+/// URL path.
+fn sanitise_filename(suggested: &str, url: &str, content_type: Option<&str>) -> String {
+    let raw = if !suggested.trim().is_empty() {
+        suggested.to_string()
+    } else {
+        filename_from_url(url)
+    };
+
+    // Strip any leading path components (prevents traversal).
+    let raw = PathBuf::from(&raw)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or(raw.clone());
+
+    // Split stem and extension before sanitising to preserve extension as-is.
+    let (stem, ext) = split_stem_ext(&raw);
+    let stem = sanitise_component(&stem);
+    let ext = sanitise_ext(&ext);
+    let ext = if ext.is_empty() {
+        ext_from_mime(content_type)
+            .or_else(|| ext_from_url(url))
+            .unwrap_or_default()
+    } else {
+        ext
+    };
+
+    if ext.is_empty() {
+        stem
+    } else {
+        format!("{}.{}", stem, ext)
+    }
+}
+
+/// Split a filename into `(stem, extension)`.
+/// Extension is the part after the last `.`; empty if no dot or leading dot only.
+fn split_stem_ext(name: &str) -> (String, String) {
+    let p = PathBuf::from(name);
+    let ext = p
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let stem = p
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| name.to_string());
+    (stem, ext)
+}
+
+/// Sanitise an extension: lowercase, alphanumeric only, max 10 chars.
+fn sanitise_ext(ext: &str) -> String {
+    ext.chars()
+        .filter(|c| c.is_alphanumeric())
+        .take(10)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Extract the extension from the URL path (strip query / fragment first).
+/// Returns `None` when the URL path has no recognisable extension.
+fn ext_from_url(url: &str) -> Option<String> {
+    let url = url.split('?').next().unwrap_or(url);
+    let url = url.split('#').next().unwrap_or(url);
+    let last_seg = url.rsplit('/').find(|s| !s.is_empty())?;
+    let ext = PathBuf::from(last_seg)
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())?;
+    let ext = sanitise_ext(&ext);
+    if ext.is_empty() {
+        None
+    } else {
+        Some(ext)
+    }
+}
+
+/// Extract the last non-empty path segment from a URL (strip query / fragment).
+fn filename_from_url(url: &str) -> String {
+    let url = url.split('?').next().unwrap_or(url);
+    let url = url.split('#').next().unwrap_or(url);
+    url.rsplit('/')
+        .find(|s| !s.is_empty())
+        .unwrap_or("download")
+        .to_string()
+}
+
+/// Return a path in `dir` that does not exist yet, appending `_2`, `_3`, …
+/// to `name` as needed.
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let (stem, ext) = split_stem_ext(name);
+    for n in 2u32..=9999 {
+        let new_name = if ext.is_empty() {
+            format!("{}_{}", stem, n)
+        } else {
+            format!("{}_{}.{}", stem, n, ext)
+        };
+        let candidate = dir.join(&new_name);
+        if !candidate.exists() {
+            return candidate;
+        }
+    }
+
+    // Absolute last resort — use a UUID-based name.
+    dir.join(format!("download_{}.bin", uuid::Uuid::new_v4().simple()))
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today_utc() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (y, m, d) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// Proleptic Gregorian date for a day count since 1970-01-01 (Howard
+/// Hinnant's `civil_from_days`).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
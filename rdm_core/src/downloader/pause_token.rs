@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Shared pause switch for the segment tasks of one download — the pausing
+/// counterpart of `CancellationToken`.
+///
+/// While paused, segment tasks stop reading from their connections and wait;
+/// `resume()` lets them carry on from where they were. Clones share state.
+#[derive(Debug, Clone)]
+pub struct PauseToken {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl Default for PauseToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PauseToken {
+    pub fn new() -> Self {
+        Self { tx: Arc::new(watch::Sender::new(false)) }
+    }
+
+    pub fn pause(&self) {
+        self.tx.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.tx.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.tx.borrow()
+    }
+
+    /// Returns immediately when not paused, otherwise once `resume()` is called.
+    pub async fn wait_while_paused(&self) {
+        let mut rx = self.tx.subscribe();
+        // The sender lives in `self`, so the channel cannot close here.
+        let _ = rx.wait_for(|paused| !*paused).await;
+    }
+}
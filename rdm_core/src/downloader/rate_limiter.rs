@@ -0,0 +1,185 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+/// Token bucket limiting how fast segment tasks consume bytes.
+///
+/// Clones share the same bucket, so one `RateLimiter` handed to every segment
+/// caps the aggregate speed; a fresh one per segment caps each connection.
+/// Callers may overdraw the bucket by a whole chunk — the debt is paid back
+/// by sleeping, which keeps the long-run rate at `bytes_per_sec` whatever the
+/// chunk sizes are.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    bytes_per_sec: f64,
+    /// Negative while callers are in debt.
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let refill = now.duration_since(self.last_refill).as_secs_f64() * self.bytes_per_sec;
+        self.tokens = (self.tokens + refill).min(self.bytes_per_sec);
+        self.last_refill = now;
+    }
+}
+
+impl RateLimiter {
+    /// `bytes_per_sec` is raised to 1 if zero. The bucket starts empty and
+    /// holds at most one second of tokens, so an idle period allows a burst
+    /// of no more than that.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                bytes_per_sec: bytes_per_sec.max(1) as f64,
+                tokens: 0.0,
+                last_refill: Instant::now(),
+            })),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bucket.lock().unwrap().bytes_per_sec as u64
+    }
+
+    /// Change the rate; tokens earned so far are kept.
+    pub fn set_rate(&self, bytes_per_sec: u64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill(Instant::now());
+        bucket.bytes_per_sec = bytes_per_sec.max(1) as f64;
+    }
+
+    /// Take `bytes` from the bucket, sleeping until they are paid for.
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            bucket.refill(Instant::now());
+            bucket.tokens -= bytes as f64;
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / bucket.bytes_per_sec)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// A global speed cap split fairly between concurrent downloads.
+///
+/// Each download takes a [`LimiterShare`] for as long as it transfers; the
+/// cap is divided evenly between the live shares and rebalanced whenever one
+/// is taken or dropped, so two downloads get about half each however many
+/// segments either runs. Segments draw from the global bucket as well as
+/// their download's share (global → per-download → per-segment).
+#[derive(Debug, Clone)]
+pub struct SharedRateLimiter {
+    inner: Arc<SharedInner>,
+}
+
+#[derive(Debug)]
+struct SharedInner {
+    global: RateLimiter,
+    next_id: AtomicU64,
+    shares: Mutex<Vec<(u64, RateLimiter)>>,
+}
+
+impl SharedInner {
+    /// Give every live share an equal part of the global rate.
+    fn rebalance(&self, shares: &[(u64, RateLimiter)]) {
+        if shares.is_empty() {
+            return;
+        }
+        let each = self.global.bytes_per_sec() / shares.len() as u64;
+        for (_, limiter) in shares {
+            limiter.set_rate(each);
+        }
+    }
+}
+
+impl SharedRateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            inner: Arc::new(SharedInner {
+                global: RateLimiter::new(bytes_per_sec),
+                next_id: AtomicU64::new(0),
+                shares: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.inner.global.bytes_per_sec()
+    }
+
+    /// Number of downloads currently holding a share.
+    pub fn active_shares(&self) -> usize {
+        self.inner.shares.lock().unwrap().len()
+    }
+
+    /// Join the pool; the share is given back when the returned guard drops.
+    pub fn register(&self) -> LimiterShare {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let share = RateLimiter::new(self.bytes_per_sec());
+        let mut shares = self.inner.shares.lock().unwrap();
+        shares.push((id, share.clone()));
+        self.inner.rebalance(&shares);
+        LimiterShare {
+            id,
+            global: self.inner.global.clone(),
+            share,
+            pool: Arc::clone(&self.inner),
+        }
+    }
+}
+
+/// One download's part of a [`SharedRateLimiter`]; see
+/// [`SharedRateLimiter::register`].
+#[derive(Debug)]
+pub struct LimiterShare {
+    id: u64,
+    global: RateLimiter,
+    share: RateLimiter,
+    pool: Arc<SharedInner>,
+}
+
+impl LimiterShare {
+    /// The buckets a segment of this download draws from, outermost first.
+    pub fn limiters(&self) -> Vec<RateLimiter> {
+        vec![self.global.clone(), self.share.clone()]
+    }
+}
+
+impl Drop for LimiterShare {
+    fn drop(&mut self) {
+        let mut shares = self.pool.shares.lock().unwrap();
+        shares.retain(|(id, _)| *id != self.id);
+        self.pool.rebalance(&shares);
+    }
+}
+
+/// Parses a rate such as `500000`, `500K` or `2M` (binary multiples) into
+/// bytes per second.
+pub fn parse_rate(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s[..i], 1024),
+        Some((i, 'm' | 'M')) => (&s[..i], 1024 * 1024),
+        Some((i, 'g' | 'G')) => (&s[..i], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+    match digits.parse::<u64>() {
+        Ok(n) if n > 0 => Ok(n * multiplier),
+        _ => Err(format!("invalid rate `{}`; expected e.g. 500000, 500K or 2M", s)),
+    }
+}
@@ -1,5 +1,7 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use base64::Engine;
 use futures::StreamExt;
@@ -7,9 +9,13 @@ use reqwest::Client;
 use tokio::io::AsyncWriteExt;
 use tokio_util::sync::CancellationToken;
 
-use crate::types::types::{DownloadError, HeaderData, ProbeResult, Segment, SegmentState};
+use crate::downloader::checksum::Checksum;
+use crate::downloader::pause_token::PauseToken;
+use crate::downloader::rate_limiter::{LimiterShare, RateLimiter};
+use crate::types::types::{ChecksumAlgo, DownloadError, HeaderData, ProbeResult, Segment, SegmentState, SpeedLimit, StreamType};
 
-/// Applies common headers (custom headers, cookies, auth) to a request builder.
+/// Applies common headers (custom headers, the default `Accept`, cookies,
+/// auth) to a request builder.
 /// Skips the `Range` header — rdm sets its own Range per segment/probe, and a
 /// stale browser-captured Range would create a duplicate causing the server
 /// to return incorrect data.
@@ -26,6 +32,11 @@ fn apply_headers(
             builder = builder.header(key, value);
         }
     }
+    if let Some(accept) = &header_data.default_accept {
+        if !header_data.headers.keys().any(|k| k.eq_ignore_ascii_case("accept")) {
+            builder = builder.header("Accept", accept);
+        }
+    }
     if let Some(cookies) = &header_data.cookies {
         builder = builder.header("Cookie", cookies);
     }
@@ -35,6 +46,15 @@ fn apply_headers(
     builder
 }
 
+/// Starts a request to `url` with `header_data`'s method and body.
+fn new_request(client: &Client, url: &str, header_data: &HeaderData) -> reqwest::RequestBuilder {
+    let builder = client.request(header_data.http_method(), url);
+    match &header_data.body {
+        Some(body) => builder.body(body.clone()),
+        None => builder,
+    }
+}
+
 /// Pre-computes the Basic auth header value, if authentication is configured.
 fn precompute_auth(header_data: &HeaderData) -> Option<String> {
     header_data.authentication.as_ref().map(|auth| {
@@ -44,21 +64,137 @@ fn precompute_auth(header_data: &HeaderData) -> Option<String> {
     })
 }
 
+/// Whether `to` is on another host or port than `from` — the test reqwest
+/// applies before dropping `Authorization` and `Cookie` on a redirect.
+pub fn is_cross_host(from: &str, to: &str) -> bool {
+    match (reqwest::Url::parse(from), reqwest::Url::parse(to)) {
+        (Ok(from), Ok(to)) => {
+            from.host_str() != to.host_str() || from.port_or_known_default() != to.port_or_known_default()
+        }
+        _ => false,
+    }
+}
+
+/// Removes everything that authenticates a request: Basic credentials,
+/// cookies, and any `Authorization` or `Cookie` among the custom headers.
+pub fn strip_credentials(header_data: &mut HeaderData) {
+    header_data.authentication = None;
+    header_data.cookies = None;
+    header_data
+        .headers
+        .retain(|name, _| !name.eq_ignore_ascii_case("authorization") && !name.eq_ignore_ascii_case("cookie"));
+}
+
+/// `DownloadError::Timeout` for a request that timed out, `Network` for
+/// any other failure.
+fn network_error(err: reqwest::Error) -> DownloadError {
+    if err.is_timeout() {
+        DownloadError::Timeout(err.to_string())
+    } else {
+        err.into()
+    }
+}
+
+/// Redirects followed by hand; see [`send_following_redirects`].
+const MAX_MANUAL_REDIRECTS: usize = 10;
+
+/// Sends `request(url)`, then re-sends it to the `Location` of any redirect
+/// the client handed back unfollowed. That is a cross-host redirect when the
+/// client forwards credentials (see `with_forward_auth_on_redirect`): the
+/// rebuilt request carries them, where reqwest would have dropped them. A
+/// redirect from `https` to plain `http` is refused rather than send them
+/// unencrypted.
+async fn send_following_redirects(
+    url: &str,
+    request: impl Fn(&str) -> reqwest::RequestBuilder,
+) -> Result<reqwest::Response, DownloadError> {
+    let mut response = request(url).send().await.map_err(network_error)?;
+    for _ in 0..MAX_MANUAL_REDIRECTS {
+        if !response.status().is_redirection() {
+            break;
+        }
+        let next = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|location| response.url().join(location).ok());
+        let Some(next) = next else { break };
+        if response.url().scheme() == "https" && next.scheme() == "http" {
+            return Err(DownloadError::SegmentFailed(format!(
+                "refusing to forward credentials from {} to plain-http {}",
+                response.url(),
+                next
+            )));
+        }
+        log::info!("[redirect] following {} to {} with credentials", response.status(), next);
+        response = request(next.as_str()).send().await.map_err(network_error)?;
+    }
+    Ok(response)
+}
+
+/// Response headers some CDNs name the file in when they send no
+/// `Content-Disposition`; see [`probe_url_with_filename_headers`].
+pub const DEFAULT_FILENAME_HEADERS: &[&str] = &["X-Filename", "X-File-Name"];
+
 /// Sends a probe request to determine file size, resumability, and metadata.
 /// Uses `Range: bytes=0-0` to request only 1 byte, minimizing wasted bandwidth.
 /// The file size is extracted from the `Content-Range` header.
+///
+/// A non-GET request is not sent at all — it may not be repeatable (a
+/// single-use token, say) and servers don't range it anyway — so the result
+/// is simply non-resumable with an unknown size.
 pub async fn probe_url(
     client: &Client,
     header_data: &HeaderData,
 ) -> Result<ProbeResult, DownloadError> {
-    let auth_header = precompute_auth(header_data);
-    let builder = client.get(&header_data.url);
-    let mut builder = apply_headers(builder, header_data, auth_header.as_deref());
+    let defaults: Vec<String> = DEFAULT_FILENAME_HEADERS.iter().map(|h| h.to_string()).collect();
+    probe_url_with_filename_headers(client, header_data, &defaults).await
+}
+
+/// Same as [`probe_url`], taking the file name from the first of
+/// `filename_headers` present when `Content-Disposition` names none.
+pub async fn probe_url_with_filename_headers(
+    client: &Client,
+    header_data: &HeaderData,
+    filename_headers: &[String],
+) -> Result<ProbeResult, DownloadError> {
+    if header_data.http_method() != reqwest::Method::GET {
+        log::info!(
+            "[probe_url] {} request, skipping probe (single non-resumable segment)",
+            header_data.http_method()
+        );
+        return Ok(ProbeResult {
+            resumable: false,
+            resource_size: None,
+            final_uri: header_data.url.clone(),
+            attachment_name: None,
+            content_type: None,
+            last_modified: None,
+            etag: None,
+            set_cookies: Vec::new(),
+            not_modified: false,
+        });
+    }
 
-    // Request only 1 byte to test resumability and get total size
-    builder = builder.header("Range", "bytes=0-0");
+    let auth_header = precompute_auth(header_data);
+    let response = send_following_redirects(&header_data.url, |url| {
+        let builder = apply_headers(client.get(url), header_data, auth_header.as_deref());
+        // Request only 1 byte to test resumability and get total size
+        builder.header("Range", "bytes=0-0")
+    })
+    .await?;
 
-    let response = builder.send().await?;
+    if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        let challenge = response
+            .headers()
+            .get(reqwest::header::WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_auth_challenge);
+        if let Some((scheme, realm)) = challenge {
+            log::info!("[probe_url] 401 with a {} challenge, realm={:?}", scheme, realm);
+            return Err(DownloadError::AuthRequired { scheme, realm });
+        }
+    }
 
     let resumable = response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
 
@@ -80,7 +216,8 @@ pub async fn probe_url(
             .headers()
             .get("content-disposition")
             .and_then(|v| v.to_str().ok())
-            .and_then(extract_filename),
+            .and_then(extract_filename)
+            .or_else(|| filename_from_headers(response.headers(), filename_headers)),
         content_type: response
             .headers()
             .get("content-type")
@@ -91,6 +228,19 @@ pub async fn probe_url(
             .get("last-modified")
             .and_then(|v| v.to_str().ok())
             .map(|s| s.to_string()),
+        etag: response
+            .headers()
+            .get("etag")
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string()),
+        set_cookies: response
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .filter_map(cookie_pair)
+            .collect(),
+        not_modified: response.status() == reqwest::StatusCode::NOT_MODIFIED,
     };
 
     // Drop response — only 1 byte of body data, minimal waste
@@ -99,14 +249,378 @@ pub async fn probe_url(
     Ok(probe)
 }
 
-/// Downloads a single segment of a file.
+/// Scheme and realm of the first challenge in a `WWW-Authenticate` header,
+/// e.g. `Basic realm="files"` → `("Basic", Some("files"))`. `None` if the
+/// header names no scheme.
+pub fn parse_auth_challenge(header: &str) -> Option<(String, Option<String>)> {
+    let header = header.trim();
+    let (scheme, params) = header.split_once(char::is_whitespace).unwrap_or((header, ""));
+    if scheme.is_empty() || scheme.contains('=') {
+        return None;
+    }
+    let lower = params.to_ascii_lowercase();
+    let realm = lower.find("realm=").map(|idx| {
+        let value = params[idx + "realm=".len()..].trim_start();
+        match value.strip_prefix('"') {
+            Some(quoted) => quoted.split('"').next().unwrap_or_default().to_string(),
+            None => value.split([',', ' ']).next().unwrap_or_default().to_string(),
+        }
+    });
+    Some((scheme.to_string(), realm))
+}
+
+/// The `name=value` part of a `Set-Cookie` header value, without attributes
+/// such as `Path` or `HttpOnly`. `None` if there is no usable pair.
+pub fn cookie_pair(set_cookie: &str) -> Option<String> {
+    let pair = set_cookie.split(';').next()?.trim();
+    let (name, _) = pair.split_once('=')?;
+    if name.trim().is_empty() {
+        return None;
+    }
+    Some(pair.to_string())
+}
+
+/// Merge `name=value` pairs into a `Cookie` header string. A pair replaces an
+/// existing cookie of the same name; new names are appended.
+pub fn merge_cookies(existing: Option<&str>, pairs: &[String]) -> Option<String> {
+    let mut merged: Vec<String> = existing
+        .into_iter()
+        .flat_map(|c| c.split(';'))
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    for pair in pairs {
+        let name = pair.split('=').next().unwrap_or_default().trim();
+        match merged
+            .iter_mut()
+            .find(|c| c.split('=').next().unwrap_or_default().trim() == name)
+        {
+            Some(slot) => *slot = pair.clone(),
+            None => merged.push(pair.clone()),
+        }
+    }
+    (!merged.is_empty()).then(|| merged.join("; "))
+}
+
+/// Fetches a small text document (e.g. a DASH manifest) with the same
+/// headers, cookies and auth used for segment requests.
+/// Returns the body and the final URL after redirects.
+pub async fn fetch_text(
+    client: &Client,
+    header_data: &HeaderData,
+) -> Result<(String, String), DownloadError> {
+    let auth_header = precompute_auth(header_data);
+    let response = send_following_redirects(&header_data.url, |url| {
+        apply_headers(client.get(url), header_data, auth_header.as_deref())
+    })
+    .await?
+    .error_for_status()?;
+    let final_uri = response.url().to_string();
+    let body = response.text().await?;
+
+    Ok((body, final_uri))
+}
+
+/// URL for the given attempt: the primary URL first, then each mirror in
+/// turn, wrapping around.
+fn attempt_url(header_data: &HeaderData, attempt: usize) -> &str {
+    match attempt % (header_data.mirrors.len() + 1) {
+        0 => &header_data.url,
+        i => &header_data.mirrors[i - 1],
+    }
+}
+
+/// Base delay for the first retry; doubles on each subsequent attempt.
+const BACKOFF_BASE_MS: u64 = 100;
+
+/// Upper bound on a single backoff delay.
+const BACKOFF_MAX_MS: u64 = 5_000;
+
+/// Exponential backoff with full jitter: a random delay in
+/// `[0, min(BACKOFF_BASE_MS * 2^retries, BACKOFF_MAX_MS)]`.
+///
+/// Randomising the whole interval keeps segments that failed together from
+/// retrying in lockstep against an already struggling server.
+pub fn backoff_delay(rng: &mut fastrand::Rng, retries: usize) -> std::time::Duration {
+    let ceiling = (BACKOFF_BASE_MS << retries.min(16)).min(BACKOFF_MAX_MS);
+    std::time::Duration::from_millis(rng.u64(0..=ceiling))
+}
+
+/// Default for [`SegmentOptions::idle_timeout`].
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Default for [`SegmentOptions::write_buffer_size`] (256 KB).
+pub const DEFAULT_WRITE_BUFFER_SIZE: usize = 256 * 1024;
+
+/// Smallest accepted write buffer; anything lower is raised to this.
+pub const MIN_WRITE_BUFFER_SIZE: usize = 4 * 1024;
+
+/// Per-segment transfer settings, shared by every segment of a download.
+#[derive(Debug, Clone)]
+pub struct SegmentOptions {
+    /// Retry the segment if no bytes arrive for this long, even though the
+    /// connection is still open (a stalled CDN edge, for example).
+    pub idle_timeout: Duration,
+    /// Capacity of the `BufWriter` in front of each segment's temp file.
+    pub write_buffer_size: usize,
+    /// Throttles for received bytes, outermost first; a segment waits on
+    /// each in turn. Whether one caps the aggregate or a single connection
+    /// depends on whether segments share it.
+    pub rate_limiters: Vec<RateLimiter>,
+    /// Retries shared by every segment of the download, on top of each
+    /// segment's own retry limit. `None` leaves only the per-segment cap.
+    pub retry_budget: Option<RetryBudget>,
+    /// Size of the whole resource the segments were cut from. A ranged
+    /// response reporting another total fails with `ResourceChanged`; set
+    /// when the size was not probed but taken on trust.
+    pub expected_size: Option<u64>,
+    /// Content-Type prefix the responses should have (`video/`, say); an
+    /// HTML page instead fails the segment with `UnexpectedContentType`
+    /// (see [`check_content_type`]). Set when the type was not probed but
+    /// taken on trust.
+    pub expect_content_type: Option<String>,
+    /// Most bytes the whole download may write, shared by every segment;
+    /// crossing it fails the segment with `TooLarge`.
+    pub size_cap: Option<SizeCap>,
+    /// Push buffered bytes to disk mid-stream and record the segment's
+    /// progress beside its temp file (see [`segment_state_path`]). `None`
+    /// flushes only when a response ends or fails.
+    pub flush_interval: Option<FlushInterval>,
+    /// Keep a SHA-256 of each ranged segment's bytes and store it in the
+    /// flush record, so a resume can check the temp file still holds them.
+    pub record_hash: bool,
+    /// Fail a ranged segment with `RangeIgnored` as soon as the server
+    /// answers its range with the whole file, instead of keeping only the
+    /// requested bytes of it. Lets the caller fall back to a single stream
+    /// rather than fetch the file once per segment.
+    pub restart_on_ignored_range: bool,
+    /// Records the largest total a ranged response of the primary stream
+    /// reports, so the strategy can fetch the tail of a resource that is
+    /// bigger than the probe said.
+    pub reported_total: Option<ReportedTotal>,
+    /// Client for the attempt after a connection reset: the download's
+    /// settings, but no pooled connections, so the retry cannot be handed
+    /// another idle connection to the host that dropped it. `None` retries
+    /// with the download's client.
+    pub reconnect_client: Option<Client>,
+}
+
+/// How often a segment flushes while streaming: whenever `bytes` more have
+/// been written or `period` has passed since the last flush, whichever
+/// comes first. Each flush is a write syscall, so small values cost
+/// throughput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlushInterval {
+    pub bytes: u64,
+    pub period: Duration,
+}
+
+impl Default for FlushInterval {
+    /// 8 MB or 5 s.
+    fn default() -> Self {
+        Self { bytes: 8 * 1024 * 1024, period: Duration::from_secs(5) }
+    }
+}
+
+/// Where a segment's last flushed state is recorded: `<id>.state` next to
+/// its temp file, a [`SegmentRecord`] whose `downloaded` matches what is on
+/// disk.
+pub fn segment_state_path(temp_dir: &Path, segment_id: &str) -> PathBuf {
+    temp_dir.join(format!("{}.state", segment_id))
+}
+
+/// Contents of a segment's [`segment_state_path`]: the [`Segment`]'s own
+/// fields, plus the hash of its bytes when `SegmentOptions::record_hash`
+/// is set.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SegmentRecord {
+    #[serde(flatten)]
+    pub segment: Segment,
+    /// Lowercase hex SHA-256 of the first `segment.downloaded` bytes of the
+    /// temp file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Flushes `writer` and records `segment` (with the digest of what has been
+/// written, if kept) at [`segment_state_path`], replacing the previous
+/// record atomically.
+async fn flush_and_record(
+    writer: &mut tokio::io::BufWriter<tokio::fs::File>,
+    temp_dir: &Path,
+    segment: &Segment,
+    hash: Option<&Checksum>,
+) -> Result<(), DownloadError> {
+    writer.flush().await.map_err(DownloadError::Disk)?;
+    let record = SegmentRecord { segment: segment.clone(), sha256: hash.cloned().map(Checksum::finalize_hex) };
+    let json = serde_json::to_vec(&record).map_err(|e| DownloadError::Disk(std::io::Error::other(e)))?;
+    let path = segment_state_path(temp_dir, &segment.id);
+    let tmp = path.with_extension("state.tmp");
+    tokio::fs::write(&tmp, json).await.map_err(DownloadError::Disk)?;
+    tokio::fs::rename(&tmp, &path).await.map_err(DownloadError::Disk)
+}
+
+/// A SHA-256 seeded with the first `len` bytes of `path`, the part of a
+/// segment's temp file a resumed attempt appends to.
+async fn hash_written_prefix(path: &Path, len: u64) -> Result<Checksum, DownloadError> {
+    let mut hash = Checksum::new(ChecksumAlgo::Sha256);
+    if len == 0 {
+        return Ok(hash);
+    }
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || hash.update_from_file_prefix(&path, len).map(|()| hash))
+        .await
+        .map_err(|e| DownloadError::Disk(std::io::Error::other(e)))?
+        .map_err(DownloadError::Disk)
+}
+
+impl SegmentOptions {
+    /// The options for one segment task under `limit`: a global limit is
+    /// already shared through `self` (see [`SegmentOptions::with_speed_limit`]),
+    /// a per-connection one gets a bucket of its own.
+    pub fn for_segment(&self, limit: Option<SpeedLimit>) -> Self {
+        let mut options = self.clone();
+        if let Some(SpeedLimit::PerConnection(rate)) = limit {
+            options.rate_limiters.push(RateLimiter::new(rate));
+        }
+        options
+    }
+
+    /// Adds the limiter shared by every segment for a global `limit`.
+    pub fn with_speed_limit(mut self, limit: Option<SpeedLimit>) -> Self {
+        if let Some(SpeedLimit::Global(rate)) = limit {
+            self.rate_limiters.push(RateLimiter::new(rate));
+        }
+        self
+    }
+
+    /// Puts the download's part of a [`SharedRateLimiter`](crate::downloader::rate_limiter::SharedRateLimiter)
+    /// in front of any limiters of its own.
+    pub fn with_share(mut self, share: Option<&LimiterShare>) -> Self {
+        if let Some(share) = share {
+            self.rate_limiters.splice(0..0, share.limiters());
+        }
+        self
+    }
+
+    /// Whether another retry may be made, taking it from the shared budget
+    /// if there is one.
+    fn take_retry(&self) -> bool {
+        self.retry_budget.as_ref().is_none_or(RetryBudget::try_take)
+    }
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        Self {
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            rate_limiters: Vec::new(),
+            retry_budget: None,
+            expected_size: None,
+            expect_content_type: None,
+            size_cap: None,
+            flush_interval: None,
+            record_hash: false,
+            restart_on_ignored_range: false,
+            reported_total: None,
+            reconnect_client: None,
+        }
+    }
+}
+
+/// Retries left for a whole download. Clones share the count, so against a
+/// server that is down for everyone the download fails after `total` retries
+/// instead of every segment spending its own.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    remaining: Arc<AtomicUsize>,
+}
+
+impl RetryBudget {
+    pub fn new(total: usize) -> Self {
+        Self { remaining: Arc::new(AtomicUsize::new(total)) }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.remaining.load(Ordering::Relaxed)
+    }
+
+    /// Take one retry; `false` once the budget is spent.
+    pub fn try_take(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+/// The largest resource size the segments' `Content-Range` headers have
+/// reported. Clones share the value.
+#[derive(Debug, Clone, Default)]
+pub struct ReportedTotal {
+    total: Arc<AtomicU64>,
+}
+
+impl ReportedTotal {
+    pub fn record(&self, total: u64) {
+        self.total.fetch_max(total, Ordering::AcqRel);
+    }
+
+    /// `None` until a response reported a total.
+    pub fn get(&self) -> Option<u64> {
+        Some(self.total.load(Ordering::Acquire)).filter(|&total| total > 0)
+    }
+}
+
+/// Upper bound on the bytes a download writes across all its segments, for
+/// downloads whose size is not known up front. Clones share the count.
+#[derive(Debug, Clone)]
+pub struct SizeCap {
+    limit: u64,
+    written: Arc<AtomicU64>,
+}
+
+impl SizeCap {
+    pub fn new(limit: u64) -> Self {
+        Self { limit, written: Arc::new(AtomicU64::new(0)) }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Count afresh from the `held` bytes a run starts with (what earlier
+    /// runs left on disk); `TooLarge` if they are past the limit already.
+    pub fn restart(&self, held: u64) -> Result<(), DownloadError> {
+        self.written.store(held, Ordering::Release);
+        if held > self.limit {
+            return Err(DownloadError::TooLarge { size: held, limit: self.limit });
+        }
+        Ok(())
+    }
+
+    /// Count `bytes` more; `TooLarge` once the total is past the limit.
+    pub fn add(&self, bytes: u64) -> Result<(), DownloadError> {
+        let written = self.written.fetch_add(bytes, Ordering::AcqRel) + bytes;
+        if written > self.limit {
+            return Err(DownloadError::TooLarge { size: written, limit: self.limit });
+        }
+        Ok(())
+    }
+}
+
+/// Attempts per segment before giving up, not counting extra mirror attempts.
+const MAX_RETRIES: usize = 3;
+
+/// Downloads a single segment of a file with the default [`SegmentOptions`].
 ///
 /// For resumable downloads, sends `Range: bytes={start}-{end}`.
 /// For non-resumable downloads (segment.length == -1), sends no Range header
 /// and downloads the entire response body.
 ///
-/// Uses async I/O (tokio::fs) with a 256 KB write buffer to avoid blocking
-/// the tokio runtime. Retries with exponential backoff on network errors.
+/// Uses async I/O (tokio::fs) with a buffered writer to avoid blocking
+/// the tokio runtime. Retries with jittered exponential backoff on network errors
+/// and error statuses, moving to the next of `header_data.mirrors` on each retry.
 pub async fn download_segment(
     segment: Segment,
     client: &Client,
@@ -114,10 +628,57 @@ pub async fn download_segment(
     temp_dir: PathBuf,
     cancel_token: CancellationToken,
     on_progress: impl Fn(u64),
+) -> Result<Segment, DownloadError> {
+    download_segment_with_options(
+        segment,
+        client,
+        header_data,
+        temp_dir,
+        cancel_token,
+        PauseToken::new(),
+        SegmentOptions::default(),
+        on_progress,
+        |_| {},
+    )
+    .await
+}
+
+/// Same as [`download_segment`], with explicit [`SegmentOptions`] and a
+/// [`PauseToken`]. While paused, a ranged segment drops its connection and
+/// re-requests from `downloaded` on resume; a non-ranged one holds it open.
+///
+/// `on_retry` is called with the retry number each time the segment is about
+/// to be retried after a failed attempt.
+#[allow(clippy::too_many_arguments)]
+pub async fn download_segment_with_options(
+    segment: Segment,
+    client: &Client,
+    header_data: &Arc<HeaderData>,
+    temp_dir: PathBuf,
+    cancel_token: CancellationToken,
+    pause_token: PauseToken,
+    options: SegmentOptions,
+    on_progress: impl Fn(u64),
+    on_retry: impl Fn(u32),
 ) -> Result<Segment, DownloadError> {
     let mut segment = segment;
     let mut retries = 0;
-    const MAX_RETRIES: usize = 3;
+    // Every mirror gets at least one attempt on top of the usual budget.
+    let max_retries = MAX_RETRIES + header_data.mirrors.len();
+    // Per-task RNG so concurrent segments draw independent backoff delays.
+    let mut rng = fastrand::Rng::new();
+    // Bytes already counted against `options.size_cap`; a refetch from the
+    // start only counts once it gets past them.
+    let mut capped = segment.downloaded.max(0) as u64;
+    // Set after the connection was reset: the next attempt goes through
+    // `options.reconnect_client`, on a connection of its own.
+    let mut fresh_connection = false;
+    // SHA-256 of the temp file so far, kept across retries when the flush
+    // records carry one.
+    let mut written_hash: Option<Checksum> = None;
+    // What timed out on the latest attempt, if that is why it failed: the
+    // segment then gives up with `Timeout` rather than `MaxRetryExceeded`.
+    let mut timed_out: Option<String>;
 
     segment.state = SegmentState::Downloading;
 
@@ -128,13 +689,27 @@ pub async fn download_segment(
         if cancel_token.is_cancelled() {
             return Err(DownloadError::Cancelled);
         }
+        wait_unpaused(&pause_token, &cancel_token).await?;
+
+        timed_out = None;
+
+        // Without a Range header a retry re-fetches the whole body, so any
+        // partial file from a previous attempt has to be discarded.
+        if segment.length <= 0 {
+            segment.downloaded = 0;
+        }
 
         // Build request with shared helper
-        let builder = client.get(&header_data.url);
+        let url = attempt_url(header_data, retries);
+        let attempt_client = match (std::mem::take(&mut fresh_connection), &options.reconnect_client) {
+            (true, Some(fresh)) => fresh,
+            _ => client,
+        };
+        let builder = new_request(attempt_client, url, header_data);
         let mut builder = apply_headers(builder, header_data, auth_header.as_deref());
 
-        // Add Range header for resumable downloads
-        if segment.length > 0 {
+        // Add Range header for resumable downloads (never for non-GET ones)
+        if segment.length > 0 && header_data.http_method() == reqwest::Method::GET {
             let start = segment.offset + segment.downloaded;
             let end = segment.offset + segment.length - 1;
             log::info!(
@@ -149,7 +724,23 @@ pub async fn download_segment(
             );
         }
 
-        match builder.send().await {
+        // Waiting for the response counts as waiting for data, too; a cancel
+        // does not wait for it.
+        let send = tokio::time::timeout(options.idle_timeout, builder.send());
+        let sent = match tokio::select! {
+            sent = send => sent,
+            _ = cancel_token.cancelled() => return Err(DownloadError::Cancelled),
+        } {
+            Ok(sent) => sent.map_err(|e| {
+                timed_out = e.is_timeout().then(|| e.to_string());
+                Some(e)
+            }),
+            Err(_) => {
+                timed_out = Some(format!("no response for {:?}", options.idle_timeout));
+                Err(None)
+            }
+        };
+        match sent {
             Ok(response) => {
                 let status = response.status();
                 let content_length = response.content_length();
@@ -158,23 +749,126 @@ pub async fn download_segment(
                     segment.id, status, content_length, segment.length
                 );
 
-                // BUG DETECTION: If we sent a Range request but got 200 (not 206),
-                // the server ignored our Range header and is sending the ENTIRE file.
-                // Each of the N segments will download the full file, resulting in Nx file size.
-                if segment.length > 0 && status == reqwest::StatusCode::OK {
-                    log::error!(
-                        "[download_segment] BUG: segment={}: sent Range request but server responded with 200 OK instead of 206 Partial Content! \
-                         The server is sending the ENTIRE file body ({:?} bytes) instead of just the requested range. \
-                         This segment expected only {} bytes. With {} connections, the final file will be {}x too large.",
-                        segment.id,
-                        content_length,
-                        segment.length,
-                        8, // MAX_CONNECTIONS
-                        8  // MAX_CONNECTIONS
+                // The whole range lies past the real end of a resource the
+                // probe over-reported: nothing is left for this segment.
+                if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE
+                    && segment.length > 0
+                    && options.expected_size.is_none()
+                {
+                    let total = response
+                        .headers()
+                        .get("content-range")
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(content_range_total)
+                        .map(|t| t as i64);
+                    if let Some(total) = total.filter(|&t| t <= segment.offset + segment.downloaded) {
+                        log::warn!(
+                            "[download_segment] segment={}: resource ends at {}, before this segment's range",
+                            segment.id, total
+                        );
+                        // Bytes already on disk stay part of the segment.
+                        segment.length = segment.downloaded;
+                        segment.state = SegmentState::Finished;
+                        return Ok(segment);
+                    }
+                }
+
+                // An error page is not segment data; try again (on the next mirror).
+                if !status.is_success() {
+                    log::warn!(
+                        "[download_segment] segment={}: {} responded {}",
+                        segment.id, url, status
                     );
+                    retries += 1;
+                    if retries >= max_retries || !options.take_retry() {
+                        segment.state = SegmentState::Failed;
+                        return Err(DownloadError::MaxRetryExceeded);
+                    }
+                    on_retry(retries as u32);
+                    tokio::time::sleep(backoff_delay(&mut rng, retries)).await;
+                    continue;
+                }
+
+                if let Some(expected) = &options.expect_content_type {
+                    let got = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+                    if let Err(e) = check_content_type(expected, got) {
+                        segment.state = SegmentState::Failed;
+                        return Err(e);
+                    }
+                }
+
+                // The whole file in answer to a range: every segment would
+                // fetch it again just to keep its own slice.
+                if segment.length > 0 && options.restart_on_ignored_range {
+                    let requested = (segment.offset + segment.downloaded) as u64;
+                    let wanted = (segment.length - segment.downloaded) as u64;
+                    let span = response
+                        .headers()
+                        .get("content-range")
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(content_range_span);
+                    let ignored = if status == reqwest::StatusCode::OK {
+                        Some("answered 200".to_string())
+                    } else if status == reqwest::StatusCode::PARTIAL_CONTENT {
+                        span.filter(|&(start, end)| start <= requested && end - start + 1 >= 2 * wanted)
+                            .map(|(start, end)| format!("sent bytes {}-{}", start, end))
+                    } else {
+                        None
+                    };
+                    if let Some(sent) = ignored {
+                        segment.state = SegmentState::Failed;
+                        return Err(DownloadError::RangeIgnored(format!(
+                            "segment {} asked for {} bytes from {} but the server {}",
+                            segment.id, wanted, requested, sent
+                        )));
+                    }
                 }
 
-                // Open temp file with async I/O + 256 KB write buffer
+                // A 206 for a different range than requested would write
+                // misaligned bytes into this segment; stop instead.
+                if segment.length > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT {
+                    let requested = (segment.offset + segment.downloaded) as u64;
+                    let content_range = response
+                        .headers()
+                        .get("content-range")
+                        .and_then(|v| v.to_str().ok());
+                    let served = content_range.and_then(content_range_start);
+                    if let Some(start) = served.filter(|&start| start != requested) {
+                        segment.state = SegmentState::Failed;
+                        return Err(DownloadError::ResourceChanged(format!(
+                            "segment {} asked for bytes from {} but the server sent bytes from {}",
+                            segment.id, requested, start
+                        )));
+                    }
+                    let total = content_range.and_then(content_range_total);
+                    if let (Some(expected), Some(total)) = (options.expected_size, total) {
+                        if expected != total {
+                            segment.state = SegmentState::Failed;
+                            return Err(DownloadError::ResourceChanged(format!(
+                                "expected a {} byte resource but the server reports {} bytes",
+                                expected, total
+                            )));
+                        }
+                    }
+                    if let (Some(reported), Some(total), StreamType::Primary) =
+                        (&options.reported_total, total, &segment.stream_type)
+                    {
+                        reported.record(total);
+                    }
+                    // The probe over-reported the size: keep to what the
+                    // server actually has (the strategy reconciles the plan).
+                    if let Some(total) = total.map(|t| t as i64) {
+                        if total < segment.offset + segment.length {
+                            log::warn!(
+                                "[download_segment] segment={}: resource ends at {}, trimming segment length {} -> {}",
+                                segment.id, total, segment.length, total - segment.offset
+                            );
+                            segment.length = total - segment.offset;
+                        }
+                    }
+                }
+
+                // Open temp file with async I/O + write buffer
                 let file_path = temp_dir.join(&segment.id);
                 let file = if segment.downloaded > 0 {
                     tokio::fs::OpenOptions::new()
@@ -187,7 +881,10 @@ pub async fn download_segment(
                         .await
                         .map_err(DownloadError::Disk)?
                 };
-                let mut writer = tokio::io::BufWriter::with_capacity(256 * 1024, file);
+                let mut writer = tokio::io::BufWriter::with_capacity(
+                    options.write_buffer_size.max(MIN_WRITE_BUFFER_SIZE),
+                    file,
+                );
 
                 // How many bytes this segment still needs. For non-resumable
                 // downloads (length == -1) we accept everything the server sends.
@@ -197,12 +894,48 @@ pub async fn download_segment(
                     u64::MAX
                 };
                 let mut bytes_written: u64 = 0;
+                // Only a ranged segment can be resumed from its record.
+                let flush_interval = options.flush_interval.filter(|_| segment.length > 0);
+                if flush_interval.is_some() && options.record_hash && written_hash.is_none() {
+                    written_hash = Some(hash_written_prefix(&file_path, segment.downloaded.max(0) as u64).await?);
+                }
+                let mut unflushed: u64 = 0;
+                let mut last_flush = Instant::now();
 
                 // Stream the response body chunk by chunk
                 let mut stream = response.bytes_stream();
                 let mut stream_error = false;
+                let mut paused = false;
+
+                loop {
+                    if pause_token.is_paused() {
+                        let _ = writer.flush().await;
+                        if segment.length > 0 {
+                            // Reconnect with a fresh Range on resume rather
+                            // than trusting an idle connection to survive.
+                            paused = true;
+                            break;
+                        }
+                        wait_unpaused(&pause_token, &cancel_token).await?;
+                    }
+
+                    let chunk_result = match tokio::time::timeout(options.idle_timeout, stream.next()).await {
+                        Ok(Some(chunk_result)) => chunk_result,
+                        Ok(None) => break,
+                        Err(_) => {
+                            // No bytes for `idle_timeout` — treat the stall like a
+                            // network error and resume from `downloaded`.
+                            log::warn!(
+                                "[download_segment] segment={}: no data for {:?}, retrying from downloaded={}",
+                                segment.id, options.idle_timeout, segment.downloaded
+                            );
+                            timed_out = Some(format!("no data for {:?}", options.idle_timeout));
+                            let _ = writer.flush().await;
+                            stream_error = true;
+                            break;
+                        }
+                    };
 
-                while let Some(chunk_result) = stream.next().await {
                     if cancel_token.is_cancelled() {
                         let _ = writer.flush().await;
                         return Err(DownloadError::Cancelled);
@@ -236,11 +969,46 @@ pub async fn download_segment(
                                 .write_all(to_write)
                                 .await
                                 .map_err(DownloadError::Disk)?;
+                            if let Some(hash) = &mut written_hash {
+                                hash.update(to_write);
+                            }
                             let written_len = to_write.len() as u64;
                             bytes_written += written_len;
                             segment.downloaded += written_len as i64;
                             on_progress(written_len);
 
+                            if let Some(interval) = flush_interval {
+                                unflushed += written_len;
+                                if unflushed >= interval.bytes || last_flush.elapsed() >= interval.period {
+                                    flush_and_record(&mut writer, &temp_dir, &segment, written_hash.as_ref()).await?;
+                                    unflushed = 0;
+                                    last_flush = Instant::now();
+                                }
+                            }
+
+                            if let Some(cap) = &options.size_cap {
+                                let downloaded = segment.downloaded as u64;
+                                if downloaded > capped {
+                                    let counted = cap.add(downloaded - capped);
+                                    capped = downloaded;
+                                    if let Err(e) = counted {
+                                        let _ = writer.flush().await;
+                                        segment.state = SegmentState::Failed;
+                                        return Err(e);
+                                    }
+                                }
+                            }
+
+                            for limiter in &options.rate_limiters {
+                                tokio::select! {
+                                    _ = limiter.acquire(written_len) => {}
+                                    _ = cancel_token.cancelled() => {
+                                        let _ = writer.flush().await;
+                                        return Err(DownloadError::Cancelled);
+                                    }
+                                }
+                            }
+
                             // If we have exactly enough, stop reading.
                             if segment.length > 0 && bytes_written >= remaining {
                                 log::debug!(
@@ -250,8 +1018,21 @@ pub async fn download_segment(
                                 break;
                             }
                         }
-                        Err(_e) => {
+                        Err(e) => {
                             // Network error mid-stream — flush what we have, then retry
+                            if is_connection_reset(&e) {
+                                log::warn!(
+                                    "[download_segment] segment={}: connection reset, retrying on a fresh connection from downloaded={}",
+                                    segment.id, segment.downloaded
+                                );
+                                fresh_connection = true;
+                            } else {
+                                log::warn!(
+                                    "[download_segment] segment={}: stream error, retrying from downloaded={}: {}",
+                                    segment.id, segment.downloaded, e
+                                );
+                            }
+                            timed_out = e.is_timeout().then(|| e.to_string());
                             let _ = writer.flush().await;
                             stream_error = true;
                             break;
@@ -259,15 +1040,30 @@ pub async fn download_segment(
                     }
                 }
 
+                if paused {
+                    continue;
+                }
+
+                // A clean end of the body short of the requested range: the
+                // server or a proxy cut the response. Resume instead of
+                // finishing a truncated segment.
+                if !stream_error && segment.length > 0 && segment.downloaded < segment.length {
+                    log::warn!(
+                        "[download_segment] segment={}: response ended after {} of {} bytes, resuming",
+                        segment.id, segment.downloaded, segment.length
+                    );
+                    writer.flush().await.map_err(DownloadError::Disk)?;
+                    stream_error = true;
+                }
+
                 if stream_error {
                     retries += 1;
-                    if retries >= MAX_RETRIES {
+                    if retries >= max_retries || !options.take_retry() {
                         segment.state = SegmentState::Failed;
-                        return Err(DownloadError::MaxRetryExceeded);
+                        return Err(retries_exhausted(timed_out));
                     }
-                    // Exponential backoff: 100ms, 200ms, 400ms
-                    let delay_ms = 100u64 * (1u64 << retries.min(5));
-                    tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                    on_retry(retries as u32);
+                    tokio::time::sleep(backoff_delay(&mut rng, retries)).await;
                     continue;
                 }
 
@@ -279,33 +1075,129 @@ pub async fn download_segment(
                     if segment.length > 0 { segment.downloaded == segment.length } else { true }
                 );
 
-                // BUG DETECTION: downloaded more bytes than the segment should contain
-                if segment.length > 0 && segment.downloaded != segment.length {
-                    log::error!(
-                        "[download_segment] BUG: segment={}: size mismatch! downloaded={} but expected={}. \
-                         The server likely ignored the Range header and sent the full file. \
-                         This will cause the assembled output to be larger than the original file.",
-                        segment.id, segment.downloaded, segment.length
-                    );
-                }
-
                 segment.state = SegmentState::Finished;
+                if flush_interval.is_some() {
+                    flush_and_record(&mut writer, &temp_dir, &segment, written_hash.as_ref()).await?;
+                }
                 return Ok(segment);
             }
-            Err(_e) => {
+            Err(Some(e)) => {
+                if is_connection_reset(&e) {
+                    log::warn!(
+                        "[download_segment] segment={}: connection reset before a response, retrying on a fresh connection",
+                        segment.id
+                    );
+                    fresh_connection = true;
+                } else {
+                    log::warn!("[download_segment] segment={}: request failed: {}", segment.id, e);
+                }
                 retries += 1;
-                if retries >= MAX_RETRIES {
+                if retries >= max_retries || !options.take_retry() {
                     segment.state = SegmentState::Failed;
-                    return Err(DownloadError::MaxRetryExceeded);
+                    return Err(retries_exhausted(timed_out));
                 }
-                // Exponential backoff: 100ms, 200ms, 400ms
-                let delay_ms = 100u64 * (1u64 << retries.min(5));
-                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                on_retry(retries as u32);
+                tokio::time::sleep(backoff_delay(&mut rng, retries)).await;
+            }
+            Err(None) => {
+                log::warn!(
+                    "[download_segment] segment={}: no response for {:?}, retrying",
+                    segment.id, options.idle_timeout
+                );
+                retries += 1;
+                if retries >= max_retries || !options.take_retry() {
+                    segment.state = SegmentState::Failed;
+                    return Err(retries_exhausted(timed_out));
+                }
+                on_retry(retries as u32);
+                tokio::time::sleep(backoff_delay(&mut rng, retries)).await;
             }
         }
     }
 }
 
+/// The error for a segment out of retries: `Timeout` when the last attempt
+/// timed out, `MaxRetryExceeded` otherwise.
+fn retries_exhausted(timed_out: Option<String>) -> DownloadError {
+    match timed_out {
+        Some(what) => DownloadError::Timeout(what),
+        None => DownloadError::MaxRetryExceeded,
+    }
+}
+
+/// Whether `err` comes from the peer dropping the connection (reset,
+/// aborted, broken pipe or cut off mid-read) rather than from a timeout or
+/// a malformed response.
+fn is_connection_reset(err: &reqwest::Error) -> bool {
+    use std::io::ErrorKind;
+
+    let mut source = std::error::Error::source(err);
+    while let Some(e) = source {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io.kind(),
+                ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe | ErrorKind::UnexpectedEof
+            );
+        }
+        source = e.source();
+    }
+    false
+}
+
+/// Fails when `got` is an HTML page but `expected` (a Content-Type prefix
+/// such as `video/`) does not cover it: what a server sends for an expired
+/// or forbidden media URL, with a `200`. Any other type passes, as servers
+/// label plenty of media `application/octet-stream`.
+pub(crate) fn check_content_type(expected: &str, got: Option<&str>) -> Result<(), DownloadError> {
+    let got = got.unwrap_or_default().split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    let html = got == "text/html" || got == "application/xhtml+xml";
+    if html && !got.starts_with(&expected.to_ascii_lowercase()) {
+        return Err(DownloadError::UnexpectedContentType { expected: expected.to_string(), got });
+    }
+    Ok(())
+}
+
+/// First byte position of a `Content-Range: bytes a-b/total` value.
+fn content_range_start(content_range: &str) -> Option<u64> {
+    content_range
+        .trim()
+        .strip_prefix("bytes")?
+        .trim_start()
+        .split('-')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// First and last byte of a `Content-Range: bytes a-b/total` value.
+fn content_range_span(content_range: &str) -> Option<(u64, u64)> {
+    let range = content_range.trim().strip_prefix("bytes")?.trim_start();
+    let (start, end) = range.split('/').next()?.split_once('-')?;
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (start <= end).then_some((start, end))
+}
+
+/// Total size of a `Content-Range: bytes a-b/total` or `bytes */total`
+/// value; `None` when the total is `*`.
+fn content_range_total(content_range: &str) -> Option<u64> {
+    content_range.rsplit('/').next()?.trim().parse().ok()
+}
+
+/// Wait out a pause, giving up if the download is cancelled meanwhile.
+async fn wait_unpaused(
+    pause_token: &PauseToken,
+    cancel_token: &CancellationToken,
+) -> Result<(), DownloadError> {
+    if !pause_token.is_paused() {
+        return Ok(());
+    }
+    tokio::select! {
+        _ = pause_token.wait_while_paused() => Ok(()),
+        _ = cancel_token.cancelled() => Err(DownloadError::Cancelled),
+    }
+}
+
 /// Extract the filename from a `Content-Disposition` header value.
 ///
 /// Handles both the plain `filename=` form and the RFC 5987 `filename*=`
@@ -321,6 +1213,17 @@ pub fn extract_filename(disposition: &str) -> Option<String> {
     extract_filename_plain(disposition)
 }
 
+/// The file name in the first of `names` the response carries: quotes
+/// stripped, percent-decoded, and only the last path component kept.
+fn filename_from_headers(headers: &reqwest::header::HeaderMap, names: &[String]) -> Option<String> {
+    names.iter().find_map(|name| {
+        let value = headers.get(name.as_str())?.to_str().ok()?;
+        let decoded = percent_decode(value.trim().trim_matches('"'));
+        let base = decoded.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+        (!base.is_empty() && base != "." && base != "..").then(|| base.to_string())
+    })
+}
+
 /// Extract `filename*=UTF-8''...` (RFC 5987 extended notation).
 fn extract_filename_star(disposition: &str) -> Option<String> {
     // Case-insensitive search for "filename*="
@@ -332,20 +1235,18 @@ fn extract_filename_star(disposition: &str) -> Option<String> {
     let rest = rest.split(';').next().unwrap_or(rest).trim();
 
     // Format: charset'language'encoded-value
-    // We only handle UTF-8 (the overwhelmingly common case).
-    let after_charset = if let Some(s) = rest.strip_prefix("UTF-8''").or_else(|| rest.strip_prefix("utf-8''")) {
-        s
-    } else {
-        // Unknown charset — fall through to plain filename=
-        return None;
-    };
+    // We only handle UTF-8 (the overwhelmingly common case); an unknown
+    // charset falls through to plain filename=.
+    let after_charset = rest
+        .strip_prefix("UTF-8''")
+        .or_else(|| rest.strip_prefix("utf-8''"))?;
 
     // Percent-decode the value.
     Some(percent_decode(after_charset))
 }
 
 /// Percent-decode a URL-encoded string (e.g. `My%20File.mp4` → `My File.mp4`).
-fn percent_decode(s: &str) -> String {
+pub(crate) fn percent_decode(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
     let mut chars = s.chars().peekable();
     // Collect bytes for multi-byte UTF-8 sequences
@@ -0,0 +1,243 @@
+//! Options every strategy builder shares.
+//!
+//! Headers, credentials, the HTTP client's TLS and proxy settings, checksums,
+//! limits and retry behaviour mean the same thing for a multipart download
+//! and a DASH stream, so they are set through one trait rather than a copy
+//! of each setter per builder. Bring [`CommonOptions`] into scope to call
+//! them.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock};
+
+use tokio::sync::mpsc;
+
+use crate::downloader::connect_guard::ConnectGuard;
+use crate::downloader::rate_limiter::SharedRateLimiter;
+use crate::downloader::segment_grabber::{RetryBudget, SegmentOptions, SizeCap, MIN_WRITE_BUFFER_SIZE};
+#[cfg(feature = "compression")]
+use crate::types::types::Codec;
+use crate::types::types::{
+    AuthenticationInfo, ChecksumAlgo, DownloadError, DownloaderState, ProgressEvent, SpeedLimit,
+};
+
+/// The parts of a strategy under construction that [`CommonOptions`]
+/// configures.
+pub struct CommonParts<'a> {
+    pub(crate) state: &'a StdRwLock<DownloaderState>,
+    pub(crate) segment_options: &'a mut SegmentOptions,
+    pub(crate) system_proxy: &'a mut bool,
+    pub(crate) shared_limiter: &'a mut Option<SharedRateLimiter>,
+    pub(crate) create_parent: &'a mut bool,
+    pub(crate) keep_temp: &'a mut bool,
+    pub(crate) connections: &'a mut AtomicUsize,
+    pub(crate) connect_guard: &'a mut Option<Arc<dyn ConnectGuard>>,
+    pub(crate) ffmpeg: &'a mut Option<PathBuf>,
+}
+
+pub trait CommonOptions: Sized {
+    #[doc(hidden)]
+    fn common_parts(&mut self) -> CommonParts<'_>;
+
+    fn with_cookies(mut self, cookies: String) -> Self {
+        self.common_parts().state.write().unwrap().cookies = Some(cookies);
+        self
+    }
+
+    fn with_headers(mut self, headers: HashMap<String, Vec<String>>) -> Self {
+        self.common_parts().state.write().unwrap().headers = headers;
+        self
+    }
+
+    /// Set header `key`, replacing any value it already has — so
+    /// `add_header("User-Agent", ua)` never duplicates a captured one.
+    fn add_header<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.common_parts().state.write().unwrap().headers.insert(key.into(), vec![value.into()]);
+        self
+    }
+
+    fn with_authentication(mut self, auth: AuthenticationInfo) -> Self {
+        self.common_parts().state.write().unwrap().authentication = Some(auth);
+        self
+    }
+
+    /// Present the PEM certificate at `cert_path` with the key at
+    /// `key_path` to servers that require mutual TLS.
+    fn with_client_cert(mut self, cert_path: impl Into<String>, key_path: impl Into<String>) -> Self {
+        {
+            let parts = self.common_parts();
+            let mut state = parts.state.write().unwrap();
+            state.tls.client_cert = Some(cert_path.into());
+            state.tls.client_key = Some(key_path.into());
+        }
+        self
+    }
+
+    /// Trust the CA certificate(s) in the PEM file at `ca_path` besides the
+    /// system roots, e.g. an internal CA.
+    fn with_root_cert(mut self, ca_path: impl Into<String>) -> Self {
+        self.common_parts().state.write().unwrap().tls.root_cert = Some(ca_path.into());
+        self
+    }
+
+    /// User-Agent to send when the captured headers carry none (default
+    /// [`DEFAULT_USER_AGENT`](super::multipart_download_strategy::DEFAULT_USER_AGENT)).
+    fn with_user_agent(mut self, user_agent: String) -> Self {
+        self.common_parts().state.write().unwrap().user_agent = Some(user_agent);
+        self
+    }
+
+    /// Use `HTTP_PROXY`/`HTTPS_PROXY`/`NO_PROXY` when no explicit proxy is
+    /// set (default true). Pass false to always connect directly.
+    fn with_system_proxy(mut self, enabled: bool) -> Self {
+        *self.common_parts().system_proxy = enabled;
+        self
+    }
+
+    /// Retry a segment when no bytes arrive for `timeout` (default 20 s).
+    fn with_idle_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.common_parts().segment_options.idle_timeout = timeout;
+        self
+    }
+
+    /// Stop retrying once `total` retries have been spent across all
+    /// segments, so a server that is down fails fast instead of costing
+    /// every segment its own attempts. Each segment still has its own limit.
+    fn with_total_retry_budget(mut self, total: usize) -> Self {
+        self.common_parts().segment_options.retry_budget = Some(RetryBudget::new(total));
+        self
+    }
+
+    /// Refuse to download more than `limit` bytes: a known size over it
+    /// fails preprocess with `TooLarge` before anything is fetched, and a
+    /// download of unknown size (a DASH stream always is) fails as soon as
+    /// it crosses it.
+    fn with_max_file_size(mut self, limit: u64) -> Self {
+        self.common_parts().segment_options.size_cap = Some(SizeCap::new(limit));
+        self
+    }
+
+    /// Capacity of each segment's write buffer, in bytes (default 256 KB,
+    /// minimum 4 KB). Larger values mean fewer syscalls on fast disks.
+    fn with_write_buffer_size(mut self, size: usize) -> Self {
+        if size < MIN_WRITE_BUFFER_SIZE {
+            log::warn!(
+                "write buffer size {} is below the {} byte minimum; using the minimum",
+                size, MIN_WRITE_BUFFER_SIZE
+            );
+        }
+        self.common_parts().state.write().unwrap().write_buffer_size = size.max(MIN_WRITE_BUFFER_SIZE);
+        self
+    }
+
+    /// Cap the download speed, either in aggregate or per connection
+    /// (see [`SpeedLimit`]). Unlimited by default.
+    fn with_speed_limit(mut self, limit: SpeedLimit) -> Self {
+        self.common_parts().state.write().unwrap().speed_limit = Some(limit);
+        self
+    }
+
+    /// Draw from a speed cap shared with other downloads (clones of one
+    /// [`SharedRateLimiter`] share it), on top of any own limit set with
+    /// `with_speed_limit`.
+    fn with_shared_limiter(mut self, limiter: SharedRateLimiter) -> Self {
+        *self.common_parts().shared_limiter = Some(limiter);
+        self
+    }
+
+    /// Hash the output with `algo` during postprocess and report the hex
+    /// digest in `DownloadSummary::checksum`. The file itself is unaffected.
+    fn with_compute_checksum(mut self, algo: ChecksumAlgo) -> Self {
+        self.common_parts().state.write().unwrap().compute_checksum = Some(algo);
+        self
+    }
+
+    /// Compute `algo` (as [`with_compute_checksum`](Self::with_compute_checksum))
+    /// and compare it with `hex`; the outcome is `DownloadSummary::checksum_ok`.
+    fn with_expected_checksum(mut self, algo: ChecksumAlgo, hex: String) -> Self {
+        {
+            let parts = self.common_parts();
+            let mut state = parts.state.write().unwrap();
+            state.compute_checksum = Some(algo);
+            state.expected_checksum = Some(hex);
+        }
+        self
+    }
+
+    /// Store the finished file compressed: it is streamed through `codec`
+    /// into `<output>.gz` / `<output>.zst` and the uncompressed file is
+    /// removed. A requested checksum still covers the uncompressed bytes.
+    #[cfg(feature = "compression")]
+    fn with_store_compression(mut self, codec: Codec) -> Self {
+        self.common_parts().state.write().unwrap().store_compression = Some(codec);
+        self
+    }
+
+    /// Create the output file's directory if it is missing (default on).
+    /// When off, preprocess fails on a missing directory instead.
+    fn with_create_parent(mut self, create: bool) -> Self {
+        *self.common_parts().create_parent = create;
+        self
+    }
+
+    /// Keep the temp directory and per-segment files after the download
+    /// instead of deleting them, for inspecting a corrupt result. Also
+    /// enabled by setting `RDM_KEEP_TEMP`.
+    fn with_keep_temp(mut self, keep: bool) -> Self {
+        *self.common_parts().keep_temp = keep;
+        self
+    }
+
+    fn with_connection_size(mut self, connections: usize) -> Self {
+        *self.common_parts().connections.get_mut() = connections;
+        self
+    }
+
+    /// Check every host the download connects to with `guard` — the URL's,
+    /// each redirect's, mirrors', an audio or DASH segment URL — and fail
+    /// with `DownloadError::HostRefused` on one it refuses.
+    fn with_connect_guard(mut self, guard: Arc<dyn ConnectGuard>) -> Self {
+        *self.common_parts().connect_guard = Some(guard);
+        self
+    }
+
+    /// Mux a separate audio track with the ffmpeg binary at `path` instead
+    /// of `$RDM_FFMPEG` or `ffmpeg` on PATH.
+    fn with_ffmpeg(mut self, path: impl Into<PathBuf>) -> Self {
+        *self.common_parts().ffmpeg = Some(path.into());
+        self
+    }
+}
+
+/// Tells observers what preprocess is busy with; see
+/// [`ProgressEvent::phase`].
+pub(crate) fn report_phase(
+    progress_tx: &StdMutex<Option<mpsc::Sender<Result<ProgressEvent, String>>>>,
+    description: impl Into<String>,
+) {
+    if let Some(tx) = progress_tx.lock().unwrap().as_ref() {
+        let _ = tx.try_send(Ok(ProgressEvent::phase(description)));
+    }
+}
+
+/// `DownloadStrategy::set_request_headers` for a strategy that has
+/// `started` preprocess or not.
+pub(crate) fn set_request_headers(
+    started: bool,
+    state: &StdRwLock<DownloaderState>,
+    headers: HashMap<String, Vec<String>>,
+    cookies: Option<String>,
+) -> Result<(), DownloadError> {
+    if started {
+        return Err(DownloadError::InvalidState);
+    }
+    let mut state = state.write().unwrap();
+    state.headers = headers;
+    state.cookies = cookies;
+    Ok(())
+}
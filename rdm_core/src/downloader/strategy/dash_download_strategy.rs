@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock};
+
+use async_trait::async_trait;
+use reqwest::Client;
+use tokio::sync::{mpsc, RwLock, Semaphore};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::downloader::connect_guard::{check_url, ConnectGuard};
+use crate::downloader::dash_manifest::{parse_manifest, DashTrack};
+use crate::downloader::checksum::{hash_file, Checksum};
+use crate::downloader::muxer::mux_audio_video;
+use crate::downloader::pause_token::PauseToken;
+use crate::downloader::rate_limiter::SharedRateLimiter;
+use crate::downloader::segment_grabber::{download_segment_with_options, fetch_text, probe_url, SegmentOptions};
+use crate::downloader::strategy::common_options::{self, CommonOptions, CommonParts};
+use crate::downloader::strategy::download_strategy::DownloadStrategy;
+use crate::progress::snapshot::format_bytes;
+use crate::downloader::strategy::multipart_download_strategy::{
+    assemble_segments, build_clients, discard_partial_output, build_header_data, default_clients, ensure_output_dir,
+    keep_temp_from_env, store_compressed, TempDirGuard,
+};
+use crate::types::types::{
+    DownloadError, DownloaderState, HeaderData, PreprocessInfo, ProbeResult, ProgressEvent, Segment,
+    SegmentState, StreamType, HttpVersion,
+};
+
+/// Default number of media segments fetched concurrently.
+const MAX_CONNECTIONS: usize = 8;
+
+/// Downloads an MPEG-DASH stream described by an `.mpd` manifest.
+///
+/// `preprocess` fetches and parses the manifest, then creates one `Segment`
+/// per init/media URL. `Segment::offset` holds the segment's position in the
+/// track (init = 0) and `Segment::stream_type` tags the track: `Primary` for
+/// video, `Secondary` for a separate audio adaptation set. `postprocess`
+/// concatenates each track and, when both exist, muxes them with ffmpeg.
+pub struct DashDownloadStrategy {
+    state: Arc<StdRwLock<DownloaderState>>,
+    segments: Arc<RwLock<HashMap<String, Segment>>>,
+    /// Segment id → absolute media URL, filled by `preprocess`.
+    segment_urls: StdRwLock<HashMap<String, String>>,
+    client: Arc<Client>,
+    cancel_token: CancellationToken,
+    /// Holds segment tasks in place while paused; see `pause()`/`resume()`.
+    pause_token: PauseToken,
+    /// Set by `HttpDownloader` just before `download()` runs.
+    progress_tx: StdMutex<Option<mpsc::Sender<Result<ProgressEvent, String>>>>,
+    /// Upper bound on concurrent connections; see `set_connection_size()`.
+    connections: AtomicUsize,
+    /// Set once `preprocess()` begins; locks the output path and connection count.
+    started: AtomicBool,
+    segment_options: SegmentOptions,
+    /// Leave the temp directory and segment files in place after assembly.
+    keep_temp: bool,
+    /// Create a missing output directory in preprocess (otherwise fail there).
+    create_parent: bool,
+    /// Removes the temp directory if the strategy is dropped before a
+    /// successful postprocess; armed by preprocess unless `keep_temp` is set.
+    temp_guard: StdMutex<Option<TempDirGuard>>,
+    /// Cross-download speed cap; a share is held while `download()` runs.
+    shared_limiter: Option<SharedRateLimiter>,
+    /// Why the configured client could not be built; preprocess fails with it.
+    client_error: Option<String>,
+    /// Checks every host the download connects to; see `with_connect_guard`.
+    connect_guard: Option<Arc<dyn ConnectGuard>>,
+    /// ffmpeg binary for muxing; see `with_ffmpeg`.
+    ffmpeg: Option<PathBuf>,
+}
+
+pub struct DashDownloadStrategyBuilder {
+    strategy: DashDownloadStrategy,
+    system_proxy: bool,
+}
+
+impl DashDownloadStrategy {
+    pub fn new(manifest_url: String, output_path: PathBuf) -> Self {
+        let output_path_str = output_path.to_string_lossy().to_string();
+
+        let clients = default_clients();
+
+        Self {
+            state: Arc::new(StdRwLock::new(DownloaderState::new(manifest_url, Some(output_path_str)))),
+            segments: Arc::new(RwLock::new(HashMap::new())),
+            segment_urls: StdRwLock::new(HashMap::new()),
+            client: Arc::new(clients.pooled),
+            cancel_token: CancellationToken::new(),
+            pause_token: PauseToken::new(),
+            progress_tx: StdMutex::new(None),
+            connections: AtomicUsize::new(MAX_CONNECTIONS),
+            started: AtomicBool::new(false),
+            segment_options: SegmentOptions { reconnect_client: Some(clients.fresh), ..SegmentOptions::default() },
+            keep_temp: keep_temp_from_env(),
+            create_parent: true,
+            temp_guard: StdMutex::new(None),
+            shared_limiter: None,
+            client_error: None,
+            connect_guard: None,
+            ffmpeg: None,
+        }
+    }
+
+    pub fn builder(manifest_url: String, path: PathBuf) -> DashDownloadStrategyBuilder {
+        DashDownloadStrategyBuilder::new(manifest_url, path)
+    }
+
+    /// Returns a reference to the internal state lock (for testing/inspection).
+    pub fn state(&self) -> &Arc<StdRwLock<DownloaderState>> {
+        &self.state
+    }
+
+    /// Returns a reference to the internal segments lock (for testing/inspection).
+    pub fn segments(&self) -> &Arc<RwLock<HashMap<String, Segment>>> {
+        &self.segments
+    }
+
+    fn report_phase(&self, description: impl Into<String>) {
+        common_options::report_phase(&self.progress_tx, description);
+    }
+}
+
+/// Creates the segments for one track: the init segment (if any) at
+/// position 0 followed by the media segments at 1..=N.
+fn track_segments(track: &DashTrack, stream_type: StreamType) -> Vec<(Segment, String)> {
+    let urls = track
+        .init_url
+        .iter()
+        .chain(track.media_urls.iter())
+        .cloned();
+    let first = if track.init_url.is_some() { 0 } else { 1 };
+
+    urls.enumerate()
+        .map(|(i, url)| {
+            let mut segment = Segment::new(Uuid::new_v4().to_string(), first + i as i64, -1);
+            segment.stream_type = stream_type;
+            (segment, url)
+        })
+        .collect()
+}
+
+#[async_trait]
+impl DownloadStrategy for DashDownloadStrategy {
+    fn set_progress_tx(&self, tx: mpsc::Sender<Result<ProgressEvent, String>>) {
+        *self.progress_tx.lock().unwrap() = Some(tx);
+    }
+
+    fn clear_progress_tx(&self) {
+        *self.progress_tx.lock().unwrap() = None;
+    }
+
+    fn state_snapshot(&self) -> DownloaderState {
+        self.state.read().unwrap().clone()
+    }
+
+    async fn segments_snapshot(&self) -> Vec<Segment> {
+        self.segments.read().await.values().cloned().collect()
+    }
+
+    fn set_output_path(&self, output_path: String) -> Result<(), DownloadError> {
+        if self.started.load(Ordering::SeqCst) {
+            return Err(DownloadError::InvalidState);
+        }
+        self.state.write().unwrap().output_path = Some(output_path);
+        Ok(())
+    }
+
+    fn set_connection_size(&self, connections: usize) -> Result<(), DownloadError> {
+        if self.started.load(Ordering::SeqCst) {
+            return Err(DownloadError::InvalidState);
+        }
+        self.connections.store(connections.max(1), Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn set_request_headers(
+        &self,
+        headers: HashMap<String, Vec<String>>,
+        cookies: Option<String>,
+    ) -> Result<(), DownloadError> {
+        common_options::set_request_headers(self.started.load(Ordering::SeqCst), &self.state, headers, cookies)
+    }
+
+    /// Probes the manifest URL; its content type is the manifest's, not the
+    /// muxed output's.
+    async fn probe(&self) -> Result<ProbeResult, DownloadError> {
+        if let Some(e) = &self.client_error {
+            return Err(DownloadError::Tls(e.clone()));
+        }
+        let header_data = build_header_data(&self.state)?;
+        if let Some(guard) = &self.connect_guard {
+            check_url(guard.as_ref(), &header_data.url).map_err(|e| DownloadError::HostRefused(e.0))?;
+        }
+        probe_url(&self.client, &header_data).await
+    }
+
+    /// Fetches and parses the manifest, creates the temp directory, and
+    /// creates one segment per init/media URL of the selected tracks.
+    async fn preprocess(&self) -> Result<PreprocessInfo, DownloadError> {
+        self.started.store(true, Ordering::SeqCst);
+        if let Some(e) = &self.client_error {
+            return Err(DownloadError::Tls(e.clone()));
+        }
+        ensure_output_dir(&self.state, self.create_parent).await?;
+
+        let header_data = build_header_data(&self.state)?;
+        if let Some(guard) = &self.connect_guard {
+            check_url(guard.as_ref(), &header_data.url).map_err(|e| DownloadError::HostRefused(e.0))?;
+        }
+        self.report_phase("Fetching manifest…");
+        let (xml, final_uri) = fetch_text(&self.client, &header_data).await?;
+        self.report_phase(format!("Parsing manifest ({})…", format_bytes(xml.len() as u64)));
+        let manifest = parse_manifest(&xml, &final_uri)?;
+
+        let mut planned = Vec::new();
+        if let Some(video) = &manifest.video {
+            log::info!(
+                "[dash] video representation={} bandwidth={} segments={}",
+                video.representation_id, video.bandwidth, video.media_urls.len()
+            );
+            planned.extend(track_segments(video, StreamType::Primary));
+        }
+        if let Some(audio) = &manifest.audio {
+            // Audio-only manifests keep the audio as the primary stream.
+            let stream_type = if manifest.video.is_some() {
+                StreamType::Secondary
+            } else {
+                StreamType::Primary
+            };
+            log::info!(
+                "[dash] audio representation={} bandwidth={} segments={}",
+                audio.representation_id, audio.bandwidth, audio.media_urls.len()
+            );
+            planned.extend(track_segments(audio, stream_type));
+        }
+        // The manifest names the segment URLs; they may point anywhere.
+        if let Some(guard) = &self.connect_guard {
+            for (_, url) in &planned {
+                check_url(guard.as_ref(), url).map_err(|e| DownloadError::HostRefused(e.0))?;
+            }
+        }
+
+        let (temp_dir_path, content_type) = {
+            let mut s = self.state.write().unwrap();
+            s.content_type = manifest
+                .video
+                .as_ref()
+                .or(manifest.audio.as_ref())
+                .and_then(|t| t.mime_type.clone());
+            s.resumable = false;
+            (s.temp_dir.clone(), s.content_type.clone())
+        };
+
+        tokio::fs::create_dir_all(&temp_dir_path)
+            .await
+            .map_err(DownloadError::Disk)?;
+        if !self.keep_temp {
+            *self.temp_guard.lock().unwrap() = Some(TempDirGuard::new(&temp_dir_path));
+        }
+
+        let segment_count = planned.len();
+        self.report_phase(format!("Planning {} segment(s)…", segment_count));
+        let mut segments = self.segments.write().await;
+        segments.clear();
+        let mut urls = HashMap::with_capacity(planned.len());
+        for (segment, url) in planned {
+            urls.insert(segment.id.clone(), url);
+            segments.insert(segment.id.clone(), segment);
+        }
+        *self.segment_urls.write().unwrap() = urls;
+
+        // Fragment sizes are not known until they are fetched.
+        Ok(PreprocessInfo {
+            file_size: None,
+            resumable: false,
+            segment_count,
+            content_type,
+            attachment_name: None,
+        })
+    }
+
+    /// Downloads all media segments, at most `connections` at a time.
+    async fn download(&self) -> Result<(), DownloadError> {
+        let progress_tx = self.progress_tx.lock().unwrap().clone();
+        let base_header_data = build_header_data(&self.state)?;
+        let temp_dir = PathBuf::from(&self.state.read().unwrap().temp_dir);
+
+        let segments_to_download: Vec<Segment> = {
+            let segments_guard = self.segments.read().await;
+            segments_guard
+                .values()
+                .filter(|s| s.state == SegmentState::NotStarted)
+                .cloned()
+                .collect()
+        };
+
+        if segments_to_download.is_empty() {
+            return Ok(());
+        }
+
+        // The cap is on the whole stream, so what is already on disk counts;
+        // an unfinished unranged segment starts over and holds nothing.
+        if let Some(cap) = &self.segment_options.size_cap {
+            let held: u64 = self
+                .segments
+                .read()
+                .await
+                .values()
+                .filter(|s| s.length > 0 || s.state == SegmentState::Finished)
+                .map(|s| s.downloaded.max(0) as u64)
+                .sum();
+            cap.restart(held)?;
+        }
+
+        let limiter = Arc::new(Semaphore::new(self.connections.load(Ordering::SeqCst).max(1)));
+        // Read once here; each task gets its own copy.
+        let (write_buffer_size, speed_limit) = {
+            let state = self.state.read().unwrap();
+            (state.write_buffer_size, state.speed_limit)
+        };
+        // Held until every segment task below has finished.
+        let share = self.shared_limiter.as_ref().map(SharedRateLimiter::register);
+        let segment_options = SegmentOptions {
+            write_buffer_size,
+            ..self.segment_options.clone()
+        }
+        .with_share(share.as_ref())
+        .with_speed_limit(speed_limit);
+        let mut handles = Vec::with_capacity(segments_to_download.len());
+
+        for segment in segments_to_download {
+            let Some(url) = self.segment_urls.read().unwrap().get(&segment.id).cloned() else {
+                return Err(DownloadError::InvalidState);
+            };
+            let header_data = Arc::new(HeaderData {
+                url,
+                mirrors: Vec::new(),
+                ..base_header_data.clone()
+            });
+            let client = Arc::clone(&self.client);
+            let temp_dir = temp_dir.clone();
+            let cancel_token = self.cancel_token.clone();
+            let pause_token = self.pause_token.clone();
+            let segment_options = segment_options.for_segment(speed_limit);
+            let limiter = Arc::clone(&limiter);
+            let segment_tx = progress_tx.clone();
+            let segment_id_for_progress = segment.id.clone();
+            let segment_id_for_handle = segment.id.clone();
+
+            let handle = tokio::spawn(async move {
+                let _permit = limiter
+                    .acquire_owned()
+                    .await
+                    .map_err(|e| DownloadError::SegmentFailed(e.to_string()))?;
+                download_segment_with_options(
+                    segment,
+                    &client,
+                    &header_data,
+                    temp_dir,
+                    cancel_token,
+                    pause_token,
+                    segment_options,
+                    |bytes_delta| {
+                        if let Some(tx) = &segment_tx {
+                            let _ = tx.try_send(Ok(ProgressEvent {
+                                segment_id: segment_id_for_progress.clone(),
+                                bytes_delta,
+                                total_bytes: None,
+                                retry: false,
+                                warning: None,
+                                phase: None,
+                            }));
+                        }
+                    },
+                    |_retries| {
+                        if let Some(tx) = &segment_tx {
+                            let _ = tx.try_send(Ok(ProgressEvent {
+                                segment_id: segment_id_for_progress.clone(),
+                                bytes_delta: 0,
+                                total_bytes: None,
+                                retry: true,
+                                warning: None,
+                                phase: None,
+                            }));
+                        }
+                    },
+                )
+                .await
+            });
+            handles.push((segment_id_for_handle, handle));
+        }
+
+        let results: Vec<_> = futures::future::join_all(
+            handles.into_iter().map(|(id, handle)| async move { (id, handle.await) }),
+        )
+        .await;
+
+        let mut segments_guard = self.segments.write().await;
+        let mut first_error: Option<DownloadError> = None;
+
+        for (segment_id, result) in results {
+            match result {
+                Ok(Ok(updated_segment)) => {
+                    segments_guard.insert(segment_id, updated_segment);
+                }
+                Ok(Err(e)) => {
+                    if let Some(s) = segments_guard.get_mut(&segment_id) {
+                        s.state = SegmentState::Failed;
+                    }
+                    first_error.get_or_insert(e);
+                }
+                Err(join_err) => {
+                    if let Some(s) = segments_guard.get_mut(&segment_id) {
+                        s.state = SegmentState::Failed;
+                    }
+                    first_error.get_or_insert(DownloadError::SegmentFailed(join_err.to_string()));
+                }
+            }
+        }
+
+        drop(segments_guard);
+
+        if let Some(e) = first_error {
+            if let Some(tx) = &progress_tx {
+                let _ = tx.try_send(Err(e.to_string()));
+            }
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    async fn pause(&self) -> Result<(), DownloadError> {
+        // Cancel the token to stop all in-flight downloads.
+        // On resume, a new token would be created and incomplete segments restarted.
+        self.cancel_token.cancel();
+        Ok(())
+    }
+
+    /// Holds every segment task until `resume()`; nothing is cancelled.
+    async fn hold(&self) -> Result<(), DownloadError> {
+        self.pause_token.pause();
+        Ok(())
+    }
+
+    async fn resume(&self) -> Result<(), DownloadError> {
+        self.pause_token.resume();
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<(), DownloadError> {
+        self.cancel_token.cancel();
+        Ok(())
+    }
+
+    /// Concatenates each track's segments in order. With a single track the
+    /// result is written straight to the output path; with separate audio
+    /// and video the two tracks are muxed into the output via ffmpeg.
+    async fn postprocess(&self) -> Result<(), DownloadError> {
+        let (primary, secondary, temp_dir, output_file, compute_checksum, store_compression) = {
+            let segments = self.segments.read().await;
+            let state = self.state.read().unwrap();
+
+            for segment in segments.values() {
+                if segment.state != SegmentState::Finished {
+                    return Err(DownloadError::SegmentFailed(format!(
+                        "segment {} is in state {:?}, expected Finished",
+                        segment.id, segment.state
+                    )));
+                }
+            }
+
+            let ordered_ids = |stream_type: StreamType| -> Vec<String> {
+                let mut track: Vec<_> = segments
+                    .values()
+                    .filter(|s| s.stream_type == stream_type)
+                    .collect();
+                track.sort_by_key(|s| s.offset);
+                track.iter().map(|s| s.id.clone()).collect()
+            };
+
+            let output_file = state
+                .output_path
+                .clone()
+                .unwrap_or_else(|| "download.mp4".to_string());
+
+            (
+                ordered_ids(StreamType::Primary),
+                ordered_ids(StreamType::Secondary),
+                PathBuf::from(&state.temp_dir),
+                PathBuf::from(output_file),
+                state.compute_checksum,
+                state.store_compression,
+            )
+        };
+
+        self.state.write().unwrap().output_path = Some(output_file.to_string_lossy().to_string());
+
+        let keep_temp = self.keep_temp;
+        let ffmpeg = self.ffmpeg.clone();
+        let cancel = self.cancel_token.clone();
+
+        let (output_file, checksum, compressed_size) = tokio::task::spawn_blocking(move || {
+            let assemble = || -> Result<Option<String>, DownloadError> {
+                Ok(if secondary.is_empty() {
+                    let mut checksum = compute_checksum.map(Checksum::new);
+                    let bytes = assemble_segments(&temp_dir, &primary, &output_file, false, checksum.as_mut(), &cancel)?;
+                    log::info!("[dash] assembled {} bytes into {:?}", bytes, output_file);
+                    checksum.map(Checksum::finalize_hex)
+                } else {
+                    let video_path = temp_dir.join("video.track");
+                    let audio_path = temp_dir.join("audio.track");
+                    assemble_segments(&temp_dir, &primary, &video_path, false, None, &cancel)?;
+                    assemble_segments(&temp_dir, &secondary, &audio_path, false, None, &cancel)?;
+                    mux_audio_video(ffmpeg.as_deref(), &video_path, &audio_path, &output_file)?;
+                    log::info!("[dash] muxed audio + video into {:?}", output_file);
+                    compute_checksum.map(|algo| hash_file(&output_file, algo)).transpose()?
+                })
+            };
+            let checksum = match assemble() {
+                Err(_) if cancel.is_cancelled() => {
+                    discard_partial_output(&output_file, None);
+                    return Err(DownloadError::Cancelled);
+                }
+                result => result?,
+            };
+
+            let (output_file, compressed_size) =
+                store_compressed(output_file.to_string_lossy().to_string(), store_compression)?;
+
+            if keep_temp {
+                log::info!("[dash] keeping temp files in {}", temp_dir.display());
+            } else {
+                let _ = std::fs::remove_dir_all(&temp_dir);
+            }
+            Ok::<_, DownloadError>((output_file, checksum, compressed_size))
+        })
+        .await
+        .map_err(|e| DownloadError::SegmentFailed(e.to_string()))??;
+
+        if let Some(guard) = self.temp_guard.lock().unwrap().take() {
+            guard.disarm();
+        }
+        let mut state = self.state.write().unwrap();
+        state.output_path = Some(output_file);
+        state.checksum = checksum;
+        state.compressed_size = compressed_size;
+        Ok(())
+    }
+}
+
+impl CommonOptions for DashDownloadStrategyBuilder {
+    fn common_parts(&mut self) -> CommonParts<'_> {
+        CommonParts {
+            state: &self.strategy.state,
+            segment_options: &mut self.strategy.segment_options,
+            system_proxy: &mut self.system_proxy,
+            shared_limiter: &mut self.strategy.shared_limiter,
+            create_parent: &mut self.strategy.create_parent,
+            keep_temp: &mut self.strategy.keep_temp,
+            connections: &mut self.strategy.connections,
+            connect_guard: &mut self.strategy.connect_guard,
+            ffmpeg: &mut self.strategy.ffmpeg,
+        }
+    }
+}
+
+impl DashDownloadStrategyBuilder {
+    pub fn new(manifest_url: String, path: PathBuf) -> Self {
+        Self {
+            strategy: DashDownloadStrategy::new(manifest_url, path),
+            system_proxy: true,
+        }
+    }
+
+    /// A client certificate or key that can't be loaded is reported by
+    /// `preprocess` as `DownloadError::Tls`.
+    pub fn build(mut self) -> DashDownloadStrategy {
+        let tls = self.strategy.state.read().unwrap().tls.clone();
+        if !self.system_proxy || !tls.is_empty() || self.strategy.connect_guard.is_some() {
+            let guard = self.strategy.connect_guard.as_ref();
+            match build_clients(None, self.system_proxy, &tls, HttpVersion::Auto, false, guard) {
+                Ok(clients) => {
+                    self.strategy.client = Arc::new(clients.pooled);
+                    self.strategy.segment_options.reconnect_client = Some(clients.fresh);
+                }
+                Err(e) => self.strategy.client_error = Some(e),
+            }
+        }
+        self.strategy
+    }
+}
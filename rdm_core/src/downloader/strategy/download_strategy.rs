@@ -1,6 +1,8 @@
+use std::collections::HashMap;
+
 use tokio::sync::mpsc;
 
-use crate::types::types::{DownloadError, ProgressEvent};
+use crate::types::types::{DownloadError, DownloaderState, PreprocessInfo, ProbeResult, ProgressEvent, Segment};
 use async_trait::async_trait;
 
 #[async_trait]
@@ -12,9 +14,73 @@ pub trait DownloadStrategy: Send + Sync {
     /// Drop the progress sender so the notifier channel closes after download.
     fn clear_progress_tx(&self);
 
-    async fn preprocess(&self) -> Result<(), DownloadError>;
+    /// Change where the finished file is written. Only allowed before
+    /// `preprocess()` has run; afterwards returns `DownloadError::InvalidState`.
+    fn set_output_path(&self, output_path: String) -> Result<(), DownloadError>;
+
+    /// Change how many connections the download may use (at least 1). Same
+    /// rule as `set_output_path`: segments are planned in `preprocess()`, so
+    /// afterwards this returns `DownloadError::InvalidState`.
+    fn set_connection_size(&self, connections: usize) -> Result<(), DownloadError>;
+
+    /// Replace the headers and cookies sent with every request, e.g. when
+    /// the captured ones went stale while the download waited. Same rule as
+    /// `set_output_path`.
+    fn set_request_headers(
+        &self,
+        headers: HashMap<String, Vec<String>>,
+        cookies: Option<String>,
+    ) -> Result<(), DownloadError>;
+
+    /// Copy of the current download state (URL, output path, resumability, …).
+    fn state_snapshot(&self) -> DownloaderState;
+
+    /// Copy of the current segment list.
+    async fn segments_snapshot(&self) -> Vec<Segment>;
+
+    /// Bytes the current segment plan still has to fetch. `None` before
+    /// `preprocess()` has planned the segments and while any segment is an
+    /// unranged stream of unknown length. Together with the throughput this
+    /// gives the time left; see `ProgressSnapshot::eta_secs`.
+    async fn estimated_remaining(&self) -> Option<u64> {
+        let segments = self.segments_snapshot().await;
+        if segments.is_empty() || segments.iter().any(|s| s.length < 0) {
+            return None;
+        }
+        Some(segments.iter().map(|s| (s.length - s.downloaded).max(0) as u64).sum())
+    }
+
+    /// Ask the server about the source without downloading it, sending what
+    /// the download would (headers, cookies, credentials) through the same
+    /// client (proxy, TLS, HTTP version). Lets a caller name the output, with
+    /// `set_output_path`, before `preprocess()`.
+    async fn probe(&self) -> Result<ProbeResult, DownloadError>;
+
+    /// Probe the source and plan the segments; returns what was learned.
+    async fn preprocess(&self) -> Result<PreprocessInfo, DownloadError>;
     async fn download(&self) -> Result<(), DownloadError>;
     async fn pause(&self) -> Result<(), DownloadError>;
+    /// Hold the download in place until `resume()`; unlike `pause()`,
+    /// nothing is cancelled.
+    async fn hold(&self) -> Result<(), DownloadError>;
+    async fn resume(&self) -> Result<(), DownloadError>;
     async fn stop(&self) -> Result<(), DownloadError>;
+
+    /// Stop the running task of segment `id` alone, leaving the rest of the
+    /// download running. The segment keeps what it wrote; `download()` lets
+    /// the others finish and then fails with `DownloadError::Cancelled`, and
+    /// the next `download()` fetches the segment's remainder. Returns `false`
+    /// when no such segment is running or its task could not pick up again
+    /// where it stopped; strategies without per-segment tasks always return
+    /// `false`.
+    fn cancel_segment(&self, _id: &str) -> bool {
+        false
+    }
+
+    /// The download is given up: remove its temp files now, unless it keeps
+    /// them. A resumable download otherwise leaves them for a later run to
+    /// restore; strategies that always clean up when dropped need not
+    /// override this.
+    fn discard_temp_files(&self) {}
     async fn postprocess(&self) -> Result<(), DownloadError>;
 }
@@ -1,2 +1,4 @@
+pub mod common_options;
 pub mod download_strategy;
 pub mod multipart_download_strategy;
+pub mod dash_download_strategy;
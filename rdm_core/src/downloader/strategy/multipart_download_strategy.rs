@@ -1,16 +1,29 @@
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use reqwest::Client;
 use tokio::sync::{mpsc, RwLock};
 use tokio_util::sync::CancellationToken;
-use uuid::Uuid;
 
-use crate::downloader::segment_grabber::{download_segment, probe_url};
+use crate::downloader::connect_guard::{check_url, ConnectGuard, GuardedResolver};
+use crate::downloader::checksum::{algo_for_digest, hash_file, parse_checksum_file, Checksum, HashingWriter};
+use crate::downloader::muxer::mux_audio_video;
+use crate::downloader::cache::{self, CacheRecord};
+use crate::downloader::naming::{ext_from_mime, sanitise_component};
+use crate::downloader::pause_token::PauseToken;
+use crate::downloader::rate_limiter::SharedRateLimiter;
+use crate::downloader::segment_grabber::{
+    check_content_type, download_segment_with_options, fetch_text, is_cross_host, merge_cookies, probe_url, probe_url_with_filename_headers,
+    percent_decode, segment_state_path, strip_credentials, FlushInterval, SegmentRecord,
+    ReportedTotal, SegmentOptions, SizeCap, DEFAULT_FILENAME_HEADERS,
+};
+use crate::downloader::strategy::common_options::{self, CommonOptions, CommonParts};
 use crate::downloader::strategy::download_strategy::DownloadStrategy;
-use crate::types::types::{AuthenticationInfo, DownloadError, DownloaderState, HeaderData, Segment, ProgressEvent, ProxyInfo, SegmentState};
+use crate::types::types::{DownloadError, DownloaderState, HeaderData, PreprocessInfo, ProbeResult, Segment, ProgressEvent, ProbeHints, ProxyInfo, SegmentState, TlsFiles, StreamType, ChecksumAlgo, Codec, HttpVersion, DefaultAccept, OutputTarget};
 
 /// Default maximum number of concurrent download connections.
 const MAX_CONNECTIONS: usize = 8;
@@ -18,58 +31,172 @@ const MAX_CONNECTIONS: usize = 8;
 /// Minimum segment size in bytes (256 KB). Segments won't be split below this.
 const MIN_SEGMENT_SIZE: i64 = 256 * 1024;
 
+/// Default size each segment aims for (1 MB); a file gets one segment per
+/// this many bytes, up to the connection count.
+pub const DEFAULT_TARGET_SEGMENT_SIZE: u64 = 1024 * 1024;
+
+/// Default size below which a file is fetched as one segment (4 MB); the
+/// connection setup and assembly of a split cost more than they save there.
+pub const DEFAULT_MULTIPART_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+/// How long a segment may crawl before it is re-split; see
+/// `MultipartDownloadStrategyBuilder::with_slow_segment_window`.
+pub const DEFAULT_SLOW_SEGMENT_WINDOW: Duration = Duration::from_secs(15);
+
+/// How often running segments' throughput is compared.
+const SLOW_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A segment is slow while its speed is below this fraction of the median
+/// speed of the other running segments.
+const SLOW_SEGMENT_RATIO: f64 = 0.2;
+
 pub struct MultipartDownloadStrategy {
     state: Arc<StdRwLock<DownloaderState>>,
     segments: Arc<RwLock<HashMap<String, Segment>>>,
     client: Arc<Client>,
     cancel_token: CancellationToken,
+    /// Tokens of the running ranged segment tasks, children of
+    /// `cancel_token`; see `cancel_segment()`.
+    segment_tokens: StdMutex<HashMap<String, CancellationToken>>,
+    /// Holds segment tasks in place while paused; see `pause()`/`resume()`.
+    pause_token: PauseToken,
     /// Set by `HttpDownloader` just before `download()` runs.
     /// `None` while no progress consumer is attached (events are silently dropped).
     progress_tx: StdMutex<Option<mpsc::Sender<Result<ProgressEvent, String>>>>,
-    connections: usize,
+    /// Upper bound on concurrent connections; see `set_connection_size()`.
+    connections: AtomicUsize,
+    /// Set once `preprocess()` begins; locks the output path and connection count.
+    started: AtomicBool,
+    /// Set once `download()` begins; locks the segment plan.
+    downloading: AtomicBool,
+    segment_options: SegmentOptions,
+    /// Leave the temp directory and segment files in place after assembly.
+    keep_temp: bool,
+    /// Resume into an existing partial output file instead of replacing it.
+    continue_partial: bool,
+    /// Bytes of the existing output file kept by `continue_partial`; the
+    /// assembled segments are appended after them.
+    existing_bytes: AtomicU64,
+    /// Append an extension sniffed from the file's magic bytes when the
+    /// output name still has none after postprocess.
+    sniff_extension: bool,
+    /// Give the output file the server's `Last-Modified` as its mtime.
+    preserve_mtime: bool,
+    /// Segments copied into the output at once by postprocess.
+    assembly_threads: usize,
+    /// Keep the output's validators in a sidecar and probe conditionally
+    /// against them; see `with_conditional_cache`.
+    conditional_cache: bool,
+    /// Validators from this run's probe, written to the sidecar on success.
+    validators: StdMutex<Option<CacheRecord>>,
+    /// Create a missing output directory in preprocess (otherwise fail there).
+    create_parent: bool,
+    /// Bytes per segment the split aims for; see `create_segments()`.
+    target_segment_size: u64,
+    /// Downloads of fewer bytes than this are never split.
+    multipart_threshold: u64,
+    /// Known facts about the resource that let preprocess skip the probe.
+    probe_hints: Option<ProbeHints>,
+    /// Set when preprocess took `probe_hints` instead of probing; segments
+    /// then check the size they are served against the hinted one.
+    used_hints: AtomicBool,
+    /// Download in one stream without ranges even when the server claims
+    /// range support.
+    force_single_stream: bool,
+    /// Fetch only these bytes (inclusive) of the resource, as one
+    /// non-resumable segment; see `with_byte_range`.
+    byte_range: Option<(u64, u64)>,
+    /// Where postprocess writes the assembled download.
+    output_target: OutputTarget,
+    /// Content-Type prefix the download should have; an HTML answer fails
+    /// preprocess. See `with_expect_content_type`.
+    expect_content_type: Option<String>,
+    /// Removes the temp directory if the strategy is dropped before a
+    /// successful postprocess; armed by preprocess unless `keep_temp` is set
+    /// or the download is resumable, whose segments a later run may restore.
+    temp_guard: StdMutex<Option<TempDirGuard>>,
+    /// Cross-download speed cap; a share is held while `download()` runs.
+    shared_limiter: Option<SharedRateLimiter>,
+    /// Response headers to take the file name from when the probe has no
+    /// `Content-Disposition`; see `with_filename_headers`.
+    filename_headers: Vec<String>,
+    /// How long a ranged segment may stay far slower than the others before
+    /// its remaining range moves to a new connection; `None` never re-splits.
+    slow_segment_window: Option<Duration>,
+    /// One connection at a time; see `with_sequential`.
+    sequential: bool,
+    /// Why the configured client could not be built; preprocess fails with it.
+    client_error: Option<String>,
+    /// Set by `restore_segments`: preprocess keeps the installed segment map
+    /// instead of creating one.
+    restored: AtomicBool,
+    /// Keep sending credentials and cookies after the probe is redirected to
+    /// another host; see `with_forward_auth_on_redirect`.
+    forward_auth_on_redirect: bool,
+    /// Checks every host the download connects to; see `with_connect_guard`.
+    connect_guard: Option<Arc<dyn ConnectGuard>>,
+    /// ffmpeg binary for muxing; see `with_ffmpeg`.
+    ffmpeg: Option<PathBuf>,
 }
 pub struct MultipartDownloadStrategyBuilder {
     strategy: MultipartDownloadStrategy,
+    system_proxy: bool,
+    http_version: HttpVersion,
 }
 
 impl MultipartDownloadStrategy {
+    /// A download of `url` to `output_path`. When `output_path` is empty or
+    /// an existing directory, postprocess names the file after the
+    /// attachment name or the URL, with an extension from the content type,
+    /// and numbers it (`clip_2.mp4`) rather than overwrite an existing file.
     pub fn new(url: String, output_path: PathBuf) -> Self {
-        let id = Uuid::new_v4().to_string();
-        let temp_dir = std::env::temp_dir().join(&id);
         let output_path_str = output_path.to_string_lossy().to_string();
 
+        let clients = default_clients();
+
         Self {
-            state: Arc::new(StdRwLock::new(DownloaderState {
-                id,
-                url,
-                output_path: Some(output_path_str),
-                temp_dir: temp_dir.to_string_lossy().to_string(),
-                file_size: -1,
-                headers: HashMap::new(),
-                cookies: None,
-                authentication: None,
-                proxy: None,
-                convert_to_mp3: false,
-                last_modified: None,
-                resumable: false,
-                attachment_name: None,
-                content_type: None,
-            })),
+            state: Arc::new(StdRwLock::new(DownloaderState::new(url, Some(output_path_str)))),
             segments: Arc::new(RwLock::new(HashMap::new())),
-            client: Arc::new(
-                Client::builder()
-                    .connect_timeout(std::time::Duration::from_secs(10))
-                    .pool_max_idle_per_host(MAX_CONNECTIONS)
-                    .tcp_nodelay(true)
-                    .no_gzip()
-                    .no_deflate()
-                    .no_brotli()
-                    .build()
-                    .expect("failed to build HTTP client"),
-            ),
+            client: Arc::new(clients.pooled),
             cancel_token: CancellationToken::new(),
+            segment_tokens: StdMutex::new(HashMap::new()),
+            pause_token: PauseToken::new(),
             progress_tx: StdMutex::new(None),
-            connections: MAX_CONNECTIONS,
+            connections: AtomicUsize::new(MAX_CONNECTIONS),
+            started: AtomicBool::new(false),
+            downloading: AtomicBool::new(false),
+            segment_options: SegmentOptions {
+                restart_on_ignored_range: true,
+                reconnect_client: Some(clients.fresh),
+                ..SegmentOptions::default()
+            },
+            keep_temp: keep_temp_from_env(),
+            continue_partial: false,
+            existing_bytes: AtomicU64::new(0),
+            sniff_extension: false,
+            preserve_mtime: false,
+            assembly_threads: DEFAULT_ASSEMBLY_THREADS,
+            conditional_cache: false,
+            validators: StdMutex::new(None),
+            create_parent: true,
+            target_segment_size: DEFAULT_TARGET_SEGMENT_SIZE,
+            multipart_threshold: DEFAULT_MULTIPART_THRESHOLD,
+            probe_hints: None,
+            used_hints: AtomicBool::new(false),
+            force_single_stream: false,
+            byte_range: None,
+            output_target: OutputTarget::File,
+            expect_content_type: None,
+            temp_guard: StdMutex::new(None),
+            shared_limiter: None,
+            filename_headers: DEFAULT_FILENAME_HEADERS.iter().map(|h| h.to_string()).collect(),
+            slow_segment_window: Some(DEFAULT_SLOW_SEGMENT_WINDOW),
+            sequential: false,
+            client_error: None,
+            restored: AtomicBool::new(false),
+            forward_auth_on_redirect: false,
+            connect_guard: None,
+            ffmpeg: None,
         }
     }
 
@@ -77,200 +204,269 @@ impl MultipartDownloadStrategy {
         MultipartDownloadStrategyBuilder::new(url,path)
     }
 
-    /// Returns the temp directory path from the current state, if available.
-    pub async fn temp_dir(&self) -> String {
-        let state = self.state.read().unwrap();
-        state.temp_dir.clone()
+    /// Segments for `size` bytes of a resumable download: one below
+    /// `multipart_threshold`, otherwise `create_segments`' split.
+    fn plan_segments(&self, size: u64, connections: usize) -> Vec<Segment> {
+        if size < self.multipart_threshold {
+            log::info!(
+                "[preprocess] {} bytes is below the multipart threshold of {}; using a single segment",
+                size, self.multipart_threshold
+            );
+            return create_segments(size, 1, self.target_segment_size);
+        }
+        create_segments(size, connections, self.target_segment_size)
     }
 
-    /// Returns a reference to the internal state lock (for testing/inspection).
-    pub fn state(&self) -> &Arc<StdRwLock<DownloaderState>> {
-        &self.state
+    /// Length of the existing output file to resume into, or 0 to start over.
+    ///
+    /// Only honoured for a resumable, known-size download without a separate
+    /// audio track, and only when the partial is no larger than the remote file.
+    fn existing_partial_len(&self, resumable: bool, resource_size: Option<u64>) -> u64 {
+        if !self.continue_partial || self.output_target == OutputTarget::Stdout {
+            return 0;
+        }
+        let (output_path, has_audio) = {
+            let s = self.state.read().unwrap();
+            (s.output_path.clone(), s.audio_url.is_some())
+        };
+        let existing = output_path
+            .and_then(|p| std::fs::metadata(p).ok())
+            .filter(|m| m.is_file())
+            .map(|m| m.len())
+            .unwrap_or(0);
+        if existing == 0 {
+            return 0;
+        }
+        match resource_size {
+            Some(size) if resumable && !has_audio && existing <= size => existing,
+            _ => {
+                log::warn!(
+                    "[preprocess] cannot continue partial of {} bytes (resumable={}, size={:?}); restarting",
+                    existing, resumable, resource_size
+                );
+                0
+            }
+        }
     }
 
-    /// Returns a reference to the internal segments lock (for testing/inspection).
-    pub fn segments(&self) -> &Arc<RwLock<HashMap<String, Segment>>> {
-        &self.segments
+    /// The file an earlier download of `url` was saved as for this output,
+    /// with its cache record, when `conditional_cache` is on and the record
+    /// still matches the file on disk (see [`cache::find`]).
+    fn cached_record(&self, url: &str) -> Option<(PathBuf, CacheRecord)> {
+        if !self.conditional_cache || self.output_target == OutputTarget::Stdout {
+            return None;
+        }
+        let output = self.state.read().unwrap().output_path.clone()?;
+        cache::find(Path::new(&output), url)
     }
 
-    /// Returns a reference to the cancellation token.
-    pub fn cancel_token(&self) -> &CancellationToken {
-        &self.cancel_token
+    fn report_phase(&self, description: impl Into<String>) {
+        common_options::report_phase(&self.progress_tx, description);
     }
-}
-
-/// Creates download segments using XDM-style dynamic halving.
-///
-/// Starts with a single segment covering the entire file, then repeatedly
-/// splits the largest segment in half until we reach `max_connections` segments
-/// or every segment is at the minimum size.
-fn create_segments(file_size: u64, max_connections: usize) -> Vec<Segment> {
-    log::info!(
-        "[create_segments] file_size={}, max_connections={}",
-        file_size,
-        max_connections
-    );
-
-    // Start with one segment covering the whole file
-    let mut segments = vec![Segment::new(
-        Uuid::new_v4().to_string(),
-        0,
-        file_size as i64,
-    )];
-
-    // Repeatedly halve the largest segment
-    while segments.len() < max_connections {
-        // Find the segment with the most bytes
-        let max_idx = segments
-            .iter()
-            .enumerate()
-            .max_by_key(|(_, s)| s.length)
-            .map(|(i, _)| i)
-            .unwrap();
 
-        let segment = &segments[max_idx];
-
-        // Don't split if it would produce segments below minimum size
-        if segment.length < MIN_SEGMENT_SIZE * 2 {
-            log::debug!(
-                "[create_segments] stopping split: largest segment length={} < MIN_SEGMENT_SIZE*2={}",
-                segment.length,
-                MIN_SEGMENT_SIZE * 2
+    /// Fetches the `checksum_url` file, if one is set, and has postprocess
+    /// hash the output with the algorithm of its digest. Returns the digest
+    /// the output must match. A checksum the caller set explicitly wins: with
+    /// an expected digest the file is not fetched, and a published digest of
+    /// another algorithm than the one asked for is not checked.
+    async fn fetch_published_checksum(&self) -> Result<Option<String>, DownloadError> {
+        let (checksum_url, file_name, requested) = {
+            let s = self.state.read().unwrap();
+            let Some(checksum_url) = s.checksum_url.clone() else { return Ok(None) };
+            if s.expected_checksum.is_some() {
+                log::info!("[postprocess] an expected checksum is set; not fetching {}", checksum_url);
+                return Ok(None);
+            }
+            let file_name = reqwest::Url::parse(&s.url)
+                .ok()
+                .and_then(|u| u.path_segments()?.next_back().map(str::to_string))
+                .unwrap_or_default();
+            (checksum_url, file_name, s.compute_checksum)
+        };
+        let header_data = HeaderData {
+            url: checksum_url.clone(),
+            mirrors: Vec::new(),
+            method: None,
+            body: None,
+            ..build_header_data(&self.state)?
+        };
+        let (text, _) = fetch_text(&self.client, &header_data)
+            .await
+            .map_err(|e| DownloadError::ChecksumFile(format!("could not fetch {}: {}", checksum_url, e)))?;
+        let hex = parse_checksum_file(&text, &file_name)
+            .ok_or_else(|| DownloadError::ChecksumFile(format!("no digest for {:?} in {}", file_name, checksum_url)))?;
+        let algo = algo_for_digest(&hex).ok_or_else(|| {
+            DownloadError::ChecksumFile(format!(
+                "{} has a {}-digit digest; only md5, sha256 and sha512 are supported",
+                checksum_url,
+                hex.len()
+            ))
+        })?;
+        if let Some(requested) = requested.filter(|&requested| requested != algo) {
+            log::warn!(
+                "[postprocess] {} publishes a {} digest but {} was asked for; not checking it",
+                checksum_url,
+                algo,
+                requested
             );
-            break;
+            return Ok(None);
         }
-
-        let half = segment.length / 2;
-        let new_offset = segment.offset + half;
-        let new_length = segment.length - half;
-
-        log::debug!(
-            "[create_segments] splitting segment[{}]: offset={}, length={} -> half={}, new_offset={}, new_length={}",
-            max_idx, segment.offset, segment.length, half, new_offset, new_length
-        );
-
-        // Shrink the original segment
-        segments[max_idx].length = half;
-
-        // Create the new segment for the second half
-        segments.push(Segment::new(
-            Uuid::new_v4().to_string(),
-            new_offset,
-            new_length,
-        ));
+        log::info!("[postprocess] expecting {} {} from {}", algo, hex, checksum_url);
+        let mut s = self.state.write().unwrap();
+        s.compute_checksum = Some(algo);
+        s.expected_checksum = Some(hex.clone());
+        Ok(Some(hex))
     }
 
-    // Log final segments summary
-    let total: i64 = segments.iter().map(|s| s.length).sum();
-    log::info!(
-        "[create_segments] created {} segments, total_bytes={}, file_size={}",
-        segments.len(),
-        total,
-        file_size
-    );
-    for (i, s) in segments.iter().enumerate() {
-        log::debug!(
-            "[create_segments]   segment[{}]: offset={}, length={}, end={}",
-            i, s.offset, s.length, s.offset + s.length - 1
-        );
+    /// Installs the segment map of an earlier, interrupted run so the
+    /// download resumes it: preprocess still probes, but keeps these
+    /// segments instead of splitting the file again, and `download()`
+    /// fetches only what is unfinished. A segment with `downloaded > 0`
+    /// continues from `offset + downloaded`; its temp file must already be
+    /// in the temp directory.
+    ///
+    /// The map is dropped (and the file split afresh) if the probe finds
+    /// the download is no longer resumable or the map does not cover the
+    /// probed size exactly.
+    pub async fn restore_segments(&self, segments: Vec<Segment>) -> Result<(), DownloadError> {
+        if self.started.load(Ordering::SeqCst) {
+            return Err(DownloadError::InvalidState);
+        }
+        let mut map = self.segments.write().await;
+        map.clear();
+        map.extend(segments.into_iter().map(|s| (s.id.clone(), s)));
+        self.restored.store(!map.is_empty(), Ordering::SeqCst);
+        Ok(())
     }
 
-    segments
-}
-
-/// Extracts HeaderData from the current DownloaderState.
-/// Acquires the read lock once and copies all needed fields.
-fn build_header_data(
-    state: &Arc<StdRwLock<DownloaderState>>,
-) -> Result<HeaderData, DownloadError> {
-    let s = state.read().unwrap();
-    Ok(HeaderData {
-        url: s.url.clone(),
-        headers: s.headers.clone(),
-        cookies: s.cookies.clone(),
-        authentication: s.authentication.clone(),
-        proxy: s.proxy.clone(),
-    })
-}
-
-#[async_trait]
-impl DownloadStrategy for MultipartDownloadStrategy {
-    fn set_progress_tx(&self, tx: mpsc::Sender<Result<ProgressEvent, String>>) {
-        *self.progress_tx.lock().unwrap() = Some(tx);
+    /// The primary stream's segments as `(offset, length)`, in file order.
+    /// Between `preprocess` and `download` this is the plan about to run.
+    pub async fn segment_plan(&self) -> Vec<(i64, i64)> {
+        let mut plan: Vec<(i64, i64)> = self
+            .segments
+            .read()
+            .await
+            .values()
+            .filter(|s| s.stream_type == StreamType::Primary)
+            .map(|s| (s.offset, s.length))
+            .collect();
+        plan.sort_unstable();
+        plan
     }
 
-    fn clear_progress_tx(&self) {
-        *self.progress_tx.lock().unwrap() = None;
+    /// Replaces the primary stream's segments with `ranges`, given as
+    /// `(offset, length)` in any order, to force a particular split. Call it
+    /// after `preprocess` (so the size is known) and before `download`.
+    ///
+    /// The ranges must tile exactly what the computed plan covers —
+    /// `[0, file_size)`, or the remainder after a continued partial — with
+    /// no gap or overlap. Only a resumable download with no data fetched
+    /// yet can be re-planned; an audio track's segments are left alone.
+    pub async fn set_segment_plan(&self, mut ranges: Vec<(i64, i64)>) -> Result<(), DownloadError> {
+        if !self.started.load(Ordering::SeqCst) || self.downloading.load(Ordering::SeqCst) {
+            return Err(DownloadError::InvalidState);
+        }
+        let (resumable, temp_dir) = {
+            let s = self.state.read().unwrap();
+            (s.resumable, PathBuf::from(&s.temp_dir))
+        };
+        if !resumable {
+            return Err(DownloadError::NonResumable);
+        }
+        let mut segments = self.segments.write().await;
+        let current: Vec<&Segment> = segments.values().filter(|s| s.stream_type == StreamType::Primary).collect();
+        if current.is_empty() {
+            return Err(DownloadError::InvalidState);
+        }
+        if current.iter().any(|s| s.length < 0 || s.downloaded > 0 || s.state != SegmentState::NotStarted) {
+            return Err(DownloadError::SegmentPlan(
+                "the current plan already has data or segments of unknown length".to_string(),
+            ));
+        }
+        let start = current.iter().map(|s| s.offset).min().unwrap_or(0);
+        let end = current.iter().map(|s| s.offset + s.length).max().unwrap_or(0);
+        ranges.sort_unstable();
+        check_plan_tiles(&ranges, start, end).map_err(DownloadError::SegmentPlan)?;
+
+        let replaced: Vec<String> = current.iter().map(|s| s.id.clone()).collect();
+        for id in replaced {
+            segments.remove(&id);
+            let _ = std::fs::remove_file(temp_dir.join(&id));
+            let _ = std::fs::remove_file(segment_state_path(&temp_dir, &id));
+        }
+        log::info!("[set_segment_plan] {} segment(s) over [{}, {})", ranges.len(), start, end);
+        for (offset, length) in ranges {
+            let segment = Segment::new(segment_id(StreamType::Primary, offset), offset, length);
+            segments.insert(segment.id.clone(), segment);
+        }
+        Ok(())
     }
 
-    /// Probes the URL, determines file size and resumability, creates temp
-    /// directory, and splits the file into download segments.
-    async fn preprocess(&self) -> Result<(), DownloadError> {
-        // 1. Build HeaderData from current state (sync lock)
-        let header_data = build_header_data(&self.state)?;
-
-        // 2. Probe the URL
-        let probe = probe_url(&self.client, &header_data).await?;
-
-        // 3. Extract Copy fields before moving probe
-        let resumable = probe.resumable;
-        let resource_size = probe.resource_size;
-
-        // 4. Update state with probe results (sync lock — no await while held)
-        let temp_dir_path = {
-            let mut s = self.state.write().unwrap();
-            s.file_size = resource_size.map(|sz| sz as i64).unwrap_or(-1);
-            s.url = probe.final_uri;
-            s.last_modified = probe.last_modified;
-            s.resumable = resumable;
-            s.attachment_name = probe.attachment_name;
-            s.content_type = probe.content_type;
-            s.temp_dir.clone()
+    /// The restored segment map, brought in line with the temp files on
+    /// disk, if it still fits a resumable download of `resource_size`.
+    async fn take_restored_segments(
+        &self,
+        resumable: bool,
+        resource_size: Option<u64>,
+        temp_dir: &Path,
+    ) -> Option<Vec<Segment>> {
+        if !self.restored.swap(false, Ordering::SeqCst) {
+            return None;
+        }
+        let segments: Vec<Segment> = self.segments.read().await.values().cloned().collect();
+        let mut ranges: Vec<(i64, i64)> = segments
+            .iter()
+            .filter(|s| s.stream_type == StreamType::Primary)
+            .map(|s| (s.offset, s.length))
+            .collect();
+        ranges.sort_unstable();
+        let tiles = match resource_size {
+            Some(size) => check_plan_tiles(&ranges, 0, size as i64),
+            None => Err("the size is unknown".to_string()),
         };
-
-        // 5. Create temp directory (async, non-blocking)
-        tokio::fs::create_dir_all(&temp_dir_path)
-            .await
-            .map_err(DownloadError::Disk)?;
-
-        // 6. Create segments based on probe results
-        let new_segments = if resumable {
-            if let Some(file_size) = resource_size {
-                log::info!(
-                    "[preprocess] resumable=true, file_size={}, creating multipart segments with max_connections={}",
-                    file_size, self.connections
-                );
-                create_segments(file_size, self.connections)
-            } else {
-                log::info!("[preprocess] resumable=true but file_size unknown, using single segment");
-                vec![Segment::new(Uuid::new_v4().to_string(), 0, -1)]
+        if !resumable || tiles.is_err() {
+            log::warn!(
+                "[preprocess] restored segments no longer fit the download (resumable={}, size={:?}: {}); starting over",
+                resumable,
+                resource_size,
+                tiles.err().unwrap_or_default()
+            );
+            return None;
+        }
+        let segments = segments
+            .into_iter()
+            .map(|segment| reconcile_with_temp_file(segment, temp_dir))
+            .collect::<std::io::Result<Vec<_>>>();
+        let segments = match segments {
+            Ok(segments) if self.segment_options.record_hash => {
+                self.report_phase(format!("Verifying {} segment(s) on disk…", segments.len()));
+                verify_segments(segments, temp_dir).await
             }
-        } else {
-            log::info!("[preprocess] resumable=false, using single segment (full download)");
-            vec![Segment::new(Uuid::new_v4().to_string(), 0, -1)]
+            other => other,
         };
-
-        // 7. Store segments
-        {
-            let mut segments = self.segments.write().await;
-            segments.clear();
-            for segment in new_segments {
-                segments.insert(segment.id.clone(), segment);
+        match segments {
+            Ok(segments) => Some(segments),
+            Err(e) => {
+                log::warn!("[preprocess] could not check restored segment files: {}; starting over", e);
+                None
             }
         }
-
-        Ok(())
     }
 
-    /// Downloads all segments concurrently. Each segment is downloaded in its own
-    /// tokio task. Waits for all tasks to complete and propagates errors.
-    async fn download(&self) -> Result<(), DownloadError> {
+    /// Downloads the segments that have not started, each in its own tokio
+    /// task. Waits for all tasks to complete and propagates errors.
+    async fn fetch_segments(&self) -> Result<(), DownloadError> {
+        self.downloading.store(true, Ordering::SeqCst);
         // Snapshot the optional sender once — all segment tasks share a clone.
         let progress_tx: Option<mpsc::Sender<Result<ProgressEvent, String>>> =
             self.progress_tx.lock().unwrap().clone();
 
-        // Wrap HeaderData in Arc — shared across all segment tasks without cloning
+        // Wrap HeaderData in Arc — shared across all segment tasks without cloning.
+        // Secondary (audio) segments share a copy pointing at the audio URL.
         let header_data = Arc::new(build_header_data(&self.state)?);
+        let audio_header_data = self.state.read().unwrap().audio_url.clone().map(|url| {
+            Arc::new(HeaderData { url, mirrors: Vec::new(), ..(*header_data).clone() })
+        });
 
         let temp_dir = {
             let s = self.state.read().unwrap();
@@ -278,7 +474,7 @@ impl DownloadStrategy for MultipartDownloadStrategy {
         };
 
         // Collect all segments that need downloading
-        let segments_to_download: Vec<Segment> = {
+        let mut segments_to_download: Vec<Segment> = {
             let segments_guard = self.segments.read().await;
             segments_guard
                 .values()
@@ -286,212 +482,2160 @@ impl DownloadStrategy for MultipartDownloadStrategy {
                 .cloned()
                 .collect()
         };
+        // A sequential download takes them front to back, the audio track last.
+        segments_to_download.sort_by_key(|s| (s.stream_type != StreamType::Primary, s.offset));
 
         if segments_to_download.is_empty() {
             return Ok(());
         }
 
-        // No need to mark segments as Downloading here — download_segment() does it
-        // at segment_grabber.rs:90, and the cloned copies in the HashMap are never
-        // read during the download phase.
+        // The cap is on the whole file, so what is already on disk counts;
+        // an unfinished unranged segment starts over and holds nothing.
+        if let Some(cap) = &self.segment_options.size_cap {
+            let held: u64 = self
+                .segments
+                .read()
+                .await
+                .values()
+                .filter(|s| s.length > 0 || s.state == SegmentState::Finished)
+                .map(|s| s.downloaded.max(0) as u64)
+                .sum();
+            cap.restart(held + self.existing_bytes.load(Ordering::SeqCst))?;
+        }
+
+        // No need to mark segments as Downloading here — download_segment() does it
+        // at segment_grabber.rs:90, and the cloned copies in the HashMap are never
+        // read during the download phase.
+
+        // Spawn a tokio task for each segment — true concurrent downloads
+        // Read once here; each task gets its own copy.
+        let (write_buffer_size, speed_limit, file_size) = {
+            let state = self.state.read().unwrap();
+            (state.write_buffer_size, state.speed_limit, state.file_size)
+        };
+        // Held until every segment task below has finished.
+        let share = self.shared_limiter.as_ref().map(SharedRateLimiter::register);
+        let used_hints = self.used_hints.load(Ordering::SeqCst);
+        let expected_size = (used_hints && file_size > 0).then_some(file_size as u64);
+        // A resource the probe under-reported grows by a tail segment; not
+        // when hints fixed the size or only a byte range is wanted.
+        let reported_total = (expected_size.is_none() && self.byte_range.is_none() && file_size > 0)
+            .then(ReportedTotal::default);
+        let segment_options = SegmentOptions {
+            write_buffer_size,
+            expected_size,
+            // Hints carry the type the page saw, not what the server sends now.
+            expect_content_type: self.expect_content_type.clone().filter(|_| used_hints),
+            restart_on_ignored_range: self.segment_options.restart_on_ignored_range && self.byte_range.is_none(),
+            reported_total: reported_total.clone(),
+            ..self.segment_options.clone()
+        }
+        .with_share(share.as_ref())
+        .with_speed_limit(speed_limit);
+        let mut tasks = tokio::task::JoinSet::new();
+        let mut running: HashMap<tokio::task::Id, SegmentTask> = HashMap::new();
+
+        // Starts one segment on its own cancellation token, so a chronically
+        // slow one can be stopped without touching the rest.
+        let spawn = |tasks: &mut tokio::task::JoinSet<Result<Segment, DownloadError>>, segment: Segment| {
+            let client = Arc::clone(&self.client);
+            let header_data = match (&segment.stream_type, &audio_header_data) {
+                (StreamType::Secondary, Some(audio)) => Arc::clone(audio),
+                _ => Arc::clone(&header_data), // cheap Arc clone
+            };
+            let temp_dir = temp_dir.clone();
+            let cancel_token = self.cancel_token.child_token();
+            // Only a ranged segment can pick up again where it was stopped.
+            if segment.length > 0 {
+                self.segment_tokens.lock().unwrap().insert(segment.id.clone(), cancel_token.clone());
+            }
+            let pause_token = self.pause_token.clone();
+            let segment_options = segment_options.for_segment(speed_limit);
+            let segment_tx = progress_tx.clone();
+            let segment_id_for_progress = segment.id.clone();
+            let bytes = Arc::new(AtomicU64::new(0));
+            let bytes_for_progress = Arc::clone(&bytes);
+            // A single unranged stream still knows its total when the probe did.
+            let segment_total_bytes = if segment.length > 0 {
+                Some(segment.length as u64)
+            } else if segment.stream_type == StreamType::Primary && file_size > 0 {
+                Some(file_size as u64)
+            } else {
+                None
+            };
+
+            let task = SegmentTask {
+                segment: segment.clone(),
+                cancel: cancel_token.clone(),
+                bytes,
+                last_bytes: 0,
+                slow_since: None,
+                resplit: false,
+            };
+            let handle = tasks.spawn(async move {
+                download_segment_with_options(
+                    segment,
+                    &client,
+                    &header_data,
+                    temp_dir,
+                    cancel_token,
+                    pause_token,
+                    segment_options,
+                    |bytes_delta| {
+                        bytes_for_progress.fetch_add(bytes_delta, Ordering::Relaxed);
+                        if let Some(tx) = &segment_tx {
+                            let _ = tx.try_send(Ok(ProgressEvent {
+                                segment_id: segment_id_for_progress.clone(),
+                                bytes_delta,
+                                total_bytes: segment_total_bytes,
+                                retry: false,
+                                warning: None,
+                                phase: None,
+                            }));
+                        }
+                    },
+                    |_retries| {
+                        if let Some(tx) = &segment_tx {
+                            let _ = tx.try_send(Ok(ProgressEvent {
+                                segment_id: segment_id_for_progress.clone(),
+                                bytes_delta: 0,
+                                total_bytes: segment_total_bytes,
+                                retry: true,
+                                warning: None,
+                                phase: None,
+                            }));
+                        }
+                    },
+                )
+                .await
+            });
+            (handle.id(), task)
+        };
+
+        // Segments waiting for the running one to finish, when sequential.
+        let mut pending: VecDeque<Segment> = segments_to_download.into();
+        while let Some(segment) = pending.pop_front() {
+            let (id, task) = spawn(&mut tasks, segment);
+            running.insert(id, task);
+            if self.sequential {
+                break;
+            }
+        }
+
+        let mut finished: Vec<Segment> = Vec::new();
+        let mut failed: Vec<String> = Vec::new();
+        // Segments stopped by `cancel_segment()`.
+        let mut stopped: Vec<Segment> = Vec::new();
+        let mut first_error: Option<DownloadError> = None;
+        // Where the server says the primary stream really ends, when a
+        // segment found it short of the probed size.
+        let mut real_end: Option<i64> = None;
+        let mut dropped: Vec<String> = Vec::new();
+        // End of the primary stream as planned, moved out by each tail
+        // segment added for a bigger resource.
+        let mut planned_end = file_size;
+        let mut check = tokio::time::interval(SLOW_CHECK_INTERVAL);
+        check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        check.reset();
+
+        while !running.is_empty() {
+            let joined = tokio::select! {
+                joined = tasks.join_next_with_id() => joined,
+                _ = check.tick(), if self.slow_segment_window.is_some() => {
+                    if !self.pause_token.is_paused() {
+                        mark_slow_segments(&mut running, self.slow_segment_window.unwrap_or_default());
+                    }
+                    continue;
+                }
+            };
+            let Some(joined) = joined else { break };
+            let (task_id, result) = match joined {
+                Ok((task_id, result)) => (task_id, Ok(result)),
+                Err(join_err) => (join_err.id(), Err(join_err)),
+            };
+            let Some(task) = running.remove(&task_id) else { continue };
+            let segment_id = task.segment.id.clone();
+            self.segment_tokens.lock().unwrap().remove(&segment_id);
+
+            match result {
+                Ok(Ok(updated_segment)) => {
+                    let total = reported_total.as_ref().and_then(ReportedTotal::get).map(|t| t as i64);
+                    if let Some(total) = total.filter(|&total| total > planned_end && real_end.is_none()) {
+                        let tail = Segment::new(self::segment_id(StreamType::Primary, planned_end), planned_end, total - planned_end);
+                        log::warn!(
+                            "[download] server holds {} bytes, more than the planned {}; adding segment={} for the rest",
+                            total, planned_end, tail.id
+                        );
+                        self.segments.write().await.insert(tail.id.clone(), tail.clone());
+                        planned_end = total;
+                        if self.sequential {
+                            pending.push_back(tail);
+                        } else {
+                            let (id, task) = spawn(&mut tasks, tail);
+                            running.insert(id, task);
+                        }
+                    }
+                    if let Some(next) = pending.pop_front() {
+                        let (id, task) = spawn(&mut tasks, next);
+                        running.insert(id, task);
+                    }
+                    if updated_segment.length < task.segment.length {
+                        let end = updated_segment.offset + updated_segment.length;
+                        if updated_segment.stream_type == StreamType::Primary {
+                            real_end = Some(real_end.map_or(end, |e| e.min(end)));
+                        }
+                        if let Some(tx) = &progress_tx {
+                            let _ = tx.try_send(Ok(ProgressEvent {
+                                segment_id: segment_id.clone(),
+                                bytes_delta: 0,
+                                total_bytes: Some(updated_segment.length.max(0) as u64),
+                                retry: false,
+                                warning: None,
+                                phase: None,
+                            }));
+                        }
+                        if updated_segment.length == 0 {
+                            dropped.push(segment_id);
+                            continue;
+                        }
+                    }
+                    finished.push(updated_segment);
+                }
+                // Every segment would get the same error page; stop them all.
+                Ok(Err(e @ DownloadError::UnexpectedContentType { .. })) => {
+                    for other in running.values() {
+                        other.cancel.cancel();
+                    }
+                    failed.push(segment_id);
+                    first_error = Some(e);
+                }
+                // The server ignores ranges: the other segments would each
+                // fetch the whole file too, so stop them all.
+                Ok(Err(e @ DownloadError::RangeIgnored(_))) => {
+                    for other in running.values() {
+                        other.cancel.cancel();
+                    }
+                    failed.push(segment_id);
+                    if !matches!(first_error, Some(DownloadError::RangeIgnored(_))) {
+                        first_error = Some(e);
+                    }
+                }
+                // The slowness check cancelled this segment's token alone.
+                Ok(Err(DownloadError::Cancelled))
+                    if task.resplit
+                        && !self.cancel_token.is_cancelled()
+                        && task.segment.length > 0
+                        && !matches!(
+                            first_error,
+                            Some(DownloadError::RangeIgnored(_) | DownloadError::UnexpectedContentType { .. })
+                        ) =>
+                {
+                    let (done, rest) = resplit_segment(&task.segment, &temp_dir);
+                    log::info!(
+                        "[download] segment={} re-split after sustained slowness: keeping {} bytes, {} left as segment={}",
+                        segment_id, done.downloaded, rest.length, rest.id
+                    );
+                    if let Some(tx) = &progress_tx {
+                        // Shrink the old segment's total to what it kept.
+                        let _ = tx.try_send(Ok(ProgressEvent {
+                            segment_id: segment_id.clone(),
+                            bytes_delta: 0,
+                            total_bytes: Some(done.length.max(0) as u64),
+                            retry: false,
+                            warning: None,
+                            phase: None,
+                        }));
+                    }
+                    {
+                        let mut segments = self.segments.write().await;
+                        if done.length > 0 {
+                            segments.insert(segment_id, done.clone());
+                        } else {
+                            segments.remove(&segment_id);
+                        }
+                        segments.insert(rest.id.clone(), rest.clone());
+                    }
+                    if done.length > 0 {
+                        finished.push(done);
+                    }
+                    let (id, task) = spawn(&mut tasks, rest);
+                    running.insert(id, task);
+                }
+                // `cancel_segment()` stopped this segment alone: it keeps
+                // what it wrote and waits for the next `download()`.
+                Ok(Err(DownloadError::Cancelled)) if !self.cancel_token.is_cancelled() && task.segment.length > 0 => {
+                    let written = written_len(&task.segment, &temp_dir);
+                    log::info!("[download] segment={} cancelled on its own after {} bytes", segment_id, written);
+                    let state = if written == task.segment.length { SegmentState::Finished } else { SegmentState::NotStarted };
+                    stopped.push(Segment { downloaded: written, state, ..task.segment });
+                    if let Some(next) = pending.pop_front() {
+                        let (id, task) = spawn(&mut tasks, next);
+                        running.insert(id, task);
+                    }
+                }
+                Ok(Err(e)) => {
+                    failed.push(segment_id);
+                    if first_error.is_none() {
+                        first_error = Some(e);
+                    }
+                }
+                Err(join_err) => {
+                    failed.push(segment_id);
+                    if first_error.is_none() {
+                        first_error = Some(DownloadError::SegmentFailed(join_err.to_string()));
+                    }
+                }
+            }
+        }
+
+        // Record every outcome under a single lock.
+        let mut segments_guard = self.segments.write().await;
+        for updated_segment in finished {
+            segments_guard.insert(updated_segment.id.clone(), updated_segment);
+        }
+        for segment_id in dropped {
+            segments_guard.remove(&segment_id);
+        }
+        let stopped_any = !stopped.is_empty();
+        for segment in stopped {
+            segments_guard.insert(segment.id.clone(), segment);
+        }
+        for segment_id in failed {
+            if let Some(s) = segments_guard.get_mut(&segment_id) {
+                s.state = SegmentState::Failed;
+            }
+        }
+        drop(segments_guard);
+
+        if planned_end > file_size {
+            self.state.write().unwrap().file_size = planned_end;
+            if let Some(tx) = &progress_tx {
+                let _ = tx.send(Ok(ProgressEvent::warning(format!(
+                    "the server reported {} bytes but has {}; downloaded all of it",
+                    file_size, planned_end
+                )))).await;
+            }
+        }
+        if let Some(end) = real_end.filter(|&end| end < file_size) {
+            log::warn!(
+                "[download] server holds {} bytes, not the probed {}; segments past the end were trimmed",
+                end, file_size
+            );
+            self.state.write().unwrap().file_size = end;
+            if let Some(tx) = &progress_tx {
+                let _ = tx.send(Ok(ProgressEvent::warning(format!(
+                    "the server reported {} bytes but has {}; downloaded what it has",
+                    file_size, end
+                )))).await;
+            }
+        }
+
+        if let Some(e) = first_error {
+            // `download()` recovers from this one.
+            if let (Some(tx), false) = (&progress_tx, matches!(e, DownloadError::RangeIgnored(_))) {
+                let _ = tx.try_send(Err(e.to_string()));
+            }
+            return Err(e);
+        }
+        if stopped_any {
+            return Err(DownloadError::Cancelled);
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the segment map with one unranged segment per stream after
+    /// the server answered a range with the whole file. What the old
+    /// segments fetched is discarded, from disk and from the progress, and
+    /// so is a partial output being continued: the stream starts at byte 0.
+    async fn fall_back_to_single_stream(&self, reason: &str) {
+        log::warn!("[download] {}; restarting as a single stream", reason);
+        let (temp_dir, output_path) = {
+            let s = self.state.read().unwrap();
+            (PathBuf::from(&s.temp_dir), s.output_path.clone())
+        };
+        if let Some(output_path) = output_path.filter(|_| self.existing_bytes.load(Ordering::SeqCst) > 0) {
+            // Postprocess still appends, keeping the partial's name, but to
+            // an empty file; failing that, it writes the output afresh.
+            log::warn!("[download] discarding the partial output {}", output_path);
+            let truncated = match tokio::fs::OpenOptions::new().write(true).open(&output_path).await {
+                Ok(file) => file.set_len(0).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = truncated {
+                log::warn!("[download] could not truncate {}: {}", output_path, e);
+                self.existing_bytes.store(0, Ordering::SeqCst);
+            }
+        }
+        let progress_tx = self.progress_tx.lock().unwrap().clone();
+        let mut segments = self.segments.write().await;
+        let mut streams: Vec<StreamType> = Vec::new();
+        for segment in segments.values() {
+            let _ = tokio::fs::remove_file(temp_dir.join(&segment.id)).await;
+            let _ = tokio::fs::remove_file(segment_state_path(&temp_dir, &segment.id)).await;
+            if let Some(tx) = &progress_tx {
+                let _ = tx.try_send(Ok(ProgressEvent {
+                    segment_id: segment.id.clone(),
+                    bytes_delta: 0,
+                    total_bytes: Some(0),
+                    retry: false,
+                    warning: None,
+                    phase: None,
+                }));
+            }
+            if !streams.contains(&segment.stream_type) {
+                streams.push(segment.stream_type);
+            }
+        }
+        segments.clear();
+        for stream_type in streams {
+            let mut segment = Segment::new(segment_id(stream_type, 0), 0, -1);
+            segment.stream_type = stream_type;
+            segments.insert(segment.id.clone(), segment);
+        }
+        drop(segments);
+        self.state.write().unwrap().resumable = false;
+        if let Some(tx) = &progress_tx {
+            let _ = tx.send(Ok(ProgressEvent::warning(
+                "the server answers ranges with the whole file; downloading as a single stream",
+            ))).await;
+        }
+    }
+
+    /// Returns the temp directory path from the current state, if available.
+    pub async fn temp_dir(&self) -> String {
+        let state = self.state.read().unwrap();
+        state.temp_dir.clone()
+    }
+
+    /// Returns a reference to the internal state lock (for testing/inspection).
+    pub fn state(&self) -> &Arc<StdRwLock<DownloaderState>> {
+        &self.state
+    }
+
+    /// Returns a reference to the internal segments lock (for testing/inspection).
+    pub fn segments(&self) -> &Arc<RwLock<HashMap<String, Segment>>> {
+        &self.segments
+    }
+
+    /// Returns a reference to the cancellation token.
+    pub fn cancel_token(&self) -> &CancellationToken {
+        &self.cancel_token
+    }
+
+    /// Returns a reference to the pause token.
+    pub fn pause_token(&self) -> &PauseToken {
+        &self.pause_token
+    }
+}
+
+/// The HTTP clients of a download, built with the same settings.
+pub(crate) struct Clients {
+    /// Shared by all segment tasks, keeping idle connections for reuse.
+    pub pooled: Client,
+    /// Keeps no idle connections, so each request opens its own; for the
+    /// attempt after a connection reset (see `SegmentOptions::reconnect_client`).
+    pub fresh: Client,
+}
+
+/// Builds the HTTP clients shared by all segment tasks of a download.
+/// Auto-decompression is disabled so byte ranges map 1:1 onto the file.
+/// Proxies come from `HTTP_PROXY`/`HTTPS_PROXY`/`NO_PROXY`.
+pub(crate) fn default_clients() -> Clients {
+    build_clients(None, true, &TlsFiles::default(), HttpVersion::Auto, false, None).expect("failed to build HTTP client")
+}
+
+/// Like [`default_clients`], with explicit proxy, TLS, HTTP version and
+/// redirect settings: `proxy` takes precedence; otherwise the environment's
+/// proxy variables are used unless `system_proxy` is false. Fails, with a
+/// message for `DownloadError::Tls`, if a TLS file can't be loaded.
+///
+/// Redirects within a host are followed as usual. reqwest drops
+/// `Authorization` and `Cookie` when a redirect leaves the host; with
+/// `forward_auth` the client instead stops there, and the probe re-sends the
+/// request with them to the new location (or refuses to, for a redirect to
+/// plain http; the client stops on that too).
+///
+/// With a `guard`, every name the client resolves and every redirect hop is
+/// checked with it; see [`connect_guard`](crate::downloader::connect_guard).
+pub(crate) fn build_clients(
+    proxy: Option<&ProxyInfo>,
+    system_proxy: bool,
+    tls: &TlsFiles,
+    http_version: HttpVersion,
+    forward_auth: bool,
+    guard: Option<&Arc<dyn ConnectGuard>>,
+) -> Result<Clients, String> {
+    let builder = || client_builder(proxy, system_proxy, tls, http_version, forward_auth, guard);
+    // Idle connections kept per host. Over HTTP/1.1 each running segment
+    // holds its own connection, and ones beyond this are closed rather than
+    // reused; HTTP/2 multiplexes every segment over a single connection,
+    // so the limit does not come into play.
+    let pooled = builder()?.pool_max_idle_per_host(MAX_CONNECTIONS).build().map_err(|e| e.to_string())?;
+    let fresh = builder()?.pool_max_idle_per_host(0).build().map_err(|e| e.to_string())?;
+    Ok(Clients { pooled, fresh })
+}
+
+/// The settings [`build_clients`] gives both clients.
+fn client_builder(
+    proxy: Option<&ProxyInfo>,
+    system_proxy: bool,
+    tls: &TlsFiles,
+    http_version: HttpVersion,
+    forward_auth: bool,
+    guard: Option<&Arc<dyn ConnectGuard>>,
+) -> Result<reqwest::ClientBuilder, String> {
+    let mut builder = Client::builder()
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .tcp_nodelay(true)
+        .no_gzip()
+        .no_deflate()
+        .no_brotli();
+    builder = match http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge(),
+    };
+    if let Some(guard) = guard {
+        builder = builder.dns_resolver(Arc::new(GuardedResolver { guard: Arc::clone(guard) }));
+    }
+    if forward_auth || guard.is_some() {
+        let guard = guard.cloned();
+        builder = builder.redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if let Some(guard) = &guard {
+                if let Err(refused) = check_url(guard.as_ref(), attempt.url().as_str()) {
+                    return attempt.error(refused);
+                }
+            }
+            // Hops that would drop credentials (another host) or expose them
+            // (down to plain http) are left to `send_following_redirects`.
+            let unsafe_hop = attempt.previous().last().is_some_and(|previous| {
+                is_cross_host(previous.as_str(), attempt.url().as_str())
+                    || (previous.scheme() == "https" && attempt.url().scheme() == "http")
+            });
+            if unsafe_hop && forward_auth {
+                attempt.stop()
+            } else if attempt.previous().len() >= 10 {
+                attempt.error("too many redirects")
+            } else {
+                attempt.follow()
+            }
+        }));
+    }
+
+    match proxy.map(to_reqwest_proxy) {
+        // Setting an explicit proxy also turns off the environment lookup.
+        Some(Ok(proxy)) => builder = builder.proxy(proxy),
+        Some(Err(e)) => {
+            log::warn!("ignoring invalid proxy: {}", e);
+            builder = builder.no_proxy();
+        }
+        None if !system_proxy => builder = builder.no_proxy(),
+        None => {}
+    }
+
+    apply_tls(builder, tls)
+}
+
+/// Adds the client identity and extra root certificates from `tls`.
+fn apply_tls(mut builder: reqwest::ClientBuilder, tls: &TlsFiles) -> Result<reqwest::ClientBuilder, String> {
+    let read = |what: &str, path: &str| {
+        std::fs::read(path).map_err(|e| format!("could not read {} {}: {}", what, path, e))
+    };
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            // rustls takes certificate chain and key from one PEM buffer.
+            let mut pem = read("client certificate", cert_path)?;
+            pem.push(b'\n');
+            pem.extend(read("client key", key_path)?);
+            let identity = reqwest::Identity::from_pem(&pem)
+                .map_err(|e| format!("invalid client certificate {} or key {}: {}", cert_path, key_path, e))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => return Err("a client certificate needs both a certificate and a key".to_string()),
+    }
+    if let Some(ca_path) = &tls.root_cert {
+        let certs = reqwest::Certificate::from_pem_bundle(&read("root certificate", ca_path)?)
+            .map_err(|e| format!("invalid root certificate {}: {}", ca_path, e))?;
+        if certs.is_empty() {
+            return Err(format!("no certificates in {}", ca_path));
+        }
+        builder = builder.tls_certs_merge(certs);
+    }
+    Ok(builder)
+}
+
+fn to_reqwest_proxy(info: &ProxyInfo) -> reqwest::Result<reqwest::Proxy> {
+    let url = if info.host.contains("://") {
+        format!("{}:{}", info.host, info.port)
+    } else {
+        format!("http://{}:{}", info.host, info.port)
+    };
+    let proxy = reqwest::Proxy::all(url)?;
+    Ok(match &info.username {
+        Some(user) => proxy.basic_auth(user, info.password.as_deref().unwrap_or("")),
+        None => proxy,
+    })
+}
+
+/// Makes sure the output file's directory exists before anything is fetched,
+/// creating it when `create` is set, so a bad path fails in preprocess rather
+/// than in postprocess after the whole transfer.
+pub(crate) async fn ensure_output_dir(
+    state: &StdRwLock<DownloaderState>,
+    create: bool,
+) -> Result<(), DownloadError> {
+    let Some(output_path) = state.read().unwrap().output_path.clone() else {
+        return Ok(());
+    };
+    let dir = match Path::new(&output_path).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => return Ok(()),
+    };
+    if tokio::fs::metadata(&dir).await.map(|m| m.is_dir()).unwrap_or(false) {
+        return Ok(());
+    }
+    if !create {
+        return Err(DownloadError::Disk(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("output directory {} does not exist", dir.display()),
+        )));
+    }
+    log::info!("[preprocess] creating output directory {}", dir.display());
+    tokio::fs::create_dir_all(&dir).await.map_err(|e| {
+        DownloadError::Disk(std::io::Error::new(
+            e.kind(),
+            format!("cannot create output directory {}: {}", dir.display(), e),
+        ))
+    })
+}
+
+/// Whether `RDM_KEEP_TEMP` asks for temp files to be kept (any value other
+/// than empty, `0` or `false`).
+pub(crate) fn keep_temp_from_env() -> bool {
+    std::env::var("RDM_KEEP_TEMP")
+        .map(|v| !matches!(v.trim().to_lowercase().as_str(), "" | "0" | "false"))
+        .unwrap_or(false)
+}
+
+/// Removes a download's temp directory when dropped, unless disarmed, so a
+/// download that is aborted or panics between preprocess and a successful
+/// postprocess doesn't leave its segment files behind.
+pub(crate) struct TempDirGuard {
+    path: PathBuf,
+    armed: AtomicBool,
+}
+
+impl TempDirGuard {
+    pub(crate) fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), armed: AtomicBool::new(true) }
+    }
+
+    /// Leave the directory alone on drop.
+    pub(crate) fn disarm(&self) {
+        self.armed.store(false, Ordering::SeqCst);
+    }
+}
+
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        if self.armed.load(Ordering::SeqCst) && self.path.exists() {
+            log::info!("[cleanup] removing abandoned temp dir {}", self.path.display());
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+}
+
+/// Creates download segments using XDM-style dynamic halving.
+///
+/// Aims for one segment per `target_segment_size` bytes, capped at
+/// `max_connections`, so small files don't pay for connections they can't
+/// use. Starts with a single segment covering the entire file, then
+/// repeatedly splits the largest segment in half until that count is reached
+/// or every segment is at the minimum size. An empty file gets a single
+/// zero-length segment, fetched without a `Range` header.
+fn create_segments(file_size: u64, max_connections: usize, target_segment_size: u64) -> Vec<Segment> {
+    let target_count = (file_size / target_segment_size.max(1)).clamp(1, max_connections.max(1) as u64) as usize;
+    log::info!(
+        "[create_segments] file_size={}, max_connections={}, target_segment_size={}, target_count={}",
+        file_size,
+        max_connections,
+        target_segment_size,
+        target_count
+    );
+
+    // Start with one segment covering the whole file
+    let mut segments = vec![Segment::new(
+        segment_id(StreamType::Primary, 0),
+        0,
+        file_size as i64,
+    )];
+
+    // Repeatedly halve the largest segment
+    while segments.len() < target_count {
+        // Find the segment with the most bytes
+        let max_idx = segments
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, s)| s.length)
+            .map(|(i, _)| i)
+            .unwrap();
+
+        let segment = &segments[max_idx];
+
+        // Don't split if it would produce segments below minimum size
+        if segment.length < MIN_SEGMENT_SIZE * 2 {
+            log::debug!(
+                "[create_segments] stopping split: largest segment length={} < MIN_SEGMENT_SIZE*2={}",
+                segment.length,
+                MIN_SEGMENT_SIZE * 2
+            );
+            break;
+        }
+
+        let half = segment.length / 2;
+        let new_offset = segment.offset + half;
+        let new_length = segment.length - half;
+
+        log::debug!(
+            "[create_segments] splitting segment[{}]: offset={}, length={} -> half={}, new_offset={}, new_length={}",
+            max_idx, segment.offset, segment.length, half, new_offset, new_length
+        );
+
+        // Shrink the original segment
+        segments[max_idx].length = half;
+
+        // Create the new segment for the second half
+        segments.push(Segment::new(
+            segment_id(StreamType::Primary, new_offset),
+            new_offset,
+            new_length,
+        ));
+    }
+
+    // Log final segments summary
+    let total: i64 = segments.iter().map(|s| s.length).sum();
+    log::info!(
+        "[create_segments] created {} segments, total_bytes={}, file_size={}",
+        segments.len(),
+        total,
+        file_size
+    );
+    for (i, s) in segments.iter().enumerate() {
+        log::debug!(
+            "[create_segments]   segment[{}]: offset={}, length={}, end={}",
+            i, s.offset, s.length, s.offset + s.length - 1
+        );
+    }
+
+    segments
+}
+
+/// Default for `with_assembly_threads`: serial, until parallel copies are
+/// shown to pay off on common disks.
+pub const DEFAULT_ASSEMBLY_THREADS: usize = 1;
+
+/// Checks that the sorted `ranges` cover `[start, end)` back to back.
+fn check_plan_tiles(ranges: &[(i64, i64)], start: i64, end: i64) -> Result<(), String> {
+    if ranges.is_empty() {
+        return Err("no ranges".to_string());
+    }
+    let mut next = start;
+    for &(offset, length) in ranges {
+        if length <= 0 {
+            return Err(format!("range at {} has length {}", offset, length));
+        }
+        if offset < next {
+            return Err(format!("range at {} overlaps the one before it", offset));
+        }
+        if offset > next {
+            return Err(format!("gap between {} and {}", next, offset));
+        }
+        next = offset + length;
+    }
+    if next != end {
+        return Err(format!("ranges end at {}, but the download ends at {}", next, end));
+    }
+    Ok(())
+}
+
+/// User-Agent sent when neither a captured header nor the builder sets one.
+pub const DEFAULT_USER_AGENT: &str = concat!("rdm/", env!("CARGO_PKG_VERSION"));
+
+/// `Accept` preferring `content_type` and its family, then anything:
+/// `video/mp4,video/*;q=0.9,*/*;q=0.8`. Just `*/*` when the type is unknown.
+pub fn accept_for_content_type(content_type: Option<&str>) -> String {
+    let essence = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_ascii_lowercase())
+        .filter(|ct| ct.contains('/') && ct != "*/*");
+    match essence {
+        Some(ct) => {
+            let major = ct.split('/').next().unwrap_or_default();
+            format!("{},{}/*;q=0.9,*/*;q=0.8", ct, major)
+        }
+        None => "*/*".to_string(),
+    }
+}
+
+/// Extracts HeaderData from the current DownloaderState.
+/// Acquires the read lock once and copies all needed fields.
+///
+/// A `User-Agent` header always ends up in the result, exactly once:
+/// a captured header wins over `state.user_agent`, which wins over
+/// [`DEFAULT_USER_AGENT`]. `state.default_accept` decides the `Accept` sent
+/// when none was captured.
+pub(crate) fn build_header_data(
+    state: &Arc<StdRwLock<DownloaderState>>,
+) -> Result<HeaderData, DownloadError> {
+    let s = state.read().unwrap();
+    let mut headers = s.headers.clone();
+    // Collapse however the captured headers spell it into a single value.
+    let captured_ua = headers
+        .keys()
+        .filter(|k| k.eq_ignore_ascii_case("user-agent"))
+        .cloned()
+        .collect::<Vec<_>>()
+        .into_iter()
+        .filter_map(|k| headers.remove(&k))
+        .flatten()
+        .next();
+    let ua = captured_ua
+        .or_else(|| s.user_agent.clone())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+    headers.insert("User-Agent".to_string(), vec![ua]);
+    let default_accept = match &s.default_accept {
+        DefaultAccept::FromContentType => Some(accept_for_content_type(s.content_type.as_deref())),
+        DefaultAccept::Value(value) => Some(value.clone()),
+        DefaultAccept::Skip => None,
+    };
+    if s.no_cache {
+        headers.retain(|k, _| !k.eq_ignore_ascii_case("cache-control") && !k.eq_ignore_ascii_case("pragma"));
+        headers.insert("Cache-Control".to_string(), vec!["no-cache".to_string()]);
+        headers.insert("Pragma".to_string(), vec!["no-cache".to_string()]);
+    }
+    let mut header_data = HeaderData {
+        url: s.url.clone(),
+        headers,
+        cookies: s.cookies.clone(),
+        authentication: s.authentication.clone(),
+        proxy: s.proxy.clone(),
+        mirrors: s.mirrors.clone(),
+        method: s.method.clone(),
+        body: s.body.clone(),
+        default_accept,
+    };
+    if s.credentials_url.as_deref().is_some_and(|origin| is_cross_host(origin, &s.url)) {
+        strip_credentials(&mut header_data);
+        header_data.cookies = s.redirect_cookies.clone();
+    }
+    Ok(header_data)
+}
+
+/// Probes every mirror in `header_data` and checks it serves the same file as
+/// the primary `probe`: same size, and same ETag when both sides send one.
+/// Returns the mirrors' final URLs.
+async fn verify_mirrors(
+    client: &Client,
+    header_data: &HeaderData,
+    probe: &ProbeResult,
+) -> Result<Vec<String>, DownloadError> {
+    let mut verified = Vec::with_capacity(header_data.mirrors.len());
+    for mirror in &header_data.mirrors {
+        let mirror_data = HeaderData {
+            url: mirror.clone(),
+            mirrors: Vec::new(),
+            ..header_data.clone()
+        };
+        let mirror_probe = probe_url(client, &mirror_data).await?;
+        if mirror_probe.resource_size != probe.resource_size {
+            return Err(DownloadError::MirrorMismatch(format!(
+                "{} has size {:?}, expected {:?}",
+                mirror, mirror_probe.resource_size, probe.resource_size
+            )));
+        }
+        if let (Some(expected), Some(actual)) = (&probe.etag, &mirror_probe.etag) {
+            if expected != actual {
+                return Err(DownloadError::MirrorMismatch(format!(
+                    "{} has ETag {}, expected {}",
+                    mirror, actual, expected
+                )));
+            }
+        }
+        if !mirror_probe.resumable {
+            log::warn!("[preprocess] mirror {} does not support ranges; skipping it", mirror);
+            continue;
+        }
+        verified.push(mirror_probe.final_uri);
+    }
+    Ok(verified)
+}
+
+#[async_trait]
+impl DownloadStrategy for MultipartDownloadStrategy {
+    fn set_progress_tx(&self, tx: mpsc::Sender<Result<ProgressEvent, String>>) {
+        *self.progress_tx.lock().unwrap() = Some(tx);
+    }
+
+    fn clear_progress_tx(&self) {
+        *self.progress_tx.lock().unwrap() = None;
+    }
+
+    fn state_snapshot(&self) -> DownloaderState {
+        self.state.read().unwrap().clone()
+    }
+
+    async fn segments_snapshot(&self) -> Vec<Segment> {
+        self.segments.read().await.values().cloned().collect()
+    }
+
+    fn set_output_path(&self, output_path: String) -> Result<(), DownloadError> {
+        if self.started.load(Ordering::SeqCst) {
+            return Err(DownloadError::InvalidState);
+        }
+        self.state.write().unwrap().output_path = Some(output_path);
+        Ok(())
+    }
+
+    fn set_connection_size(&self, connections: usize) -> Result<(), DownloadError> {
+        if self.started.load(Ordering::SeqCst) {
+            return Err(DownloadError::InvalidState);
+        }
+        self.connections.store(connections.max(1), Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn set_request_headers(
+        &self,
+        headers: HashMap<String, Vec<String>>,
+        cookies: Option<String>,
+    ) -> Result<(), DownloadError> {
+        common_options::set_request_headers(self.started.load(Ordering::SeqCst), &self.state, headers, cookies)
+    }
+
+    async fn probe(&self) -> Result<ProbeResult, DownloadError> {
+        if let Some(e) = &self.client_error {
+            return Err(DownloadError::Tls(e.clone()));
+        }
+        let header_data = build_header_data(&self.state)?;
+        if let Some(guard) = &self.connect_guard {
+            check_url(guard.as_ref(), &header_data.url).map_err(|e| DownloadError::HostRefused(e.0))?;
+        }
+        probe_url_with_filename_headers(&self.client, &header_data, &self.filename_headers).await
+    }
+
+    /// Probes the URL, determines file size and resumability, creates temp
+    /// directory, and splits the file into download segments.
+    async fn preprocess(&self) -> Result<PreprocessInfo, DownloadError> {
+        self.started.store(true, Ordering::SeqCst);
+        if let Some(e) = &self.client_error {
+            return Err(DownloadError::Tls(e.clone()));
+        }
+        let connections = if self.sequential { 1 } else { self.connections.load(Ordering::SeqCst) };
+        if self.output_target == OutputTarget::Stdout {
+            if self.state.read().unwrap().audio_url.is_some() {
+                return Err(DownloadError::Mux("muxing needs an output file, not stdout".to_string()));
+            }
+        } else {
+            ensure_output_dir(&self.state, self.create_parent).await?;
+        }
+
+        // 1. Build HeaderData from current state (sync lock). A default
+        //    Accept from the content type is settled first: the probe is
+        //    about to learn the type, and the segments must not send
+        //    another value than the probe did.
+        {
+            let mut s = self.state.write().unwrap();
+            if s.default_accept == DefaultAccept::FromContentType {
+                let known = s.content_type.clone().or_else(|| self.probe_hints.as_ref()?.content_type.clone());
+                s.default_accept = DefaultAccept::Value(accept_for_content_type(known.as_deref()));
+            }
+        }
+        let mut header_data = build_header_data(&self.state)?;
+        if let Some(guard) = &self.connect_guard {
+            let audio_url = self.state.read().unwrap().audio_url.clone();
+            let urls = std::iter::once(&header_data.url).chain(&header_data.mirrors).chain(audio_url.as_ref());
+            for url in urls {
+                check_url(guard.as_ref(), url).map_err(|e| DownloadError::HostRefused(e.0))?;
+            }
+        }
+
+        // 2. Probe the URL, keeping any cookies it hands out for the
+        //    segment requests (CDNs often set a signed cookie here). Complete
+        //    hints stand in for the probe, unless mirrors or an audio track
+        //    need the primary's real headers.
+        //    With a cache record for the output the probe is conditional,
+        //    and never replaced by hints.
+        let cached = self.cached_record(&header_data.url);
+        let hinted = self
+            .probe_hints
+            .as_ref()
+            .filter(|_| header_data.mirrors.is_empty() && self.state.read().unwrap().audio_url.is_none())
+            .filter(|_| cached.is_none() && self.byte_range.is_none())
+            .and_then(|hints| hints.to_probe(&header_data.url));
+        self.used_hints.store(hinted.is_some(), Ordering::SeqCst);
+        let mut probe = match hinted {
+            Some(probe) => {
+                log::info!(
+                    "[preprocess] using probe hints: resumable={}, size={:?}; skipping probe",
+                    probe.resumable, probe.resource_size
+                );
+                probe
+            }
+            None => {
+                self.report_phase("Probing…");
+                let mut conditional = header_data.clone();
+                for (name, value) in cached.iter().flat_map(|(_, record)| record.conditional_headers()) {
+                    conditional.headers.insert(name, vec![value]);
+                }
+                probe_url_with_filename_headers(&self.client, &conditional, &self.filename_headers).await?
+            }
+        };
+        if probe.not_modified {
+            match cached.as_ref().filter(|(file, record)| record.matches(&header_data.url, file)) {
+                Some((file, record)) => {
+                    log::info!("[preprocess] {:?} is unchanged on the server; skipping the download", file);
+                    let mut s = self.state.write().unwrap();
+                    s.skipped = true;
+                    s.file_size = record.size as i64;
+                    // The file it was saved as, for a directory output.
+                    s.output_path = Some(file.to_string_lossy().into_owned());
+                    return Ok(PreprocessInfo {
+                        file_size: Some(record.size),
+                        resumable: false,
+                        segment_count: 0,
+                        content_type: s.content_type.clone(),
+                        attachment_name: s.attachment_name.clone(),
+                    });
+                }
+                None => {
+                    // The file went away after the conditional probe was sent.
+                    log::info!("[preprocess] the cached file is gone despite a 304; probing again");
+                    probe = probe_url_with_filename_headers(&self.client, &header_data, &self.filename_headers).await?;
+                }
+            }
+        }
+        if let Some(expected) = &self.expect_content_type {
+            check_content_type(expected, probe.content_type.as_deref())?;
+        }
+        if self.conditional_cache {
+            *self.validators.lock().unwrap() = Some(CacheRecord {
+                url: header_data.url.clone(),
+                etag: probe.etag.clone(),
+                last_modified: probe.last_modified.clone(),
+                size: 0,
+            });
+        }
+        // Segments go straight to where the probe ended up. Credentials meant
+        // for the original host are not sent to another one (reqwest already
+        // dropped them on the redirect), unless forwarding was asked for.
+        if is_cross_host(&header_data.url, &probe.final_uri) && !self.forward_auth_on_redirect {
+            log::info!(
+                "[preprocess] redirected to another host ({}); dropping credentials and cookies",
+                reqwest::Url::parse(&probe.final_uri).ok().and_then(|u| u.host_str().map(str::to_string)).unwrap_or_default()
+            );
+            // Only this run's requests lose them; the state keeps them, with
+            // the URL they belong to, so later requests withhold them too.
+            strip_credentials(&mut header_data);
+            self.state.write().unwrap().credentials_url = Some(header_data.url.clone());
+        }
+        if !probe.set_cookies.is_empty() {
+            log::info!("[preprocess] probe set {} cookie(s)", probe.set_cookies.len());
+            header_data.cookies = merge_cookies(header_data.cookies.as_deref(), &probe.set_cookies);
+            let mut s = self.state.write().unwrap();
+            if s.credentials_url.is_some() {
+                s.redirect_cookies = header_data.cookies.clone();
+            } else {
+                s.cookies = header_data.cookies.clone();
+            }
+        }
+
+        // 3. Make sure every mirror serves the same bytes
+        if !header_data.mirrors.is_empty() {
+            self.report_phase(format!("Checking {} mirror(s)…", header_data.mirrors.len()));
+        }
+        let mirrors = verify_mirrors(&self.client, &header_data, &probe).await?;
+
+        // 4. Extract Copy fields before moving probe
+        if self.force_single_stream && probe.resumable {
+            log::info!("[preprocess] server supports ranges, but single-stream download was forced");
+        }
+        let resumable = probe.resumable && !self.force_single_stream && self.byte_range.is_none();
+        if let Some((start, end)) = self.byte_range {
+            if start > end {
+                return Err(DownloadError::SegmentPlan(format!("byte range {}-{} is empty", start, end)));
+            }
+            if let Some(size) = probe.resource_size.filter(|&size| end >= size) {
+                return Err(DownloadError::SegmentPlan(format!(
+                    "byte range {}-{} ends past the {} bytes of the file",
+                    start, end, size
+                )));
+            }
+        }
+        // A byte range stands for the whole download from here on.
+        let resource_size = match self.byte_range {
+            Some((start, end)) => Some(end - start + 1),
+            None => probe.resource_size,
+        };
+        let mut total_size = resource_size;
+        let max_file_size = self.segment_options.size_cap.as_ref().map(SizeCap::limit);
+        if let (Some(size), Some(limit)) = (resource_size, max_file_size) {
+            if size > limit {
+                return Err(DownloadError::TooLarge { size, limit });
+            }
+        }
+
+        // 5. Update state with probe results (sync lock — no await while held)
+        let temp_dir_path = {
+            let mut s = self.state.write().unwrap();
+            s.mirrors = mirrors;
+            s.file_size = resource_size.map(|sz| sz as i64).unwrap_or(-1);
+            s.url = probe.final_uri;
+            s.last_modified = probe.last_modified;
+            s.resumable = resumable;
+            s.attachment_name = probe.attachment_name;
+            s.content_type = probe.content_type.or(s.content_type.take());
+            s.temp_dir.clone()
+        };
+
+        // 6. Create temp directory (async, non-blocking)
+        tokio::fs::create_dir_all(&temp_dir_path)
+            .await
+            .map_err(DownloadError::Disk)?;
+        if !self.keep_temp && !resumable {
+            *self.temp_guard.lock().unwrap() = Some(TempDirGuard::new(&temp_dir_path));
+        }
+
+        // 7. Create segments based on probe results, unless an earlier run's
+        //    map was restored
+        let restored = self
+            .take_restored_segments(resumable, resource_size, Path::new(&temp_dir_path))
+            .await;
+        let existing = if restored.is_some() { 0 } else { self.existing_partial_len(resumable, resource_size) };
+        self.existing_bytes.store(existing, Ordering::SeqCst);
+
+        let new_segments = if let Some((start, end)) = self.byte_range {
+            log::info!("[preprocess] fetching bytes {}-{} as a single segment", start, end);
+            vec![Segment::new(segment_id(StreamType::Primary, start as i64), start as i64, (end - start + 1) as i64)]
+        } else if let Some(restored) = &restored {
+            let unfinished = restored.iter().filter(|s| s.state != SegmentState::Finished).count();
+            log::info!(
+                "[preprocess] resuming {} restored segments, {} unfinished",
+                restored.len(), unfinished
+            );
+            restored.clone()
+        } else if existing > 0 {
+            let file_size = resource_size.unwrap_or(0);
+            log::info!(
+                "[preprocess] continuing partial output: existing={} of file_size={}",
+                existing, file_size
+            );
+            let mut remaining = self.plan_segments(file_size - existing, connections);
+            for segment in &mut remaining {
+                segment.offset += existing as i64;
+                segment.id = segment_id(segment.stream_type, segment.offset);
+            }
+            remaining.retain(|s| s.length > 0);
+            remaining
+        } else if resumable {
+            if let Some(file_size) = resource_size {
+                log::info!(
+                    "[preprocess] resumable=true, file_size={}, creating multipart segments with max_connections={}",
+                    file_size, connections
+                );
+                self.plan_segments(file_size, connections)
+            } else {
+                log::info!("[preprocess] resumable=true but file_size unknown, using single segment");
+                vec![Segment::new(segment_id(StreamType::Primary, 0), 0, -1)]
+            }
+        } else {
+            log::info!("[preprocess] resumable=false, using single segment (full download)");
+            vec![Segment::new(segment_id(StreamType::Primary, 0), 0, -1)]
+        };
+
+        // 8. Probe the separate audio track, if any, and tag its segments Secondary
+        let audio_url = self.state.read().unwrap().audio_url.clone();
+        let mut new_segments = new_segments;
+        if let Some(audio_url) = audio_url {
+            let audio_header_data = HeaderData { url: audio_url, mirrors: Vec::new(), ..header_data };
+            let audio_probe = probe_url(&self.client, &audio_header_data).await?;
+            let combined = resource_size.zip(audio_probe.resource_size).map(|(video, audio)| video + audio);
+            total_size = combined;
+            if let (Some(size), Some(limit)) = (combined, max_file_size) {
+                if size > limit {
+                    return Err(DownloadError::TooLarge { size, limit });
+                }
+            }
+            let audio_segments = match (audio_probe.resumable, audio_probe.resource_size) {
+                (true, Some(size)) if !self.force_single_stream => self.plan_segments(size, connections),
+                _ => vec![Segment::new(segment_id(StreamType::Secondary, 0), 0, -1)],
+            };
+            log::info!(
+                "[preprocess] audio track: resumable={}, size={:?}, segments={}",
+                audio_probe.resumable, audio_probe.resource_size, audio_segments.len()
+            );
+            self.state.write().unwrap().audio_url = Some(audio_probe.final_uri);
+            // A restored map already has the audio segments.
+            if restored.is_none() {
+                new_segments.extend(audio_segments.into_iter().map(|mut s| {
+                    s.stream_type = StreamType::Secondary;
+                    s.id = segment_id(s.stream_type, s.offset);
+                    s
+                }));
+            }
+        }
+
+        // 9. Clear out what an earlier run left that this plan won't use
+        match remove_stale_temp_files(Path::new(&temp_dir_path), &new_segments) {
+            Ok(0) => {}
+            Ok(removed) => log::info!("[preprocess] removed {} stale file(s) from the temp dir", removed),
+            Err(e) => log::warn!("[preprocess] could not clear stale files from the temp dir: {}", e),
+        }
+
+        // 10. Store segments
+        let segment_count = new_segments.len();
+        {
+            let mut segments = self.segments.write().await;
+            segments.clear();
+            for segment in new_segments {
+                segments.insert(segment.id.clone(), segment);
+            }
+        }
+
+        let state = self.state.read().unwrap();
+        Ok(PreprocessInfo {
+            file_size: total_size,
+            resumable,
+            segment_count,
+            content_type: state.content_type.clone(),
+            attachment_name: state.attachment_name.clone(),
+        })
+    }
+
+    /// Downloads all segments concurrently. When the server turns out to
+    /// answer ranges with the whole file, starts over as a single stream
+    /// (unless `with_single_stream_fallback(false)`).
+    async fn download(&self) -> Result<(), DownloadError> {
+        match self.fetch_segments().await {
+            Err(DownloadError::RangeIgnored(reason)) => {
+                self.fall_back_to_single_stream(&reason).await;
+                self.fetch_segments().await
+            }
+            result => result,
+        }
+    }
+
+    async fn pause(&self) -> Result<(), DownloadError> {
+        // Cancel the token to stop all in-flight downloads.
+        // On resume, a new token would be created and incomplete segments restarted.
+        self.cancel_token.cancel();
+        Ok(())
+    }
+
+    /// Holds every segment task until `resume()`; nothing is cancelled.
+    async fn hold(&self) -> Result<(), DownloadError> {
+        self.pause_token.pause();
+        Ok(())
+    }
+
+    async fn resume(&self) -> Result<(), DownloadError> {
+        self.pause_token.resume();
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<(), DownloadError> {
+        self.cancel_token.cancel();
+        Ok(())
+    }
+
+    fn cancel_segment(&self, id: &str) -> bool {
+        match self.segment_tokens.lock().unwrap().get(id) {
+            Some(token) => {
+                log::info!("[cancel_segment] segment={}", id);
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn discard_temp_files(&self) {
+        if let Some(guard) = self.temp_guard.lock().unwrap().take() {
+            guard.disarm();
+        }
+        if self.keep_temp {
+            return;
+        }
+        let temp_dir = PathBuf::from(&self.state.read().unwrap().temp_dir);
+        if temp_dir.exists() {
+            log::info!("[cleanup] removing temp dir {} of a discarded download", temp_dir.display());
+            let _ = std::fs::remove_dir_all(&temp_dir);
+        }
+    }
+
+    /// Assembles all downloaded segments into the final output file.
+    /// Sorts segments by offset and concatenates their temp files; a single
+    /// segment is renamed into place instead. When a
+    /// separate audio track was downloaded, each stream is assembled into its
+    /// own file in the temp directory and the two are muxed with ffmpeg.
+    async fn postprocess(&self) -> Result<(), DownloadError> {
+        if self.state.read().unwrap().skipped {
+            return Ok(());
+        }
+        let append = self.existing_bytes.load(Ordering::SeqCst) > 0;
+        let published_digest = self.fetch_published_checksum().await?;
+
+        // Extract all needed data under locks, then drop them before I/O
+        let (video_ids, audio_ids, ranged, temp_dir, output_file, derived_for, compute_checksum, store_compression) = {
+            let segments = self.segments.read().await;
+            let state = self.state.read().unwrap();
+
+            // Verify all segments are finished
+            for segment in segments.values() {
+                if segment.state != SegmentState::Finished {
+                    return Err(DownloadError::SegmentFailed(format!(
+                        "segment {} is in state {:?}, expected Finished",
+                        segment.id, segment.state
+                    )));
+                }
+            }
+
+            // Sort each stream's segments by offset
+            let sorted_ids = |stream_type: StreamType| -> Vec<String> {
+                let mut sorted: Vec<_> = segments
+                    .values()
+                    .filter(|s| s.stream_type == stream_type)
+                    .collect();
+                sorted.sort_by_key(|s| s.offset);
+                sorted.iter().map(|s| s.id.clone()).collect()
+            };
+
+            let video_ids = sorted_ids(StreamType::Primary);
+            let audio_ids = sorted_ids(StreamType::Secondary);
+            // Segments with a known range; their temp files must not be longer.
+            let ranged: Vec<(String, u64)> = segments
+                .values()
+                .filter(|s| s.length > 0)
+                .map(|s| (s.id.clone(), s.length as u64))
+                .collect();
+            let temp_dir = state.temp_dir.clone();
+
+            // Resolve the output file path:
+            //   1. Use the pre-computed output_path if it names a file.
+            //   2. Otherwise (unset, empty or a directory) derive a name in
+            //      that directory from the attachment_name or the URL; it is
+            //      made unique below.
+            let (base_output, derived) = match state
+                .output_path
+                .clone()
+                .filter(|p| !p.is_empty() && !Path::new(p).is_dir())
+            {
+                Some(path) => (path, false),
+                None => {
+                    let dir = PathBuf::from(state.output_path.clone().unwrap_or_default());
+                    let name = fallback_file_name(&state.url, state.attachment_name.as_deref());
+                    (dir.join(name).to_string_lossy().into_owned(), true)
+                }
+            };
+
+            // If the resolved path has no extension, try to add one from:
+            //   a) the attachment_name (Content-Disposition)
+            //   b) the content_type (MIME type)
+            // A continued partial keeps its existing name.
+            let output_file = if append {
+                base_output
+            } else {
+                ensure_extension(
+                    base_output,
+                    state.attachment_name.as_deref(),
+                    state.content_type.as_deref(),
+                )
+            };
+
+            (video_ids, audio_ids, ranged, temp_dir, output_file, derived.then(|| state.id.clone()), state.compute_checksum, state.store_compression)
+        }; // locks dropped here — not held during I/O
+
+        if self.output_target == OutputTarget::Stdout {
+            return self.write_to_stdout(video_ids, ranged, temp_dir, compute_checksum, published_digest).await;
+        }
+
+        // A derived name must not clobber an earlier download's file.
+        let (output_file, reserved) = match derived_for {
+            Some(id) => {
+                let path = reserve_unique_path(&output_file, &id).await.map_err(DownloadError::Disk)?;
+                (path.clone(), Some(path))
+            }
+            None => (output_file, None),
+        };
+
+        // Record the resolved path so callers see where the file actually went.
+        self.state.write().unwrap().output_path = Some(output_file.clone());
+
+        let keep_temp = self.keep_temp;
+        let sniff = self.sniff_extension && !append;
+        let assembly_threads = self.assembly_threads;
+        let ffmpeg = self.ffmpeg.clone();
+        // Stopping the download (at a deadline, say) also stops the
+        // assembly, which the dropped postprocess no longer waits for.
+        let cancel = self.cancel_token.clone();
+
+        // File assembly is CPU/IO bound — run on a blocking thread
+        let final_output = tokio::task::spawn_blocking(move || {
+            let temp_dir = PathBuf::from(&temp_dir);
+            truncate_overlong_segments(&temp_dir, &ranged)?;
+            let kept = if append { std::fs::metadata(&output_file)?.len() } else { 0 };
+
+            // A lone segment already is the whole file: move it into place
+            // instead of copying it (kept temp files must stay put).
+            let moved = !append
+                && !keep_temp
+                && audio_ids.is_empty()
+                && video_ids.len() == 1
+                && move_segment(&temp_dir.join(&video_ids[0]), Path::new(&output_file));
+
+            let assemble = || -> Result<Option<String>, DownloadError> {
+                Ok(if moved {
+                    compute_checksum
+                        .map(|algo| hash_file(Path::new(&output_file), algo))
+                        .transpose()?
+                } else if audio_ids.is_empty() && compute_checksum.is_none() && assembly_threads > 1 && video_ids.len() > 1 {
+                    assemble_segments_parallel(&temp_dir, &video_ids, Path::new(&output_file), append, assembly_threads, &cancel)?;
+                    None
+                } else if audio_ids.is_empty() {
+                    let mut checksum = compute_checksum.map(Checksum::new);
+                    assemble_segments(&temp_dir, &video_ids, Path::new(&output_file), append, checksum.as_mut(), &cancel)?;
+                    checksum.map(Checksum::finalize_hex)
+                } else {
+                    let video_path = temp_dir.join("video.track");
+                    let audio_path = temp_dir.join("audio.track");
+                    assemble_segments(&temp_dir, &video_ids, &video_path, false, None, &cancel)?;
+                    assemble_segments(&temp_dir, &audio_ids, &audio_path, false, None, &cancel)?;
+                    mux_audio_video(ffmpeg.as_deref(), &video_path, &audio_path, Path::new(&output_file))?;
+                    if !keep_temp {
+                        let _ = std::fs::remove_file(video_path);
+                        let _ = std::fs::remove_file(audio_path);
+                    }
+                    // ffmpeg wrote the output, so it is hashed afterwards.
+                    compute_checksum
+                        .map(|algo| hash_file(Path::new(&output_file), algo))
+                        .transpose()?
+                })
+            };
+            let checksum = match assemble() {
+                Err(_) if cancel.is_cancelled() => {
+                    discard_partial_output(Path::new(&output_file), append.then_some(kept));
+                    return Err(DownloadError::Cancelled);
+                }
+                result => result?,
+            };
+            // A muxed file has its own size; only a plain assembly can be
+            // checked against the server's.
+            let assembled_size = if audio_ids.is_empty() {
+                std::fs::metadata(&output_file).map(|m| m.len()).ok()
+            } else {
+                None
+            };
+
+            let output_file = if sniff {
+                append_sniffed_extension(output_file)
+            } else {
+                output_file
+            };
+            let (output_file, compressed_size) = store_compressed(output_file, store_compression)?;
+
+            if keep_temp {
+                log::info!("[postprocess] keeping temp files in {}", temp_dir.display());
+                return Ok((output_file, checksum, compressed_size, assembled_size));
+            }
+
+            // Clean up segment files and their `.state` records
+            let _ = std::fs::remove_dir_all(&temp_dir);
+
+            Ok::<_, DownloadError>((output_file, checksum, compressed_size, assembled_size))
+        })
+        .await
+        .map_err(|e| DownloadError::SegmentFailed(e.to_string()))
+        .and_then(|assembled| assembled);
+        let final_output = match final_output {
+            Ok(final_output) => final_output,
+            Err(e) => {
+                // Don't leave the reserved name (empty, or half assembled) behind.
+                if let Some(reserved) = reserved {
+                    let _ = tokio::fs::remove_file(&reserved).await;
+                }
+                return Err(e);
+            }
+        };
+
+        let (final_output, checksum, compressed_size, assembled_size) = final_output;
+        if let Some(guard) = self.temp_guard.lock().unwrap().take() {
+            guard.disarm();
+        }
+
+        let expected_size = self.state.read().unwrap().file_size;
+        if let Some(actual) = assembled_size.filter(|&actual| expected_size > 0 && actual != expected_size as u64) {
+            let message = format!(
+                "downloaded size differs from expected ({} vs {} bytes)",
+                actual, expected_size
+            );
+            log::warn!("[postprocess] {}", message);
+            let progress_tx = self.progress_tx.lock().unwrap().clone();
+            if let Some(tx) = progress_tx {
+                let _ = tx.send(Ok(ProgressEvent::warning(message))).await;
+            }
+        }
+
+        let last_modified = self.state.read().unwrap().last_modified.clone();
+        if let Some(last_modified) = last_modified.filter(|_| self.preserve_mtime) {
+            set_mtime_from_http_date(Path::new(&final_output), &last_modified);
+        }
+        if let Some(record) = self.validators.lock().unwrap().take() {
+            store_cache_record(Path::new(&final_output), record);
+        }
+
+        {
+            let mut state = self.state.write().unwrap();
+            state.output_path = Some(final_output);
+            state.checksum = checksum.clone();
+            state.compressed_size = compressed_size;
+        }
+
+        if let (Some(expected), Some(actual)) = (published_digest, checksum) {
+            if expected != actual {
+                return Err(DownloadError::ChecksumMismatch { expected, actual });
+            }
+            log::info!("[postprocess] output matches the published checksum");
+        }
+        Ok(())
+    }
+}
+
+impl MultipartDownloadStrategy {
+    /// `postprocess` for [`OutputTarget::Stdout`]: copies the finished
+    /// segments, in order, to standard output, then checks the size and
+    /// any published checksum as for a file. The output path is left alone
+    /// and recorded as `-`.
+    async fn write_to_stdout(
+        &self,
+        segment_ids: Vec<String>,
+        ranged: Vec<(String, u64)>,
+        temp_dir: String,
+        compute_checksum: Option<ChecksumAlgo>,
+        published_digest: Option<String>,
+    ) -> Result<(), DownloadError> {
+        let keep_temp = self.keep_temp;
+        let (written, checksum) = tokio::task::spawn_blocking(move || {
+            let temp_dir = PathBuf::from(&temp_dir);
+            truncate_overlong_segments(&temp_dir, &ranged)?;
+            let mut checksum = compute_checksum.map(Checksum::new);
+            let written = copy_segments(&temp_dir, &segment_ids, &mut std::io::stdout().lock(), checksum.as_mut())?;
+            if !keep_temp {
+                let _ = std::fs::remove_dir_all(&temp_dir);
+            }
+            Ok::<_, DownloadError>((written, checksum.map(Checksum::finalize_hex)))
+        })
+        .await
+        .map_err(|e| DownloadError::SegmentFailed(e.to_string()))??;
+        log::info!("[postprocess] wrote {} bytes to stdout", written);
+
+        if let Some(guard) = self.temp_guard.lock().unwrap().take() {
+            guard.disarm();
+        }
+        let expected_size = self.state.read().unwrap().file_size;
+        if expected_size > 0 && written != expected_size as u64 {
+            let message = format!("downloaded size differs from expected ({} vs {} bytes)", written, expected_size);
+            log::warn!("[postprocess] {}", message);
+            let progress_tx = self.progress_tx.lock().unwrap().clone();
+            if let Some(tx) = progress_tx {
+                let _ = tx.send(Ok(ProgressEvent::warning(message))).await;
+            }
+        }
+        {
+            let mut state = self.state.write().unwrap();
+            state.output_path = Some("-".to_string());
+            state.checksum = checksum.clone();
+        }
+
+        if let (Some(expected), Some(actual)) = (published_digest, checksum) {
+            if expected != actual {
+                return Err(DownloadError::ChecksumMismatch { expected, actual });
+            }
+            log::info!("[postprocess] output matches the published checksum");
+        }
+        Ok(())
+    }
+}
+
+/// Sets the modification time of `path` to the HTTP date `last_modified`.
+/// An unparseable date or a failure to set it is logged and otherwise
+/// ignored; the download has succeeded either way.
+fn set_mtime_from_http_date(path: &Path, last_modified: &str) {
+    let mtime = match httpdate::parse_http_date(last_modified.trim()) {
+        Ok(mtime) => mtime,
+        Err(e) => {
+            log::warn!("[postprocess] not setting mtime: Last-Modified {:?} is not an HTTP date ({})", last_modified, e);
+            return;
+        }
+    };
+    let result = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(mtime));
+    match result {
+        Ok(()) => log::info!("[postprocess] set mtime of {} to {}", path.display(), last_modified),
+        Err(e) => log::warn!("[postprocess] could not set mtime of {}: {}", path.display(), e),
+    }
+}
+
+/// Records `record`'s validators, with the size of the finished `output`, in
+/// its sidecar; without validators any old sidecar is removed instead. A
+/// failure only costs the next run its shortcut, so it is logged.
+fn store_cache_record(output: &Path, mut record: CacheRecord) {
+    if record.etag.is_none() && record.last_modified.is_none() {
+        cache::remove(output);
+        return;
+    }
+    let result = std::fs::metadata(output).and_then(|m| {
+        record.size = m.len();
+        cache::store(output, &record)
+    });
+    if let Err(e) = result {
+        log::warn!("[postprocess] could not write {}: {}", cache::sidecar_path(output).display(), e);
+    }
+}
+
+/// Compress the finished `output` with `codec`, if any (see
+/// `with_store_compression`). Returns the path the file now lives at and
+/// its compressed size.
+pub(crate) fn store_compressed(
+    output: String,
+    codec: Option<Codec>,
+) -> Result<(String, Option<u64>), DownloadError> {
+    match codec {
+        #[cfg(feature = "compression")]
+        Some(codec) => {
+            let (path, size) = crate::downloader::compression::compress_file(Path::new(&output), codec)?;
+            Ok((path, Some(size)))
+        }
+        _ => Ok((output, None)),
+    }
+}
+
+/// Bytes read from the start of the output for magic-byte sniffing.
+const SNIFF_LEN: usize = 8 * 1024;
 
-        // Spawn a tokio task for each segment — true concurrent downloads
-        let mut handles = Vec::with_capacity(segments_to_download.len());
+/// If `path` has no extension, sniff one from the file's leading bytes and
+/// rename the file to carry it. Returns the (possibly new) path; any failure
+/// leaves the file where it is.
+fn append_sniffed_extension(path: String) -> String {
+    use std::io::Read;
 
-        for segment in segments_to_download {
-            let client = Arc::clone(&self.client);
-            let header_data = Arc::clone(&header_data); // cheap Arc clone
-            let temp_dir = temp_dir.clone();
-            let cancel_token = self.cancel_token.clone();
-            let segment_tx = progress_tx.clone();
-            let segment_id_for_progress = segment.id.clone();
-            let segment_id_for_handle = segment.id.clone();
-            let segment_total_bytes = if segment.length > 0 {
-                Some(segment.length as u64)
-            } else {
-                None
-            };
+    if Path::new(&path).extension().is_some() {
+        return path;
+    }
 
-            let handle = tokio::spawn(async move {
-                download_segment(
-                    segment,
-                    &client,
-                    &header_data,
-                    temp_dir,
-                    cancel_token,
-                    |bytes_delta| {
-                        if let Some(tx) = &segment_tx {
-                            let _ = tx.try_send(Ok(ProgressEvent {
-                                segment_id: segment_id_for_progress.clone(),
-                                bytes_delta,
-                                total_bytes: segment_total_bytes,
-                            }));
-                        }
-                    },
-                )
-                .await
-            });
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    let read = std::fs::File::open(&path)
+        .and_then(|f| f.take(SNIFF_LEN as u64).read_to_end(&mut head));
+    if read.is_err() {
+        return path;
+    }
+    let Some(kind) = infer::get(&head) else {
+        return path;
+    };
 
-            handles.push((segment_id_for_handle, handle));
+    let renamed = format!("{}.{}", path, kind.extension());
+    if Path::new(&renamed).exists() {
+        log::warn!("[postprocess] not renaming {} to existing {}", path, renamed);
+        return path;
+    }
+    match std::fs::rename(&path, &renamed) {
+        Ok(()) => {
+            log::info!("[postprocess] sniffed {} ({}), renamed to {}", kind.mime_type(), path, renamed);
+            renamed
+        }
+        Err(e) => {
+            log::warn!("[postprocess] could not rename {}: {}", path, e);
+            path
         }
+    }
+}
 
-        // Wait for ALL tasks to complete, then update segments in a single lock
-        let results: Vec<_> = futures::future::join_all(
-            handles.into_iter().map(|(id, handle)| async move {
-                (id, handle.await)
-            }),
-        )
-        .await;
+/// Cuts each ranged segment's temp file down to the segment's length.
+/// `download_segment` stops at the end of the range, so anything past it can
+/// only be the rest of a full body a server sent instead of the range; left
+/// in place it would be spliced into the middle of the output.
+fn truncate_overlong_segments(temp_dir: &Path, ranged: &[(String, u64)]) -> std::io::Result<()> {
+    for (segment_id, length) in ranged {
+        let path = temp_dir.join(segment_id);
+        let size = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => continue,
+        };
+        if size > *length {
+            log::warn!(
+                "[postprocess] segment={} temp file is {} bytes, longer than its {} byte range; truncating",
+                segment_id, size, length
+            );
+            std::fs::OpenOptions::new().write(true).open(&path)?.set_len(*length)?;
+        }
+    }
+    Ok(())
+}
 
-        let mut segments_guard = self.segments.write().await;
-        let mut first_error: Option<DownloadError> = None;
+/// A running segment task in `download()`, with what the slowness check
+/// needs to know about it.
+struct SegmentTask {
+    /// The segment as it was handed to the task.
+    segment: Segment,
+    /// Stops just this task; a child of the download's token.
+    cancel: CancellationToken,
+    /// Bytes written so far, counted by the task's progress callback.
+    bytes: Arc<AtomicU64>,
+    /// `bytes` at the previous check.
+    last_bytes: u64,
+    /// When the segment was first seen below the slow threshold, if it
+    /// still is.
+    slow_since: Option<Instant>,
+    /// Cancelled in order to re-split, not because the download stopped.
+    resplit: bool,
+}
 
-        for (segment_id, result) in results {
-            match result {
-                Ok(Ok(updated_segment)) => {
-                    segments_guard.insert(segment_id, updated_segment);
-                }
-                Ok(Err(e)) => {
-                    if let Some(s) = segments_guard.get_mut(&segment_id) {
-                        s.state = SegmentState::Failed;
-                    }
-                    if first_error.is_none() {
-                        first_error = Some(e);
-                    }
-                }
-                Err(join_err) => {
-                    if let Some(s) = segments_guard.get_mut(&segment_id) {
-                        s.state = SegmentState::Failed;
-                    }
-                    if first_error.is_none() {
-                        first_error = Some(DownloadError::SegmentFailed(join_err.to_string()));
-                    }
-                }
-            }
-        }
+/// Compares each running segment's throughput since the last check with the
+/// median of the others, and cancels (for `resplit_segment`) those that have
+/// stayed below `SLOW_SEGMENT_RATIO` of it for `window`. Only ranged
+/// segments with at least `MIN_SEGMENT_SIZE` left qualify, and only when
+/// two or more others are running to compare against.
+fn mark_slow_segments(running: &mut HashMap<tokio::task::Id, SegmentTask>, window: Duration) {
+    let now = Instant::now();
+    let deltas: Vec<(tokio::task::Id, u64)> = running
+        .iter_mut()
+        .map(|(id, task)| {
+            let bytes = task.bytes.load(Ordering::Relaxed);
+            let delta = bytes.saturating_sub(task.last_bytes);
+            task.last_bytes = bytes;
+            (*id, delta)
+        })
+        .collect();
+    if deltas.len() < 3 {
+        return;
+    }
 
-        drop(segments_guard);
+    for (id, delta) in &deltas {
+        let mut others: Vec<u64> = deltas.iter().filter(|(other, _)| other != id).map(|(_, d)| *d).collect();
+        others.sort_unstable();
+        let median = others[others.len() / 2] as f64;
 
-        if let Some(e) = first_error {
-            if let Some(tx) = &progress_tx {
-                let _ = tx.try_send(Err(e.to_string()));
+        let task = running.get_mut(id).unwrap();
+        let left = task.segment.length - task.segment.downloaded - task.bytes.load(Ordering::Relaxed) as i64;
+        if task.resplit || task.segment.length <= 0 || left < MIN_SEGMENT_SIZE {
+            continue;
+        }
+        if median > 0.0 && (*delta as f64) < median * SLOW_SEGMENT_RATIO {
+            let since = *task.slow_since.get_or_insert(now);
+            if now.duration_since(since) >= window {
+                log::info!(
+                    "[download] segment={} has stayed below {:.0}% of the median speed for {:?}; re-splitting",
+                    task.segment.id, SLOW_SEGMENT_RATIO * 100.0, window
+                );
+                task.resplit = true;
+                task.cancel.cancel();
             }
-            return Err(e);
+        } else {
+            task.slow_since = None;
         }
+    }
+}
 
-        Ok(())
+/// Splits a segment cancelled by `mark_slow_segments` at the end of its temp
+/// file: the first part keeps the file and is finished, the second is a new
+/// segment for the rest of the range. Cutting at the file's length keeps the
+/// two temp files contiguous, so assembly concatenates them unchanged.
+fn resplit_segment(segment: &Segment, temp_dir: &Path) -> (Segment, Segment) {
+    let written = written_len(segment, temp_dir);
+    let done = Segment {
+        length: written,
+        downloaded: written,
+        state: SegmentState::Finished,
+        ..segment.clone()
+    };
+    let mut rest = Segment::new(
+        segment_id(segment.stream_type, segment.offset + written),
+        segment.offset + written,
+        segment.length - written,
+    );
+    rest.stream_type = segment.stream_type;
+    if written == 0 {
+        let _ = std::fs::remove_file(temp_dir.join(&segment.id));
     }
+    (done, rest)
+}
 
-    async fn pause(&self) -> Result<(), DownloadError> {
-        // Cancel the token to stop all in-flight downloads.
-        // On resume, a new token would be created and incomplete segments restarted.
-        self.cancel_token.cancel();
-        Ok(())
+/// Bytes of `segment`'s range in its temp file.
+fn written_len(segment: &Segment, temp_dir: &Path) -> i64 {
+    std::fs::metadata(temp_dir.join(&segment.id))
+        .map(|m| m.len() as i64)
+        .unwrap_or(0)
+        .clamp(0, segment.length)
+}
+
+/// Id, and so temp file name, of the segment of `stream_type` starting at
+/// `offset`. The same plan always gets the same names, so a resumed
+/// download finds its own files; see `remove_stale_temp_files`.
+fn segment_id(stream_type: StreamType, offset: i64) -> String {
+    match stream_type {
+        StreamType::Primary => format!("seg_{}", offset),
+        StreamType::Secondary => format!("audio_{}", offset),
     }
+}
 
-    async fn stop(&self) -> Result<(), DownloadError> {
-        self.cancel_token.cancel();
-        Ok(())
+/// Removes the segment files in `temp_dir` except the temp and state files
+/// of those `segments` that have data to resume from. An earlier run with
+/// another plan (a different connection count, say) may have left files
+/// under the names this one uses, which must be neither kept nor appended
+/// to. Files not named like a segment's are left alone. Returns how many
+/// files were removed.
+fn remove_stale_temp_files(temp_dir: &Path, segments: &[Segment]) -> std::io::Result<usize> {
+    let keep: std::collections::HashSet<String> = segments
+        .iter()
+        .filter(|s| s.downloaded > 0)
+        .flat_map(|s| [s.id.clone(), format!("{}.state", s.id)])
+        .collect();
+    let mut removed = 0;
+    for entry in std::fs::read_dir(temp_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if !entry.file_type()?.is_file() || !is_segment_file_name(&name) || keep.contains(&name) {
+            continue;
+        }
+        log::debug!("[preprocess] removing stale temp file {}", entry.path().display());
+        std::fs::remove_file(entry.path())?;
+        removed += 1;
     }
+    Ok(removed)
+}
 
-    /// Assembles all downloaded segments into the final output file.
-    /// Sorts segments by offset and concatenates their temp files.
-    async fn postprocess(&self) -> Result<(), DownloadError> {
-        // Extract all needed data under locks, then drop them before I/O
-        let (segment_ids, temp_dir, output_file) = {
-            let segments = self.segments.read().await;
-            let state = self.state.read().unwrap();
+/// A segment's temp file or `.state` record: `seg_<offset>`,
+/// `audio_<offset>`, or the UUID segments were named by before.
+fn is_segment_file_name(name: &str) -> bool {
+    let id = name.strip_suffix(".state").unwrap_or(name);
+    match id.strip_prefix("seg_").or_else(|| id.strip_prefix("audio_")) {
+        Some(offset) => !offset.is_empty() && offset.bytes().all(|b| b.is_ascii_digit()),
+        None => id.len() == 36 && uuid::Uuid::parse_str(id).is_ok(),
+    }
+}
 
-            // Verify all segments are finished
-            for segment in segments.values() {
-                if segment.state != SegmentState::Finished {
-                    return Err(DownloadError::SegmentFailed(format!(
-                        "segment {} is in state {:?}, expected Finished",
-                        segment.id, segment.state
-                    )));
-                }
-            }
+/// Brings a restored segment in line with its temp file: a segment only
+/// counts what its file really holds, and bytes written past the recorded
+/// `downloaded` (flushed before a crash but never recorded) are cut off, so
+/// the resumed range appends right after them. A finished segment whose file
+/// is short goes back to downloading; an unranged one starts over.
+fn reconcile_with_temp_file(mut segment: Segment, temp_dir: &Path) -> std::io::Result<Segment> {
+    let path = temp_dir.join(&segment.id);
+    let on_disk = match std::fs::metadata(&path) {
+        Ok(metadata) => metadata.len() as i64,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e),
+    };
+    if segment.state == SegmentState::Finished && on_disk >= segment.length.max(0) {
+        return Ok(segment);
+    }
+    let downloaded = if segment.length > 0 { segment.downloaded.clamp(0, on_disk.min(segment.length)) } else { 0 };
+    if downloaded != segment.downloaded || segment.state == SegmentState::Finished {
+        log::warn!(
+            "[preprocess] restored segment={} recorded {} bytes ({:?}) but its file has {}; resuming from {}",
+            segment.id, segment.downloaded, segment.state, on_disk, downloaded
+        );
+    }
+    if on_disk > downloaded {
+        std::fs::OpenOptions::new().write(true).open(&path)?.set_len(downloaded as u64)?;
+    }
+    segment.downloaded = downloaded;
+    segment.state = SegmentState::NotStarted;
+    Ok(segment)
+}
 
-            // Sort segments by offset
-            let mut sorted: Vec<_> = segments.values().collect();
-            sorted.sort_by_key(|s| s.offset);
+/// Checks, in parallel, that each restored segment's temp file still holds
+/// the bytes its flush record hashed (see `with_verify_on_resume`). A
+/// segment whose file matches keeps the recorded prefix; one that does not,
+/// or has no hash to check, starts over from its first byte.
+async fn verify_segments(segments: Vec<Segment>, temp_dir: &Path) -> std::io::Result<Vec<Segment>> {
+    let checks = segments.into_iter().map(|segment| {
+        let temp_dir = temp_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || verify_segment(segment, &temp_dir))
+    });
+    futures::future::join_all(checks)
+        .await
+        .into_iter()
+        .map(|joined| joined.map_err(std::io::Error::other)?)
+        .collect()
+}
 
-            let segment_ids: Vec<String> = sorted.iter().map(|s| s.id.clone()).collect();
-            let temp_dir = state.temp_dir.clone();
+fn verify_segment(mut segment: Segment, temp_dir: &Path) -> std::io::Result<Segment> {
+    if segment.length <= 0 || segment.downloaded <= 0 {
+        return Ok(segment);
+    }
+    let path = temp_dir.join(&segment.id);
+    let record: Option<SegmentRecord> = std::fs::read(segment_state_path(temp_dir, &segment.id))
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok());
+    let verified = match record.and_then(|r| Some((r.segment.downloaded, r.sha256?))) {
+        Some((len, expected)) if len > 0 && len <= segment.downloaded => {
+            let mut hash = Checksum::new(ChecksumAlgo::Sha256);
+            hash.update_from_file_prefix(&path, len as u64)?;
+            (hash.finalize_hex() == expected).then_some(len)
+        }
+        _ => None,
+    };
+    let keep = verified.unwrap_or(0);
+    if keep == segment.downloaded {
+        return Ok(segment);
+    }
+    match verified {
+        Some(len) => log::warn!(
+            "[preprocess] segment={} is verified up to {} of {} bytes; resuming from there",
+            segment.id, len, segment.downloaded
+        ),
+        None => log::warn!(
+            "[preprocess] segment={} does not match its recorded hash; downloading it again",
+            segment.id
+        ),
+    }
+    std::fs::OpenOptions::new().write(true).open(&path)?.set_len(keep as u64)?;
+    segment.downloaded = keep;
+    segment.state = SegmentState::NotStarted;
+    Ok(segment)
+}
 
-            // Resolve the output file path:
-            //   1. Use the pre-computed output_path if set.
-            //   2. Fall back to the attachment_name from Content-Disposition.
-            //   3. Last resort: "download.bin".
-            let base_output = state
-                .output_path
-                .clone()
-                .or_else(|| state.attachment_name.clone())
-                .unwrap_or_else(|| "download.bin".to_string());
+/// Renames a single segment's temp file to `output`. Returns `false` when
+/// that is not possible (e.g. the temp directory is on another filesystem),
+/// in which case the caller copies it instead.
+fn move_segment(segment_path: &Path, output: &Path) -> bool {
+    match std::fs::rename(segment_path, output) {
+        Ok(()) => {
+            log::info!("[postprocess] moved single segment to {}", output.display());
+            true
+        }
+        Err(e) => {
+            log::info!("[postprocess] could not move {} ({}); copying instead", segment_path.display(), e);
+            false
+        }
+    }
+}
 
-            // If the resolved path has no extension, try to add one from:
-            //   a) the attachment_name (Content-Disposition)
-            //   b) the content_type (MIME type)
-            let output_file = ensure_extension(
-                base_output,
-                state.attachment_name.as_deref(),
-                state.content_type.as_deref(),
-            );
+/// Concatenates the temp files of `segment_ids` (already sorted) into `output`,
+/// replacing it, or appending to it when `append` is set. When `checksum` is
+/// given, the whole resulting file is hashed on the way (an appended-to file
+/// is read back first). Fails part way once `cancel` is cancelled. Returns
+/// the number of bytes written.
+pub(crate) fn assemble_segments(
+    temp_dir: &Path,
+    segment_ids: &[String],
+    output: &Path,
+    append: bool,
+    mut checksum: Option<&mut Checksum>,
+    cancel: &CancellationToken,
+) -> std::io::Result<u64> {
+    use std::fs::{File, OpenOptions};
+
+    if let (true, Some(checksum)) = (append, checksum.as_deref_mut()) {
+        checksum.update_from_file(output)?;
+    }
 
-            (segment_ids, temp_dir, output_file)
-        }; // locks dropped here — not held during I/O
+    let mut out = if append {
+        OpenOptions::new().append(true).open(output)?
+    } else {
+        File::create(output)?
+    };
+    let total_assembled = copy_segments(temp_dir, segment_ids, &mut CancellableWriter { inner: &mut out, cancel }, checksum)?;
 
-        // File assembly is CPU/IO bound — run on a blocking thread
-        tokio::task::spawn_blocking(move || {
-            use std::fs::File;
-            use std::io::Write;
+    log::info!(
+        "[postprocess] assembly complete: total_assembled={} bytes across {} segments, output={}",
+        total_assembled,
+        segment_ids.len(),
+        output.display()
+    );
 
-            let mut output = File::create(&output_file)?;
-            let mut total_assembled: u64 = 0;
+    Ok(total_assembled)
+}
 
-            for segment_id in &segment_ids {
-                let segment_path = PathBuf::from(&temp_dir).join(segment_id);
-                let segment_file_size = std::fs::metadata(&segment_path)?.len();
-                log::info!(
-                    "[postprocess] assembling segment={}: file_size={} bytes",
-                    segment_id, segment_file_size
-                );
-                total_assembled += segment_file_size;
+/// Writes the temp files of `segment_ids` (already sorted) to `out` one after
+/// another, hashing them into `checksum` on the way, and flushes it. Returns
+/// the number of bytes written.
+fn copy_segments(
+    temp_dir: &Path,
+    segment_ids: &[String],
+    out: &mut impl std::io::Write,
+    mut checksum: Option<&mut Checksum>,
+) -> std::io::Result<u64> {
+    let mut total: u64 = 0;
+    for segment_id in segment_ids {
+        let segment_path = temp_dir.join(segment_id);
+        let segment_file_size = std::fs::metadata(&segment_path)?.len();
+        log::info!(
+            "[postprocess] assembling segment={}: file_size={} bytes",
+            segment_id, segment_file_size
+        );
+        total += segment_file_size;
 
-                let mut input = File::open(&segment_path)?;
-                std::io::copy(&mut input, &mut output)?;
+        let mut input = std::fs::File::open(&segment_path)?;
+        match checksum.as_deref_mut() {
+            Some(checksum) => {
+                std::io::copy(&mut input, &mut HashingWriter { inner: &mut *out, checksum })?;
+            }
+            None => {
+                std::io::copy(&mut input, out)?;
             }
+        }
+    }
+    out.flush()?;
+    Ok(total)
+}
 
-            output.flush()?;
+/// Like [`assemble_segments`] without hashing, but copies up to `threads`
+/// segments at once: the output is sized up front, and each worker writes
+/// segments at their own offsets through a file handle of its own, so the
+/// reads and writes of different segments overlap. Returns the number of
+/// bytes written.
+pub(crate) fn assemble_segments_parallel(
+    temp_dir: &Path,
+    segment_ids: &[String],
+    output: &Path,
+    append: bool,
+    threads: usize,
+    cancel: &CancellationToken,
+) -> std::io::Result<u64> {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Seek, SeekFrom};
+
+    let base = if append { std::fs::metadata(output)?.len() } else { 0 };
+    let mut jobs = Vec::with_capacity(segment_ids.len());
+    let mut end = base;
+    for segment_id in segment_ids {
+        let segment_path = temp_dir.join(segment_id);
+        let len = std::fs::metadata(&segment_path)?.len();
+        jobs.push((segment_path, end, len));
+        end += len;
+    }
+    let out = if append {
+        OpenOptions::new().write(true).open(output)?
+    } else {
+        File::create(output)?
+    };
+    out.set_len(end)?;
+    drop(out);
 
-            log::info!(
-                "[postprocess] assembly complete: total_assembled={} bytes across {} segments, output={}",
-                total_assembled,
-                segment_ids.len(),
-                output_file
-            );
+    let threads = threads.clamp(1, jobs.len().max(1));
+    log::info!(
+        "[postprocess] assembling {} segments with {} threads into {}",
+        jobs.len(), threads, output.display()
+    );
+    let next = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| -> std::io::Result<()> {
+                    let mut out = OpenOptions::new().write(true).open(output)?;
+                    while let Some((segment_path, offset, len)) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        out.seek(SeekFrom::Start(*offset))?;
+                        let mut writer = CancellableWriter { inner: &mut out, cancel };
+                        std::io::copy(&mut File::open(segment_path)?.take(*len), &mut writer)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        workers.into_iter().try_for_each(|worker| {
+            worker
+                .join()
+                .unwrap_or_else(|_| Err(std::io::Error::other("assembly worker panicked")))
+        })
+    })?;
 
-            // Clean up temp files
-            for segment_id in &segment_ids {
-                let segment_path = PathBuf::from(&temp_dir).join(segment_id);
-                let _ = std::fs::remove_file(segment_path);
-            }
-            let _ = std::fs::remove_dir(&temp_dir);
+    log::info!(
+        "[postprocess] assembly complete: total_assembled={} bytes across {} segments, output={}",
+        end - base,
+        segment_ids.len(),
+        output.display()
+    );
+    Ok(end - base)
+}
 
-            Ok::<(), std::io::Error>(())
-        })
-        .await
-        .map_err(|e| DownloadError::SegmentFailed(e.to_string()))?
-        .map_err(DownloadError::Disk)?;
+/// Passes writes on to `inner` until `cancel` is cancelled, then fails
+/// them, so a copy into it stops part way.
+struct CancellableWriter<'a, W> {
+    inner: W,
+    cancel: &'a CancellationToken,
+}
 
-        Ok(())
+impl<W: std::io::Write> std::io::Write for CancellableWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(std::io::Error::other("assembly cancelled"));
+        }
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
     }
 }
 
+/// Undoes a stopped assembly into `output`: removes the file, or cuts one
+/// that was being appended to back to the bytes it `kept`.
+pub(crate) fn discard_partial_output(output: &Path, kept: Option<u64>) {
+    log::info!("[postprocess] assembly stopped; discarding the partial {}", output.display());
+    let _ = match kept {
+        Some(len) => std::fs::OpenOptions::new().write(true).open(output).and_then(|f| f.set_len(len)),
+        None => std::fs::remove_file(output),
+    };
+}
+
 // ---------------------------------------------------------------------------
 // Extension helpers
 // ---------------------------------------------------------------------------
 
+/// File name for a download the caller gave no file name: the sanitised
+/// `attachment_name`, else the URL's last path segment, else `download`.
+fn fallback_file_name(url: &str, attachment_name: Option<&str>) -> String {
+    let raw = attachment_name
+        .and_then(|n| Path::new(n).file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .or_else(|| {
+            let url = reqwest::Url::parse(url).ok()?;
+            let last = url.path_segments()?.rev().find(|s| !s.is_empty())?;
+            Some(percent_decode(last))
+        })
+        .unwrap_or_default();
+    sanitise_component(&raw)
+}
+
+/// Creates `path`, or `name_2.ext`, `name_3.ext`, … when it is taken, and
+/// returns the path created. Creating the file claims the name, so two
+/// downloads finishing at once still get different files. After 9999 taken
+/// names the download `id` is appended instead.
+async fn reserve_unique_path(path: &str, id: &str) -> std::io::Result<String> {
+    let pb = Path::new(path);
+    let stem = pb.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = pb.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    let candidates = std::iter::once(path.to_string())
+        .chain((2..=9999).map(|n| format!("{}_{}{}", stem, n, ext)))
+        .chain(std::iter::once(format!("{}_{}{}", stem, id, ext)));
+    for (i, name) in candidates.enumerate() {
+        let candidate = if i == 0 { pb.to_path_buf() } else { pb.with_file_name(name) };
+        match tokio::fs::OpenOptions::new().write(true).create_new(true).open(&candidate).await {
+            Ok(_) => return Ok(candidate.to_string_lossy().into_owned()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(std::io::Error::new(
+        std::io::ErrorKind::AlreadyExists,
+        format!("no free file name for {}", path),
+    ))
+}
+
 /// If `path` already has a file extension, return it unchanged.
 /// Otherwise try to derive an extension from `attachment_name` (Content-
 /// Disposition) or `content_type` (MIME type) and append it.
@@ -516,97 +2660,110 @@ fn ensure_extension(
     }
 }
 
-/// Map a MIME type string to a file extension.
-fn ext_from_mime(content_type: Option<&str>) -> Option<String> {
-    let mime = content_type?
-        .split(';')
-        .next()?
-        .trim()
-        .to_lowercase();
-
-    let ext = match mime.as_str() {
-        "video/mp4" | "video/x-m4v"                        => "mp4",
-        "video/x-matroska"                                  => "mkv",
-        "video/webm"                                        => "webm",
-        "video/x-msvideo"                                   => "avi",
-        "video/quicktime"                                   => "mov",
-        "video/x-ms-wmv"                                    => "wmv",
-        "video/3gpp"                                        => "3gp",
-        "video/x-flv"                                       => "flv",
-        "video/mpeg"                                        => "mpg",
-        "audio/mpeg"                                        => "mp3",
-        "audio/flac"                                        => "flac",
-        "audio/ogg"                                         => "ogg",
-        "audio/wav" | "audio/x-wav"                        => "wav",
-        "audio/aac"                                         => "aac",
-        "audio/x-m4a" | "audio/mp4"                        => "m4a",
-        "audio/opus"                                        => "opus",
-        "application/zip"                                   => "zip",
-        "application/x-tar"                                 => "tar",
-        "application/gzip" | "application/x-gzip"          => "gz",
-        "application/x-bzip2"                               => "bz2",
-        "application/x-7z-compressed"                       => "7z",
-        "application/x-rar-compressed" | "application/vnd.rar" => "rar",
-        "application/pdf"                                   => "pdf",
-        "application/x-msdownload"                          => "exe",
-        "application/x-ms-installer" | "application/x-msi" => "msi",
-        "application/vnd.debian.binary-package"             => "deb",
-        "application/x-rpm"                                 => "rpm",
-        "application/x-apple-diskimage"                     => "dmg",
-        _ => return None,
-    };
-    Some(ext.to_string())
+impl CommonOptions for MultipartDownloadStrategyBuilder {
+    fn common_parts(&mut self) -> CommonParts<'_> {
+        CommonParts {
+            state: &self.strategy.state,
+            segment_options: &mut self.strategy.segment_options,
+            system_proxy: &mut self.system_proxy,
+            shared_limiter: &mut self.strategy.shared_limiter,
+            create_parent: &mut self.strategy.create_parent,
+            keep_temp: &mut self.strategy.keep_temp,
+            connections: &mut self.strategy.connections,
+            connect_guard: &mut self.strategy.connect_guard,
+            ffmpeg: &mut self.strategy.ffmpeg,
+        }
+    }
 }
 
 impl MultipartDownloadStrategyBuilder {
     pub fn new(url: String, path: PathBuf) -> Self {
         Self {
             strategy: MultipartDownloadStrategy::new(url, path),
+            system_proxy: true,
+            http_version: HttpVersion::Auto,
         }
     }
 
-    pub fn with_cookies(self, cookies: String) -> Self {
+    /// Downloads `audio_url` alongside the main (video) URL and muxes the two
+    /// into the output file with ffmpeg.
+    pub fn with_audio_url(self, audio_url: String) -> Self {
         {
             let mut state = self.strategy.state.write().unwrap();
-            state.cookies = Some(cookies);
+            state.audio_url = Some(audio_url);
         }
         self
     }
 
-    pub fn with_headers(self, headers: HashMap<String, Vec<String>>) -> Self {
+    /// Alternative URLs for the same file. Each is checked against the
+    /// primary URL's size and ETag before downloading; failed segment
+    /// attempts move on to the next mirror.
+    pub fn with_mirrors(self, mirrors: Vec<String>) -> Self {
         {
             let mut state = self.strategy.state.write().unwrap();
-            state.headers = headers;
+            state.mirrors = mirrors;
         }
         self
     }
 
-    pub fn add_header<K, V>(self, key: K, value: V) -> Self
-    where
-        K: Into<String>,
-        V: Into<String>,
-    {
+    /// Send requests with `method` instead of GET. Servers rarely honour
+    /// ranges on anything else, so a non-GET download skips the probe and
+    /// runs as one non-resumable segment; it cannot be continued either.
+    pub fn with_method(self, method: reqwest::Method) -> Self {
         {
             let mut state = self.strategy.state.write().unwrap();
-            let key = key.into();
-            let value = value.into();
-            // Replace the existing value(s) for this key — using insert instead
-            // of push so that calling add_header("User-Agent", ua) never
-            // produces a duplicate when the key is already present in the
-            // captured request headers.
-            state.headers.insert(key, vec![value]);
+            state.method = (method != reqwest::Method::GET).then(|| method.to_string());
         }
         self
     }
 
-    pub fn with_authentication(self, auth: AuthenticationInfo) -> Self {
+    /// Body sent with every request, typically alongside
+    /// [`with_method`](Self::with_method).
+    pub fn with_body(self, body: Vec<u8>) -> Self {
         {
             let mut state = self.strategy.state.write().unwrap();
-            state.authentication = Some(auth);
+            state.body = Some(body);
         }
         self
     }
 
+    /// `Accept` to send when the captured headers carry none (default
+    /// [`DefaultAccept::Skip`]; see [`accept_for_content_type`]).
+    pub fn with_default_accept(self, accept: DefaultAccept) -> Self {
+        self.strategy.state.write().unwrap().default_accept = accept;
+        self
+    }
+
+    /// Send `Cache-Control: no-cache` and `Pragma: no-cache` with the probe
+    /// and every segment request (default off), so caching proxies on the
+    /// way check with the origin instead of answering with a stale size,
+    /// ETag or bytes of a resource that changes. Every request then reaches
+    /// the origin, which adds to its load.
+    pub fn with_no_cache(self, no_cache: bool) -> Self {
+        self.strategy.state.write().unwrap().no_cache = no_cache;
+        self
+    }
+
+    /// HTTP version to download with (default `Auto`). Under HTTP/2 the
+    /// segments are concurrent streams over one connection rather than
+    /// separate connections, so `with_connection_size` sets how many streams
+    /// run at once; `Http1` keeps one connection per segment, which suits
+    /// servers and CDNs that throttle each connection.
+    pub fn with_http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    /// When the URL redirects to another host, keep sending the credentials,
+    /// cookies and `Authorization`/`Cookie` headers there (default false).
+    /// By default they go only to the original host: the probe's redirect
+    /// drops them, and so do the segment requests to the final URL. Enable
+    /// for a host that hands off to a CDN which checks the same credentials.
+    pub fn with_forward_auth_on_redirect(mut self, forward: bool) -> Self {
+        self.strategy.forward_auth_on_redirect = forward;
+        self
+    }
+
     pub fn with_proxy(self, proxy: ProxyInfo) -> Self {
         {
             let mut state = self.strategy.state.write().unwrap();
@@ -647,14 +2804,229 @@ impl MultipartDownloadStrategyBuilder {
         self
     }
     
-    pub fn with_connection_size(mut self, connections: usize) -> Self {
+    /// Flush each segment's buffered bytes to disk every `interval` while
+    /// streaming and record its progress in `<id>.state` beside the temp
+    /// file, so a crash loses at most one interval and the record matches
+    /// what was written. Off by default, as every flush costs throughput.
+    pub fn with_flush_interval(mut self, interval: FlushInterval) -> Self {
+        self.strategy.segment_options.flush_interval = Some(interval);
+        self
+    }
+
+    /// Hash each segment's bytes into its `<id>.state` record as they are
+    /// written, and when a segment map is restored (`restore_segments`)
+    /// re-read every temp file and check it against that hash before
+    /// resuming. A segment that fails the check (bit-rot, or a stale file
+    /// from another download) is fetched again from its first byte. Turns
+    /// on the default flush interval if none is set. Off by default: it
+    /// reads every kept byte back on resume.
+    pub fn with_verify_on_resume(mut self, verify: bool) -> Self {
+        let options = &mut self.strategy.segment_options;
+        options.record_hash = verify;
+        if verify && options.flush_interval.is_none() {
+            options.flush_interval = Some(FlushInterval::default());
+        }
+        self
+    }
+
+    /// Sniff the file type from its first bytes after assembly and append a
+    /// matching extension when the output name has none (default off). An
+    /// existing extension is never replaced.
+    pub fn with_sniff_extension(mut self, sniff: bool) -> Self {
+        self.strategy.sniff_extension = sniff;
+        self
+    }
+
+    /// Segments postprocess copies into the output at once (default
+    /// [`DEFAULT_ASSEMBLY_THREADS`], minimum 1). Parallel copies may help
+    /// fast disks on large files; 1 assembles serially, which spares a
+    /// spinning disk the seeking. Assembly is serial anyway while a checksum is
+    /// computed, since the hash needs the bytes in order.
+    pub fn with_assembly_threads(mut self, threads: usize) -> Self {
+        self.strategy.assembly_threads = threads.max(1);
+        self
+    }
+
+    /// Keep the server's ETag and Last-Modified in a sidecar next to the
+    /// output (`<output>.rdm-cache`, default off). Downloading the same URL
+    /// to the same path again then probes with `If-None-Match` /
+    /// `If-Modified-Since`; on `304` with the file still in place nothing
+    /// is fetched and the summary reports `skipped`.
+    pub fn with_conditional_cache(mut self, enabled: bool) -> Self {
+        self.strategy.conditional_cache = enabled;
+        self
+    }
+
+    /// Set the output file's modification time to the server's
+    /// `Last-Modified` (default off), as `wget -N` does. Skipped when the
+    /// server sends none or an unparseable date.
+    pub fn with_preserve_mtime(mut self, preserve: bool) -> Self {
+        self.strategy.preserve_mtime = preserve;
+        self
+    }
+
+    /// Download in a single stream without `Range` requests, as if the
+    /// server did not support them (default off). An escape hatch for
+    /// servers that advertise ranges but serve them wrong; the probed size
+    /// is still used for progress. `with_continue` has no effect with it.
+    pub fn with_force_single_stream(mut self, force: bool) -> Self {
+        self.strategy.force_single_stream = force;
+        self
+    }
+
+    /// Restart as a single stream when the server answers a segment's
+    /// range with the whole file, a `200` or a `206` spanning far more than
+    /// asked (default on). Some CDNs honour the `bytes=0-0` probe that way
+    /// and nothing else. Off, the first segment keeps its slice of the
+    /// response and the others fail with `ResourceChanged`.
+    pub fn with_single_stream_fallback(mut self, fallback: bool) -> Self {
+        self.strategy.segment_options.restart_on_ignored_range = fallback;
+        self
+    }
+
+    /// Download only bytes `start..=end` of the resource, in one segment
+    /// whose requests carry `Range: bytes=start-end`, instead of the whole
+    /// file. For sub-resources a browser fetched by range (a DASH init
+    /// segment, say). The download is not resumable, `with_probe_hints` is
+    /// ignored, and a range past the probed size fails preprocess with
+    /// [`DownloadError::SegmentPlan`].
+    pub fn with_byte_range(mut self, start: u64, end: u64) -> Self {
+        self.strategy.byte_range = Some((start, end));
+        self
+    }
+
+    /// Expect a Content-Type starting with `prefix` (`video/`, say) and fail
+    /// download with [`DownloadError::UnexpectedContentType`] when the
+    /// probe finds an HTML page instead (the segment responses, when probe
+    /// hints stood in for the probe), rather than save an error page as the
+    /// file. Other types, including none, are let through (default
+    /// off).
+    pub fn with_expect_content_type(mut self, prefix: impl Into<String>) -> Self {
+        self.strategy.expect_content_type = Some(prefix.into());
+        self
+    }
+
+    /// Write the finished download to standard output instead of the
+    /// output path (default [`OutputTarget::File`]). The segments are still
+    /// downloaded to the temp directory and written out, in order, by
+    /// postprocess. `with_continue`, `with_conditional_cache`,
+    /// `with_preserve_mtime` and `with_store_compression` have no effect,
+    /// and a separate audio track fails preprocess, as muxing needs a file.
+    pub fn with_output_target(mut self, target: OutputTarget) -> Self {
+        self.strategy.output_target = target;
+        self
+    }
+
+    /// Response headers that may carry the file name when the server sends
+    /// no `Content-Disposition`, tried in order (default
+    /// [`DEFAULT_FILENAME_HEADERS`]: `X-Filename`, `X-File-Name`). An empty
+    /// list turns the fallback off.
+    pub fn with_filename_headers(mut self, headers: Vec<String>) -> Self {
+        self.strategy.filename_headers = headers;
+        self
+    }
+
+    /// Re-split a ranged segment that stays far slower than the others for
+    /// `window` (default [`DEFAULT_SLOW_SEGMENT_WINDOW`]): it is stopped and
+    /// the rest of its range continues on a new connection. `None` disables
+    /// this; a retry only happens on errors and stalls then.
+    pub fn with_slow_segment_window(mut self, window: Option<Duration>) -> Self {
+        self.strategy.slow_segment_window = window;
+        self
+    }
+
+    /// Download over one connection at a time, for servers that support
+    /// ranges but throttle or refuse parallel connections: the file is one
+    /// segment whatever the connection size, resumed with a `Range` request
+    /// when its connection drops, and segments restored from a saved state
+    /// or an audio track run one after another instead of side by side.
+    pub fn with_sequential(mut self, sequential: bool) -> Self {
+        self.strategy.sequential = sequential;
+        self
+    }
+
+    /// Bytes each segment aims for (default [`DEFAULT_TARGET_SEGMENT_SIZE`]):
+    /// a file is split into `size / target` segments, at least one and at
+    /// most the connection count, which stays the hard upper bound.
+    pub fn with_target_segment_size(mut self, size: u64) -> Self {
+        self.strategy.target_segment_size = size.max(1);
+        self
+    }
+
+    /// Fetch anything smaller than `size` bytes as a single (still
+    /// resumable) segment, however many connections are allowed (default
+    /// [`DEFAULT_MULTIPART_THRESHOLD`]). `0` splits every file by
+    /// `with_target_segment_size` alone.
+    pub fn with_multipart_threshold(mut self, size: u64) -> Self {
+        self.strategy.multipart_threshold = size;
+        self
+    }
+
+    /// Facts about the resource already known from an earlier response.
+    /// When they settle resumability (and the size, if resumable) the probe
+    /// request is skipped and the segments verify the size instead; partial
+    /// hints are ignored and the URL is probed as usual.
+    /// A hinted content type also shapes the default `Accept` header.
+    pub fn with_probe_hints(mut self, hints: ProbeHints) -> Self {
         {
-            self.strategy.connections= connections;
+            let mut state = self.strategy.state.write().unwrap();
+            if state.content_type.is_none() {
+                state.content_type = hints.content_type.clone();
+            }
         }
+        self.strategy.probe_hints = Some(hints);
+        self
+    }
+
+    /// Verify the output against a published checksum file such as
+    /// `file.iso.sha256`, fetched with the download's headers, cookies and
+    /// credentials after the segments are in. Both `sha256sum` and BSD
+    /// formats are read; md5, sha256 and sha512 digests are supported. A mismatch
+    /// fails postprocess with `ChecksumMismatch` (the output is kept). An
+    /// explicit `with_expected_checksum` takes precedence over the file.
+    pub fn with_checksum_url(self, url: String) -> Self {
+        self.strategy.state.write().unwrap().checksum_url = Some(url);
         self
     }
 
-    pub fn build(self) -> MultipartDownloadStrategy {
+    /// Resume into an existing partial file at the output path: only the
+    /// missing tail is downloaded and appended. Falls back to a full download
+    /// when the server is not resumable.
+    pub fn with_continue(mut self, continue_partial: bool) -> Self {
+        self.strategy.continue_partial = continue_partial;
+        self
+    }
+
+    /// A client certificate or key that can't be loaded is reported by
+    /// `preprocess` as `DownloadError::Tls`.
+    pub fn build(mut self) -> MultipartDownloadStrategy {
+        let (proxy, tls) = {
+            let state = self.strategy.state.read().unwrap();
+            (state.proxy.clone(), state.tls.clone())
+        };
+        let forward_auth = self.strategy.forward_auth_on_redirect;
+        if proxy.is_some()
+            || !self.system_proxy
+            || !tls.is_empty()
+            || self.http_version != HttpVersion::Auto
+            || forward_auth
+            || self.strategy.connect_guard.is_some()
+        {
+            match build_clients(
+                proxy.as_ref(),
+                self.system_proxy,
+                &tls,
+                self.http_version,
+                forward_auth,
+                self.strategy.connect_guard.as_ref(),
+            ) {
+                Ok(clients) => {
+                    self.strategy.client = Arc::new(clients.pooled);
+                    self.strategy.segment_options.reconnect_client = Some(clients.fresh);
+                }
+                Err(e) => self.strategy.client_error = Some(e),
+            }
+        }
         self.strategy
     }
 }
@@ -0,0 +1,119 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use super::observer::ProgressObserver;
+use super::snapshot::ProgressSnapshot;
+
+/// Default gap between two progress writes; see [`FileProgressObserver::with_min_interval`].
+pub const DEFAULT_WRITE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Writes the latest `ProgressSnapshot` as JSON to a file, for processes
+/// that poll rather than subscribe (`cat`, `jq`, a monitoring script).
+///
+/// Each write goes to `<path>.tmp` and is renamed over `path`, so a reader
+/// never sees a half-written file. Progress is written at most once per
+/// interval; the completed or failed snapshot is always written. Write
+/// errors are logged and otherwise ignored — they never fail the download.
+pub struct FileProgressObserver {
+    path: PathBuf,
+    min_interval: Duration,
+    /// Written into every snapshot's `download_id`, when set.
+    download_id: Option<String>,
+    /// Last snapshot seen (an error is recorded on top of it) and when it
+    /// was last written.
+    last: Mutex<(ProgressSnapshot, Option<Instant>)>,
+}
+
+impl FileProgressObserver {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            min_interval: DEFAULT_WRITE_INTERVAL,
+            download_id: None,
+            last: Mutex::new((ProgressSnapshot::empty(), None)),
+        }
+    }
+
+    /// Write progress at most once per `interval` (default 250 ms);
+    /// `Duration::ZERO` writes on every event.
+    pub fn with_min_interval(mut self, interval: Duration) -> Self {
+        self.min_interval = interval;
+        self
+    }
+
+    /// Stamp `id` as the `download_id` of every snapshot written.
+    pub fn with_download_id(mut self, id: impl Into<String>) -> Self {
+        self.download_id = Some(id.into());
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn write(&self, snapshot: &ProgressSnapshot) {
+        let stamped;
+        let snapshot = match &self.download_id {
+            Some(id) => {
+                stamped = ProgressSnapshot { download_id: id.clone(), ..snapshot.clone() };
+                &stamped
+            }
+            None => snapshot,
+        };
+        let json = match serde_json::to_vec_pretty(snapshot) {
+            Ok(json) => json,
+            Err(e) => {
+                log::warn!("[FileProgressObserver] could not serialize progress: {}", e);
+                return;
+            }
+        };
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let result = async {
+            tokio::fs::write(&tmp, &json).await?;
+            tokio::fs::rename(&tmp, &self.path).await
+        }
+        .await;
+        if let Err(e) = result {
+            log::warn!("[FileProgressObserver] could not write {}: {}", self.path.display(), e);
+            let _ = tokio::fs::remove_file(&tmp).await;
+        }
+    }
+}
+
+#[async_trait]
+impl ProgressObserver for FileProgressObserver {
+    async fn on_progress(&self, snapshot: &ProgressSnapshot) {
+        let due = {
+            let mut last = self.last.lock().unwrap();
+            last.0 = snapshot.clone();
+            let now = Instant::now();
+            let due = last.1.is_none_or(|at| now.duration_since(at) >= self.min_interval);
+            if due {
+                last.1 = Some(now);
+            }
+            due
+        };
+        if due {
+            self.write(snapshot).await;
+        }
+    }
+
+    async fn on_complete(&self, snapshot: &ProgressSnapshot) {
+        self.last.lock().unwrap().0 = snapshot.clone();
+        self.write(snapshot).await;
+    }
+
+    async fn on_error(&self, error: &str) {
+        let snapshot = {
+            let mut last = self.last.lock().unwrap();
+            last.0.done = true;
+            last.0.error = Some(error.to_string());
+            last.0.clone()
+        };
+        self.write(&snapshot).await;
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use super::observer::ProgressObserver;
+use super::snapshot::{format_bytes, ProgressSnapshot};
+use crate::downloader::strategy::download_strategy::DownloadStrategy;
+use crate::types::types::{PreprocessInfo, StreamType};
+
+/// Appends one download's lifecycle to its own log file: what the probe
+/// found, the segment plan, each segment starting, retrying and finishing,
+/// warnings, and how the download ended. Meant to be attached to a bug
+/// report, so it records transitions rather than every progress event.
+///
+/// Each observer writes only its own file, so concurrent downloads never
+/// interleave. A retried download appends to the same file. Write errors
+/// are logged and otherwise ignored — they never fail the download.
+pub struct DownloadLogObserver {
+    path: PathBuf,
+    /// Source of the probe results and segment plan logged at start.
+    strategy: Option<Arc<dyn DownloadStrategy>>,
+    started: Instant,
+    segments: Mutex<HashMap<String, SegmentLog>>,
+    /// Serialises appends so lines from overlapping calls stay whole.
+    write_lock: tokio::sync::Mutex<()>,
+}
+
+/// What has been logged about one segment so far.
+#[derive(Default)]
+struct SegmentLog {
+    retries: u32,
+    finished: bool,
+}
+
+impl DownloadLogObserver {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            strategy: None,
+            started: Instant::now(),
+            segments: Mutex::new(HashMap::new()),
+            write_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Log the URL, probe results and segment plan of `strategy` when the
+    /// download starts.
+    pub fn with_strategy(mut self, strategy: Arc<dyn DownloadStrategy>) -> Self {
+        self.strategy = Some(strategy);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn write(&self, lines: &[String]) {
+        if lines.is_empty() {
+            return;
+        }
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let text: String = lines.iter().map(|line| format!("[{:>9.3}s] {}\n", elapsed, line)).collect();
+        let _guard = self.write_lock.lock().await;
+        let result = async {
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(text.as_bytes()).await?;
+            file.flush().await
+        }
+        .await;
+        if let Err(e) = result {
+            log::warn!("[DownloadLogObserver] could not write {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[async_trait]
+impl ProgressObserver for DownloadLogObserver {
+    async fn on_start(&self, info: &PreprocessInfo) {
+        let unix = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut lines = vec![format!("download started (unix time {})", unix)];
+        if let Some(strategy) = &self.strategy {
+            let state = strategy.state_snapshot();
+            lines.push(format!("url: {}", state.url));
+            lines.push(format!("output: {}", state.output_path.unwrap_or_default()));
+            if let Some(last_modified) = state.last_modified {
+                lines.push(format!("last-modified: {}", last_modified));
+            }
+        }
+        lines.push(format!(
+            "probe: size={}, resumable={}, content-type={}, name={}",
+            info.file_size.map(|s| format!("{} bytes", s)).unwrap_or_else(|| "unknown".to_string()),
+            info.resumable,
+            info.content_type.as_deref().unwrap_or("-"),
+            info.attachment_name.as_deref().unwrap_or("-"),
+        ));
+        lines.push(format!("plan: {} segment(s)", info.segment_count));
+        if let Some(strategy) = &self.strategy {
+            let mut segments = strategy.segments_snapshot().await;
+            segments.sort_by_key(|s| (s.stream_type == StreamType::Secondary, s.offset));
+            for s in segments {
+                lines.push(format!(
+                    "  segment {} {:?}: offset={} length={}",
+                    s.id, s.stream_type, s.offset, s.length
+                ));
+            }
+        }
+        self.write(&lines).await;
+    }
+
+    async fn on_progress(&self, snapshot: &ProgressSnapshot) {
+        let lines: Vec<String> = {
+            let mut logged = self.segments.lock().unwrap();
+            let mut lines = Vec::new();
+            for s in &snapshot.segments {
+                let entry = logged.entry(s.segment_id.clone()).or_insert_with(|| {
+                    lines.push(format!("segment {} started", s.segment_id));
+                    SegmentLog::default()
+                });
+                if s.retry_count > entry.retries {
+                    entry.retries = s.retry_count;
+                    lines.push(format!(
+                        "segment {} retry #{} after {} bytes",
+                        s.segment_id, s.retry_count, s.bytes_downloaded
+                    ));
+                }
+                if !entry.finished && s.total_bytes > 0 && s.bytes_downloaded >= s.total_bytes {
+                    entry.finished = true;
+                    lines.push(format!("segment {} finished ({} bytes)", s.segment_id, s.bytes_downloaded));
+                }
+            }
+            lines
+        };
+        self.write(&lines).await;
+    }
+
+    async fn on_phase(&self, phase: &str) {
+        self.write(&[format!("phase: {}", phase)]).await;
+    }
+
+    async fn on_warning(&self, message: &str) {
+        self.write(&[format!("warning: {}", message)]).await;
+    }
+
+    async fn on_complete(&self, snapshot: &ProgressSnapshot) {
+        self.write(&[format!(
+            "complete: {} ({} bytes) in {:.1}s",
+            format_bytes(snapshot.total_bytes_downloaded),
+            snapshot.total_bytes_downloaded,
+            self.started.elapsed().as_secs_f64()
+        )])
+        .await;
+    }
+
+    async fn on_error(&self, error: &str) {
+        self.write(&[format!("failed: {}", error)]).await;
+    }
+}
+
+/// Removes `*.log` files in `dir` last written more than `max_age` ago.
+/// Returns how many were removed.
+pub fn prune_logs(dir: &Path, max_age: Duration) -> io::Result<usize> {
+    let now = SystemTime::now();
+    let mut removed = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("log") {
+            continue;
+        }
+        let age = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if age.is_some_and(|age| age > max_age) && std::fs::remove_file(&path).is_ok() {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
@@ -1,6 +1,8 @@
 pub mod observer;
 pub mod notifier;
 pub mod snapshot;
+pub mod file_observer;
+pub mod log_observer;
 
 // // Convenient re-exports
 // pub use observer::ProgressObserver;
@@ -1,15 +1,28 @@
-use std::collections::HashMap;
-use std::time::Instant;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 
 use tokio::sync::mpsc;
 
-use crate::types::types::ProgressEvent;
+use crate::types::types::{PreprocessInfo, ProgressEvent};
 use super::observer::ProgressObserver;
 use super::snapshot::{SegmentSnapshot, ProgressSnapshot};
 
 /// EMA smoothing factor. 0.3 = responsive but stable.
 const EMA_ALPHA: f64 = 0.3;
 
+/// Minimum spacing between two entries in the speed-sample ring buffer.
+const SPEED_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum number of speed samples kept for the sparkline (~1 minute).
+pub const MAX_SPEED_SAMPLES: usize = 60;
+
+/// Trailing window over which aggregate throughput is measured for the ETA.
+const ETA_WINDOW: Duration = Duration::from_secs(5);
+
+/// Time constant (seconds) for letting the ETA climb towards a higher
+/// estimate. Drops are applied immediately, so the ETA mostly counts down.
+const ETA_RISE_TAU: f64 = 3.0;
+
 /// Internal per-segment tracking (purely data, no UI).
 struct SegmentProgress {
     segment_id: String,
@@ -17,6 +30,7 @@ struct SegmentProgress {
     total_bytes: u64,
     speed: f64,
     last_update: Instant,
+    retry_count: u32,
 }
 
 /// Consumes `Result<ProgressEvent, String>` from the download channel,
@@ -28,6 +42,8 @@ struct SegmentProgress {
 /// | Channel message        | Observer method called          |
 /// |------------------------|---------------------------------|
 /// | `Ok(ProgressEvent)`    | `on_progress(&snapshot)`        |
+/// | `Ok` with a `warning`  | `on_warning(&msg)`              |
+/// | `Ok` with a `phase`    | `on_phase(&msg)`                |
 /// | `Err(String)`          | `on_error(&msg)` then stops     |
 /// | Channel closed (no err)| `on_complete(&final_snapshot)`  |
 pub struct ProgressNotifier {
@@ -35,15 +51,42 @@ pub struct ProgressNotifier {
     segments: HashMap<String, SegmentProgress>,
     segment_order: Vec<String>,
     start_time: Instant,
+    /// Ring buffer of `(elapsed_secs, bytes_per_sec)` samples.
+    speed_samples: VecDeque<(f64, f64)>,
+    /// Time and aggregate byte count when the last sample was taken.
+    last_sample_at: Instant,
+    last_sample_bytes: u64,
+    /// `(time, aggregate bytes)` points covering the last `ETA_WINDOW`.
+    eta_window: VecDeque<(Instant, u64)>,
+    /// Last published ETA and when it was computed.
+    last_eta: Option<(Instant, f64)>,
+    /// Warnings received so far, copied into every snapshot.
+    warnings: Vec<String>,
+    /// Latest phase, until the first progress event clears it.
+    phase: Option<String>,
+}
+
+impl Default for ProgressNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ProgressNotifier {
     pub fn new() -> Self {
+        let now = Instant::now();
         Self {
             observers: Vec::new(),
             segments: HashMap::new(),
             segment_order: Vec::new(),
-            start_time: Instant::now(),
+            start_time: now,
+            speed_samples: VecDeque::with_capacity(MAX_SPEED_SAMPLES),
+            last_sample_at: now,
+            last_sample_bytes: 0,
+            eta_window: VecDeque::new(),
+            last_eta: None,
+            warnings: Vec::new(),
+            phase: None,
         }
     }
 
@@ -52,34 +95,78 @@ impl ProgressNotifier {
         self.observers.push(observer);
     }
 
+    /// Tell every observer the download is starting. Called before `run()`.
+    pub async fn start(&self, info: &PreprocessInfo) {
+        for observer in &self.observers {
+            observer.on_start(info).await;
+        }
+    }
+
     /// Consume progress messages until the channel closes or an error arrives.
     pub async fn run(
         mut self,
         mut progress_rx: mpsc::Receiver<Result<ProgressEvent, String>>,
     ) {
         while let Some(msg) = progress_rx.recv().await {
-            match msg {
-                Ok(ev) => {
-                    let snapshot = self.handle_event(ev);
-                    for observer in &self.observers {
-                        observer.on_progress(&snapshot).await;
-                    }
-                }
-                Err(error) => {
-                    for observer in &self.observers {
-                        observer.on_error(&error).await;
-                    }
-                    return; // stop processing after error
-                }
+            if !self.dispatch(msg).await {
+                return; // stop processing after error
             }
         }
         // Channel closed cleanly — all senders dropped, no error received
         self.finish().await;
     }
 
+    /// Hand one channel message to the observers. Returns false for an
+    /// error, after which nothing more should be dispatched.
+    pub async fn dispatch(&mut self, msg: Result<ProgressEvent, String>) -> bool {
+        match msg {
+            Ok(ProgressEvent { warning: Some(warning), .. }) => {
+                for observer in &self.observers {
+                    observer.on_warning(&warning).await;
+                }
+                self.warnings.push(warning);
+            }
+            Ok(ProgressEvent { phase: Some(phase), .. }) => {
+                for observer in &self.observers {
+                    observer.on_phase(&phase).await;
+                }
+                self.phase = Some(phase);
+            }
+            Ok(ev) => {
+                let snapshot = self.handle_event(ev);
+                for observer in &self.observers {
+                    observer.on_progress(&snapshot).await;
+                }
+            }
+            Err(error) => {
+                for observer in &self.observers {
+                    observer.on_error(&error).await;
+                }
+                return false;
+            }
+        }
+        true
+    }
+
     /// Process a single progress event and return the updated snapshot.
-    fn handle_event(&mut self, ev: ProgressEvent) -> ProgressSnapshot {
+    ///
+    /// `run()` calls this for every `Ok` message; it is public so callers
+    /// that already have the events in hand can aggregate without a channel.
+    pub fn handle_event(&mut self, ev: ProgressEvent) -> ProgressSnapshot {
         let now = Instant::now();
+        self.phase = None;
+
+        // A zero total for a segment that has bytes: its data was discarded
+        // (a restart as a single stream), so it no longer counts.
+        let discarded = ev.total_bytes == Some(0)
+            && self.segments.get(&ev.segment_id).is_some_and(|s| s.bytes_downloaded > 0);
+        if discarded {
+            self.segments.remove(&ev.segment_id);
+            self.segment_order.retain(|id| id != &ev.segment_id);
+            let mut snapshot = self.build_snapshot();
+            snapshot.eta_secs = self.smoothed_eta(now, &snapshot);
+            return snapshot;
+        }
 
         // Lazy init: track new segment_id on first sight
         if !self.segments.contains_key(&ev.segment_id) {
@@ -93,6 +180,7 @@ impl ProgressNotifier {
                     total_bytes: total,
                     speed: 0.0,
                     last_update: now,
+                    retry_count: 0,
                 },
             );
         }
@@ -101,24 +189,105 @@ impl ProgressNotifier {
         {
             let segment = self.segments.get_mut(&ev.segment_id).unwrap();
             segment.bytes_downloaded += ev.bytes_delta;
+            if ev.retry {
+                segment.retry_count += 1;
+            }
 
-            // Update total_bytes if we didn't know it before
-            if segment.total_bytes == 0 {
-                if let Some(tb) = ev.total_bytes {
-                    segment.total_bytes = tb;
-                }
+            // Take the latest total; it only changes when a slow segment is
+            // re-split and keeps just what it has downloaded.
+            if let Some(tb) = ev.total_bytes {
+                segment.total_bytes = tb;
             }
 
-            // Compute EMA speed
+            // Compute EMA speed (retry events carry no bytes, so skip them)
             let elapsed = now.duration_since(segment.last_update).as_secs_f64();
-            if elapsed > 0.0 {
+            if elapsed > 0.0 && !ev.retry {
                 let instant_speed = ev.bytes_delta as f64 / elapsed;
                 segment.speed = EMA_ALPHA * instant_speed + (1.0 - EMA_ALPHA) * segment.speed;
                 segment.last_update = now;
             }
         }
 
-        self.build_snapshot()
+        self.record_speed_sample(now);
+
+        let mut snapshot = self.build_snapshot();
+        snapshot.eta_secs = self.smoothed_eta(now, &snapshot);
+        snapshot
+    }
+
+    /// Overall ETA from aggregate throughput over the trailing `ETA_WINDOW`.
+    ///
+    /// Summed per-segment EMA speeds swing at start-up and drop whenever a
+    /// segment finishes; windowed throughput doesn't. On top of that, rises
+    /// are eased in over `ETA_RISE_TAU` so the countdown stays steady.
+    fn smoothed_eta(&mut self, now: Instant, snapshot: &ProgressSnapshot) -> f64 {
+        let downloaded = snapshot.total_bytes_downloaded;
+        self.eta_window.push_back((now, downloaded));
+        // Keep the newest point at or before the window start as baseline.
+        while self.eta_window.len() > 1
+            && now.duration_since(self.eta_window[1].0) >= ETA_WINDOW
+        {
+            self.eta_window.pop_front();
+        }
+
+        let remaining = snapshot.total_bytes.saturating_sub(downloaded);
+        if remaining == 0 {
+            self.last_eta = None;
+            return 0.0;
+        }
+
+        let (start, start_bytes) = self.eta_window[0];
+        let span = now.duration_since(start).as_secs_f64();
+        let throughput = if span > 0.0 && downloaded > start_bytes {
+            (downloaded - start_bytes) as f64 / span
+        } else {
+            snapshot.speed
+        };
+        if throughput <= 0.0 {
+            return self.last_eta.map(|(_, eta)| eta).unwrap_or(0.0);
+        }
+        let raw = remaining as f64 / throughput;
+
+        let eta = match self.last_eta {
+            Some((at, prev)) => {
+                let dt = now.duration_since(at).as_secs_f64();
+                let expected = (prev - dt).max(0.0);
+                if raw > expected {
+                    let alpha = 1.0 - (-dt / ETA_RISE_TAU).exp();
+                    expected + (raw - expected) * alpha
+                } else {
+                    raw
+                }
+            }
+            None => raw,
+        };
+        self.last_eta = Some((now, eta));
+        eta
+    }
+
+    /// Append a moving-average speed sample if at least
+    /// `SPEED_SAMPLE_INTERVAL` has passed since the previous one.
+    ///
+    /// The speed is the aggregate byte delta over the whole interval, which
+    /// is much steadier than the per-chunk EMA for graphing.
+    fn record_speed_sample(&mut self, now: Instant) {
+        let since_last = now.duration_since(self.last_sample_at);
+        if since_last < SPEED_SAMPLE_INTERVAL {
+            return;
+        }
+
+        let total_downloaded: u64 = self.segments.values().map(|s| s.bytes_downloaded).sum();
+        let delta = total_downloaded.saturating_sub(self.last_sample_bytes);
+        let bytes_per_sec = delta as f64 / since_last.as_secs_f64();
+        let elapsed = now.duration_since(self.start_time).as_secs_f64();
+
+        if self.speed_samples.len() == MAX_SPEED_SAMPLES {
+            self.speed_samples.pop_front();
+        }
+        self.speed_samples.push_back((elapsed, bytes_per_sec));
+
+        self.last_sample_at = now;
+        self.last_sample_bytes = total_downloaded;
     }
 
     /// Build a `ProgressSnapshot` from current aggregation state.
@@ -126,12 +295,6 @@ impl ProgressNotifier {
         let total_bytes: u64 = self.segments.values().map(|s| s.total_bytes).sum();
         let total_downloaded: u64 = self.segments.values().map(|s| s.bytes_downloaded).sum();
         let combined_speed: f64 = self.segments.values().map(|s| s.speed).sum();
-        let remaining = total_bytes.saturating_sub(total_downloaded);
-        let eta = if combined_speed > 0.0 {
-            remaining as f64 / combined_speed
-        } else {
-            0.0
-        };
 
         let segment_snapshots: Vec<SegmentSnapshot> = self
             .segment_order
@@ -150,22 +313,46 @@ impl ProgressNotifier {
                     total_bytes: s.total_bytes,
                     speed: s.speed,
                     eta_secs: segment_eta,
+                    retry_count: s.retry_count,
                 }
             })
             .collect();
 
+        let completed_segments = self
+            .segments
+            .values()
+            .filter(|s| s.total_bytes > 0 && s.bytes_downloaded >= s.total_bytes)
+            .count();
+
         ProgressSnapshot {
             segments: segment_snapshots,
             total_bytes_downloaded: total_downloaded,
             total_bytes,
             speed: combined_speed,
-            eta_secs: eta,
+            // Filled in by `handle_event` with the smoothed estimate.
+            eta_secs: 0.0,
             done: false,
+            speed_samples: self.speed_samples.iter().copied().collect(),
+            total_segments: self.segments.len(),
+            completed_segments,
+            error: None,
+            warnings: self.warnings.clone(),
+            phase: self.phase.clone(),
+            download_id: String::new(),
         }
     }
 
     /// Finalize: build final snapshot with `done = true`, notify all observers.
     async fn finish(self) {
+        let final_snapshot = self.final_snapshot();
+        for observer in &self.observers {
+            observer.on_complete(&final_snapshot).await;
+        }
+    }
+
+    /// The snapshot observers get on a clean finish: `done` is set and
+    /// `speed` is the average over the whole run rather than the EMA.
+    pub fn final_snapshot(&self) -> ProgressSnapshot {
         let elapsed = self.start_time.elapsed();
         let total_downloaded: u64 = self.segments.values().map(|s| s.bytes_downloaded).sum();
         let avg_speed = if elapsed.as_secs_f64() > 0.0 {
@@ -178,9 +365,15 @@ impl ProgressNotifier {
         final_snapshot.done = true;
         final_snapshot.speed = avg_speed;
         final_snapshot.eta_secs = 0.0;
-
-        for observer in &self.observers {
-            observer.on_complete(&final_snapshot).await;
+        // Segments of unknown size never reach `total_bytes`; a clean finish
+        // means every one of them completed, at the size it turned out to be.
+        final_snapshot.completed_segments = final_snapshot.total_segments;
+        for segment in &mut final_snapshot.segments {
+            if segment.total_bytes == 0 {
+                segment.total_bytes = segment.bytes_downloaded;
+            }
         }
+        final_snapshot.total_bytes = final_snapshot.segments.iter().map(|s| s.total_bytes).sum();
+        final_snapshot
     }
 }
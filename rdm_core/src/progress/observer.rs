@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use super::snapshot::ProgressSnapshot;
+use crate::types::types::PreprocessInfo;
 
 /// Trait for anything that wants to observe download progress.
 ///
@@ -7,16 +8,34 @@ use super::snapshot::ProgressSnapshot;
 /// after aggregating raw `ProgressEvent`s into a `ProgressSnapshot`.
 ///
 /// Lifecycle:
+/// - `on_phase` is called while preprocessing, with what the download is
+///   busy with before any data is fetched (probing, reading a manifest).
+/// - `on_start` is called once after preprocessing, before any data is
+///   fetched, with the size and plan of the download. Not called when
+///   preprocessing fails.
 /// - `on_progress` is called for every progress event (per-chunk granularity).
+/// - `on_warning` is called for a problem that did not stop the download,
+///   such as the output size differing from what the server announced.
 /// - `on_complete` is called once when the download finishes successfully
 ///   (the progress channel closed without an error message).
 /// - `on_error` is called once when the download fails (an `Err(String)`
 ///   was received on the progress channel).
 #[async_trait]
 pub trait ProgressObserver: Send + Sync + 'static {
+    /// Called with a short description of the current preprocessing step.
+    /// Later snapshots carry it in `phase` until data arrives.
+    async fn on_phase(&self, _phase: &str) {}
+
+    /// Called once the download has been probed and planned.
+    async fn on_start(&self, _info: &PreprocessInfo) {}
+
     /// Called with the latest aggregated snapshot after each progress event.
     async fn on_progress(&self, snapshot: &ProgressSnapshot);
 
+    /// Called with a non-fatal problem worth showing to the user. The
+    /// message is also kept in later snapshots' `warnings`.
+    async fn on_warning(&self, _message: &str) {}
+
     /// Called when the download completes successfully.
     async fn on_complete(&self, snapshot: &ProgressSnapshot);
 
@@ -1,17 +1,20 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Per-segment progress snapshot.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SegmentSnapshot {
     pub segment_id: String,
     pub bytes_downloaded: u64,
     pub total_bytes: u64,
     pub speed: f64,
     pub eta_secs: f64,
+    /// Times this segment has been retried after a failed attempt.
+    #[serde(default)]
+    pub retry_count: u32,
 }
 
 /// Aggregate progress snapshot for an entire download.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProgressSnapshot {
     pub segments: Vec<SegmentSnapshot>,
     pub total_bytes_downloaded: u64,
@@ -19,6 +22,33 @@ pub struct ProgressSnapshot {
     pub speed: f64,
     pub eta_secs: f64,
     pub done: bool,
+    /// Recent `(elapsed_secs, bytes_per_sec)` samples, oldest first.
+    /// Sampled at most once per second and capped at 60 points so the UI
+    /// can draw a speed sparkline.
+    #[serde(default)]
+    pub speed_samples: Vec<(f64, f64)>,
+    /// Number of segments seen so far.
+    #[serde(default)]
+    pub total_segments: usize,
+    /// Segments whose `bytes_downloaded` has reached their `total_bytes`.
+    #[serde(default)]
+    pub completed_segments: usize,
+    /// Set (together with `done`) when the download failed.
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Non-fatal problems reported so far, oldest first; see
+    /// `ProgressObserver::on_warning`.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// What the download is doing before its first byte, e.g. `Probing…`;
+    /// `None` once data flows. See `ProgressObserver::on_phase`.
+    #[serde(default)]
+    pub phase: Option<String>,
+    /// Id of the download this snapshot belongs to, so a consumer of several
+    /// streams can tell them apart. Stamped by observers that know it (the
+    /// server's); empty otherwise.
+    #[serde(default)]
+    pub download_id: String,
 }
 
 impl ProgressSnapshot {
@@ -30,6 +60,13 @@ impl ProgressSnapshot {
             speed: 0.0,
             eta_secs: 0.0,
             done: false,
+            speed_samples: Vec::new(),
+            total_segments: 0,
+            completed_segments: 0,
+            error: None,
+            warnings: Vec::new(),
+            phase: None,
+            download_id: String::new(),
         }
     }
 }
@@ -51,3 +88,51 @@ pub fn format_bytes(bytes: u64) -> String {
         format!("{} B", bytes)
     }
 }
+
+/// How transfer speeds are displayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SpeedUnit {
+    /// Powers of 1024 in bytes, as [`format_bytes`]: "4.77 MB/s".
+    #[default]
+    #[serde(rename = "binary")]
+    BinaryBytes,
+    /// Powers of 1000 in bytes: "5.00 MB/s".
+    #[serde(rename = "decimal")]
+    DecimalBytes,
+    /// Powers of 1000 in bits, as ISPs quote plans: "40.00 Mbps".
+    Bits,
+}
+
+impl std::str::FromStr for SpeedUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "binary" => Ok(Self::BinaryBytes),
+            "decimal" => Ok(Self::DecimalBytes),
+            "bits" => Ok(Self::Bits),
+            _ => Err(format!("unknown speed unit `{}`; expected binary, decimal or bits", s)),
+        }
+    }
+}
+
+/// Human-readable transfer speed in `unit`.
+pub fn format_speed(bytes_per_sec: f64, unit: SpeedUnit) -> String {
+    let (value, base, units) = match unit {
+        SpeedUnit::BinaryBytes => return format!("{}/s", format_bytes(bytes_per_sec as u64)),
+        SpeedUnit::DecimalBytes => (bytes_per_sec, 1000.0, ["B/s", "kB/s", "MB/s", "GB/s"]),
+        SpeedUnit::Bits => (bytes_per_sec * 8.0, 1000.0, ["bps", "Kbps", "Mbps", "Gbps"]),
+    };
+    let mut scaled = value.max(0.0);
+    let mut index = 0;
+    while scaled >= base && index < units.len() - 1 {
+        scaled /= base;
+        index += 1;
+    }
+    match index {
+        0 => format!("{:.0} {}", scaled, units[0]),
+        1 => format!("{:.1} {}", scaled, units[1]),
+        _ => format!("{:.2} {}", scaled, units[index]),
+    }
+}
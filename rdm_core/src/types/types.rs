@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SegmentState {
@@ -46,6 +47,66 @@ pub struct ProbeResult {
     pub attachment_name: Option<String>,
     pub content_type: Option<String>,
     pub last_modified: Option<String>,
+    pub etag: Option<String>,
+    /// `name=value` pairs from the response's `Set-Cookie` headers.
+    #[serde(default)]
+    pub set_cookies: Vec<String>,
+    /// The server answered a conditional probe with `304 Not Modified`.
+    #[serde(default)]
+    pub not_modified: bool,
+}
+
+/// What an earlier response (typically the one the browser saw when it
+/// detected the media) already told us about a resource. With enough of it a
+/// download can skip its probe; see `ProbeHints::to_probe`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProbeHints {
+    pub file_size: Option<u64>,
+    pub resumable: Option<bool>,
+    pub content_type: Option<String>,
+    pub attachment_name: Option<String>,
+}
+
+impl ProbeHints {
+    /// The probe result the hints stand for, or `None` when a probe is still
+    /// needed: resumability unknown, or a resumable resource of unknown size.
+    pub fn to_probe(&self, url: &str) -> Option<ProbeResult> {
+        let resumable = self.resumable?;
+        if resumable && self.file_size.is_none() {
+            return None;
+        }
+        Some(ProbeResult {
+            resumable,
+            resource_size: self.file_size,
+            final_uri: url.to_string(),
+            attachment_name: self.attachment_name.clone(),
+            content_type: self.content_type.clone(),
+            last_modified: None,
+            etag: None,
+            set_cookies: Vec::new(),
+            not_modified: false,
+        })
+    }
+}
+
+/// PEM files for TLS connections beyond the system defaults.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsFiles {
+    /// Client certificate (chain) presented to servers that require mutual TLS.
+    pub client_cert: Option<String>,
+    /// Private key of `client_cert` (PKCS#8, PKCS#1 or SEC1).
+    pub client_key: Option<String>,
+    /// CA certificates (one PEM file, possibly a bundle) trusted in addition
+    /// to the system roots.
+    pub root_cert: Option<String>,
+}
+
+impl TlsFiles {
+    /// Nothing to load. A key without its certificate is not nothing: the
+    /// client then fails to build, rather than quietly skip it.
+    pub fn is_empty(&self) -> bool {
+        self.client_cert.is_none() && self.client_key.is_none() && self.root_cert.is_none()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -55,6 +116,30 @@ pub struct HeaderData {
     pub url: String,
     pub authentication: Option<AuthenticationInfo>,
     pub proxy: Option<ProxyInfo>,
+    /// Alternative URLs serving the same bytes as `url`; segment retries
+    /// rotate through them.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// Request method; `None` means GET. Anything else disables ranges.
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Request body sent with every request.
+    #[serde(default)]
+    pub body: Option<Vec<u8>>,
+    /// `Accept` sent when `headers` carry none.
+    #[serde(default)]
+    pub default_accept: Option<String>,
+}
+
+impl HeaderData {
+    /// The method requests are sent with. An unrecognised name falls back
+    /// to GET.
+    pub fn http_method(&self) -> reqwest::Method {
+        self.method
+            .as_deref()
+            .and_then(|m| reqwest::Method::from_bytes(m.as_bytes()).ok())
+            .unwrap_or(reqwest::Method::GET)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,28 +156,285 @@ pub struct ProxyInfo {
     pub password: Option<String>,
 }
 
+/// Download speed cap, in bytes per second.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpeedLimit {
+    /// One bucket shared by all connections: caps the aggregate speed.
+    Global(u64),
+    /// A bucket per connection: N connections reach at most N times this.
+    /// Useful against ISPs that shape each flow separately.
+    PerConnection(u64),
+}
+
+/// Digest computed over the assembled output file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgo {
+    Md5,
+    Sha256,
+    Sha512,
+}
+
+impl std::fmt::Display for ChecksumAlgo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Md5 => "md5",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        })
+    }
+}
+
+impl std::str::FromStr for ChecksumAlgo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().replace('-', "").as_str() {
+            "md5" => Ok(Self::Md5),
+            "sha256" => Ok(Self::Sha256),
+            "sha512" => Ok(Self::Sha512),
+            _ => Err(format!("unknown checksum algorithm `{}`; expected md5, sha256 or sha512", s)),
+        }
+    }
+}
+
+/// Codec the finished file is stored with; see `with_store_compression`
+/// (cargo feature `compression`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    Gzip,
+    Zstd,
+}
+
+impl Codec {
+    /// Extension appended to the output path, without the dot.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => "gz",
+            Self::Zstd => "zst",
+        }
+    }
+}
+
+impl std::fmt::Display for Codec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        })
+    }
+}
+
+impl std::str::FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Self::Gzip),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            _ => Err(format!("unknown codec `{}`; expected gzip or zstd", s)),
+        }
+    }
+}
+
+/// `Accept` header sent when the captured request headers carry none; see
+/// `with_default_accept`. Some media servers answer `403`, or other content,
+/// unless it names the media type the way a browser's request does.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultAccept {
+    /// Prefer the content type known before the probe (from probe hints),
+    /// e.g. `video/mp4,video/*;q=0.9,*/*;q=0.8`; `*/*` when none is.
+    FromContentType,
+    /// Always this value.
+    Value(String),
+    /// Add none; the HTTP client's own `Accept: */*` goes out.
+    #[default]
+    Skip,
+}
+
+/// Where a finished download is written; see `with_output_target`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputTarget {
+    /// The output path.
+    #[default]
+    File,
+    /// Standard output, in order, once every segment is in; nothing is
+    /// written at the output path.
+    Stdout,
+}
+
+/// HTTP version the download client speaks; see `with_http_version`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HttpVersion {
+    /// HTTP/2 where TLS negotiates it, HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    /// HTTP/1.1 only: one connection per segment.
+    Http1,
+    /// HTTP/2 without negotiation, also over plain `http://` (h2c). A
+    /// server that does not speak it fails every request.
+    Http2,
+}
+
+impl std::fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Auto => "auto",
+            Self::Http1 => "1.1",
+            Self::Http2 => "2",
+        })
+    }
+}
+
+impl std::str::FromStr for HttpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().trim_start_matches("http").trim_start_matches('/') {
+            "auto" => Ok(Self::Auto),
+            "1" | "1.1" => Ok(Self::Http1),
+            "2" => Ok(Self::Http2),
+            _ => Err(format!("unknown HTTP version `{}`; expected auto, 1.1 or 2", s)),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloaderState {
     pub id: String,
     pub url: String,
+    /// Separate audio track, muxed with `url` (the video) after download.
+    pub audio_url: Option<String>,
+    /// Mirrors of `url`, verified to match its size and ETag in preprocess.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
     pub output_path: Option<String>,
     pub temp_dir: String,
     pub file_size: i64,
     pub headers: HashMap<String, Vec<String>>,
     pub cookies: Option<String>,
+    /// Set by preprocess when a redirect took `url` to another host: the
+    /// URL the captured credentials (`authentication`, `cookies` and any
+    /// `Authorization` or `Cookie` header) were given for. They are kept,
+    /// but not sent to `url` (unless forwarding was asked for).
+    #[serde(default)]
+    pub credentials_url: Option<String>,
+    /// Cookies `url`'s host set while the captured ones are withheld.
+    #[serde(default)]
+    pub redirect_cookies: Option<String>,
+    /// User-Agent used when `headers` carries none; `None` means the crate
+    /// default.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// `Accept` used when `headers` carries none.
+    #[serde(default)]
+    pub default_accept: DefaultAccept,
+    /// Ask caches on the way to revalidate with the origin; see
+    /// `MultipartDownloadStrategyBuilder::with_no_cache`.
+    #[serde(default)]
+    pub no_cache: bool,
+    /// Request method (e.g. `POST`); `None` means GET. A non-GET download is
+    /// never probed or ranged, so it always runs as a single segment.
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Request body for a non-GET download.
+    #[serde(default)]
+    pub body: Option<Vec<u8>>,
     pub authentication: Option<AuthenticationInfo>,
     pub proxy: Option<ProxyInfo>,
+    /// Client certificate and extra CAs; applied when the client is built.
+    #[serde(default)]
+    pub tls: TlsFiles,
     pub convert_to_mp3: bool,
     pub last_modified: Option<String>,
     pub resumable: bool,
     pub attachment_name: Option<String>,
     pub content_type: Option<String>,
+    /// Per-segment `BufWriter` capacity in bytes.
+    #[serde(default = "default_write_buffer_size")]
+    pub write_buffer_size: usize,
+    #[serde(default)]
+    pub speed_limit: Option<SpeedLimit>,
+    /// Hash the output during postprocess with this algorithm.
+    #[serde(default)]
+    pub compute_checksum: Option<ChecksumAlgo>,
+    /// Hex digest the computed checksum is compared against.
+    #[serde(default)]
+    pub expected_checksum: Option<String>,
+    /// Hex digest of the output, set by postprocess when requested.
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// Published checksum file (`file.sha256`) the output must match.
+    #[serde(default)]
+    pub checksum_url: Option<String>,
+    /// Compress the assembled output with this codec in postprocess.
+    #[serde(default)]
+    pub store_compression: Option<Codec>,
+    /// Size of the compressed output, set by postprocess.
+    #[serde(default)]
+    pub compressed_size: Option<u64>,
+    /// Set by preprocess when the server reported the existing output
+    /// unchanged (`304`); nothing is fetched and the file is left alone.
+    #[serde(default)]
+    pub skipped: bool,
+}
+
+fn default_write_buffer_size() -> usize {
+    crate::downloader::segment_grabber::DEFAULT_WRITE_BUFFER_SIZE
+}
+
+impl DownloaderState {
+    /// Fresh state for a download of `url`, with a unique id and a temp dir
+    /// under the system temp directory named after that id.
+    pub fn new(url: String, output_path: Option<String>) -> Self {
+        let id = uuid::Uuid::new_v4().to_string();
+        let temp_dir = std::env::temp_dir().join(&id);
+        Self {
+            id,
+            url,
+            audio_url: None,
+            mirrors: Vec::new(),
+            output_path,
+            temp_dir: temp_dir.to_string_lossy().to_string(),
+            file_size: -1,
+            headers: HashMap::new(),
+            cookies: None,
+            credentials_url: None,
+            redirect_cookies: None,
+            user_agent: None,
+            default_accept: DefaultAccept::default(),
+            no_cache: false,
+            method: None,
+            body: None,
+            authentication: None,
+            proxy: None,
+            tls: TlsFiles::default(),
+            convert_to_mp3: false,
+            last_modified: None,
+            resumable: false,
+            attachment_name: None,
+            content_type: None,
+            write_buffer_size: default_write_buffer_size(),
+            speed_limit: None,
+            compute_checksum: None,
+            expected_checksum: None,
+            checksum: None,
+            checksum_url: None,
+            store_compression: None,
+            compressed_size: None,
+            skipped: false,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum DownloadError {
     #[error("network error: {0}")]
-    Network(#[from] reqwest::Error),
+    Network(reqwest::Error),
     #[error("disk error: {0}")]
     Disk(#[from] std::io::Error),
     #[error("invalid state")]
@@ -105,6 +447,109 @@ pub enum DownloadError {
     Cancelled,
     #[error("segment failed: {0}")]
     SegmentFailed(String),
+    #[error("invalid manifest: {0}")]
+    Manifest(String),
+    #[error("mux failed: {0}")]
+    Mux(String),
+    #[error("mirror mismatch: {0}")]
+    MirrorMismatch(String),
+    #[error("resource changed: {0}")]
+    ResourceChanged(String),
+    #[error("TLS setup failed: {0}")]
+    Tls(String),
+    #[error("file too large: {size} bytes exceeds the limit of {limit} bytes")]
+    TooLarge { size: u64, limit: u64 },
+    /// The server answered `401` with a `WWW-Authenticate` challenge; retry
+    /// with credentials (`with_authentication`).
+    #[error(
+        "authentication required: the server asks for {scheme} credentials{}",
+        .realm.as_ref().map(|realm| format!(" (realm {:?})", realm)).unwrap_or_default()
+    )]
+    AuthRequired { scheme: String, realm: Option<String> },
+    /// The output does not match the digest of its published checksum file.
+    #[error("checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    /// The published checksum file could not be fetched or read.
+    #[error("checksum file: {0}")]
+    ChecksumFile(String),
+    /// `set_segment_plan` was given ranges that don't tile the download.
+    #[error("invalid segment plan: {0}")]
+    SegmentPlan(String),
+    /// The whole download did not finish within `HttpDownloader::set_deadline`.
+    /// Unlike a stall, this is not retried.
+    #[error("deadline of {}s exceeded", .0.as_secs_f64())]
+    Deadline(Duration),
+    /// A ranged request came back with the whole file (a `200`, or a `206`
+    /// spanning far more than asked): the server only claims to serve
+    /// ranges. `MultipartDownloadStrategy` restarts as one stream on it.
+    #[error("range ignored: {0}")]
+    RangeIgnored(String),
+    /// The server answered with an HTML page where `expected` media was
+    /// wanted (see `with_expect_content_type`): typically an error page
+    /// for an expired link, served with a `200`.
+    #[error("unexpected content type: expected {expected}, got {got}")]
+    UnexpectedContentType { expected: String, got: String },
+    /// A request timed out (connecting, or waiting for data), and so did a
+    /// segment's last retry: the host is slow or unreachable, where
+    /// `Network` means it answered with an error.
+    #[error("timed out: {0}")]
+    Timeout(String),
+    /// The connect guard (`with_connect_guard`) refused a host the download
+    /// would have connected to: the URL's, a redirect's, a mirror's.
+    #[error("host refused: {0}")]
+    HostRefused(String),
+}
+
+impl From<reqwest::Error> for DownloadError {
+    fn from(err: reqwest::Error) -> Self {
+        match crate::downloader::connect_guard::find_refusal(&err) {
+            Some(refused) => DownloadError::HostRefused(refused.0.clone()),
+            None => DownloadError::Network(err),
+        }
+    }
+}
+
+/// What `DownloadStrategy::preprocess` learned about the download, passed to
+/// observers' `on_start` before any data is fetched.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PreprocessInfo {
+    /// Total bytes to download, when the server reported it (video and
+    /// audio together for a split download).
+    pub file_size: Option<u64>,
+    /// The server honours ranges, so segments run in parallel and the
+    /// download can be continued.
+    pub resumable: bool,
+    pub segment_count: usize,
+    pub content_type: Option<String>,
+    /// File name from `Content-Disposition`, if any.
+    pub attachment_name: Option<String>,
+}
+
+/// Outcome of a finished download, returned by `HttpDownloader::download`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadSummary {
+    /// Path of the assembled output file.
+    pub path: String,
+    pub bytes: u64,
+    /// Wall-clock time of the whole preprocess → download → postprocess run.
+    pub duration: Duration,
+    /// Average speed over `duration`, in bytes per second.
+    pub avg_speed: f64,
+    pub segments: usize,
+    pub resumable: bool,
+    /// Hex digest of the output file, when one was requested.
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// `None` when there was no checksum to verify against.
+    pub checksum_ok: Option<bool>,
+    /// Size of the stored file when it was compressed; `bytes` stays the
+    /// uncompressed size.
+    #[serde(default)]
+    pub compressed_bytes: Option<u64>,
+    /// The existing output was current, so nothing was downloaded; see
+    /// `with_conditional_cache`.
+    #[serde(default)]
+    pub skipped: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -112,4 +557,39 @@ pub struct ProgressEvent {
     pub segment_id: String,
     pub bytes_delta: u64,
     pub total_bytes: Option<u64>,
+    /// Set when the segment is about to be retried; `bytes_delta` is 0.
+    pub retry: bool,
+    /// A non-fatal problem to report to observers instead of progress; see
+    /// [`ProgressEvent::warning`].
+    pub warning: Option<String>,
+    /// What the download is busy with before data flows, instead of
+    /// progress; see [`ProgressEvent::phase`].
+    pub phase: Option<String>,
+}
+
+impl ProgressEvent {
+    /// An event carrying only a warning for the download as a whole.
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self {
+            segment_id: String::new(),
+            bytes_delta: 0,
+            total_bytes: None,
+            retry: false,
+            warning: Some(message.into()),
+            phase: None,
+        }
+    }
+
+    /// An event saying what the download is doing, e.g. `Probing…`, while
+    /// nothing is being transferred yet.
+    pub fn phase(description: impl Into<String>) -> Self {
+        Self {
+            segment_id: String::new(),
+            bytes_delta: 0,
+            total_bytes: None,
+            retry: false,
+            warning: None,
+            phase: Some(description.into()),
+        }
+    }
 }
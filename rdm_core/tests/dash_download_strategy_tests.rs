@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use rdm_core::downloader::dash_manifest::{
+    expand_template, is_dash_manifest, parse_iso8601_duration, parse_manifest,
+};
+use rdm_core::downloader::strategy::common_options::CommonOptions;
+use rdm_core::downloader::strategy::dash_download_strategy::DashDownloadStrategy;
+use rdm_core::downloader::strategy::download_strategy::DownloadStrategy;
+use rdm_core::types::types::{DownloadError, SegmentState, StreamType};
+
+const VIDEO_ONLY_MPD: &str = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT6S">
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate initialization="init-$RepresentationID$.mp4"
+                       media="seg-$RepresentationID$-$Number%03d$.m4s"
+                       startNumber="1" duration="2" timescale="1"/>
+      <Representation id="low" bandwidth="100000"/>
+      <Representation id="high" bandwidth="500000"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+
+fn temp_output(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rdm_dash_{}_{}", name, uuid::Uuid::new_v4()))
+}
+
+// ── Parser ───────────────────────────────────────────────────────────────────
+
+#[test]
+fn test_is_dash_manifest() {
+    assert!(is_dash_manifest("https://cdn.example.com/a/stream.mpd?token=1", None));
+    assert!(is_dash_manifest("https://cdn.example.com/manifest", Some("application/dash+xml")));
+    assert!(!is_dash_manifest("https://cdn.example.com/video.mp4", Some("video/mp4")));
+}
+
+#[test]
+fn test_expand_template() {
+    assert_eq!(
+        expand_template("$RepresentationID$/$Number%05d$.m4s", "v1", 0, Some(42), None),
+        "v1/00042.m4s"
+    );
+    assert_eq!(expand_template("t-$Time$-$$.m4s", "v1", 0, None, Some(9000)), "t-9000-$.m4s");
+}
+
+#[test]
+fn test_parse_iso8601_duration() {
+    assert_eq!(parse_iso8601_duration("PT1H2M3.5S"), Some(3723.5));
+    assert_eq!(parse_iso8601_duration("PT6S"), Some(6.0));
+    assert_eq!(parse_iso8601_duration("garbage"), None);
+}
+
+#[test]
+fn test_parse_manifest_picks_highest_bandwidth() {
+    let manifest = parse_manifest(VIDEO_ONLY_MPD, "https://cdn.example.com/v/stream.mpd").unwrap();
+    let video = manifest.video.expect("video track");
+    assert!(manifest.audio.is_none());
+    assert_eq!(video.representation_id, "high");
+    assert_eq!(video.init_url.as_deref(), Some("https://cdn.example.com/v/init-high.mp4"));
+    assert_eq!(
+        video.media_urls,
+        vec![
+            "https://cdn.example.com/v/seg-high-001.m4s",
+            "https://cdn.example.com/v/seg-high-002.m4s",
+            "https://cdn.example.com/v/seg-high-003.m4s",
+        ]
+    );
+}
+
+#[test]
+fn test_parse_manifest_segment_timeline() {
+    let mpd = r#"<MPD type="static"><Period><AdaptationSet mimeType="audio/mp4">
+        <Representation id="a" bandwidth="64000">
+          <SegmentTemplate media="a-$Time$.m4s" timescale="1000">
+            <SegmentTimeline><S t="0" d="2000" r="2"/><S d="1000"/></SegmentTimeline>
+          </SegmentTemplate>
+        </Representation>
+      </AdaptationSet></Period></MPD>"#;
+    let manifest = parse_manifest(mpd, "http://h/x.mpd").unwrap();
+    let audio = manifest.audio.expect("audio track");
+    assert_eq!(
+        audio.media_urls,
+        vec!["http://h/a-0.m4s", "http://h/a-2000.m4s", "http://h/a-4000.m4s", "http://h/a-6000.m4s"]
+    );
+}
+
+#[test]
+fn test_parse_manifest_rejects_live_and_drm() {
+    let live = r#"<MPD type="dynamic"><Period/></MPD>"#;
+    assert!(matches!(parse_manifest(live, "http://h/x.mpd"), Err(DownloadError::Manifest(_))));
+
+    let drm = r#"<MPD type="static"><Period><AdaptationSet mimeType="video/mp4">
+        <ContentProtection schemeIdUri="urn:mpeg:dash:mp4protection:2011"/>
+        <Representation id="v" bandwidth="1"><BaseURL>v.mp4</BaseURL></Representation>
+      </AdaptationSet></Period></MPD>"#;
+    assert!(matches!(parse_manifest(drm, "http://h/x.mpd"), Err(DownloadError::Manifest(_))));
+}
+
+// ── Strategy ─────────────────────────────────────────────────────────────────
+
+#[tokio::test]
+async fn test_dash_preprocess_creates_ordered_segments() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/stream.mpd"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(VIDEO_ONLY_MPD))
+        .mount(&server)
+        .await;
+
+    let output = temp_output("pre");
+    let strategy = DashDownloadStrategy::new(format!("{}/stream.mpd", server.uri()), output);
+    strategy.preprocess().await.unwrap();
+
+    let segments = strategy.segments().read().await;
+    assert_eq!(segments.len(), 4, "init + 3 media segments");
+    let mut offsets: Vec<i64> = segments.values().map(|s| s.offset).collect();
+    offsets.sort();
+    assert_eq!(offsets, vec![0, 1, 2, 3]);
+    assert!(segments.values().all(|s| s.stream_type == StreamType::Primary));
+    assert!(segments.values().all(|s| s.state == SegmentState::NotStarted));
+}
+
+#[tokio::test]
+async fn test_dash_download_concatenates_in_order() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v/stream.mpd"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(VIDEO_ONLY_MPD))
+        .mount(&server)
+        .await;
+
+    let parts: [(&str, &[u8]); 4] = [
+        ("/v/init-high.mp4", b"INIT"),
+        ("/v/seg-high-001.m4s", b"-one"),
+        ("/v/seg-high-002.m4s", b"-two"),
+        ("/v/seg-high-003.m4s", b"-three"),
+    ];
+    for (p, body) in parts {
+        Mock::given(method("GET"))
+            .and(path(p))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(body.to_vec()))
+            .mount(&server)
+            .await;
+    }
+
+    let output = temp_output("full");
+    let strategy = DashDownloadStrategy::builder(format!("{}/v/stream.mpd", server.uri()), output.clone())
+        .with_connection_size(2)
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    let written = std::fs::read(&output).unwrap();
+    assert_eq!(written, b"INIT-one-two-three");
+
+    let temp_dir = strategy.state().read().unwrap().temp_dir.clone();
+    assert!(!std::path::Path::new(&temp_dir).exists(), "temp dir should be cleaned up");
+
+    let _ = std::fs::remove_file(&output);
+}
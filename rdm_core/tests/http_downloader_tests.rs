@@ -4,7 +4,10 @@ use std::sync::Arc;
 use wiremock::matchers::{header_regex, method};
 use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
+use rdm_test_support::start_chunked_server;
+
 use rdm_core::downloader::http_downloader::HttpDownloader;
+use rdm_core::downloader::strategy::common_options::CommonOptions;
 use rdm_core::downloader::strategy::download_strategy::DownloadStrategy;
 use rdm_core::downloader::strategy::multipart_download_strategy::MultipartDownloadStrategy;
 
@@ -129,6 +132,41 @@ async fn test_http_downloader_non_resumable() {
     let _ = std::fs::remove_file(output_filename);
 }
 
+#[tokio::test]
+async fn test_size_mismatch_is_reported_as_warning() {
+    let body = generate_test_data(4096);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(body.clone()))
+        .mount(&server)
+        .await;
+
+    // The client was told the file is larger than what the server sends.
+    let output_filename = format!("size_mismatch_{}.bin", uuid::Uuid::new_v4());
+    let strategy = Arc::new(
+        MultipartDownloadStrategy::builder(server.uri(), PathBuf::from(&output_filename))
+            .with_probe_hints(rdm_core::types::types::ProbeHints {
+                file_size: Some(5000),
+                resumable: Some(false),
+                ..Default::default()
+            })
+            .build(),
+    );
+
+    let observer = Arc::new(CollectingObserver::new());
+    let mut downloader = HttpDownloader::new(strategy);
+    downloader.add_observer(Box::new(CollectingObserverHandle(Arc::clone(&observer))));
+    let summary = downloader.download().await.unwrap();
+
+    assert_eq!(summary.bytes, 4096, "a size mismatch does not fail the download");
+    assert_eq!(
+        *observer.warnings.lock().unwrap(),
+        vec!["downloaded size differs from expected (4096 vs 5000 bytes)".to_string()]
+    );
+
+    let _ = std::fs::remove_file(&output_filename);
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_http_downloader_stop_during_download() {
     let body_size: usize = 2 * 1024 * 1024;
@@ -170,25 +208,75 @@ async fn test_http_downloader_stop_during_download() {
     let strategy_clone = strategy.clone();
     let download_handle = tokio::spawn(async move { strategy_clone.download().await });
 
-    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    // Stop once a segment request is in flight (the server holds it for 5s).
+    tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            let requests = server.received_requests().await.unwrap();
+            if requests.iter().any(|r| r.headers.get("Range").is_some_and(|v| v != "bytes=0-0")) {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .expect("no segment was requested");
 
     strategy.stop().await.unwrap();
 
-    let result = tokio::time::timeout(std::time::Duration::from_secs(15), download_handle)
+    let result = tokio::time::timeout(std::time::Duration::from_secs(4), download_handle)
         .await
-        .expect("download should complete within timeout after stop")
+        .expect("download should end as soon as it is stopped")
         .unwrap();
 
-    assert!(
-        result.is_err() || result.is_ok(),
-        "download should complete after stop"
-    );
+    assert!(matches!(result, Err(DownloadError::Cancelled)), "got {:?}", result);
 
     let temp_dir = strategy.temp_dir().await;
     let _ = std::fs::remove_dir_all(&temp_dir);
     let _ = std::fs::remove_file("stoptest.bin");
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_deadline_ends_a_slow_download() {
+    let body_size: usize = 2 * 1024 * 1024;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(header_regex("Range", "^bytes=0-0$"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .set_body_bytes(vec![0u8; 1])
+                .insert_header("Content-Range", format!("bytes 0-0/{}", body_size)),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(206).set_delay(std::time::Duration::from_secs(10)))
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = Arc::new(MultipartDownloadStrategy::new(server.uri(), dir.path().join("deadline.bin")));
+    let observer = Arc::new(CollectingObserver::new());
+    let mut downloader = HttpDownloader::new(strategy.clone());
+    downloader.add_observer(Box::new(CollectingObserverHandle(observer.clone())));
+    downloader.set_deadline(std::time::Duration::from_millis(300));
+
+    let started = std::time::Instant::now();
+    let result = downloader.download().await;
+    assert!(
+        matches!(result, Err(DownloadError::Deadline(limit)) if limit == std::time::Duration::from_millis(300)),
+        "got {:?}",
+        result
+    );
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    assert_eq!(*observer.errors.lock().unwrap(), vec!["deadline of 0.3s exceeded".to_string()]);
+
+    // Nothing is retried after a deadline, so the partial files are gone
+    // even though the download was resumable.
+    let temp_dir = PathBuf::from(strategy.temp_dir().await);
+    assert!(!temp_dir.exists());
+    assert!(!dir.path().join("deadline.bin").exists());
+}
+
 #[tokio::test]
 async fn test_http_downloader_invalid_url_fails() {
     let strategy = Arc::new(MultipartDownloadStrategy::new(
@@ -196,9 +284,13 @@ async fn test_http_downloader_invalid_url_fails() {
         PathBuf::from("fail_test.bin"),
     ));
 
+    let observer = Arc::new(CollectingObserver::new());
     let mut downloader = HttpDownloader::new(strategy);
+    downloader.add_observer(Box::new(CollectingObserverHandle(Arc::clone(&observer))));
     let result = downloader.download().await;
     assert!(result.is_err(), "download to unreachable host should fail");
+    assert_eq!(observer.errors.lock().unwrap().len(), 1, "observers should hear about the failure");
+    assert!(observer.started.lock().unwrap().is_none(), "a failed probe never starts");
 }
 
 // ---------------------------------------------------------------
@@ -209,17 +301,29 @@ use std::sync::Mutex;
 use async_trait::async_trait;
 use rdm_core::progress::observer::ProgressObserver;
 use rdm_core::progress::snapshot::ProgressSnapshot;
+use rdm_core::types::types::{DownloadError, PreprocessInfo};
 
 struct CollectingObserver {
     total_bytes: Mutex<u64>,
+    /// `ProgressSnapshot::total_bytes` of the latest event.
+    expected_total: Mutex<u64>,
     event_count: Mutex<u64>,
+    errors: Mutex<Vec<String>>,
+    started: Mutex<Option<PreprocessInfo>>,
+    warnings: Mutex<Vec<String>>,
+    completed: Mutex<Option<ProgressSnapshot>>,
 }
 
 impl CollectingObserver {
     fn new() -> Self {
         Self {
             total_bytes: Mutex::new(0),
+            expected_total: Mutex::new(0),
             event_count: Mutex::new(0),
+            errors: Mutex::new(Vec::new()),
+            started: Mutex::new(None),
+            warnings: Mutex::new(Vec::new()),
+            completed: Mutex::new(None),
         }
     }
 
@@ -230,13 +334,24 @@ impl CollectingObserver {
 
 #[async_trait]
 impl ProgressObserver for CollectingObserver {
+    async fn on_start(&self, info: &PreprocessInfo) {
+        *self.started.lock().unwrap() = Some(info.clone());
+    }
+    async fn on_warning(&self, message: &str) {
+        self.warnings.lock().unwrap().push(message.to_string());
+    }
     async fn on_progress(&self, snapshot: &ProgressSnapshot) {
         let delta: u64 = snapshot.segments.iter().map(|p| p.bytes_downloaded).sum();
         *self.total_bytes.lock().unwrap() = delta;
+        *self.expected_total.lock().unwrap() = snapshot.total_bytes;
         *self.event_count.lock().unwrap() += 1;
     }
-    async fn on_complete(&self, _snapshot: &ProgressSnapshot) {}
-    async fn on_error(&self, _error: &str) {}
+    async fn on_complete(&self, snapshot: &ProgressSnapshot) {
+        *self.completed.lock().unwrap() = Some(snapshot.clone());
+    }
+    async fn on_error(&self, error: &str) {
+        self.errors.lock().unwrap().push(error.to_string());
+    }
 }
 
 #[tokio::test]
@@ -275,6 +390,12 @@ struct CollectingObserverHandle(Arc<CollectingObserver>);
 
 #[async_trait]
 impl ProgressObserver for CollectingObserverHandle {
+    async fn on_start(&self, info: &PreprocessInfo) {
+        self.0.on_start(info).await;
+    }
+    async fn on_warning(&self, message: &str) {
+        self.0.on_warning(message).await;
+    }
     async fn on_progress(&self, snapshot: &ProgressSnapshot) {
         self.0.on_progress(snapshot).await;
     }
@@ -285,3 +406,321 @@ impl ProgressObserver for CollectingObserverHandle {
         self.0.on_error(error).await;
     }
 }
+
+/// Records the order of `on_phase`, `on_start` and the first `on_progress`.
+#[derive(Clone, Default)]
+struct LifecycleRecorder(Arc<Mutex<Vec<String>>>);
+
+#[async_trait]
+impl ProgressObserver for LifecycleRecorder {
+    async fn on_phase(&self, phase: &str) {
+        self.0.lock().unwrap().push(format!("phase {}", phase));
+    }
+    async fn on_start(&self, _info: &PreprocessInfo) {
+        self.0.lock().unwrap().push("start".to_string());
+    }
+    async fn on_progress(&self, snapshot: &ProgressSnapshot) {
+        let mut events = self.0.lock().unwrap();
+        if !events.iter().any(|e| e.starts_with("progress")) {
+            events.push(format!("progress phase={:?}", snapshot.phase));
+        }
+    }
+    async fn on_complete(&self, _snapshot: &ProgressSnapshot) {}
+    async fn on_error(&self, _error: &str) {}
+}
+
+#[tokio::test]
+async fn test_preprocess_phases_reach_observers_before_start() {
+    let body = generate_test_data(64 * 1024);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(RangeResponder { body })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = Arc::new(MultipartDownloadStrategy::new(server.uri(), dir.path().join("phases.bin")));
+    let recorder = LifecycleRecorder::default();
+    let mut downloader = HttpDownloader::new(strategy);
+    downloader.add_observer(Box::new(recorder.clone()));
+    downloader.download().await.unwrap();
+
+    let events = recorder.0.lock().unwrap().clone();
+    assert_eq!(events, vec!["phase Probing…", "start", "progress phase=None"]);
+}
+
+#[tokio::test]
+async fn test_http_downloader_returns_summary() {
+    let body_size = 512 * 1024;
+    let body = generate_test_data(body_size);
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .respond_with(RangeResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output_path = dir.path().join("summary.bin");
+    let strategy = Arc::new(
+        MultipartDownloadStrategy::builder(server.uri(), output_path.clone())
+            .with_target_segment_size(256 * 1024)
+            .with_multipart_threshold(0)
+            .build(),
+    );
+
+    let observer = Arc::new(CollectingObserver::new());
+    let mut downloader = HttpDownloader::new(strategy);
+    downloader.add_observer(Box::new(CollectingObserverHandle(Arc::clone(&observer))));
+    let summary = downloader.download().await.unwrap();
+
+    let started = observer.started.lock().unwrap().clone().expect("on_start should be called");
+    assert_eq!(started.file_size, Some(body_size as u64));
+    assert!(started.resumable);
+    assert_eq!(started.segment_count, summary.segments);
+
+    assert_eq!(summary.path, output_path.to_string_lossy());
+    assert_eq!(summary.bytes, body_size as u64);
+    assert!(summary.resumable);
+    assert!(summary.segments > 1, "512 KB resumable body should be split");
+    assert!(summary.avg_speed > 0.0);
+    assert_eq!(summary.checksum, None);
+    assert_eq!(summary.checksum_ok, None);
+}
+
+#[tokio::test]
+async fn test_forced_single_stream_skips_ranges_but_knows_the_total() {
+    let body_size = 512 * 1024;
+    let body = generate_test_data(body_size);
+
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .respond_with(RangeResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let output_filename = format!("test_single_stream_{}.bin", uuid::Uuid::new_v4());
+    let strategy = Arc::new(
+        MultipartDownloadStrategy::builder(server.uri(), PathBuf::from(&output_filename))
+            .with_target_segment_size(64 * 1024)
+            .with_force_single_stream(true)
+            .build(),
+    );
+
+    let observer = Arc::new(CollectingObserver::new());
+    let mut downloader = HttpDownloader::new(strategy);
+    downloader.add_observer(Box::new(CollectingObserverHandle(Arc::clone(&observer))));
+    let summary = downloader.download().await.unwrap();
+
+    assert_eq!(summary.segments, 1);
+    assert!(!summary.resumable);
+    assert_eq!(std::fs::read(&output_filename).unwrap(), body);
+    assert_eq!(*observer.expected_total.lock().unwrap(), body_size as u64);
+
+    // Only the probe asked for a range.
+    let ranges: Vec<_> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|r| r.headers.get("Range").map(|v| v.to_str().unwrap().to_string()))
+        .collect();
+    assert_eq!(ranges, vec!["bytes=0-0".to_string()]);
+
+    let _ = std::fs::remove_file(&output_filename);
+}
+
+#[tokio::test]
+async fn test_output_is_verified_against_published_checksum_file() {
+    use rdm_core::types::types::DownloadError;
+    use sha2::{Digest, Sha256};
+    use wiremock::matchers::{header, path};
+
+    let body = generate_test_data(300 * 1024);
+    let digest: String = Sha256::digest(&body).iter().map(|b| format!("{:02x}", b)).collect();
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/pub/image.iso"))
+        .respond_with(RangeResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+    // coreutils format listing several files; only the cookie holder may read it.
+    Mock::given(method("GET"))
+        .and(path("/pub/SHA256SUMS"))
+        .and(header("Cookie", "session=abc"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!(
+            "{}  other.iso\n{} *image.iso\n",
+            "ab".repeat(32),
+            digest
+        )))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/pub/image.iso.sha256"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!("{}\n", "00".repeat(32))))
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let download = |sidecar: &str, name: &str| {
+        let strategy = MultipartDownloadStrategy::builder(format!("{}/pub/image.iso", server.uri()), dir.path().join(name))
+            .with_cookies("session=abc".to_string())
+            .with_checksum_url(format!("{}/pub/{}", server.uri(), sidecar))
+            .build();
+        async move { HttpDownloader::new(Arc::new(strategy)).download().await }
+    };
+
+    let summary = download("SHA256SUMS", "good.iso").await.unwrap();
+    assert_eq!(summary.checksum.as_deref(), Some(digest.as_str()));
+    assert_eq!(summary.checksum_ok, Some(true));
+
+    match download("image.iso.sha256", "bad.iso").await {
+        Err(DownloadError::ChecksumMismatch { expected, actual }) => {
+            assert_eq!(expected, "00".repeat(32));
+            assert_eq!(actual, digest);
+        }
+        other => panic!("expected a checksum mismatch, got {:?}", other.map(|s| s.path)),
+    }
+    // The mismatching file is kept for inspection.
+    assert_eq!(std::fs::read(dir.path().join("bad.iso")).unwrap(), body);
+
+    let missing = download("image.iso.md5", "missing.iso").await;
+    assert!(matches!(missing, Err(DownloadError::ChecksumFile(_))), "{:?}", missing.map(|s| s.path));
+}
+
+#[tokio::test]
+async fn test_published_md5_is_verified_and_an_explicit_checksum_wins() {
+    use rdm_core::downloader::checksum::Checksum;
+    use rdm_core::types::types::ChecksumAlgo;
+    use wiremock::matchers::path;
+
+    let digest = |algo, data: &[u8]| {
+        let mut checksum = Checksum::new(algo);
+        checksum.update(data);
+        checksum.finalize_hex()
+    };
+    assert_eq!(digest(ChecksumAlgo::Md5, b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+
+    let body = generate_test_data(300 * 1024);
+    let md5 = digest(ChecksumAlgo::Md5, &body);
+    let sha256 = digest(ChecksumAlgo::Sha256, &body);
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/pub/image.iso"))
+        .respond_with(RangeResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/pub/image.iso.md5"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!("{}  image.iso\n", md5)))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/pub/stale.md5"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!("{}\n", "00".repeat(16))))
+        .mount(&server)
+        .await;
+    // With an expected digest the published file is never read.
+    Mock::given(method("GET"))
+        .and(path("/pub/unread.md5"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(format!("{}\n", "00".repeat(16))))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/pub/image.iso", server.uri());
+    let sidecar = |name: &str| format!("{}/pub/{}", server.uri(), name);
+
+    let strategy = MultipartDownloadStrategy::builder(url.clone(), dir.path().join("md5.iso"))
+        .with_checksum_url(sidecar("image.iso.md5"))
+        .build();
+    let summary = HttpDownloader::new(Arc::new(strategy)).download().await.unwrap();
+    assert_eq!(summary.checksum.as_deref(), Some(md5.as_str()));
+    assert_eq!(summary.checksum_ok, Some(true));
+
+    let strategy = MultipartDownloadStrategy::builder(url.clone(), dir.path().join("expected.iso"))
+        .with_expected_checksum(ChecksumAlgo::Sha256, sha256.clone())
+        .with_checksum_url(sidecar("unread.md5"))
+        .build();
+    let summary = HttpDownloader::new(Arc::new(strategy)).download().await.unwrap();
+    assert_eq!(summary.checksum.as_deref(), Some(sha256.as_str()));
+    assert_eq!(summary.checksum_ok, Some(true));
+
+    // The sha256 asked for is reported; the md5 published next to it cannot
+    // be checked against it.
+    let strategy = MultipartDownloadStrategy::builder(url, dir.path().join("computed.iso"))
+        .with_compute_checksum(ChecksumAlgo::Sha256)
+        .with_checksum_url(sidecar("stale.md5"))
+        .build();
+    let summary = HttpDownloader::new(Arc::new(strategy)).download().await.unwrap();
+    assert_eq!(summary.checksum.as_deref(), Some(sha256.as_str()));
+    assert_eq!(summary.checksum_ok, None);
+}
+
+#[tokio::test]
+async fn test_http_downloader_reports_checksum() {
+    use rdm_core::types::types::ChecksumAlgo;
+    use sha2::{Digest, Sha256};
+
+    let body = generate_test_data(512 * 1024);
+    let expected: String = Sha256::digest(&body).iter().map(|b| format!("{:02x}", b)).collect();
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(RangeResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let cases = [
+        (None, None),
+        (Some(expected.to_uppercase()), Some(true)),
+        (Some("00".repeat(32)), Some(false)),
+    ];
+    for (i, (expected_hex, checksum_ok)) in cases.into_iter().enumerate() {
+        let output = dir.path().join(format!("checksum_{}.bin", i));
+        let builder = MultipartDownloadStrategy::builder(server.uri(), output.clone());
+        let builder = match expected_hex {
+            Some(hex) => builder.with_expected_checksum(ChecksumAlgo::Sha256, hex),
+            None => builder.with_compute_checksum(ChecksumAlgo::Sha256),
+        };
+
+        let summary = HttpDownloader::new(Arc::new(builder.build())).download().await.unwrap();
+
+        assert_eq!(summary.checksum.as_deref(), Some(expected.as_str()));
+        assert_eq!(summary.checksum_ok, checksum_ok);
+        // Reporting only: even a mismatch leaves the file in place, intact.
+        assert_eq!(std::fs::read(&output).unwrap(), body);
+    }
+}
+
+#[tokio::test]
+async fn test_chunked_download_without_length_completes() {
+    let body = generate_test_data(300 * 1024 + 7);
+    let url = start_chunked_server(body.clone()).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("stream.bin");
+    let strategy = Arc::new(MultipartDownloadStrategy::new(url, output.clone()));
+    let observer = Arc::new(CollectingObserver::new());
+    let mut downloader = HttpDownloader::new(strategy);
+    downloader.add_observer(Box::new(CollectingObserverHandle(Arc::clone(&observer))));
+    let summary = downloader.download().await.unwrap();
+
+    let started = observer.started.lock().unwrap().clone().unwrap();
+    assert_eq!((started.file_size, started.resumable, started.segment_count), (None, false, 1));
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+    assert_eq!(summary.bytes, body.len() as u64);
+
+    let done = observer.completed.lock().unwrap().clone().expect("on_complete should be called");
+    assert!(done.done);
+    assert_eq!(done.total_bytes_downloaded, body.len() as u64);
+    assert_eq!(done.total_bytes, done.total_bytes_downloaded);
+    assert_eq!((done.completed_segments, done.total_segments), (1, 1));
+    assert!(observer.errors.lock().unwrap().is_empty());
+}
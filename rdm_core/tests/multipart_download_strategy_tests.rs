@@ -3,9 +3,14 @@ use std::path::PathBuf;
 use wiremock::matchers::{header, method};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
+use rdm_test_support::{
+    start_preface_recorder, start_single_connection_server, start_throttled_range_server, RangeSlice,
+};
+
+use rdm_core::downloader::strategy::common_options::CommonOptions;
 use rdm_core::downloader::strategy::download_strategy::DownloadStrategy;
 use rdm_core::downloader::strategy::multipart_download_strategy::MultipartDownloadStrategy;
-use rdm_core::types::types::{Segment, SegmentState, StreamType};
+use rdm_core::types::types::{DownloadError, Segment, SegmentState, StreamType};
 
 /// Generates deterministic test data: each byte = (offset % 251) as u8.
 fn generate_test_data(size: usize) -> Vec<u8> {
@@ -68,7 +73,9 @@ async fn test_preprocess_resumable_creates_multiple_segments() {
     let body_size = 2 * 1024 * 1024;
     let (server, _body) = setup_resumable_server(body_size).await;
 
-    let strategy = MultipartDownloadStrategy::new(server.uri(), PathBuf::from("out.bin"));
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), PathBuf::from("out.bin"))
+        .with_multipart_threshold(0)
+        .build();
 
     strategy.preprocess().await.unwrap();
 
@@ -154,6 +161,7 @@ async fn test_preprocess_invalid_url_returns_error() {
 // ---------------------------------------------------------------
 
 #[tokio::test]
+#[allow(clippy::await_holding_lock)]
 async fn test_download_writes_all_segments_to_temp_files() {
     let body_size = 2 * 1024 * 1024;
     let (server, _body) = setup_resumable_server(body_size).await;
@@ -192,6 +200,59 @@ async fn test_download_writes_all_segments_to_temp_files() {
     }
 }
 
+#[tokio::test]
+async fn test_dropping_a_non_resumable_strategy_removes_temp_dir() {
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("out.bin"))
+        .with_keep_temp(false)
+        .with_probe_hints(rdm_core::types::types::ProbeHints {
+            file_size: Some(64 * 1024),
+            resumable: Some(false),
+            ..Default::default()
+        })
+        .build();
+    strategy.preprocess().await.unwrap();
+    let temp_dir = PathBuf::from(strategy.temp_dir().await);
+    assert!(temp_dir.exists());
+
+    drop(strategy);
+    assert!(!temp_dir.exists(), "the dropped download's temp dir should be removed");
+}
+
+#[tokio::test]
+async fn test_dropped_resumable_download_can_be_restored() {
+    let (server, body) = setup_resumable_server(200_000).await;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("out.bin");
+
+    // A run that stops (the process exits, say) after fetching its segments.
+    let first = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+        .with_keep_temp(false)
+        .with_connection_size(2)
+        .build();
+    first.preprocess().await.unwrap();
+    first.download().await.unwrap();
+    let temp_dir = first.temp_dir().await;
+    let segments: Vec<Segment> = first.segments().read().await.values().cloned().collect();
+    drop(first);
+    assert!(PathBuf::from(&temp_dir).exists(), "a resumable download's temp dir must survive for a restore");
+
+    let received = server.received_requests().await.unwrap().len();
+    let second = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+        .with_connection_size(2)
+        .build();
+    second.state().write().unwrap().temp_dir = temp_dir.clone();
+    second.restore_segments(segments).await.unwrap();
+    second.preprocess().await.unwrap();
+    second.download().await.unwrap();
+    second.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+    // Only the probe: every segment came from the first run.
+    assert_eq!(server.received_requests().await.unwrap().len(), received + 1);
+    assert!(!PathBuf::from(&temp_dir).exists());
+}
+
 #[tokio::test]
 async fn test_download_no_segments_is_noop() {
     let (server, _) = setup_resumable_server(1024).await;
@@ -227,6 +288,20 @@ async fn test_pause_cancels_token() {
     assert!(strategy.cancel_token().is_cancelled());
 }
 
+#[tokio::test]
+async fn test_hold_keeps_the_download_without_cancelling() {
+    let (server, _) = setup_resumable_server(1024).await;
+
+    let strategy = MultipartDownloadStrategy::new(server.uri(), PathBuf::from("out.bin"));
+
+    strategy.hold().await.unwrap();
+    assert!(strategy.pause_token().is_paused());
+    assert!(!strategy.cancel_token().is_cancelled());
+
+    strategy.resume().await.unwrap();
+    assert!(!strategy.pause_token().is_paused());
+}
+
 // ---------------------------------------------------------------
 // postprocess tests
 // ---------------------------------------------------------------
@@ -297,6 +372,51 @@ async fn test_postprocess_assembles_segments_in_order() {
     let _ = std::fs::remove_file("assembled_output.bin");
 }
 
+#[tokio::test]
+async fn test_postprocess_truncates_overlong_segment_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let output_dir = tempfile::tempdir().unwrap();
+    let output_path = output_dir.path().join("overlong.bin");
+
+    let strategy = MultipartDownloadStrategy::new("http://unused".to_string(), output_path.clone());
+    {
+        let mut s = strategy.state().write().unwrap();
+        s.temp_dir = temp_dir.path().to_string_lossy().to_string();
+        s.output_path = Some(output_path.to_string_lossy().to_string());
+    }
+
+    // p1 holds its 100 bytes followed by the start of a spurious full body.
+    let mut segment1_data = vec![0x11u8; 100];
+    segment1_data.extend_from_slice(&[0xEEu8; 300]);
+    let segment2_data = vec![0x22u8; 200];
+    std::fs::write(temp_dir.path().join("p1"), &segment1_data).unwrap();
+    std::fs::write(temp_dir.path().join("p2"), &segment2_data).unwrap();
+
+    {
+        let mut segments = strategy.segments().write().await;
+        for (id, offset, length) in [("p1", 0, 100), ("p2", 100, 200)] {
+            segments.insert(
+                id.to_string(),
+                Segment {
+                    id: id.to_string(),
+                    offset,
+                    length,
+                    downloaded: length,
+                    state: SegmentState::Finished,
+                    stream_type: StreamType::Primary,
+                },
+            );
+        }
+    }
+
+    strategy.postprocess().await.unwrap();
+
+    let output = std::fs::read(&output_path).unwrap();
+    assert_eq!(output.len(), 300);
+    assert_eq!(&output[..100], &[0x11u8; 100][..]);
+    assert_eq!(&output[100..], &segment2_data[..]);
+}
+
 #[tokio::test]
 async fn test_postprocess_fails_if_segment_not_finished() {
     let temp_dir = tempfile::tempdir().unwrap();
@@ -341,3 +461,2517 @@ async fn test_full_lifecycle_with_mock_server() {
 
     let _ = std::fs::remove_file("lifecycle_test.bin");
 }
+
+#[tokio::test]
+async fn test_estimated_remaining_follows_the_segment_plan() {
+    let body_size = 512 * 1024;
+    let (server, _expected_body) = setup_resumable_server(body_size).await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::new(server.uri(), dir.path().join("remaining.bin"));
+
+    assert_eq!(strategy.estimated_remaining().await, None, "nothing is planned before preprocess");
+    strategy.preprocess().await.unwrap();
+    assert_eq!(strategy.estimated_remaining().await, Some(body_size as u64));
+    strategy.download().await.unwrap();
+    assert_eq!(strategy.estimated_remaining().await, Some(0));
+}
+
+/// Serves `body` by range, but answers the probe with a `claimed` total.
+struct MisreportedSize {
+    body: Vec<u8>,
+    claimed: usize,
+}
+
+impl wiremock::Respond for MisreportedSize {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let range = request.headers.get("Range").and_then(|v| v.to_str().ok()).unwrap_or("");
+        if range == "bytes=0-0" {
+            return ResponseTemplate::new(206)
+                .set_body_bytes(vec![self.body[0]])
+                .insert_header("Content-Range", format!("bytes 0-0/{}", self.claimed));
+        }
+        let (start, end) = range.trim_start_matches("bytes=").split_once('-').unwrap();
+        let start: usize = start.parse().unwrap();
+        if start >= self.body.len() {
+            return ResponseTemplate::new(416).insert_header("Content-Range", format!("bytes */{}", self.body.len()));
+        }
+        let end = end.parse::<usize>().unwrap().min(self.body.len() - 1);
+        ResponseTemplate::new(206)
+            .set_body_bytes(self.body[start..=end].to_vec())
+            .insert_header("Content-Range", format!("bytes {}-{}/{}", start, end, self.body.len()))
+    }
+}
+
+#[tokio::test]
+async fn test_overstated_probe_size_is_reconciled() {
+    let body = generate_test_data(2_500_000);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(MisreportedSize { body: body.clone(), claimed: 4 * 1024 * 1024 })
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::new(server.uri(), dir.path().join("overstated.bin"));
+
+    let info = strategy.preprocess().await.unwrap();
+    assert_eq!(info.file_size, Some(4 * 1024 * 1024));
+    assert_eq!(info.segment_count, 4);
+    strategy.download().await.unwrap();
+
+    // The last segment started past the real end and was dropped; the one
+    // before it was trimmed to end there.
+    let segments = strategy.segments_snapshot().await;
+    assert_eq!(segments.len(), 3);
+    assert_eq!(segments.iter().map(|s| s.length).sum::<i64>(), body.len() as i64);
+    assert_eq!(strategy.state().read().unwrap().file_size, body.len() as i64);
+
+    strategy.postprocess().await.unwrap();
+    let output = strategy.state().read().unwrap().output_path.clone().unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_size_warning_reaches_a_slow_observer() {
+    let body = generate_test_data(2_500_000);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(MisreportedSize { body: body.clone(), claimed: 4 * 1024 * 1024 })
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::new(server.uri(), dir.path().join("slow_observer.bin"));
+    strategy.preprocess().await.unwrap();
+
+    // The reader falls behind, so the channel is full nearly all the time;
+    // progress may be dropped, the warning must not be.
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+    strategy.set_progress_tx(tx);
+    let reader = tokio::spawn(async move {
+        let mut warnings = Vec::new();
+        while let Some(event) = rx.recv().await {
+            warnings.extend(event.unwrap().warning);
+            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        }
+        warnings
+    });
+    strategy.download().await.unwrap();
+    strategy.clear_progress_tx();
+
+    let warnings = reader.await.unwrap();
+    assert_eq!(warnings.len(), 1, "{:?}", warnings);
+    assert!(warnings[0].contains("the server reported"), "{}", warnings[0]);
+}
+
+#[tokio::test]
+async fn test_understated_probe_size_gets_a_tail_segment() {
+    let body = generate_test_data(2_500_000);
+    let claimed = 2 * 1024 * 1024;
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(MisreportedSize { body: body.clone(), claimed })
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("understated.bin"))
+        .with_multipart_threshold(0)
+        .build();
+
+    let info = strategy.preprocess().await.unwrap();
+    assert_eq!(info.file_size, Some(claimed as u64));
+    let planned = info.segment_count;
+    assert!(planned > 1);
+    strategy.download().await.unwrap();
+
+    // The segments report the real total; what lies past the probed end is
+    // fetched as one more segment.
+    let segments = strategy.segments_snapshot().await;
+    assert_eq!(segments.len(), planned + 1);
+    let tail = segments.iter().max_by_key(|s| s.offset).unwrap();
+    assert_eq!((tail.offset, tail.length), (claimed as i64, (body.len() - claimed) as i64));
+    assert_eq!(strategy.state().read().unwrap().file_size, body.len() as i64);
+
+    strategy.postprocess().await.unwrap();
+    let output = strategy.state().read().unwrap().output_path.clone().unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_empty_file_produces_empty_output() {
+    // The probe's answer for a 0-byte file varies: a proper 416, a 200 that
+    // ignores the range, or a 206 claiming a total of 0.
+    let probes = [
+        ResponseTemplate::new(416).insert_header("Content-Range", "bytes */0"),
+        ResponseTemplate::new(200),
+        ResponseTemplate::new(206).insert_header("Content-Range", "bytes */0"),
+    ];
+    for (i, probe) in probes.into_iter().enumerate() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("Range", "bytes=0-0"))
+            .respond_with(probe)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).insert_header("Content-Type", "text/plain"))
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let strategy = MultipartDownloadStrategy::new(server.uri(), dir.path().join("empty.txt"));
+
+        let info = strategy.preprocess().await.unwrap();
+        assert_eq!(info.file_size, Some(0), "probe {}", i);
+        assert_eq!(info.segment_count, 1, "probe {}", i);
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+
+        let output = strategy.state().read().unwrap().output_path.clone().unwrap();
+        assert_eq!(std::fs::metadata(&output).unwrap().len(), 0, "probe {}", i);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_single_segment_is_moved_into_place() {
+    use std::os::unix::fs::MetadataExt;
+
+    let server = MockServer::start().await;
+    let body = generate_test_data(300 * 1024);
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_bytes(body.clone())
+                .insert_header("Content-Type", "application/zip"),
+        )
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    // No extension: the one from Content-Type must still be added.
+    let strategy = MultipartDownloadStrategy::new(server.uri(), dir.path().join("single"));
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    let temp_dir = PathBuf::from(strategy.temp_dir().await);
+    let segment_id = strategy.segments().read().await.keys().next().unwrap().clone();
+    let segment_inode = std::fs::metadata(temp_dir.join(&segment_id)).unwrap().ino();
+
+    strategy.postprocess().await.unwrap();
+
+    let output = strategy.state().read().unwrap().output_path.clone().unwrap();
+    assert!(output.ends_with("single.zip"), "{}", output);
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+    assert_eq!(
+        std::fs::metadata(&output).unwrap().ino(),
+        segment_inode,
+        "the segment file should have been renamed, not copied"
+    );
+    assert!(!temp_dir.exists());
+}
+
+// ---------------------------------------------------------------
+// Separate audio track (StreamType::Secondary)
+// ---------------------------------------------------------------
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_audio_url_downloads_secondary_stream_and_muxes() {
+    use std::os::unix::fs::PermissionsExt;
+    use wiremock::matchers::path;
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/video"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"VIDEO".to_vec()))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/audio"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"AUDIO".to_vec()))
+        .mount(&server)
+        .await;
+
+    // Stand-in for ffmpeg: concatenates the two inputs (args 5 and 7) into
+    // the output (arg 14) so the result can be checked without ffmpeg.
+    let dir = tempfile::tempdir().unwrap();
+    let fake_ffmpeg = dir.path().join("ffmpeg");
+    std::fs::write(&fake_ffmpeg, "#!/bin/sh\ncat \"$5\" \"$7\" > \"${14}\"\n").unwrap();
+    std::fs::set_permissions(&fake_ffmpeg, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = dir.path().join("muxed.mp4");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/video", server.uri()), output.clone())
+        .with_audio_url(format!("{}/audio", server.uri()))
+        .with_ffmpeg(&fake_ffmpeg)
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    {
+        let segments = strategy.segments().read().await;
+        assert_eq!(segments.len(), 2);
+        let secondary = segments
+            .values()
+            .filter(|s| s.stream_type == StreamType::Secondary)
+            .count();
+        assert_eq!(secondary, 1, "audio track should be tagged Secondary");
+    }
+
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), b"VIDEOAUDIO");
+}
+
+#[tokio::test]
+async fn test_set_output_path_only_before_preprocess() {
+    let (server, _body) = setup_non_resumable_server(1024).await;
+    let strategy = MultipartDownloadStrategy::new(server.uri(), PathBuf::from("before.bin"));
+
+    strategy.set_output_path("after.bin".to_string()).unwrap();
+    assert_eq!(strategy.state().read().unwrap().output_path.as_deref(), Some("after.bin"));
+
+    strategy.preprocess().await.unwrap();
+    assert!(matches!(
+        strategy.set_output_path("late.bin".to_string()),
+        Err(DownloadError::InvalidState)
+    ));
+
+    let _ = std::fs::remove_dir_all(strategy.temp_dir().await);
+}
+
+#[tokio::test]
+async fn test_set_connection_size_only_before_preprocess() {
+    let (server, _body) = setup_resumable_server(4 * 1024 * 1024).await;
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), PathBuf::from("connections.bin"))
+        .with_connection_size(8)
+        .build();
+
+    strategy.set_connection_size(2).unwrap();
+    strategy.preprocess().await.unwrap();
+    assert_eq!(strategy.segments().read().await.len(), 2);
+
+    assert!(matches!(strategy.set_connection_size(4), Err(DownloadError::InvalidState)));
+
+    let _ = std::fs::remove_dir_all(strategy.temp_dir().await);
+}
+
+#[tokio::test]
+async fn test_no_cache_sends_cache_busting_headers_on_every_request() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(512 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/fresh.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/fresh.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    for no_cache in [true, false] {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("fresh.bin");
+        let strategy = MultipartDownloadStrategy::builder(format!("{}/fresh.bin", server.uri()), output.clone())
+            .with_connection_size(2)
+            .with_multipart_threshold(0)
+            .with_target_segment_size(256 * 1024)
+            .with_headers(std::collections::HashMap::from([("cache-control".to_string(), vec!["max-age=60".to_string()])]))
+            .with_no_cache(no_cache)
+            .build();
+        let earlier = server.received_requests().await.unwrap().len();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), body);
+
+        // The probe and both segments.
+        let requests = server.received_requests().await.unwrap().split_off(earlier);
+        assert_eq!(requests.len(), 3);
+        for request in &requests {
+            let values = |name: &str| -> Vec<&str> {
+                request.headers.get_all(name).iter().map(|v| v.to_str().unwrap()).collect()
+            };
+            if no_cache {
+                assert_eq!(values("cache-control"), ["no-cache"]);
+                assert_eq!(values("pragma"), ["no-cache"]);
+            } else {
+                assert_eq!(values("cache-control"), ["max-age=60"]);
+                assert!(values("pragma").is_empty());
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_set_request_headers_only_before_preprocess() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(header("X-Token", "fresh"))
+        .and(header("Cookie", "session=new"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"payload".to_vec()))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET")).respond_with(ResponseTemplate::new(403)).mount(&server).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("headers.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/headers.bin", server.uri()), output.clone())
+        .with_headers(std::collections::HashMap::from([("X-Token".to_string(), vec!["stale".to_string()])]))
+        .with_cookies("session=old".to_string())
+        .build();
+
+    strategy
+        .set_request_headers(
+            std::collections::HashMap::from([("X-Token".to_string(), vec!["fresh".to_string()])]),
+            Some("session=new".to_string()),
+        )
+        .unwrap();
+    strategy.preprocess().await.unwrap();
+    assert!(matches!(
+        strategy.set_request_headers(std::collections::HashMap::new(), None),
+        Err(DownloadError::InvalidState)
+    ));
+
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), b"payload");
+}
+
+#[cfg(feature = "compression")]
+#[tokio::test]
+async fn test_store_compression_replaces_output_with_compressed_file() {
+    use std::io::Read;
+
+    use rdm_core::types::types::{ChecksumAlgo, Codec};
+
+    let body: Vec<u8> = b"2026-10-15 INFO request served\n".repeat(20_000);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(body.clone()))
+        .mount(&server)
+        .await;
+
+    for codec in [Codec::Gzip, Codec::Zstd] {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("server.log");
+        let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+            .with_store_compression(codec)
+            .with_compute_checksum(ChecksumAlgo::Sha256)
+            .build();
+
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+
+        let stored = dir.path().join(format!("server.log.{}", codec.extension()));
+        assert!(!output.exists(), "uncompressed intermediate should be removed");
+        let state = strategy.state().read().unwrap().clone();
+        assert_eq!(state.output_path.as_deref(), Some(stored.to_str().unwrap()));
+        let compressed = std::fs::read(&stored).unwrap();
+        assert_eq!(state.compressed_size, Some(compressed.len() as u64));
+        assert!(compressed.len() < body.len() / 10);
+
+        let mut restored = Vec::new();
+        match codec {
+            Codec::Gzip => flate2::read::GzDecoder::new(&compressed[..]).read_to_end(&mut restored).unwrap(),
+            Codec::Zstd => zstd::stream::read::Decoder::new(&compressed[..]).unwrap().read_to_end(&mut restored).unwrap(),
+        };
+        assert_eq!(restored, body);
+
+        // The checksum describes the content, not the stored encoding.
+        let mut raw = rdm_core::downloader::checksum::Checksum::new(ChecksumAlgo::Sha256);
+        raw.update(&body);
+        assert_eq!(state.checksum, Some(raw.finalize_hex()));
+    }
+}
+
+#[tokio::test]
+async fn test_keep_temp_skips_cleanup() {
+    let body_size = 512 * 1024;
+    let (server, _body) = setup_resumable_server(body_size).await;
+
+    let output = std::env::temp_dir().join(format!("keep_temp_{}.bin", uuid::Uuid::new_v4()));
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+        .with_keep_temp(true)
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    let temp_dir = strategy.temp_dir().await;
+    let segment_count = strategy.segments().read().await.len();
+    let kept = std::fs::read_dir(&temp_dir).unwrap().count();
+    assert_eq!(kept, segment_count, "every segment file should be left in the temp dir");
+    assert!(output.exists());
+
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    let _ = std::fs::remove_file(&output);
+}
+
+#[tokio::test]
+async fn test_postprocess_removes_temp_dir_with_state_files() {
+    use rdm_core::downloader::segment_grabber::FlushInterval;
+
+    let (server, _body) = setup_resumable_server(512 * 1024).await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("out.bin"))
+        .with_flush_interval(FlushInterval { bytes: 1, ..FlushInterval::default() })
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    let temp_dir = PathBuf::from(strategy.temp_dir().await);
+    assert!(
+        std::fs::read_dir(&temp_dir).unwrap().any(|e| e.unwrap().path().extension() == Some("state".as_ref())),
+        "flushing should have left .state records"
+    );
+
+    strategy.postprocess().await.unwrap();
+    assert!(!temp_dir.exists(), "temp dir should be removed along with its .state files");
+}
+
+#[tokio::test]
+async fn test_continue_appends_to_existing_partial() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(2 * 1024 * 1024);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .and(header("Range", "bytes=0-0"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .set_body_bytes(vec![body[0]])
+                .insert_header("Content-Range", format!("bytes 0-0/{}", body.len())),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    // The first 700 KB are already on disk.
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("partial.bin");
+    let existing = 700 * 1024;
+    std::fs::write(&output, &body[..existing]).unwrap();
+
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/file.bin", server.uri()), output.clone())
+        .with_continue(true)
+        .with_compute_checksum(rdm_core::types::types::ChecksumAlgo::Sha256)
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    {
+        let segments = strategy.segments().read().await;
+        let first = segments.values().map(|s| s.offset).min().unwrap();
+        assert_eq!(first, existing as i64, "only the missing tail should be requested");
+        let total: i64 = segments.values().map(|s| s.length).sum();
+        assert_eq!(total, (body.len() - existing) as i64);
+    }
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+    // The digest covers the kept prefix too, not just the appended tail.
+    let expected = rdm_core::downloader::checksum::hash_file(&output, rdm_core::types::types::ChecksumAlgo::Sha256).unwrap();
+    assert_eq!(strategy.state().read().unwrap().checksum.as_deref(), Some(expected.as_str()));
+}
+
+/// Mounts a `Range: bytes=0-0` probe answer for `route` reporting `size` and `etag`.
+async fn mount_probe(server: &MockServer, route: &str, size: usize, etag: &str) {
+    use wiremock::matchers::path;
+
+    Mock::given(method("GET"))
+        .and(path(route))
+        .and(header("Range", "bytes=0-0"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .set_body_bytes(vec![0u8])
+                .insert_header("Content-Range", format!("bytes 0-0/{}", size))
+                .insert_header("ETag", etag),
+        )
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn test_segments_fail_over_to_mirror() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(1024 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/primary.bin", body.len(), "\"v1\"").await;
+    mount_probe(&server, "/mirror.bin", body.len(), "\"v1\"").await;
+    // The primary only answers probes; every segment request fails.
+    Mock::given(method("GET"))
+        .and(path("/primary.bin"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/mirror.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("mirrored.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/primary.bin", server.uri()), output.clone())
+        .with_connection_size(4)
+        .with_mirrors(vec![format!("{}/mirror.bin", server.uri())])
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_mirror_with_different_size_is_rejected() {
+    let server = MockServer::start().await;
+    mount_probe(&server, "/primary.bin", 1024 * 1024, "\"v1\"").await;
+    mount_probe(&server, "/mirror.bin", 1024 * 1024 + 1, "\"v1\"").await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(
+        format!("{}/primary.bin", server.uri()),
+        dir.path().join("out.bin"),
+    )
+    .with_mirrors(vec![format!("{}/mirror.bin", server.uri())])
+    .build();
+
+    let result = strategy.preprocess().await;
+    assert!(matches!(result, Err(DownloadError::MirrorMismatch(_))), "got {:?}", result.err());
+}
+
+#[tokio::test]
+async fn test_write_buffer_size_is_clamped_and_used() {
+    let body = generate_test_data(300 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/small-buf.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("small-buf.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/small-buf.bin", server.uri()), output.clone())
+        .with_write_buffer_size(16)
+        .build();
+    assert_eq!(strategy.state().read().unwrap().write_buffer_size, 4 * 1024);
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_sniff_extension_appends_only_when_missing() {
+    // Minimal PDF header followed by filler.
+    let mut body = b"%PDF-1.7\n".to_vec();
+    body.resize(64 * 1024, b' ');
+    let server = MockServer::start().await;
+    mount_probe(&server, "/blob", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    for (name, expected) in [("report", "report.pdf"), ("report.dat", "report.dat")] {
+        let strategy = MultipartDownloadStrategy::builder(format!("{}/blob", server.uri()), dir.path().join(name))
+            .with_sniff_extension(true)
+            .build();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+
+        let expected = dir.path().join(expected);
+        let output_path = strategy.state().read().unwrap().output_path.clone().unwrap();
+        assert_eq!(PathBuf::from(output_path), expected);
+        assert_eq!(std::fs::read(&expected).unwrap(), body);
+    }
+}
+
+#[tokio::test]
+async fn test_probe_cookies_are_sent_with_segments() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(512 * 1024);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/signed.bin"))
+        .and(header("Range", "bytes=0-0"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .set_body_bytes(vec![body[0]])
+                .insert_header("Content-Range", format!("bytes 0-0/{}", body.len()))
+                .append_header("Set-Cookie", "edge=xyz; Path=/; HttpOnly")
+                .append_header("Set-Cookie", "sid=fresh; Secure"),
+        )
+        .mount(&server)
+        .await;
+    // Segments are only served with the merged cookie set; anything else 404s.
+    Mock::given(method("GET"))
+        .and(path("/signed.bin"))
+        .and(header("Cookie", "sid=fresh; edge=xyz"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("signed.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/signed.bin", server.uri()), output.clone())
+        .with_cookies("sid=stale".to_string())
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_post_download_sends_body_without_probe_or_ranges() {
+    use wiremock::matchers::{body_bytes, path};
+
+    let body = generate_test_data(256 * 1024);
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/export"))
+        .and(body_bytes(b"token=abc".to_vec()))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(body.clone()))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("export.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/export", server.uri()), output.clone())
+        .with_method(reqwest::Method::POST)
+        .with_body(b"token=abc".to_vec())
+        .with_connection_size(4)
+        .build();
+
+    strategy.preprocess().await.unwrap();
+    assert_eq!(strategy.segments().read().await.len(), 1);
+    assert!(!strategy.state().read().unwrap().resumable);
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|r| !r.headers.contains_key("Range")));
+}
+
+/// Records every `User-Agent` value of each request it answers.
+struct UserAgentRecorder {
+    seen: std::sync::Arc<std::sync::Mutex<Vec<Vec<String>>>>,
+}
+
+impl wiremock::Respond for UserAgentRecorder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let values = request
+            .headers
+            .get_all("User-Agent")
+            .iter()
+            .map(|v| v.to_str().unwrap().to_string())
+            .collect();
+        self.seen.lock().unwrap().push(values);
+        ResponseTemplate::new(200).set_body_bytes(b"ok".to_vec())
+    }
+}
+
+#[tokio::test]
+async fn test_user_agent_precedence() {
+    use std::collections::HashMap;
+
+    let captured = HashMap::from([("user-agent".to_string(), vec!["Browser/1.0".to_string()])]);
+    let cases = [
+        (None, None, rdm_core::downloader::strategy::multipart_download_strategy::DEFAULT_USER_AGENT),
+        (None, Some("Builder/2.0"), "Builder/2.0"),
+        (Some(captured), Some("Builder/2.0"), "Browser/1.0"),
+    ];
+
+    for (headers, builder_ua, expected) in cases {
+        let server = MockServer::start().await;
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        Mock::given(method("GET"))
+            .respond_with(UserAgentRecorder { seen: seen.clone() })
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("ua.txt"));
+        if let Some(headers) = headers {
+            builder = builder.with_headers(headers);
+        }
+        if let Some(ua) = builder_ua {
+            builder = builder.with_user_agent(ua.to_string());
+        }
+        let strategy = builder.build();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+
+        let seen = seen.lock().unwrap().clone();
+        assert!(!seen.is_empty());
+        for values in seen {
+            assert_eq!(values, vec![expected.to_string()]);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_default_accept_when_none_captured() {
+    use rdm_core::types::types::{DefaultAccept, ProbeHints};
+    use std::collections::HashMap;
+
+    let captured = HashMap::from([("accept".to_string(), vec!["image/webp".to_string()])]);
+    let cases = [
+        (None, Some(DefaultAccept::FromContentType), Some("video/mp4"), "video/mp4,video/*;q=0.9,*/*;q=0.8"),
+        // The probe finds out the type, but it sent `*/*`; so do the segments.
+        (None, Some(DefaultAccept::FromContentType), None, "*/*"),
+        (Some(captured), Some(DefaultAccept::FromContentType), Some("video/mp4"), "image/webp"),
+        (None, Some(DefaultAccept::Value("application/octet-stream".to_string())), None, "application/octet-stream"),
+        // Nothing of rdm's own, by default; the client's default goes out.
+        (None, Some(DefaultAccept::Skip), Some("video/mp4"), "*/*"),
+        (None, None, Some("video/mp4"), "*/*"),
+    ];
+
+    for (headers, default_accept, hinted_type, expected) in cases {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("Range", "bytes=0-0"))
+            .respond_with(
+                ResponseTemplate::new(206)
+                    .set_body_raw(vec![0u8], "video/mp4")
+                    .insert_header("Content-Range", format!("bytes 0-0/{}", 1024 * 1024)),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(RangeSlice { body: generate_test_data(1024 * 1024) })
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut builder = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("clip.mp4"))
+            .with_probe_hints(ProbeHints {
+                content_type: hinted_type.map(str::to_string),
+                ..Default::default()
+            });
+        if let Some(default_accept) = default_accept {
+            builder = builder.with_default_accept(default_accept);
+        }
+        if let Some(headers) = headers {
+            builder = builder.with_headers(headers);
+        }
+        let strategy = builder.build();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests.len() >= 2, "a probe and the segments");
+        for request in requests {
+            let values: Vec<_> = request.headers.get_all("Accept").iter().map(|v| v.to_str().unwrap()).collect();
+            assert_eq!(values, vec![expected]);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_downloads_into_a_directory_get_unique_names() {
+    use wiremock::matchers::path;
+
+    let server = MockServer::start().await;
+    let dir = tempfile::tempdir().unwrap();
+    let mut outputs = Vec::new();
+    for body in [b"first".to_vec(), b"second".to_vec()] {
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/media/clip"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(body.clone(), "video/mp4"))
+            .mount(&server)
+            .await;
+
+        let strategy = MultipartDownloadStrategy::builder(
+            format!("{}/media/clip?token=abc", server.uri()),
+            dir.path().to_path_buf(),
+        )
+        .build();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+
+        let output = PathBuf::from(strategy.state_snapshot().output_path.unwrap());
+        assert_eq!(std::fs::read(&output).unwrap(), body);
+        outputs.push(output);
+    }
+
+    assert_eq!(outputs[0], dir.path().join("clip.mp4"));
+    assert_eq!(outputs[1], dir.path().join("clip_2.mp4"));
+    assert_eq!(std::fs::read(&outputs[0]).unwrap(), b"first");
+}
+
+#[tokio::test]
+async fn test_preserve_mtime_uses_last_modified() {
+    use std::time::{Duration, SystemTime};
+
+    let cases = [
+        ("Sun, 06 Nov 1994 08:49:37 GMT", true, Some(SystemTime::UNIX_EPOCH + Duration::from_secs(784111777))),
+        ("Sun, 06 Nov 1994 08:49:37 GMT", false, None),
+        // Unparseable: the download still succeeds with a fresh mtime.
+        ("last tuesday", true, None),
+    ];
+
+    for (last_modified, preserve, expected) in cases {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("Last-Modified", last_modified)
+                    .set_body_bytes(b"archived".to_vec()),
+            )
+            .mount(&server)
+            .await;
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("archived.txt");
+        let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+            .with_preserve_mtime(preserve)
+            .build();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+
+        let mtime = std::fs::metadata(&output).unwrap().modified().unwrap();
+        match expected {
+            Some(expected) => assert_eq!(mtime, expected),
+            None => assert!(SystemTime::now().duration_since(mtime).unwrap() < Duration::from_secs(60)),
+        }
+    }
+}
+
+/// Serves `body` with ETag `"v1"`, or `304` to a matching `If-None-Match`.
+struct EtagResponder {
+    body: Vec<u8>,
+}
+
+impl wiremock::Respond for EtagResponder {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        if request.headers.get("If-None-Match").is_some_and(|v| v == "\"v1\"") {
+            return ResponseTemplate::new(304).insert_header("ETag", "\"v1\"");
+        }
+        ResponseTemplate::new(200).insert_header("ETag", "\"v1\"").set_body_bytes(self.body.clone())
+    }
+}
+
+#[tokio::test]
+async fn test_conditional_cache_skips_an_unchanged_file() {
+    use rdm_core::downloader::cache;
+
+    let body = generate_test_data(4096);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(EtagResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("cached.bin");
+    let run = || async {
+        let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+            .with_conditional_cache(true)
+            .build();
+        let info = strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+        (info.segment_count, strategy.state_snapshot().skipped)
+    };
+
+    assert_eq!(run().await, (1, false));
+    let record = cache::load(&output).unwrap();
+    assert_eq!(record.etag.as_deref(), Some("\"v1\""));
+    assert_eq!(record.size, body.len() as u64);
+
+    // Unchanged: only the conditional probe goes out.
+    let before = server.received_requests().await.unwrap().len();
+    assert_eq!(run().await, (0, true));
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), before + 1);
+    assert_eq!(requests[before].headers["If-None-Match"], "\"v1\"");
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    // A file that no longer matches its record is downloaded again.
+    std::fs::write(&output, b"edited").unwrap();
+    assert_eq!(run().await, (1, false));
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_conditional_cache_finds_the_file_named_in_a_directory_output() {
+    let body = generate_test_data(4096);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(EtagResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let url = format!("{}/clip.bin", server.uri());
+    let run = || async {
+        let strategy = MultipartDownloadStrategy::builder(url.clone(), dir.path().to_path_buf())
+            .with_conditional_cache(true)
+            .build();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+        let state = strategy.state_snapshot();
+        (state.skipped, state.output_path.unwrap())
+    };
+
+    let saved = dir.path().join("clip.bin").to_string_lossy().into_owned();
+    assert_eq!(run().await, (false, saved.clone()));
+
+    // The record is found under the name the file was given, not the
+    // directory's: only the conditional probe goes out, and no second copy.
+    let before = server.received_requests().await.unwrap().len();
+    assert_eq!(run().await, (true, saved));
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), before + 1);
+    assert_eq!(requests[before].headers["If-None-Match"], "\"v1\"");
+    let mut names: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+    names.sort();
+    assert_eq!(names, ["clip.bin", "clip.bin.rdm-cache"]);
+}
+
+#[tokio::test]
+async fn test_failed_assembly_frees_the_reserved_name() {
+    use wiremock::matchers::path;
+
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/media/clip"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(b"clip".to_vec(), "video/mp4"))
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/media/clip", server.uri()), dir.path().to_path_buf())
+        .build();
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    // The segment vanishes before it is assembled.
+    std::fs::remove_dir_all(strategy.temp_dir().await).unwrap();
+
+    assert!(strategy.postprocess().await.is_err());
+    assert!(!dir.path().join("clip.mp4").exists(), "the reserved output name was left behind");
+}
+
+#[tokio::test]
+async fn test_explicit_proxy_is_used() {
+    use rdm_core::types::types::ProxyInfo;
+    use wiremock::matchers::path;
+
+    // The mock server plays the proxy; the origin host does not resolve.
+    let body = generate_test_data(1024);
+    let proxy = MockServer::start().await;
+    mount_probe(&proxy, "/via-proxy.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/via-proxy.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&proxy)
+        .await;
+
+    let address = proxy.address();
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("via-proxy.bin");
+    let strategy = MultipartDownloadStrategy::builder(
+        "http://origin.invalid/via-proxy.bin".to_string(),
+        output.clone(),
+    )
+    .with_proxy(ProxyInfo {
+        host: address.ip().to_string(),
+        port: address.port(),
+        username: None,
+        password: None,
+    })
+    .build();
+
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+    assert!(!proxy.received_requests().await.unwrap().is_empty());
+}
+
+/// Time four segment tasks under `limit` take to pay for 256 KB each,
+/// on tokio's paused clock.
+async fn time_to_pay_four_segments(limit: rdm_core::types::types::SpeedLimit) -> std::time::Duration {
+    use rdm_core::downloader::segment_grabber::SegmentOptions;
+
+    let options = SegmentOptions::default().with_speed_limit(Some(limit));
+    let started = tokio::time::Instant::now();
+    let segments = (0..4).map(|_| {
+        let options = options.for_segment(Some(limit));
+        async move {
+            for limiter in &options.rate_limiters {
+                limiter.acquire(256 * 1024).await;
+            }
+        }
+    });
+    futures::future::join_all(segments).await;
+    started.elapsed()
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_per_connection_limit_adds_up_to_aggregate() {
+    use rdm_core::types::types::SpeedLimit;
+
+    // 4 connections × 256 KB/s is the same 1 MB/s as a 1 MB/s global cap:
+    // either way the four segments' 1 MB takes a second.
+    let per_connection = time_to_pay_four_segments(SpeedLimit::PerConnection(256 * 1024)).await;
+    let global = time_to_pay_four_segments(SpeedLimit::Global(1024 * 1024)).await;
+    for elapsed in [per_connection, global] {
+        assert!(
+            elapsed >= std::time::Duration::from_secs(1) && elapsed < std::time::Duration::from_millis(1010),
+            "expected 1s, took {:?}",
+            elapsed
+        );
+    }
+    // A global cap is one bucket for every segment, a per-connection one a
+    // bucket each.
+    let slow = time_to_pay_four_segments(SpeedLimit::Global(256 * 1024)).await;
+    assert!(slow >= std::time::Duration::from_secs(4), "took {:?}", slow);
+}
+
+#[tokio::test]
+async fn test_missing_output_directory_is_created_or_rejected_up_front() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(4096);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/nested.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/nested.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let url = format!("{}/nested.bin", server.uri());
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("a").join("b").join("nested.bin");
+
+    // Off: preprocess fails before any request is made.
+    let strategy = MultipartDownloadStrategy::builder(url.clone(), output.clone())
+        .with_create_parent(false)
+        .build();
+    let err = strategy.preprocess().await.unwrap_err();
+    assert!(matches!(err, DownloadError::Disk(_)), "unexpected error: {:?}", err);
+    assert!(server.received_requests().await.unwrap().is_empty());
+
+    // Default: the directory is created and the download lands in it.
+    let strategy = MultipartDownloadStrategy::builder(url, output.clone()).build();
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_shared_limiter_splits_bandwidth_fairly_between_downloads() {
+    use rdm_core::downloader::rate_limiter::SharedRateLimiter;
+    use wiremock::matchers::path;
+
+    // The cap is split per download, however many segments each runs, and
+    // rebalanced as downloads come and go.
+    let limiter = SharedRateLimiter::new(3 * 1024 * 1024);
+    let share_rate = |share: &rdm_core::downloader::rate_limiter::LimiterShare| share.limiters()[1].bytes_per_sec();
+    let first = limiter.register();
+    assert_eq!(share_rate(&first), 3 * 1024 * 1024);
+    let second = limiter.register();
+    let third = limiter.register();
+    for share in [&first, &second, &third] {
+        assert_eq!(share_rate(share), 1024 * 1024);
+    }
+    drop(second);
+    assert_eq!((share_rate(&first), share_rate(&third)), (1536 * 1024, 1536 * 1024));
+    drop((first, third));
+    assert_eq!(limiter.active_shares(), 0);
+
+    // A 4-connection and a 1-connection download take a share each while
+    // they transfer, and give it back when done.
+    let body = generate_test_data(1024 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/fair.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/fair.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+    let limiter = SharedRateLimiter::new(2 * 1024 * 1024);
+    let dir = tempfile::tempdir().unwrap();
+    let run = |name: &str, connections: usize| {
+        let output = dir.path().join(name);
+        let strategy = MultipartDownloadStrategy::builder(format!("{}/fair.bin", server.uri()), output.clone())
+            .with_connection_size(connections)
+            .with_shared_limiter(limiter.clone())
+            .build();
+        async move {
+            strategy.preprocess().await.unwrap();
+            strategy.download().await.unwrap();
+            strategy.postprocess().await.unwrap();
+            std::fs::read(&output).unwrap()
+        }
+    };
+    let both_held = async {
+        while limiter.active_shares() < 2 {
+            tokio::task::yield_now().await;
+        }
+    };
+    let both_held = tokio::time::timeout(std::time::Duration::from_secs(10), both_held);
+
+    let (wide, narrow, both_held) = tokio::join!(run("wide.bin", 4), run("narrow.bin", 1), both_held);
+
+    assert_eq!(wide, body);
+    assert_eq!(narrow, body);
+    assert!(both_held.is_ok(), "the downloads never held a share each at once");
+    assert_eq!(limiter.active_shares(), 0);
+}
+
+#[tokio::test]
+async fn test_total_retry_budget_fails_fast_against_a_down_server() {
+    use wiremock::matchers::path;
+
+    let size = 4 * 1024 * 1024;
+    // Segment requests, not counting the probe.
+    let attempts = |server: MockServer| async move {
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| r.headers.get("Range").is_some_and(|v| v != "bytes=0-0"))
+            .count()
+    };
+
+    // 4 segments, 3 attempts each, without a budget.
+    let server = MockServer::start().await;
+    mount_probe(&server, "/down.bin", size, "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/down.bin"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/down.bin", server.uri()), dir.path().join("a.bin"))
+        .with_connection_size(4)
+        .build();
+    strategy.preprocess().await.unwrap();
+    assert!(strategy.download().await.is_err());
+    assert_eq!(attempts(server).await, 4 * 3);
+
+    // One first attempt per segment, then only the 2 shared retries.
+    let server = MockServer::start().await;
+    mount_probe(&server, "/down.bin", size, "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/down.bin"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/down.bin", server.uri()), dir.path().join("b.bin"))
+        .with_connection_size(4)
+        .with_total_retry_budget(2)
+        .build();
+    strategy.preprocess().await.unwrap();
+    let err = strategy.download().await.unwrap_err();
+    assert!(matches!(err, DownloadError::MaxRetryExceeded), "{:?}", err);
+    assert_eq!(attempts(server).await, 4 + 2);
+}
+
+/// Number of segments preprocess splits a resumable file of `size` bytes into.
+async fn segment_count(size: usize, connections: usize, target_segment_size: Option<u64>) -> usize {
+    let server = MockServer::start().await;
+    mount_probe(&server, "/sized.bin", size, "\"v1\"").await;
+    let dir = tempfile::tempdir().unwrap();
+    let builder = MultipartDownloadStrategy::builder(format!("{}/sized.bin", server.uri()), dir.path().join("out.bin"))
+        .with_connection_size(connections)
+        .with_multipart_threshold(0);
+    let strategy = match target_segment_size {
+        Some(size) => builder.with_target_segment_size(size),
+        None => builder,
+    }
+    .build();
+    strategy.preprocess().await.unwrap();
+    let count = strategy.segments_snapshot().await.len();
+    let _ = std::fs::remove_dir_all(strategy.temp_dir().await);
+    count
+}
+
+#[tokio::test]
+async fn test_segment_count_follows_file_size_up_to_connections() {
+    const MB: usize = 1024 * 1024;
+
+    // Default target of 1 MB per segment.
+    assert_eq!(segment_count(MB, 8, None).await, 1);
+    assert_eq!(segment_count(3 * MB, 8, None).await, 3);
+    assert_eq!(segment_count(1024 * MB, 8, None).await, 8);
+
+    // The connection count stays the upper bound.
+    assert_eq!(segment_count(1024 * MB, 3, None).await, 3);
+
+    // A smaller target splits further, but not below the 256 KB minimum.
+    assert_eq!(segment_count(MB, 8, Some(64 * 1024)).await, 4);
+}
+
+#[tokio::test]
+async fn test_files_below_the_multipart_threshold_get_one_segment() {
+    const MB: usize = 1024 * 1024;
+
+    let server = MockServer::start().await;
+    mount_probe(&server, "/small.bin", MB, "\"v1\"").await;
+    mount_probe(&server, "/large.bin", 6 * MB, "\"v1\"").await;
+    let dir = tempfile::tempdir().unwrap();
+    let plan = |route: &str, threshold: Option<u64>| {
+        let builder = MultipartDownloadStrategy::builder(format!("{}{}", server.uri(), route), dir.path().join("out.bin"))
+            .with_target_segment_size(256 * 1024);
+        match threshold {
+            Some(threshold) => builder.with_multipart_threshold(threshold),
+            None => builder,
+        }
+        .build()
+    };
+
+    let small = plan("/small.bin", None);
+    let info = small.preprocess().await.unwrap();
+    assert_eq!((info.resumable, info.segment_count), (true, 1));
+    assert_eq!(small.segment_plan().await, [(0, MB as i64)]);
+
+    assert_eq!(plan("/small.bin", Some(0)).preprocess().await.unwrap().segment_count, 4);
+    assert_eq!(plan("/large.bin", None).preprocess().await.unwrap().segment_count, 8);
+    assert_eq!(plan("/large.bin", Some(8 * MB as u64)).preprocess().await.unwrap().segment_count, 1);
+}
+
+#[tokio::test]
+async fn test_complete_probe_hints_skip_the_probe() {
+    use rdm_core::types::types::ProbeHints;
+
+    let body = generate_test_data(2 * 1024 * 1024);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let hints = ProbeHints {
+        file_size: Some(body.len() as u64),
+        resumable: Some(true),
+        content_type: Some("video/mp4".to_string()),
+        attachment_name: Some("clip.mp4".to_string()),
+    };
+
+    let output = dir.path().join("hinted.bin");
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+        .with_probe_hints(hints.clone())
+        .with_multipart_threshold(0)
+        .build();
+    strategy.preprocess().await.unwrap();
+    assert!(server.received_requests().await.unwrap().is_empty(), "no probe request");
+    {
+        let state = strategy.state().read().unwrap();
+        assert_eq!(state.content_type.as_deref(), Some("video/mp4"));
+        assert_eq!(state.attachment_name.as_deref(), Some("clip.mp4"));
+    }
+    assert_eq!(strategy.segments_snapshot().await.len(), 2);
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    // A stale size is caught by the first ranged response.
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("stale.bin"))
+        .with_probe_hints(ProbeHints { file_size: Some(body.len() as u64 / 2), ..hints })
+        .build();
+    strategy.preprocess().await.unwrap();
+    let err = strategy.download().await.unwrap_err();
+    assert!(matches!(err, DownloadError::ResourceChanged(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn test_incomplete_probe_hints_fall_back_to_probing() {
+    use rdm_core::types::types::ProbeHints;
+
+    let (server, _body) = setup_resumable_server(1024 * 1024).await;
+    let dir = tempfile::tempdir().unwrap();
+    // Resumable but without a size: the probe still has to run.
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("out.bin"))
+        .with_probe_hints(ProbeHints { resumable: Some(true), ..ProbeHints::default() })
+        .build();
+    strategy.preprocess().await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].headers.get("Range").unwrap(), "bytes=0-0");
+    assert_eq!(strategy.state().read().unwrap().file_size, 1024 * 1024);
+    let _ = std::fs::remove_dir_all(strategy.temp_dir().await);
+}
+
+/// A throwaway CA plus a server certificate for 127.0.0.1 and a client
+/// certificate signed by it, written as PEM files into a temp dir.
+struct TestPki {
+    dir: tempfile::TempDir,
+}
+
+impl TestPki {
+    fn generate() -> Self {
+        use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+
+        let params = |name: &str, sans: Vec<String>| {
+            let mut params = CertificateParams::new(sans).unwrap();
+            params.distinguished_name.push(DnType::CommonName, name);
+            params
+        };
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = params("rdm test CA", Vec::new());
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let server_key = KeyPair::generate().unwrap();
+        let server = params("rdm test server", vec!["127.0.0.1".to_string()])
+            .signed_by(&server_key, &ca, &ca_key)
+            .unwrap();
+        let client_key = KeyPair::generate().unwrap();
+        let mut client_params = params("rdm test client", Vec::new());
+        client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+        let client = client_params.signed_by(&client_key, &ca, &ca_key).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        for (name, pem) in [
+            ("ca.pem", ca.pem()),
+            ("server.pem", server.pem()),
+            ("server.key", server_key.serialize_pem()),
+            ("client.pem", client.pem()),
+            ("client.key", client_key.serialize_pem()),
+        ] {
+            std::fs::write(dir.path().join(name), pem).unwrap();
+        }
+        Self { dir }
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.dir.path().join(name).to_string_lossy().into_owned()
+    }
+}
+
+/// Serves `body` over TLS to clients presenting a certificate signed by
+/// `pki`'s CA, one request per connection. Returns the bound address.
+async fn spawn_mtls_server(body: Vec<u8>, pki: &TestPki) -> std::net::SocketAddr {
+    let mut response =
+        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).into_bytes();
+    response.extend(body);
+    spawn_tls_server(response, pki, true).await
+}
+
+/// Answers every request over TLS with the raw `response`, one request per
+/// connection; with `client_auth`, only to clients presenting a certificate
+/// signed by `pki`'s CA. Returns the bound address.
+async fn spawn_tls_server(response: Vec<u8>, pki: &TestPki, client_auth: bool) -> std::net::SocketAddr {
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use tokio_rustls::rustls::server::WebPkiClientVerifier;
+    use tokio_rustls::rustls::{crypto, RootCertStore, ServerConfig};
+
+    let provider = Arc::new(crypto::aws_lc_rs::default_provider());
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from_pem_file(pki.path("ca.pem")).unwrap()).unwrap();
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let builder = if client_auth {
+        let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+            .build()
+            .unwrap();
+        builder.with_client_cert_verifier(verifier)
+    } else {
+        builder.with_no_client_auth()
+    };
+    let config = builder
+        .with_single_cert(
+            vec![CertificateDer::from_pem_file(pki.path("server.pem")).unwrap()],
+            PrivateKeyDer::from_pem_file(pki.path("server.key")).unwrap(),
+        )
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            let response = response.clone();
+            tokio::spawn(async move {
+                let Ok(mut tls) = acceptor.accept(tcp).await else { return };
+                let mut request = Vec::new();
+                let mut buf = [0u8; 1024];
+                while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    match tls.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let _ = tls.write_all(&response).await;
+                let _ = tls.shutdown().await;
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn test_max_file_size_rejects_known_size_before_fetching() {
+    let (server, _) = setup_resumable_server(1024 * 1024).await;
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("big.bin"))
+        .with_max_file_size(512 * 1024)
+        .build();
+
+    let err = strategy.preprocess().await.unwrap_err();
+    assert!(
+        matches!(err, DownloadError::TooLarge { size, limit } if size == 1024 * 1024 && limit == 512 * 1024),
+        "{:?}",
+        err
+    );
+    assert!(!PathBuf::from(strategy.temp_dir().await).exists());
+    // Only the probe was sent.
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_max_file_size_stops_unknown_size_download_once_crossed() {
+    let (server, _) = setup_non_resumable_server(300 * 1024).await;
+    let dir = tempfile::tempdir().unwrap();
+    let build = |limit: u64| {
+        MultipartDownloadStrategy::builder(server.uri(), dir.path().join(format!("stream_{}.bin", limit)))
+            .with_max_file_size(limit)
+            .with_probe_hints(rdm_core::types::types::ProbeHints {
+                resumable: Some(false),
+                ..Default::default()
+            })
+            .build()
+    };
+
+    let strategy = build(100 * 1024);
+    strategy.preprocess().await.unwrap();
+    let err = strategy.download().await.unwrap_err();
+    assert!(
+        matches!(err, DownloadError::TooLarge { size, limit } if size > limit && limit == 100 * 1024),
+        "{:?}",
+        err
+    );
+
+    // Under the limit the same stream downloads normally.
+    let strategy = build(400 * 1024);
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_max_file_size_counts_what_a_resumed_download_holds() {
+    let body = generate_test_data(2_500_000);
+    let half = 1024 * 1024;
+    let server = MockServer::start().await;
+    // Probed at 2 MB, so a 2.3 MB cap lets it start; it really holds 2.5 MB.
+    Mock::given(method("GET"))
+        .respond_with(MisreportedSize { body: body.clone(), claimed: 2 * half })
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let temp_dir = dir.path().join("temp");
+    std::fs::create_dir_all(&temp_dir).unwrap();
+
+    // An earlier run finished the first half before it was interrupted.
+    std::fs::write(temp_dir.join("seg_0"), &body[..half]).unwrap();
+    let mut first = Segment::new("seg_0".to_string(), 0, half as i64);
+    first.downloaded = half as i64;
+    first.state = SegmentState::Finished;
+    let second = Segment::new(format!("seg_{}", half), half as i64, half as i64);
+
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("resumed.bin"))
+        .with_max_file_size(2_300_000)
+        .build();
+    strategy.state().write().unwrap().temp_dir = temp_dir.to_string_lossy().to_string();
+    strategy.restore_segments(vec![first, second]).await.unwrap();
+    strategy.preprocess().await.unwrap();
+
+    // This run fetches only 1.45 MB, but the file would be 2.5 MB.
+    let err = strategy.download().await.unwrap_err();
+    assert!(
+        matches!(err, DownloadError::TooLarge { size, limit } if size > limit && limit == 2_300_000),
+        "{:?}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_client_certificate_for_mutual_tls() {
+    let pki = TestPki::generate();
+    let body = generate_test_data(64 * 1024);
+    let addr = spawn_mtls_server(body.clone(), &pki).await;
+    let url = format!("https://127.0.0.1:{}/secure.bin", addr.port());
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = dir.path().join("secure.bin");
+    let strategy = MultipartDownloadStrategy::builder(url.clone(), output.clone())
+        .with_system_proxy(false)
+        .with_root_cert(pki.path("ca.pem"))
+        .with_client_cert(pki.path("client.pem"), pki.path("client.key"))
+        .build();
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    // The server turns away a client without a certificate.
+    let strategy = MultipartDownloadStrategy::builder(url.clone(), dir.path().join("anon.bin"))
+        .with_system_proxy(false)
+        .with_root_cert(pki.path("ca.pem"))
+        .build();
+    let err = strategy.preprocess().await.unwrap_err();
+    assert!(matches!(err, DownloadError::Network(_)), "{:?}", err);
+
+    // Files that can't be loaded are reported as a TLS error up front.
+    let strategy = MultipartDownloadStrategy::builder(url, dir.path().join("broken.bin"))
+        .with_client_cert(pki.path("client.pem"), pki.path("missing.key"))
+        .build();
+    match strategy.preprocess().await.unwrap_err() {
+        DownloadError::Tls(msg) => assert!(msg.contains("missing.key"), "{}", msg),
+        other => panic!("expected a TLS error, got {:?}", other),
+    }
+
+    // A key without its certificate is a setting to reject, not skip.
+    let key_only = rdm_core::types::types::TlsFiles { client_key: Some(pki.path("client.key")), ..Default::default() };
+    assert!(!key_only.is_empty());
+}
+
+#[tokio::test]
+async fn test_sequential_download_uses_one_connection_and_resumes() {
+    let body = generate_test_data(1024 * 1024);
+    let (url, ranges, most_open) = start_single_connection_server(body.clone()).await;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("sequential.bin");
+
+    let strategy = MultipartDownloadStrategy::builder(url, output.clone())
+        .with_connection_size(8)
+        .with_multipart_threshold(0)
+        .with_target_segment_size(256 * 1024)
+        .with_sequential(true)
+        .build();
+    let info = strategy.preprocess().await.unwrap();
+    assert_eq!(info.segment_count, 1);
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    // The cut-off stream resumed where it stopped, never alongside another.
+    let ranges: Vec<String> = ranges.lock().unwrap().iter().filter(|r| *r != "bytes=0-0").cloned().collect();
+    assert_eq!(ranges, vec!["bytes=0-1048575".to_string(), "bytes=524288-1048575".to_string()]);
+    assert_eq!(most_open.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_sequential_download_runs_restored_segments_one_at_a_time() {
+    let body = generate_test_data(1024 * 1024);
+    let (url, ranges, most_open) = start_single_connection_server(body.clone()).await;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("sequential_restored.bin");
+
+    let strategy = MultipartDownloadStrategy::builder(url, output.clone())
+        .with_connection_size(4)
+        .with_sequential(true)
+        .build();
+    let quarter = body.len() as i64 / 4;
+    let segments = (0..4).map(|i| Segment::new(format!("part-{}", i), i * quarter, quarter)).collect();
+    strategy.restore_segments(segments).await.unwrap();
+
+    assert_eq!(strategy.preprocess().await.unwrap().segment_count, 4);
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    // Front to back, the first one resumed after its cut-off.
+    let ranges: Vec<String> = ranges.lock().unwrap().iter().filter(|r| *r != "bytes=0-0").cloned().collect();
+    assert_eq!(
+        ranges,
+        ["bytes=0-262143", "bytes=131072-262143", "bytes=262144-524287", "bytes=524288-786431", "bytes=786432-1048575"]
+    );
+    assert_eq!(most_open.load(std::sync::atomic::Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_chronically_slow_segment_is_resplit() {
+    let body = generate_test_data(6 * 1024 * 1024);
+    let url = start_throttled_range_server(body.clone()).await;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("resplit.bin");
+
+    let strategy = MultipartDownloadStrategy::builder(url, output.clone())
+        .with_connection_size(6)
+        .with_slow_segment_window(Some(std::time::Duration::ZERO))
+        .build();
+    strategy.preprocess().await.unwrap();
+    assert_eq!(strategy.segments().read().await.len(), 6);
+    strategy.download().await.unwrap();
+
+    {
+        let segments = strategy.segments().read().await;
+        assert_eq!(segments.len(), 7, "the slow first segment should have been split in two");
+        // The pieces still tile the file exactly.
+        let mut sorted: Vec<_> = segments.values().collect();
+        sorted.sort_by_key(|s| s.offset);
+        let mut end = 0;
+        for segment in sorted {
+            assert_eq!(segment.offset, end, "segment {} leaves a gap or overlap", segment.id);
+            assert_eq!(segment.state, SegmentState::Finished);
+            end += segment.length;
+        }
+        assert_eq!(end, body.len() as i64);
+    }
+
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_cancel_segment_stops_only_that_segment() {
+    use std::sync::Arc;
+
+    let body = generate_test_data(2 * 1024 * 1024);
+    let url = start_throttled_range_server(body.clone()).await;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("cancel_one.bin");
+
+    let strategy = Arc::new(
+        MultipartDownloadStrategy::builder(url, output.clone())
+            .with_connection_size(2)
+            .with_multipart_threshold(0)
+            .with_slow_segment_window(None)
+            .build(),
+    );
+    strategy.preprocess().await.unwrap();
+    let first = {
+        let segments = strategy.segments().read().await;
+        segments.values().find(|s| s.offset == 0).unwrap().id.clone()
+    };
+    assert!(!strategy.cancel_segment(&first), "nothing is running yet");
+
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1024);
+    strategy.set_progress_tx(tx);
+    let download = tokio::spawn({
+        let strategy = Arc::clone(&strategy);
+        async move { strategy.download().await }
+    });
+    // The first segment crawls; stop it once it has written something.
+    while let Some(event) = rx.recv().await {
+        if event.as_ref().is_ok_and(|e| e.segment_id == first && e.bytes_delta > 0) {
+            break;
+        }
+    }
+    assert!(strategy.cancel_segment(&first));
+    assert!(!strategy.cancel_segment("no-such-segment"));
+    assert!(matches!(download.await.unwrap(), Err(DownloadError::Cancelled)));
+
+    {
+        let segments = strategy.segments().read().await;
+        assert_eq!(segments.len(), 2, "nothing is restarted in its place");
+        let kept = &segments[&first];
+        assert_eq!(kept.state, SegmentState::NotStarted);
+        assert!(kept.downloaded > 0 && kept.downloaded < kept.length, "kept {} bytes", kept.downloaded);
+        assert!(segments.values().any(|s| s.id != first && s.state == SegmentState::Finished), "the other segment ran on");
+    }
+    // The next download picks the segment up where it stopped.
+    strategy.clear_progress_tx();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[test]
+fn test_parse_checksum_file_formats() {
+    use rdm_core::downloader::checksum::parse_checksum_file;
+
+    let hex = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+    let lower = hex.to_ascii_lowercase();
+    assert_eq!(parse_checksum_file(hex, "a.iso"), Some(lower.clone()));
+    assert_eq!(parse_checksum_file(&format!("{}  dist/b.iso\n", hex), "a.iso"), Some(lower.clone()));
+    let bsd = format!("SHA256 (other.iso) = {}\nSHA256 (a.iso) = {}\n", "0".repeat(64), hex);
+    assert_eq!(parse_checksum_file(&bsd, "a.iso"), Some(lower));
+    assert_eq!(parse_checksum_file(&bsd, "c.iso"), None);
+    assert_eq!(parse_checksum_file("<html>not found</html>", "a.iso"), None);
+}
+
+#[tokio::test]
+async fn test_restored_segments_fetch_only_the_unfinished_remainder() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(200_000);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/resume.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/resume.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("resume.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/resume.bin", server.uri()), output.clone())
+        .with_connection_size(4)
+        .build();
+
+    // An earlier run finished the first half and recorded 30 000 bytes of
+    // the second, but flushed 10 000 more before it stopped.
+    let temp_dir = PathBuf::from(strategy.temp_dir().await);
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    let mut done = Segment::new("done".to_string(), 0, 100_000);
+    done.downloaded = 100_000;
+    done.state = SegmentState::Finished;
+    std::fs::write(temp_dir.join("done"), &body[..100_000]).unwrap();
+    let mut half = Segment::new("half".to_string(), 100_000, 100_000);
+    half.downloaded = 30_000;
+    std::fs::write(temp_dir.join("half"), &body[100_000..140_000]).unwrap();
+    strategy.restore_segments(vec![done, half]).await.unwrap();
+
+    let info = strategy.preprocess().await.unwrap();
+    assert_eq!(info.segment_count, 2);
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    let ranges: Vec<String> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|r| r.headers.get("Range").map(|v| v.to_str().unwrap().to_string()))
+        .filter(|r| r != "bytes=0-0")
+        .collect();
+    assert_eq!(ranges, ["bytes=130000-199999"]);
+    assert_eq!(strategy.restore_segments(Vec::new()).await.unwrap_err().to_string(), "invalid state");
+}
+
+#[tokio::test]
+async fn test_resume_with_a_new_plan_ignores_stale_segment_files() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(1024 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/replan.bin", body.len(), "\"v2\"").await;
+    Mock::given(method("GET"))
+        .and(path("/replan.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("replan.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/replan.bin", server.uri()), output.clone())
+        .with_connection_size(2)
+        .with_multipart_threshold(0)
+        .with_target_segment_size(256 * 1024)
+        .build();
+
+    // An earlier run over four connections, of a 2 MB version of the file,
+    // left its segments (one of them under a name the new plan uses) and a
+    // file from before segment ids were offsets.
+    let temp_dir = dir.path().join("replan.tmp");
+    strategy.state().write().unwrap().temp_dir = temp_dir.to_string_lossy().to_string();
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    let old_quarter = 2 * body.len() as i64 / 4;
+    let mut old_plan = Vec::new();
+    for i in 0..4 {
+        let mut segment = Segment::new(format!("seg_{}", i * old_quarter), i * old_quarter, old_quarter);
+        segment.downloaded = 1000;
+        std::fs::write(temp_dir.join(&segment.id), vec![0xAA; 1000]).unwrap();
+        std::fs::write(temp_dir.join(format!("{}.state", segment.id)), b"{}").unwrap();
+        old_plan.push(segment);
+    }
+    std::fs::write(temp_dir.join("0b6c5c3e-2f1d-4bfa-9c4e-8f64a1d2a7e0"), b"old").unwrap();
+    // Not a segment's: someone else's file in a shared temp dir.
+    std::fs::write(temp_dir.join("seg_notes.txt"), b"mine").unwrap();
+    strategy.restore_segments(old_plan).await.unwrap();
+
+    assert_eq!(strategy.preprocess().await.unwrap().segment_count, 2);
+    let mut ids: Vec<String> = strategy.segments().read().await.keys().cloned().collect();
+    ids.sort();
+    assert_eq!(ids, ["seg_0", "seg_524288"]);
+    let left: Vec<_> = std::fs::read_dir(&temp_dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(left, ["seg_notes.txt"], "stale files were left behind, or a foreign one removed");
+
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_restored_segments_that_overlap_and_leave_a_gap_are_replanned() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(20);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/tiles.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/tiles.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("tiles.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/tiles.bin", server.uri()), output.clone()).build();
+
+    // Lengths add up to 20 and the last one ends at 20, but [5, 10) is
+    // covered twice and [10, 15) not at all.
+    let restored: Vec<Segment> = [(0, 10), (5, 5), (15, 5)]
+        .into_iter()
+        .map(|(offset, length)| Segment::new(format!("seg_{}", offset), offset, length))
+        .collect();
+    strategy.restore_segments(restored).await.unwrap();
+
+    strategy.preprocess().await.unwrap();
+    let mut ranges: Vec<(i64, i64)> =
+        strategy.segments().read().await.values().map(|s| (s.offset, s.length)).collect();
+    ranges.sort_unstable();
+    assert_ne!(ranges, [(0, 10), (5, 5), (15, 5)]);
+
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_set_segment_plan_forces_the_split() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(200_000);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/plan.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/plan.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("plan.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/plan.bin", server.uri()), output.clone()).build();
+    let err = strategy.set_segment_plan(vec![(0, 200_000)]).await.unwrap_err();
+    assert!(matches!(err, DownloadError::InvalidState));
+
+    strategy.preprocess().await.unwrap();
+    // Too small to split on its own.
+    assert_eq!(strategy.segment_plan().await, [(0, 200_000)]);
+
+    for (ranges, reason) in [
+        (vec![(0, 50_000), (60_000, 140_000)], "gap between 50000 and 60000"),
+        (vec![(0, 150_000), (100_000, 100_000)], "range at 100000 overlaps the one before it"),
+        (vec![(0, 150_000)], "ranges end at 150000, but the download ends at 200000"),
+    ] {
+        let err = strategy.set_segment_plan(ranges).await.unwrap_err();
+        assert_eq!(err.to_string(), format!("invalid segment plan: {}", reason));
+    }
+    assert_eq!(strategy.segment_plan().await, [(0, 200_000)], "a rejected plan changes nothing");
+
+    strategy
+        .set_segment_plan(vec![(190_000, 10_000), (0, 10_000), (10_000, 180_000)])
+        .await
+        .unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    let mut ranges: Vec<String> = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter_map(|r| r.headers.get("Range").map(|v| v.to_str().unwrap().to_string()))
+        .filter(|r| r != "bytes=0-0")
+        .collect();
+    ranges.sort();
+    assert_eq!(ranges, ["bytes=0-9999", "bytes=10000-189999", "bytes=190000-199999"]);
+    let err = strategy.set_segment_plan(vec![(0, 200_000)]).await.unwrap_err();
+    assert!(matches!(err, DownloadError::InvalidState));
+}
+
+#[tokio::test]
+async fn test_parallel_assembly_matches_serial() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(3 * 1024 * 1024 + 123);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/assemble.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/assemble.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    for threads in [1, 3, 8] {
+        let output = dir.path().join(format!("assemble-{}.bin", threads));
+        let strategy = MultipartDownloadStrategy::builder(format!("{}/assemble.bin", server.uri()), output.clone())
+            .with_target_segment_size(256 * 1024)
+            .with_multipart_threshold(0)
+            .with_connection_size(8)
+            .with_assembly_threads(threads)
+            .build();
+        let info = strategy.preprocess().await.unwrap();
+        assert_eq!(info.segment_count, 8);
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+        assert!(std::fs::read(&output).unwrap() == body, "{} assembly threads", threads);
+    }
+}
+
+#[tokio::test]
+async fn test_http_version_is_applied_to_the_client() {
+    use rdm_core::types::types::HttpVersion;
+
+    assert_eq!("HTTP/1.1".parse::<HttpVersion>(), Ok(HttpVersion::Http1));
+    assert_eq!("2".parse::<HttpVersion>(), Ok(HttpVersion::Http2));
+    assert!("3".parse::<HttpVersion>().is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    for (version, opening) in [(HttpVersion::Http1, "GET /file.bin "), (HttpVersion::Http2, "PRI * HTTP/2.0")] {
+        let (url, seen) = start_preface_recorder().await;
+        let strategy = MultipartDownloadStrategy::builder(url, dir.path().join("never.bin"))
+            .with_http_version(version)
+            .build();
+        assert!(strategy.preprocess().await.is_err());
+        let seen = seen.lock().unwrap();
+        assert!(!seen.is_empty());
+        assert!(seen.iter().all(|s| s == opening.as_bytes()), "{:?}: {:?}", version, seen);
+    }
+
+    // Segments run as concurrent streams over h2c.
+    let body = generate_test_data(8 * 1024 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/file.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+    let output = dir.path().join("h2.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/file.bin", server.uri()), output.clone())
+        .with_connection_size(4)
+        .with_http_version(HttpVersion::Http2)
+        .build();
+    assert_eq!(strategy.preprocess().await.unwrap().segment_count, 4);
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_credentials_stay_with_the_original_host_across_redirects() {
+    use rdm_core::types::types::AuthenticationInfo;
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(2 * 1024 * 1024);
+    let cdn = MockServer::start().await;
+    mount_probe(&cdn, "/signed.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/signed.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&cdn)
+        .await;
+    let origin = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(ResponseTemplate::new(302).insert_header("Location", format!("{}/signed.bin", cdn.uri())))
+        .mount(&origin)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    for forward in [false, true] {
+        let seen = cdn.received_requests().await.unwrap().len();
+        let output = dir.path().join(format!("forward-{}.bin", forward));
+        let strategy = MultipartDownloadStrategy::builder(format!("{}/file.bin", origin.uri()), output.clone())
+            .with_connection_size(2)
+            .with_multipart_threshold(0)
+            .with_cookies("session=secret".to_string())
+            .add_header("X-Api-Key", "k")
+            .with_authentication(AuthenticationInfo { username: "user".into(), password: "pass".into() })
+            .with_forward_auth_on_redirect(forward)
+            .build();
+        strategy.preprocess().await.unwrap();
+        strategy.download().await.unwrap();
+        strategy.postprocess().await.unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), body);
+
+        // The state keeps what was captured; only the requests go without.
+        let state = strategy.state_snapshot();
+        assert!(state.authentication.is_some() && state.headers.contains_key("X-Api-Key"));
+        assert_eq!(state.cookies.as_deref(), Some("session=secret"));
+        assert_eq!(state.credentials_url.is_some(), !forward);
+
+        let requests = cdn.received_requests().await.unwrap();
+        // The probe plus two segments, all sent to the CDN.
+        assert_eq!(requests.len() - seen, 3);
+        for request in &requests[seen..] {
+            assert_eq!(request.headers.contains_key("authorization"), forward, "{:?}", request.headers);
+            assert_eq!(request.headers.contains_key("cookie"), forward, "{:?}", request.headers);
+            assert!(request.headers.contains_key("x-api-key"));
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_forwarded_credentials_are_not_sent_down_to_plain_http() {
+    use rdm_core::types::types::AuthenticationInfo;
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(64 * 1024);
+    let cdn = MockServer::start().await;
+    mount_probe(&cdn, "/file.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&cdn)
+        .await;
+    let pki = TestPki::generate();
+    let redirect = format!(
+        "HTTP/1.1 302 Found\r\nLocation: {}/file.bin\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        cdn.uri()
+    );
+    let origin = spawn_tls_server(redirect.into_bytes(), &pki, false).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(format!("https://{}/file.bin", origin), dir.path().join("out.bin"))
+        .with_system_proxy(false)
+        .with_root_cert(pki.path("ca.pem"))
+        .with_authentication(AuthenticationInfo { username: "user".into(), password: "pass".into() })
+        .with_forward_auth_on_redirect(true)
+        .build();
+    let err = strategy.preprocess().await.unwrap_err();
+    assert!(err.to_string().contains("refusing to forward credentials"), "{err}");
+    assert!(cdn.received_requests().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_verify_on_resume_refetches_segments_that_fail_their_hash() {
+    use rdm_core::downloader::checksum::{Checksum, hash_file};
+    use rdm_core::downloader::segment_grabber::{segment_state_path, SegmentRecord};
+    use rdm_core::types::types::ChecksumAlgo;
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(200_000);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/verify.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/verify.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+    let url = format!("{}/verify.bin", server.uri());
+    let dir = tempfile::tempdir().unwrap();
+
+    // A fresh download records the hash of every segment it writes.
+    let output = dir.path().join("recorded.bin");
+    let strategy = MultipartDownloadStrategy::builder(url.clone(), output.clone())
+        .with_connection_size(2)
+        .with_verify_on_resume(true)
+        .with_keep_temp(true)
+        .build();
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    let temp_dir = PathBuf::from(strategy.temp_dir().await);
+    for segment in strategy.segments().read().await.values() {
+        let record: SegmentRecord =
+            serde_json::from_slice(&std::fs::read(segment_state_path(&temp_dir, &segment.id)).unwrap()).unwrap();
+        let actual = hash_file(&temp_dir.join(&segment.id), ChecksumAlgo::Sha256).unwrap();
+        assert_eq!(record.sha256.as_deref(), Some(actual.as_str()));
+    }
+    std::fs::remove_dir_all(&temp_dir).unwrap();
+
+    // An earlier run finished the first half and got 40 000 bytes into the
+    // second; since then a byte of the finished half has rotted.
+    let output = dir.path().join("resumed.bin");
+    let strategy = MultipartDownloadStrategy::builder(url, output.clone())
+        .with_verify_on_resume(true)
+        .build();
+    let temp_dir = PathBuf::from(strategy.temp_dir().await);
+    std::fs::create_dir_all(&temp_dir).unwrap();
+    let record = |segment: &Segment, bytes: &[u8]| {
+        let mut hash = Checksum::new(ChecksumAlgo::Sha256);
+        hash.update(bytes);
+        let record = SegmentRecord { segment: segment.clone(), sha256: Some(hash.finalize_hex()) };
+        std::fs::write(segment_state_path(&temp_dir, &segment.id), serde_json::to_vec(&record).unwrap()).unwrap();
+    };
+    let mut done = Segment::new("done".to_string(), 0, 100_000);
+    done.downloaded = 100_000;
+    done.state = SegmentState::Finished;
+    record(&done, &body[..100_000]);
+    let mut rotted = body[..100_000].to_vec();
+    rotted[5_000] ^= 0xff;
+    std::fs::write(temp_dir.join("done"), &rotted).unwrap();
+    let mut half = Segment::new("half".to_string(), 100_000, 100_000);
+    half.downloaded = 40_000;
+    record(&half, &body[100_000..140_000]);
+    std::fs::write(temp_dir.join("half"), &body[100_000..140_000]).unwrap();
+    strategy.restore_segments(vec![done, half]).await.unwrap();
+
+    let seen = server.received_requests().await.unwrap().len();
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    let mut ranges: Vec<String> = server.received_requests().await.unwrap()[seen..]
+        .iter()
+        .filter_map(|r| r.headers.get("Range").map(|v| v.to_str().unwrap().to_string()))
+        .filter(|r| r != "bytes=0-0")
+        .collect();
+    ranges.sort();
+    assert_eq!(ranges, ["bytes=0-99999", "bytes=140000-199999"]);
+}
+
+#[tokio::test]
+async fn test_byte_range_fetches_only_that_slice() {
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(600_000);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/init.mp4", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/init.mp4"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("init.mp4");
+    let url = format!("{}/init.mp4", server.uri());
+    let strategy = MultipartDownloadStrategy::builder(url.clone(), output.clone())
+        .with_connection_size(4)
+        .with_byte_range(1000, 300_999)
+        .build();
+    let info = strategy.preprocess().await.unwrap();
+    assert_eq!((info.file_size, info.resumable, info.segment_count), (Some(300_000), false, 1));
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), &body[1000..=300_999]);
+
+    let past_end = MultipartDownloadStrategy::builder(url, dir.path().join("past_end.mp4"))
+        .with_byte_range(500_000, 600_000)
+        .build();
+    let err = past_end.preprocess().await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "invalid segment plan: byte range 500000-600000 ends past the 600000 bytes of the file"
+    );
+}
+
+#[tokio::test]
+async fn test_captured_range_header_is_ignored_by_segmented_downloads() {
+    use std::collections::HashMap;
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(1024 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/full.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/full.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("full.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/full.bin", server.uri()), output.clone())
+        .with_headers(HashMap::from([("Range".to_string(), vec!["bytes=100-199".to_string()])]))
+        .with_target_segment_size(256 * 1024)
+        .with_multipart_threshold(0)
+        .with_connection_size(4)
+        .build();
+    assert!(strategy.preprocess().await.unwrap().segment_count > 1);
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|r| r.headers.get_all("Range").iter().count() == 1));
+    assert!(requests.iter().all(|r| r.headers.get("Range").unwrap() != "bytes=100-199"));
+}
+
+/// Honours only the `bytes=0-0` probe: any other range gets the whole of
+/// `body` as a `206` covering all of it, and no range a plain `200`.
+struct WholeFileRanges {
+    body: Vec<u8>,
+}
+
+impl wiremock::Respond for WholeFileRanges {
+    fn respond(&self, request: &wiremock::Request) -> ResponseTemplate {
+        let last = self.body.len() - 1;
+        match request.headers.get("Range").and_then(|v| v.to_str().ok()) {
+            Some("bytes=0-0") => ResponseTemplate::new(206)
+                .set_body_bytes(vec![self.body[0]])
+                .insert_header("Content-Range", format!("bytes 0-0/{}", self.body.len())),
+            Some(_) => ResponseTemplate::new(206)
+                .set_body_bytes(self.body.clone())
+                .insert_header("Content-Range", format!("bytes 0-{}/{}", last, self.body.len())),
+            None => ResponseTemplate::new(200).set_body_bytes(self.body.clone()),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_whole_file_answers_to_ranges_restart_as_a_single_stream() {
+    let body = generate_test_data(2 * 1024 * 1024);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(WholeFileRanges { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("cdn.bin");
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+        .with_target_segment_size(256 * 1024)
+        .with_multipart_threshold(0)
+        .with_connection_size(4)
+        .build();
+    let info = strategy.preprocess().await.unwrap();
+    assert!(info.resumable);
+    assert_eq!(info.segment_count, 4);
+    strategy.download().await.unwrap();
+
+    let segments = strategy.segments_snapshot().await;
+    assert_eq!(segments.len(), 1);
+    assert_eq!((segments[0].offset, segments[0].length), (0, -1));
+    assert!(!strategy.state().read().unwrap().resumable);
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.iter().filter(|r| r.headers.get("Range").is_none()).count(), 1);
+
+    // Without the fallback the segments past the first are served the
+    // wrong bytes and fail.
+    let strict = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("strict.bin"))
+        .with_target_segment_size(256 * 1024)
+        .with_multipart_threshold(0)
+        .with_connection_size(4)
+        .with_single_stream_fallback(false)
+        .build();
+    strict.preprocess().await.unwrap();
+    let err = strict.download().await.unwrap_err();
+    assert!(matches!(err, DownloadError::ResourceChanged(_)), "{err}");
+}
+
+#[tokio::test]
+async fn test_single_stream_fallback_restarts_a_continued_partial() {
+    let body = generate_test_data(2 * 1024 * 1024);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(WholeFileRanges { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("partial");
+    let existing = 1536 * 1024;
+    std::fs::write(&output, &body[..existing]).unwrap();
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+        .with_continue(true)
+        .with_connection_size(2)
+        .build();
+    strategy.preprocess().await.unwrap();
+    assert!(strategy.segments_snapshot().await.iter().all(|s| s.offset >= existing as i64));
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+
+    // The stream brought the whole file; the kept prefix is not repeated.
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_stopping_during_assembly_discards_the_partial_output() {
+    let body = generate_test_data(2 * 1024 * 1024);
+    let server = MockServer::start().await;
+    mount_probe(&server, "/stopped.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .respond_with(RangeSlice { body })
+        .mount(&server)
+        .await;
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("stopped.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/stopped.bin", server.uri()), output.clone())
+        .with_multipart_threshold(0)
+        .build();
+    assert!(strategy.preprocess().await.unwrap().segment_count > 1);
+    strategy.download().await.unwrap();
+
+    // A deadline passing stops the download while it is being assembled.
+    strategy.stop().await.unwrap();
+    assert!(matches!(strategy.postprocess().await, Err(DownloadError::Cancelled)));
+    assert!(!output.exists(), "the partial output was left behind");
+}
+
+#[tokio::test]
+async fn test_html_page_fails_a_download_expecting_video() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw("<html><body>Link expired</body></html>", "text/html; charset=utf-8"),
+        )
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let output = dir.path().join("video.mp4");
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), output.clone())
+        .with_expect_content_type("video/")
+        .build();
+    let err = strategy.preprocess().await.unwrap_err();
+    match &err {
+        DownloadError::UnexpectedContentType { expected, got } => {
+            assert_eq!((expected.as_str(), got.as_str()), ("video/", "text/html"));
+        }
+        other => panic!("expected UnexpectedContentType, got {other:?}"),
+    }
+    assert_eq!(err.to_string(), "unexpected content type: expected video/, got text/html");
+    assert!(!output.exists());
+
+    // Without the expectation the page is downloaded like anything else.
+    let lenient = MultipartDownloadStrategy::new(server.uri(), dir.path().join("page.html"));
+    lenient.preprocess().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_html_page_fails_a_hinted_download_expecting_video() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_raw("<html><body>Link expired</body></html>", "text/html; charset=utf-8"),
+        )
+        .mount(&server)
+        .await;
+
+    // The page saw a video; the link has expired since.
+    let dir = tempfile::tempdir().unwrap();
+    let strategy = MultipartDownloadStrategy::builder(server.uri(), dir.path().join("video.mp4"))
+        .with_probe_hints(rdm_core::types::types::ProbeHints {
+            file_size: Some(4 * 1024 * 1024),
+            resumable: Some(true),
+            content_type: Some("video/mp4".to_string()),
+            ..Default::default()
+        })
+        .with_connection_size(4)
+        .with_expect_content_type("video/")
+        .build();
+    strategy.preprocess().await.unwrap();
+    match strategy.download().await.unwrap_err() {
+        DownloadError::UnexpectedContentType { expected, got } => {
+            assert_eq!((expected.as_str(), got.as_str()), ("video/", "text/html"));
+        }
+        other => panic!("expected UnexpectedContentType, got {other:?}"),
+    }
+}
+
+/// Refuses the name `refused_name` outright, any address `resolves_refused`
+/// resolves to, and `refused_ip` however it is reached.
+struct TestGuard {
+    refused_name: &'static str,
+    resolves_refused: &'static str,
+    refused_ip: std::net::IpAddr,
+}
+
+impl rdm_core::downloader::connect_guard::ConnectGuard for TestGuard {
+    fn check_host(&self, host: &str) -> Result<(), String> {
+        if host == self.refused_name {
+            return Err(format!("{} is refused", host));
+        }
+        Ok(())
+    }
+
+    fn check_addr(&self, host: &str, ip: std::net::IpAddr) -> Result<(), String> {
+        if host == self.resolves_refused || ip == self.refused_ip {
+            return Err(format!("{} ({}) is refused", host, ip));
+        }
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn test_connect_guard_checks_redirects_resolved_addresses_and_mirrors() {
+    use std::sync::Arc;
+    use wiremock::matchers::path;
+
+    let body = generate_test_data(64 * 1024);
+    let server = MockServer::start().await;
+    let port = server.address().port();
+    mount_probe(&server, "/file.bin", body.len(), "\"v1\"").await;
+    Mock::given(method("GET"))
+        .and(path("/file.bin"))
+        .respond_with(RangeSlice { body: body.clone() })
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/hop"))
+        .respond_with(ResponseTemplate::new(302).insert_header("Location", format!("http://refused.test:{}/file.bin", port)))
+        .mount(&server)
+        .await;
+
+    let guard = || -> Arc<dyn rdm_core::downloader::connect_guard::ConnectGuard> {
+        Arc::new(TestGuard {
+            refused_name: "refused.test",
+            resolves_refused: "localhost",
+            refused_ip: "127.0.0.2".parse().unwrap(),
+        })
+    };
+    let dir = tempfile::tempdir().unwrap();
+    let refused = |url: String, mirrors: Vec<String>| {
+        MultipartDownloadStrategy::builder(url, dir.path().join("out.bin"))
+            .with_mirrors(mirrors)
+            .with_connect_guard(guard())
+            .build()
+    };
+
+    // A redirect to a refused host is not followed.
+    let err = refused(format!("{}/hop", server.uri()), vec![]).preprocess().await.unwrap_err();
+    assert!(matches!(&err, DownloadError::HostRefused(reason) if reason.contains("refused.test")), "{err:?}");
+
+    // A name that passes is still refused for the address it resolves to.
+    let err = refused(format!("http://localhost:{}/file.bin", port), vec![]).preprocess().await.unwrap_err();
+    assert!(matches!(&err, DownloadError::HostRefused(reason) if reason.contains("localhost")), "{err:?}");
+
+    // So is a mirror on a refused IP literal, before anything is fetched.
+    let seen = server.received_requests().await.unwrap().len();
+    let mirror = format!("http://127.0.0.2:{}/file.bin", port);
+    let err = refused(format!("{}/file.bin", server.uri()), vec![mirror]).preprocess().await.unwrap_err();
+    assert!(matches!(&err, DownloadError::HostRefused(reason) if reason.contains("127.0.0.2")), "{err:?}");
+    assert_eq!(server.received_requests().await.unwrap().len(), seen);
+
+    // The guard lets everything else through.
+    let output = dir.path().join("allowed.bin");
+    let strategy = MultipartDownloadStrategy::builder(format!("{}/file.bin", server.uri()), output.clone())
+        .with_connect_guard(guard())
+        .build();
+    strategy.preprocess().await.unwrap();
+    strategy.download().await.unwrap();
+    strategy.postprocess().await.unwrap();
+    assert_eq!(std::fs::read(&output).unwrap(), body);
+}
@@ -0,0 +1,162 @@
+use std::path::PathBuf;
+
+use rdm_core::downloader::naming::{
+    expand_template, naming_strategy, sanitise_component, NamingStrategy, RawNamingStrategy, SafeNamingStrategy,
+    TemplateContext, TemplateNamingStrategy,
+};
+use rdm_core::types::types::ProbeResult;
+
+fn probe(attachment_name: Option<&str>, content_type: Option<&str>) -> ProbeResult {
+    ProbeResult {
+        resumable: true,
+        resource_size: Some(1),
+        final_uri: String::new(),
+        attachment_name: attachment_name.map(str::to_string),
+        content_type: content_type.map(str::to_string),
+        last_modified: None,
+        etag: None,
+        set_cookies: Vec::new(),
+        not_modified: false,
+    }
+}
+
+fn ctx() -> TemplateContext {
+    TemplateContext {
+        host: "cdn.example.com".to_string(),
+        date: "2026-10-15".to_string(),
+        name: "My Video: Part 1".to_string(),
+        ext: "mp4".to_string(),
+    }
+}
+
+#[test]
+fn test_template_expands_tokens_into_sanitised_components() {
+    let path = expand_template("{host}/{date}/{name}.{ext}", &ctx()).unwrap();
+    assert_eq!(path, PathBuf::from("cdn.example.com/2026-10-15/My_Video_Part_1.mp4"));
+
+    let path = expand_template("/srv/media//{host}/./clip-{date}.{ext}", &ctx()).unwrap();
+    assert_eq!(path, PathBuf::from("/srv/media/cdn.example.com/clip-2026-10-15.mp4"));
+}
+
+#[test]
+fn test_token_values_cannot_add_directories() {
+    let mut ctx = ctx();
+    ctx.name = "../../etc/passwd".to_string();
+    let path = expand_template("out/{name}", &ctx).unwrap();
+    assert_eq!(path.components().count(), 2);
+    assert!(path.starts_with("out"));
+}
+
+#[test]
+fn test_template_errors() {
+    let err = expand_template("{host}/{title}.{ext}", &ctx()).unwrap_err();
+    assert!(err.contains("{title}"), "{}", err);
+    assert!(expand_template("{name", &ctx()).is_err());
+    assert!(expand_template("name}", &ctx()).is_err());
+    assert!(expand_template("../{name}", &ctx()).is_err());
+    assert!(expand_template("", &ctx()).is_err());
+}
+
+#[test]
+fn test_context_from_probe() {
+    let url = "https://CDN.Example.com/files/report.PDF?sig=1";
+
+    let ctx = TemplateContext::from_probe(url, &probe(None, None));
+    assert_eq!(ctx.host, "cdn.example.com");
+    assert_eq!((ctx.name.as_str(), ctx.ext.as_str()), ("report", "pdf"));
+
+    // Content-Type wins for the extension, Content-Disposition for the name.
+    let ctx = TemplateContext::from_probe(url, &probe(Some("Q3 results.bin"), Some("video/mp4")));
+    assert_eq!((ctx.name.as_str(), ctx.ext.as_str()), ("Q3 results", "mp4"));
+
+    let ctx = TemplateContext::from_probe("https://example.com/", &probe(None, None));
+    assert_eq!((ctx.name.as_str(), ctx.ext.as_str()), ("download", "bin"));
+
+    let date: Vec<&str> = ctx.date.split('-').collect();
+    assert_eq!(date.iter().map(|p| p.len()).collect::<Vec<_>>(), vec![4, 2, 2]);
+    assert!(date.iter().all(|p| p.chars().all(|c| c.is_ascii_digit())));
+}
+
+#[test]
+fn test_sanitise_component() {
+    assert_eq!(sanitise_component("a  b//c"), "a_b_c");
+    assert_eq!(sanitise_component("..."), "download");
+    assert_eq!(sanitise_component(&"x".repeat(300)).len(), 180);
+}
+
+/// File name `SafeNamingStrategy` picks in an empty directory.
+fn safe_name(hint: &str, url: &str, content_type: Option<&str>) -> String {
+    let dir = tempfile::tempdir().unwrap();
+    let path = SafeNamingStrategy::new(dir.path().to_path_buf()).resolve(&probe(None, content_type), url, hint);
+    assert_eq!(path.parent(), Some(dir.path()));
+    path.file_name().unwrap().to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_safe_naming_sanitises_the_hint() {
+    let name = safe_name("../../etc/passwd", "http://example.com", None);
+    assert!(!name.contains("..") && !name.contains('/'), "{name}");
+    let name = safe_name("hello:world<>?*.mp4", "http://x.com", None);
+    assert!(!name.contains([':', '<', '*', '?']), "{name}");
+    assert_eq!(safe_name("My Video HD.mp4", "http://x.com", None), "My_Video_HD.mp4");
+    assert_eq!(safe_name("  file name  .zip", "http://x.com", None), "file_name.zip");
+    assert_eq!(safe_name("hello___world.MP4<>", "http://x.com", None), "hello_world.mp4");
+    // No usable hint: the last URL path segment.
+    assert_eq!(safe_name("", "https://cdn.example.com/path/video.mp4?sig=1", None), "video.mp4");
+}
+
+#[test]
+fn test_safe_naming_adds_a_missing_extension() {
+    assert_eq!(safe_name("My_Show_Episode_1", "http://x.com/ep1", Some("video/mp4")), "My_Show_Episode_1.mp4");
+    assert_eq!(safe_name("movie.mkv", "http://x.com", Some("video/x-matroska")), "movie.mkv");
+    assert_eq!(safe_name("Unnamed Track", "https://cdn.example.com/track.flac", None), "Unnamed_Track.flac");
+    // The Content-Type wins over the URL, parameters and all.
+    assert_eq!(safe_name("Video Title", "https://cdn.example.com/file.bin", Some("video/mp4")), "Video_Title.mp4");
+    assert_eq!(safe_name("doc", "http://x.com", Some("application/pdf; charset=utf-8")), "doc.pdf");
+    assert_eq!(safe_name("song", "http://x.com", Some("audio/mpeg")), "song.mp3");
+    assert_eq!(safe_name("blob", "http://x.com/get", Some("application/octet-stream")), "blob");
+    assert_eq!(safe_name("blob", "http://x.com/get", None), "blob");
+}
+
+#[test]
+fn test_safe_naming_never_picks_an_existing_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let naming = SafeNamingStrategy::new(dir.path().to_path_buf());
+    let probe = probe(None, None);
+    std::fs::write(dir.path().join("clip.mp4"), b"x").unwrap();
+    assert_eq!(naming.resolve(&probe, "http://x.com", "clip.mp4"), dir.path().join("clip_2.mp4"));
+    std::fs::write(dir.path().join("clip_2.mp4"), b"x").unwrap();
+    assert_eq!(naming.resolve(&probe, "http://x.com", "clip.mp4"), dir.path().join("clip_3.mp4"));
+}
+
+#[test]
+fn test_raw_naming_keeps_the_name_as_given() {
+    let naming = RawNamingStrategy::new(PathBuf::from("/dl"));
+    let url = "https://cdn.example.com/files/report.PDF?sig=1";
+    assert_eq!(naming.resolve(&probe(None, None), url, "My Video: Part 1"), PathBuf::from("/dl/My Video: Part 1"));
+    assert_eq!(naming.resolve(&probe(None, None), url, "../../etc/passwd"), PathBuf::from("/dl/passwd"));
+    assert_eq!(naming.resolve(&probe(Some("Q3 results.xlsx"), None), url, ""), PathBuf::from("/dl/Q3 results.xlsx"));
+    assert_eq!(naming.resolve(&probe(None, Some("video/mp4")), url, " "), PathBuf::from("/dl/report.PDF"));
+    assert_eq!(naming.resolve(&probe(None, None), "https://example.com/a/", ".."), PathBuf::from("/dl/a"));
+}
+
+#[test]
+fn test_naming_strategy_by_name() {
+    let url = "https://cdn.example.com/files/report.pdf";
+    let probe = probe(None, Some("application/pdf"));
+
+    let naming = naming_strategy("template", PathBuf::from("/dl"), Some("{host}/{name}.{ext}")).unwrap();
+    assert_eq!(naming.resolve(&probe, url, "ignored"), PathBuf::from("/dl/cdn.example.com/report.pdf"));
+    let naming = TemplateNamingStrategy::new(PathBuf::from("/dl"), "/abs/{name}.{ext}").unwrap();
+    assert_eq!(naming.resolve(&probe, url, ""), PathBuf::from("/abs/report.pdf"));
+    assert!(TemplateNamingStrategy::new(PathBuf::new(), "{title}").is_err());
+
+    let naming = naming_strategy("RAW", PathBuf::from("/dl"), None).unwrap();
+    assert_eq!(naming.resolve(&probe, url, ""), PathBuf::from("/dl/report.pdf"));
+    assert!(naming_strategy("safe", PathBuf::from("/dl"), None).is_ok());
+
+    let err = naming_strategy("template", PathBuf::new(), None).err().unwrap();
+    assert!(err.contains("template"), "{err}");
+    let err = naming_strategy("plex", PathBuf::new(), None).err().unwrap();
+    assert_eq!(err, "unknown naming strategy `plex`; expected one of safe, raw, template");
+}
@@ -0,0 +1,347 @@
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use rdm_core::progress::notifier::ProgressNotifier;
+use rdm_core::progress::observer::ProgressObserver;
+use rdm_core::progress::snapshot::ProgressSnapshot;
+use rdm_core::types::types::ProgressEvent;
+
+/// Records every snapshot it is handed.
+#[derive(Clone, Default)]
+struct Recorder {
+    progress: Arc<Mutex<Vec<ProgressSnapshot>>>,
+    complete: Arc<Mutex<Option<ProgressSnapshot>>>,
+    warnings: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait]
+impl ProgressObserver for Recorder {
+    async fn on_progress(&self, snapshot: &ProgressSnapshot) {
+        self.progress.lock().unwrap().push(snapshot.clone());
+    }
+    async fn on_complete(&self, snapshot: &ProgressSnapshot) {
+        *self.complete.lock().unwrap() = Some(snapshot.clone());
+    }
+    async fn on_warning(&self, message: &str) {
+        self.warnings.lock().unwrap().push(message.to_string());
+    }
+    async fn on_error(&self, _error: &str) {}
+}
+
+fn event(segment_id: &str, bytes_delta: u64, total_bytes: Option<u64>) -> ProgressEvent {
+    ProgressEvent {
+        segment_id: segment_id.to_string(),
+        bytes_delta,
+        total_bytes,
+        retry: false,
+        warning: None,
+        phase: None,
+    }
+}
+
+#[tokio::test]
+async fn test_segment_counts_track_completed_parts() {
+    let recorder = Recorder::default();
+    let mut notifier = ProgressNotifier::new();
+    notifier.add_observer(Box::new(recorder.clone()));
+
+    let (tx, rx) = mpsc::channel(16);
+    let handle = tokio::spawn(notifier.run(rx));
+
+    tx.send(Ok(event("a", 50, Some(100)))).await.unwrap();
+    tx.send(Ok(event("b", 100, Some(100)))).await.unwrap();
+    tx.send(Ok(event("a", 50, Some(100)))).await.unwrap();
+    tx.send(Ok(event("c", 10, None))).await.unwrap();
+    drop(tx);
+    handle.await.unwrap();
+
+    let counts: Vec<(usize, usize)> = recorder
+        .progress
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|s| (s.completed_segments, s.total_segments))
+        .collect();
+    assert_eq!(counts, vec![(0, 1), (1, 2), (2, 2), (2, 3)]);
+
+    // Unknown-size segment "c" only counts as done on a clean finish.
+    let done = recorder.complete.lock().unwrap().clone().unwrap();
+    assert_eq!((done.completed_segments, done.total_segments), (3, 3));
+}
+
+#[tokio::test]
+async fn test_retry_events_counted_per_segment() {
+    let recorder = Recorder::default();
+    let mut notifier = ProgressNotifier::new();
+    notifier.add_observer(Box::new(recorder.clone()));
+
+    let (tx, rx) = mpsc::channel(16);
+    let handle = tokio::spawn(notifier.run(rx));
+
+    let retry = |id: &str| ProgressEvent {
+        retry: true,
+        warning: None,
+        ..event(id, 0, Some(100))
+    };
+    tx.send(Ok(event("a", 40, Some(100)))).await.unwrap();
+    tx.send(Ok(retry("a"))).await.unwrap();
+    tx.send(Ok(retry("a"))).await.unwrap();
+    tx.send(Ok(event("b", 100, Some(100)))).await.unwrap();
+    drop(tx);
+    handle.await.unwrap();
+
+    let done = recorder.complete.lock().unwrap().clone().unwrap();
+    let counts: Vec<(&str, u32)> = done
+        .segments
+        .iter()
+        .map(|s| (s.segment_id.as_str(), s.retry_count))
+        .collect();
+    assert_eq!(counts, vec![("a", 2), ("b", 0)]);
+    assert_eq!(done.total_bytes_downloaded, 140);
+}
+
+#[tokio::test]
+async fn test_eta_does_not_jump_when_work_is_added() {
+    let recorder = Recorder::default();
+    let mut notifier = ProgressNotifier::new();
+    notifier.add_observer(Box::new(recorder.clone()));
+
+    let (tx, rx) = mpsc::channel(16);
+    let handle = tokio::spawn(notifier.run(rx));
+
+    tx.send(Ok(event("a", 100, Some(1000)))).await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    tx.send(Ok(event("a", 100, Some(1000)))).await.unwrap();
+    // A large segment appearing would push the naive ETA to minutes.
+    tx.send(Ok(event("b", 0, Some(100_000)))).await.unwrap();
+    drop(tx);
+    handle.await.unwrap();
+
+    let etas: Vec<f64> = recorder
+        .progress
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|s| s.eta_secs)
+        .collect();
+    assert_eq!(etas.len(), 3);
+    assert!(etas[1] > 0.0, "eta available once throughput is known");
+    assert!(etas[2] < etas[1] * 2.0, "eta rise should be eased in: {:?}", etas);
+}
+
+#[test]
+fn test_snapshot_sums_totals_across_segments() {
+    let mut notifier = ProgressNotifier::new();
+
+    let first = notifier.handle_event(event("a", 30, Some(100)));
+    assert_eq!((first.total_bytes, first.total_bytes_downloaded), (100, 30));
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let snapshot = notifier.handle_event(event("b", 50, Some(250)));
+    assert_eq!(snapshot.total_bytes, 350);
+    assert_eq!(snapshot.total_bytes_downloaded, 80);
+    assert_eq!(snapshot.total_segments, 2);
+    assert!(!snapshot.done);
+    assert!(snapshot.error.is_none());
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let snapshot = notifier.handle_event(event("a", 70, Some(100)));
+    assert_eq!(snapshot.total_bytes, 350);
+    assert_eq!(snapshot.total_bytes_downloaded, 150);
+    assert_eq!(snapshot.completed_segments, 1);
+
+    let per_segment: Vec<(&str, u64, u64)> = snapshot
+        .segments
+        .iter()
+        .map(|s| (s.segment_id.as_str(), s.bytes_downloaded, s.total_bytes))
+        .collect();
+    assert_eq!(per_segment, vec![("a", 100, 100), ("b", 50, 250)]);
+
+    assert!(snapshot.speed >= 0.0);
+    assert!(snapshot.segments.iter().all(|s| s.speed >= 0.0));
+    assert!(snapshot.eta_secs >= 0.0);
+}
+
+#[test]
+fn test_zero_total_discards_a_segment_with_bytes() {
+    let mut notifier = ProgressNotifier::new();
+    notifier.handle_event(event("a", 40, Some(100)));
+    notifier.handle_event(event("b", 60, Some(100)));
+
+    let snapshot = notifier.handle_event(event("a", 0, Some(0)));
+    assert_eq!((snapshot.total_bytes, snapshot.total_bytes_downloaded), (100, 60));
+    assert_eq!(snapshot.total_segments, 1);
+    assert_eq!(snapshot.segments[0].segment_id, "b");
+}
+
+#[test]
+fn test_final_snapshot_marks_done_with_average_speed() {
+    let mut notifier = ProgressNotifier::new();
+    notifier.handle_event(event("a", 100, Some(100)));
+    notifier.handle_event(event("b", 40, None));
+    std::thread::sleep(std::time::Duration::from_millis(20));
+
+    let done = notifier.final_snapshot();
+    assert!(done.done);
+    assert_eq!(done.total_bytes_downloaded, 140);
+    assert_eq!(done.eta_secs, 0.0);
+    assert_eq!((done.completed_segments, done.total_segments), (2, 2));
+    // 140 bytes over at least 20ms caps the average at 7000 B/s.
+    assert!(done.speed > 0.0 && done.speed <= 7000.0, "avg speed {}", done.speed);
+}
+
+#[tokio::test]
+async fn test_file_observer_keeps_latest_snapshot_on_disk() {
+    use rdm_core::progress::file_observer::FileProgressObserver;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dl.json");
+    let read = |path: &std::path::Path| -> ProgressSnapshot {
+        serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap()
+    };
+
+    let observer = FileProgressObserver::new(&path).with_min_interval(std::time::Duration::ZERO);
+    let mut snapshot = ProgressSnapshot::empty();
+    snapshot.total_bytes = 100;
+    snapshot.total_bytes_downloaded = 40;
+    observer.on_progress(&snapshot).await;
+    assert_eq!(read(&path).total_bytes_downloaded, 40);
+
+    snapshot.total_bytes_downloaded = 100;
+    snapshot.done = true;
+    observer.on_complete(&snapshot).await;
+    let written = read(&path);
+    assert!(written.done);
+    assert_eq!(written.total_bytes_downloaded, 100);
+    // Only the final file is left behind.
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+    // Progress inside the interval is skipped; an error is always written,
+    // on top of the last snapshot seen.
+    let observer = FileProgressObserver::new(&path).with_min_interval(std::time::Duration::from_secs(60));
+    snapshot.done = false;
+    snapshot.total_bytes_downloaded = 10;
+    observer.on_progress(&snapshot).await;
+    snapshot.total_bytes_downloaded = 20;
+    observer.on_progress(&snapshot).await;
+    assert_eq!(read(&path).total_bytes_downloaded, 10);
+    observer.on_error("connection reset").await;
+    let written = read(&path);
+    assert_eq!(written.total_bytes_downloaded, 20);
+    assert!(written.done);
+    assert_eq!(written.error.as_deref(), Some("connection reset"));
+    assert_eq!(written.download_id, "");
+
+    let observer = FileProgressObserver::new(&path).with_download_id("abc123");
+    observer.on_complete(&snapshot).await;
+    assert_eq!(read(&path).download_id, "abc123");
+}
+
+#[tokio::test]
+async fn test_download_log_records_transitions_once() {
+    use rdm_core::progress::log_observer::{prune_logs, DownloadLogObserver};
+    use rdm_core::progress::snapshot::SegmentSnapshot;
+    use rdm_core::types::types::PreprocessInfo;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("dl.log");
+    let observer = DownloadLogObserver::new(&path);
+    let info = PreprocessInfo {
+        file_size: Some(200),
+        resumable: true,
+        segment_count: 2,
+        content_type: Some("application/zip".to_string()),
+        attachment_name: None,
+    };
+    observer.on_start(&info).await;
+
+    let segment = |id: &str, done: u64, retries: u32| SegmentSnapshot {
+        segment_id: id.to_string(),
+        bytes_downloaded: done,
+        total_bytes: 100,
+        speed: 0.0,
+        eta_secs: 0.0,
+        retry_count: retries,
+    };
+    let mut snapshot = ProgressSnapshot::empty();
+    for segments in [
+        vec![segment("a", 10, 0), segment("b", 0, 0)],
+        vec![segment("a", 50, 0), segment("b", 20, 1)],
+        vec![segment("a", 100, 0), segment("b", 60, 1)],
+        vec![segment("a", 100, 0), segment("b", 100, 1)],
+    ] {
+        snapshot.segments = segments;
+        observer.on_progress(&snapshot).await;
+    }
+    observer.on_warning("server ignored the range").await;
+    snapshot.total_bytes_downloaded = 200;
+    observer.on_complete(&snapshot).await;
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let events: Vec<&str> = log.lines().map(|line| line.split_once("] ").unwrap().1).collect();
+    assert_eq!(events[1], "probe: size=200 bytes, resumable=true, content-type=application/zip, name=-");
+    assert_eq!(events[2], "plan: 2 segment(s)");
+    assert_eq!(
+        &events[3..9],
+        [
+            "segment a started",
+            "segment b started",
+            "segment b retry #1 after 20 bytes",
+            "segment a finished (100 bytes)",
+            "segment b finished (100 bytes)",
+            "warning: server ignored the range",
+        ]
+        .as_slice(),
+        "{}",
+        log
+    );
+    assert!(events.len() == 10 && events[9].starts_with("complete: "), "{}", log);
+
+    // Only logs past the age limit are pruned.
+    std::fs::write(dir.path().join("notes.txt"), "keep").unwrap();
+    assert_eq!(prune_logs(dir.path(), std::time::Duration::from_secs(3600)).unwrap(), 0);
+    assert_eq!(prune_logs(dir.path(), std::time::Duration::ZERO).unwrap(), 1);
+    assert!(!path.exists());
+    assert!(dir.path().join("notes.txt").exists());
+}
+
+#[test]
+fn test_format_speed_units() {
+    use rdm_core::progress::snapshot::{format_speed, SpeedUnit};
+
+    let five_mb = 5_000_000.0;
+    assert_eq!(format_speed(five_mb, SpeedUnit::default()), "4.77 MB/s");
+    assert_eq!(format_speed(five_mb, SpeedUnit::DecimalBytes), "5.00 MB/s");
+    assert_eq!(format_speed(five_mb, SpeedUnit::Bits), "40.00 Mbps");
+    assert_eq!(format_speed(1500.0, SpeedUnit::Bits), "12.0 Kbps");
+    assert_eq!(format_speed(100.0, SpeedUnit::DecimalBytes), "100 B/s");
+    assert_eq!("BITS".parse::<SpeedUnit>(), Ok(SpeedUnit::Bits));
+    assert!("kibibytes".parse::<SpeedUnit>().is_err());
+}
+
+#[tokio::test]
+async fn test_warnings_reach_observers_and_later_snapshots() {
+    let recorder = Recorder::default();
+    let mut notifier = ProgressNotifier::new();
+    notifier.add_observer(Box::new(recorder.clone()));
+
+    let (tx, rx) = mpsc::channel(16);
+    let handle = tokio::spawn(notifier.run(rx));
+
+    tx.send(Ok(event("a", 50, Some(100)))).await.unwrap();
+    tx.send(Ok(ProgressEvent::warning("size differs"))).await.unwrap();
+    tx.send(Ok(event("a", 50, Some(100)))).await.unwrap();
+    drop(tx);
+    handle.await.unwrap();
+
+    assert_eq!(*recorder.warnings.lock().unwrap(), vec!["size differs".to_string()]);
+    // The warning is not a progress event and adds no segment.
+    let progress = recorder.progress.lock().unwrap();
+    assert_eq!(progress.len(), 2);
+    assert!(progress[0].warnings.is_empty());
+    assert_eq!(progress[1].warnings, vec!["size differs".to_string()]);
+    let done = recorder.complete.lock().unwrap().clone().unwrap();
+    assert_eq!((done.total_segments, done.warnings.len()), (1, 1));
+}
@@ -5,9 +5,16 @@ use std::sync::Arc;
 use reqwest::Client;
 use tokio_util::sync::CancellationToken;
 use wiremock::matchers::{header, method};
-use wiremock::{Mock, MockServer, ResponseTemplate};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
 
-use rdm_core::downloader::segment_grabber::{download_segment, extract_filename, probe_url};
+use rdm_test_support::{start_resetting_server, start_stalling_server};
+
+use rdm_core::downloader::pause_token::PauseToken;
+use rdm_core::downloader::segment_grabber::{
+    backoff_delay, cookie_pair, download_segment, download_segment_with_options, extract_filename,
+    merge_cookies, parse_auth_challenge, probe_url, probe_url_with_filename_headers, segment_state_path,
+    FlushInterval, SegmentOptions,
+};
 use rdm_core::types::types::{DownloadError, HeaderData, Segment, SegmentState};
 
 /// Helper: creates a minimal HeaderData pointing at the given URL.
@@ -18,9 +25,49 @@ fn make_header_data(url: &str) -> HeaderData {
         cookies: None,
         authentication: None,
         proxy: None,
+        mirrors: Vec::new(),
+        method: None,
+        body: None,
+        default_accept: None,
+    }
+}
+
+/// Generates deterministic test data: each byte = (offset % 251) as u8.
+fn generate_test_data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+/// A wiremock responder that honours Range requests by slicing the body
+/// and returning exactly the requested bytes with 206.
+struct RangeResponder {
+    body: Vec<u8>,
+}
+
+impl wiremock::Respond for RangeResponder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        if let Some(range_header) = request.headers.get(&reqwest::header::RANGE) {
+            let range_str = range_header.to_str().unwrap_or("");
+            if let Some((start, end)) = parse_range(range_str, self.body.len()) {
+                return ResponseTemplate::new(206)
+                    .set_body_bytes(self.body[start..=end].to_vec())
+                    .insert_header(
+                        "Content-Range",
+                        format!("bytes {}-{}/{}", start, end, self.body.len()),
+                    );
+            }
+        }
+        ResponseTemplate::new(200).set_body_bytes(self.body.clone())
     }
 }
 
+/// Parses a Range header like "bytes=0-" or "bytes=1024-2047"
+fn parse_range(header: &str, body_len: usize) -> Option<(usize, usize)> {
+    let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let start: usize = start.parse().ok()?;
+    let end: usize = if end.is_empty() { body_len - 1 } else { end.parse().ok()? };
+    Some((start, end.min(body_len - 1)))
+}
+
 // ---------------------------------------------------------------
 // extract_filename
 // ---------------------------------------------------------------
@@ -43,6 +90,25 @@ fn test_extract_filename_with_trailing_params() {
     assert_eq!(result, Some("image.png".to_string()));
 }
 
+#[test]
+fn test_cookie_pair_drops_attributes() {
+    assert_eq!(cookie_pair("sid=abc; Path=/; HttpOnly"), Some("sid=abc".to_string()));
+    assert_eq!(cookie_pair("token=a=b==; Secure"), Some("token=a=b==".to_string()));
+    assert_eq!(cookie_pair("; Path=/"), None);
+    assert_eq!(cookie_pair("novalue"), None);
+}
+
+#[test]
+fn test_merge_cookies_appends_and_replaces() {
+    let pairs = vec!["sid=new".to_string(), "cdn=1".to_string()];
+    assert_eq!(
+        merge_cookies(Some("sid=old; theme=dark"), &pairs),
+        Some("sid=new; theme=dark; cdn=1".to_string())
+    );
+    assert_eq!(merge_cookies(None, &pairs), Some("sid=new; cdn=1".to_string()));
+    assert_eq!(merge_cookies(None, &[]), None);
+}
+
 #[test]
 fn test_extract_filename_missing() {
     let result = extract_filename("inline");
@@ -156,6 +222,85 @@ async fn test_probe_network_error() {
     assert!(result.is_err());
 }
 
+#[tokio::test]
+async fn test_probe_timeout_is_reported_as_timeout() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(5)))
+        .mount(&server)
+        .await;
+
+    let client = Client::builder().timeout(std::time::Duration::from_millis(200)).build().unwrap();
+    let header_data = make_header_data(&format!("{}/slow.bin", server.uri()));
+
+    let err = probe_url(&client, &header_data).await.unwrap_err();
+    assert!(matches!(err, DownloadError::Timeout(_)), "{:?}", err);
+}
+
+#[tokio::test]
+async fn test_probe_falls_back_to_filename_header() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("X-Filename", "\"exports/Q3%20report.pdf\"")
+                .insert_header("X-Original-Name", "from-custom.pdf")
+                .set_body_bytes(vec![0u8; 16]),
+        )
+        .mount(&server)
+        .await;
+    let client = Client::new();
+    let header_data = make_header_data(&format!("{}/d/9f8e7a6b", server.uri()));
+
+    let probe = probe_url(&client, &header_data).await.unwrap();
+    assert_eq!(probe.attachment_name.as_deref(), Some("Q3 report.pdf"));
+
+    let custom = vec!["X-Original-Name".to_string()];
+    let probe = probe_url_with_filename_headers(&client, &header_data, &custom).await.unwrap();
+    assert_eq!(probe.attachment_name.as_deref(), Some("from-custom.pdf"));
+
+    let probe = probe_url_with_filename_headers(&client, &header_data, &[]).await.unwrap();
+    assert_eq!(probe.attachment_name, None);
+}
+
+#[tokio::test]
+async fn test_probe_reports_basic_auth_challenge() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(401)
+                .insert_header("WWW-Authenticate", "Basic realm=\"Private files\", charset=\"UTF-8\"")
+                .set_body_string("Unauthorized"),
+        )
+        .mount(&server)
+        .await;
+
+    let client = Client::new();
+    let err = probe_url(&client, &make_header_data(&server.uri())).await.unwrap_err();
+    match &err {
+        DownloadError::AuthRequired { scheme, realm } => {
+            assert_eq!(scheme, "Basic");
+            assert_eq!(realm.as_deref(), Some("Private files"));
+        }
+        other => panic!("expected AuthRequired, got {:?}", other),
+    }
+    assert_eq!(
+        err.to_string(),
+        "authentication required: the server asks for Basic credentials (realm \"Private files\")"
+    );
+}
+
+#[test]
+fn test_parse_auth_challenge() {
+    assert_eq!(
+        parse_auth_challenge(r#"Bearer realm="api", error="invalid_token""#),
+        Some(("Bearer".to_string(), Some("api".to_string())))
+    );
+    assert_eq!(parse_auth_challenge("Basic realm=files"), Some(("Basic".to_string(), Some("files".to_string()))));
+    assert_eq!(parse_auth_challenge("Negotiate"), Some(("Negotiate".to_string(), None)));
+    assert_eq!(parse_auth_challenge("  "), None);
+}
+
 // ---------------------------------------------------------------
 // download_segment
 // ---------------------------------------------------------------
@@ -240,6 +385,41 @@ async fn test_download_segment_with_range() {
     assert_eq!(file_content, body);
 }
 
+#[tokio::test]
+async fn test_download_segment_honors_range_slice() {
+    let body = generate_test_data(4096);
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .respond_with(RangeResponder { body: body.clone() })
+        .mount(&server)
+        .await;
+
+    let client = Client::new();
+    let header_data = Arc::new(make_header_data(&server.uri()));
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    // offset=1024, length=512 → Range: bytes=1024-1535
+    let segment = Segment::new("segment-slice".to_string(), 1024, 512);
+
+    let finished = download_segment(
+        segment,
+        &client,
+        &header_data,
+        temp_dir.path().to_path_buf(),
+        CancellationToken::new(),
+        |_| {},
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(finished.state, SegmentState::Finished);
+    assert_eq!(finished.downloaded, 512);
+
+    let file_content = std::fs::read(temp_dir.path().join("segment-slice")).unwrap();
+    assert_eq!(file_content, body[1024..=1535], "segment must hold exactly the requested slice");
+}
+
 #[tokio::test]
 async fn test_download_segment_cancellation() {
     let server = MockServer::start().await;
@@ -344,3 +524,342 @@ async fn test_download_segment_progress_callback_called() {
     // Total progress should equal the body size
     assert_eq!(total_progress.load(Ordering::Relaxed), 2048);
 }
+
+#[tokio::test]
+async fn test_download_segment_retries_after_idle_timeout() {
+    let body = generate_test_data(1024);
+    let (url, ranges) = start_stalling_server(body.clone()).await;
+
+    let client = Client::new();
+    let header_data = Arc::new(make_header_data(&url));
+    let temp_dir = tempfile::tempdir().unwrap();
+    let segment = Segment::new("segment-idle".to_string(), 0, body.len() as i64);
+    let options = SegmentOptions {
+        idle_timeout: std::time::Duration::from_millis(300),
+        ..SegmentOptions::default()
+    };
+
+    let finished = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        download_segment_with_options(
+            segment,
+            &client,
+            &header_data,
+            temp_dir.path().to_path_buf(),
+            CancellationToken::new(),
+            PauseToken::new(),
+            options,
+            |_| {},
+            |_| {},
+        ),
+    )
+    .await
+    .expect("idle timeout should trigger a retry instead of hanging")
+    .unwrap();
+
+    assert_eq!(finished.state, SegmentState::Finished);
+    assert_eq!(finished.downloaded, 1024);
+    assert_eq!(std::fs::read(temp_dir.path().join("segment-idle")).unwrap(), body);
+
+    // The retry resumed from the bytes already on disk.
+    let ranges = ranges.lock().unwrap().clone();
+    assert_eq!(ranges, vec!["bytes=0-1023".to_string(), "bytes=512-1023".to_string()]);
+}
+
+#[tokio::test]
+async fn test_download_segment_gives_up_with_timeout_when_every_attempt_times_out() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .insert_header("Content-Range", "bytes 0-1023/1024")
+                .set_body_bytes(generate_test_data(1024))
+                .set_delay(std::time::Duration::from_secs(5)),
+        )
+        .mount(&server)
+        .await;
+
+    let client = Client::new();
+    let header_data = Arc::new(make_header_data(&format!("{}/slow.bin", server.uri())));
+    let temp_dir = tempfile::tempdir().unwrap();
+    let segment = Segment::new("segment-slow".to_string(), 0, 1024);
+    let options = SegmentOptions {
+        idle_timeout: std::time::Duration::from_millis(200),
+        ..SegmentOptions::default()
+    };
+
+    let err = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        download_segment_with_options(
+            segment,
+            &client,
+            &header_data,
+            temp_dir.path().to_path_buf(),
+            CancellationToken::new(),
+            PauseToken::new(),
+            options,
+            |_| {},
+            |_| {},
+        ),
+    )
+    .await
+    .expect("a server that never answers should time out instead of hanging")
+    .unwrap_err();
+
+    assert!(matches!(err, DownloadError::Timeout(_)), "{:?}", err);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+/// Runs a segment against a server that stalls halfway, then aborts the task
+/// as a crash would once half the body has arrived. Returns the temp
+/// directory and how many bytes the task had received.
+async fn crash_halfway(flush_interval: Option<FlushInterval>) -> (tempfile::TempDir, u64) {
+    let body = generate_test_data(20_000);
+    let (url, _ranges) = start_stalling_server(body.clone()).await;
+    let temp_dir = tempfile::tempdir().unwrap();
+    let received = Arc::new(AtomicU64::new(0));
+
+    let task = tokio::spawn({
+        let temp_dir = temp_dir.path().to_path_buf();
+        let received = Arc::clone(&received);
+        let segment = Segment::new("segment-crash".to_string(), 0, body.len() as i64);
+        let options = SegmentOptions { flush_interval, ..SegmentOptions::default() };
+        async move {
+            let header_data = Arc::new(make_header_data(&url));
+            download_segment_with_options(
+                segment,
+                &Client::new(),
+                &header_data,
+                temp_dir,
+                CancellationToken::new(),
+                PauseToken::new(),
+                options,
+                |n| {
+                    received.fetch_add(n, Ordering::SeqCst);
+                },
+                |_| {},
+            )
+            .await
+        }
+    });
+
+    let state_path = segment_state_path(temp_dir.path(), "segment-crash");
+    let settled = || {
+        received.load(Ordering::SeqCst) == 10_000
+            && (flush_interval.is_none()
+                || std::fs::read(&state_path)
+                    .ok()
+                    .and_then(|json| serde_json::from_slice::<Segment>(&json).ok())
+                    .is_some_and(|s| s.downloaded == 10_000))
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while !settled() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("half the body should arrive");
+    task.abort();
+    let _ = task.await;
+
+    (temp_dir, received.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+async fn test_flush_interval_keeps_disk_in_step_with_recorded_progress() {
+    let (temp_dir, received) = crash_halfway(Some(FlushInterval { bytes: 1, ..FlushInterval::default() })).await;
+    let on_disk = std::fs::metadata(temp_dir.path().join("segment-crash")).unwrap().len();
+    let recorded: Segment =
+        serde_json::from_slice(&std::fs::read(segment_state_path(temp_dir.path(), "segment-crash")).unwrap())
+            .unwrap();
+    assert_eq!(on_disk, received);
+    assert_eq!(recorded.downloaded as u64, on_disk);
+    assert_eq!(recorded.state, SegmentState::Downloading);
+
+    // Without it, everything received is still in the write buffer.
+    let (temp_dir, received) = crash_halfway(None).await;
+    let on_disk = std::fs::metadata(temp_dir.path().join("segment-crash")).unwrap().len();
+    assert_eq!((received, on_disk), (10_000, 0));
+    assert!(!segment_state_path(temp_dir.path(), "segment-crash").exists());
+}
+
+#[tokio::test]
+async fn test_download_segment_retries_reset_on_fresh_connection() {
+    let body = generate_test_data(64 * 1024);
+    let (url, requests) = start_resetting_server(body.clone()).await;
+
+    // Two requests at once leave two idle connections in the pool.
+    let client = Client::new();
+    let fetch = || async { client.get(&url).send().await.unwrap().bytes().await.unwrap() };
+    let (first, second) = tokio::join!(fetch(), fetch());
+    assert_eq!((first.len(), second.len()), (body.len(), body.len()));
+    let pooled: std::collections::HashSet<usize> = requests.lock().unwrap().iter().map(|(c, _)| *c).collect();
+    assert_eq!(pooled.len(), 2);
+
+    let header_data = Arc::new(make_header_data(&url));
+    let temp_dir = tempfile::tempdir().unwrap();
+    let segment = Segment::new("segment-reset".to_string(), 0, body.len() as i64);
+    let retries = Arc::new(AtomicU64::new(0));
+    let retries_seen = Arc::clone(&retries);
+    let options = SegmentOptions {
+        reconnect_client: Some(Client::builder().pool_max_idle_per_host(0).build().unwrap()),
+        ..SegmentOptions::default()
+    };
+
+    let finished = download_segment_with_options(
+        segment,
+        &client,
+        &header_data,
+        temp_dir.path().to_path_buf(),
+        CancellationToken::new(),
+        PauseToken::new(),
+        options,
+        |_| {},
+        move |_| {
+            retries_seen.fetch_add(1, Ordering::Relaxed);
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(finished.downloaded, body.len() as i64);
+    assert_eq!(std::fs::read(temp_dir.path().join("segment-reset")).unwrap(), body);
+    assert_eq!(retries.load(Ordering::Relaxed), 1);
+
+    // The first attempt reused a pooled connection; the retry after the
+    // reset did not take the other one.
+    let requests = requests.lock().unwrap().clone();
+    let [.., (reset_on, _), (retried_on, _)] = requests.as_slice() else { panic!("{:?}", requests) };
+    assert!(pooled.contains(reset_on));
+    assert!(!pooled.contains(retried_on), "the retry went out on pooled connection {}", retried_on);
+}
+
+#[tokio::test]
+async fn test_download_segment_resumes_after_short_body() {
+    let body = generate_test_data(4096);
+    let server = MockServer::start().await;
+    // The first answer claims the full range but stops halfway.
+    Mock::given(method("GET"))
+        .and(header("Range", "bytes=0-4095"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .set_body_bytes(body[..2048].to_vec())
+                .insert_header("Content-Range", "bytes 0-4095/4096"),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(header("Range", "bytes=2048-4095"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .set_body_bytes(body[2048..].to_vec())
+                .insert_header("Content-Range", "bytes 2048-4095/4096"),
+        )
+        .mount(&server)
+        .await;
+
+    let client = Client::new();
+    let header_data = Arc::new(make_header_data(&server.uri()));
+    let temp_dir = tempfile::tempdir().unwrap();
+    let segment = Segment::new("segment-short".to_string(), 0, body.len() as i64);
+
+    let finished = download_segment(segment, &client, &header_data, temp_dir.path().to_path_buf(), CancellationToken::new(), |_| {})
+        .await
+        .unwrap();
+
+    assert_eq!(finished.state, SegmentState::Finished);
+    assert_eq!(std::fs::read(temp_dir.path().join("segment-short")).unwrap(), body);
+}
+
+#[test]
+fn test_backoff_delay_is_jittered_within_bounds() {
+    let mut rng = fastrand::Rng::with_seed(7);
+    for retries in 1..=3 {
+        let ceiling = 100u64 << retries;
+        let delays: Vec<u64> = (0..200)
+            .map(|_| backoff_delay(&mut rng, retries).as_millis() as u64)
+            .collect();
+        assert!(delays.iter().all(|&d| d <= ceiling), "delay above 100ms * 2^{}", retries);
+        assert!(delays.iter().any(|&d| d != delays[0]), "delays should not be deterministic");
+    }
+
+    // Large retry counts are capped rather than overflowing.
+    assert!(backoff_delay(&mut rng, 60).as_millis() <= 5_000);
+}
+
+#[tokio::test]
+async fn test_paused_segment_waits_for_resume() {
+    let body = generate_test_data(4096);
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(206).set_body_bytes(body.clone()))
+        .mount(&server)
+        .await;
+
+    let header_data = Arc::new(make_header_data(&server.uri()));
+    let temp_dir = tempfile::tempdir().unwrap();
+    let pause_token = PauseToken::new();
+    pause_token.pause();
+
+    let task = {
+        let pause_token = pause_token.clone();
+        let temp_path = temp_dir.path().to_path_buf();
+        tokio::spawn(async move {
+            download_segment_with_options(
+                Segment::new("segment-paused".to_string(), 0, body.len() as i64),
+                &Client::new(),
+                &header_data,
+                temp_path,
+                CancellationToken::new(),
+                pause_token,
+                SegmentOptions::default(),
+                |_| {},
+                |_| {},
+            )
+            .await
+        })
+    };
+
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert!(!task.is_finished(), "segment must not run while paused");
+    assert!(server.received_requests().await.unwrap().is_empty());
+
+    pause_token.resume();
+    let finished = tokio::time::timeout(std::time::Duration::from_secs(5), task)
+        .await
+        .expect("segment should finish after resume")
+        .unwrap()
+        .unwrap();
+    assert_eq!(finished.state, SegmentState::Finished);
+    assert_eq!(finished.downloaded, 4096);
+}
+
+#[tokio::test]
+async fn test_download_segment_rejects_mismatched_content_range() {
+    let server = MockServer::start().await;
+    // Asked for bytes 1000-1999 but the server answers for 0-999.
+    Mock::given(method("GET"))
+        .and(header("Range", "bytes=1000-1999"))
+        .respond_with(
+            ResponseTemplate::new(206)
+                .set_body_bytes(generate_test_data(1000))
+                .insert_header("Content-Range", "bytes 0-999/4000"),
+        )
+        .mount(&server)
+        .await;
+
+    let header_data = Arc::new(make_header_data(&server.uri()));
+    let temp_dir = tempfile::tempdir().unwrap();
+    let result = download_segment(
+        Segment::new("segment-misaligned".to_string(), 1000, 1000),
+        &Client::new(),
+        &header_data,
+        temp_dir.path().to_path_buf(),
+        CancellationToken::new(),
+        |_| {},
+    )
+    .await;
+
+    assert!(matches!(result, Err(DownloadError::ResourceChanged(_))), "got {:?}", result.err());
+    assert!(!temp_dir.path().join("segment-misaligned").exists(), "no bytes should be written");
+}
@@ -0,0 +1,47 @@
+//! A small web dashboard served at `GET /` and `GET /ui`, for monitoring a
+//! headless rdmd from a browser without the desktop app.
+//!
+//! The page is a single HTML file with inline CSS and JS, compiled into the
+//! binary. It only talks to the REST endpoints: it polls `/downloads` a page
+//! at a time, follows each active download on `/progress/{id}`, and posts to
+//! `/cancel/{id}`, `/downloads/{id}/retry`, `/pause-all` and `/resume-all`.
+//!
+//! Like the API it has no authentication, so anyone who can reach rdmd's
+//! port can use it; it is only served when `RDM_DASHBOARD=1`.
+
+use axum::http::header;
+use axum::response::{Html, IntoResponse};
+
+/// The dashboard page, embedded at build time.
+pub const DASHBOARD_HTML: &str = include_str!("../assets/dashboard.html");
+
+/// `RDM_DASHBOARD`: off unless set to `1`, `true` or `on`.
+pub fn dashboard_from_env() -> bool {
+    std::env::var("RDM_DASHBOARD")
+        .map(|v| matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "on"))
+        .unwrap_or(false)
+}
+
+/// GET / and GET /ui
+pub async fn dashboard_handler() -> impl IntoResponse {
+    // Always revalidate, so an upgraded rdmd never runs against a stale page.
+    ([(header::CACHE_CONTROL, "no-cache")], Html(DASHBOARD_HTML))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn serves_embedded_page_using_the_rest_endpoints() {
+        let response = dashboard_handler().await.into_response();
+        assert_eq!(response.status(), axum::http::StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let page = std::str::from_utf8(&body).unwrap();
+        for endpoint in ["`/downloads?limit=${PAGE_SIZE}&offset=${offset}`", "`/progress/", "`/cancel/", "/retry`", "\"/pause-all\"", "\"/resume-all\""] {
+            assert!(page.contains(endpoint), "dashboard does not use {}", endpoint);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Serves a finished download's output file over HTTP
+//! (`GET /downloads/{id}/file`), so a browser extension can hand it to the
+//! browser's own download UI.
+//!
+//! The file is streamed from disk, never read into memory. A single
+//! `Range: bytes=…` is honoured with `206 Partial Content`; a range past the
+//! end gets `416`, and anything else (several ranges, other units, garbage)
+//! is ignored in favour of the whole file, as RFC 9110 allows.
+
+use std::path::Path;
+
+use axum::body::Body;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::Response;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+/// What a `Range` header asks of a file of known length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeRequest {
+    /// No usable range: send everything with `200`.
+    Full,
+    /// Inclusive byte range within the file, sent with `206`.
+    Partial { start: u64, end: u64 },
+    /// The range starts past the end of the file: `416`.
+    Unsatisfiable,
+}
+
+/// Interpret a `Range` header value for a file of `len` bytes.
+pub fn parse_range(header: Option<&str>, len: u64) -> RangeRequest {
+    let Some(spec) = header.and_then(|h| h.trim().strip_prefix("bytes=")) else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((start, end)) = spec.split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        // Suffix range: the last `end` bytes.
+        return match end.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(_) if len == 0 => RangeRequest::Unsatisfiable,
+            Ok(n) => RangeRequest::Partial { start: len.saturating_sub(n), end: len - 1 },
+            Err(_) => RangeRequest::Full,
+        };
+    }
+    let Ok(start) = start.parse::<u64>() else {
+        return RangeRequest::Full;
+    };
+    let end = match end {
+        "" => len.saturating_sub(1),
+        end => match end.parse::<u64>() {
+            Ok(end) if end >= start => end.min(len.saturating_sub(1)),
+            _ => return RangeRequest::Full,
+        },
+    };
+    if start >= len {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial { start, end }
+}
+
+/// `Content-Disposition: attachment` naming the file `file_name`: a plain
+/// ASCII `filename` for old clients plus the exact name as RFC 5987
+/// `filename*`.
+pub fn content_disposition(file_name: &str) -> String {
+    let ascii: String = file_name
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' { c } else { '_' })
+        .collect();
+    let mut encoded = String::with_capacity(file_name.len());
+    for byte in file_name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", ascii, encoded)
+}
+
+/// Stream `path` (or the part of it `range` asks for) as an attachment.
+pub async fn serve_file(
+    path: &Path,
+    content_type: &str,
+    file_name: &str,
+    range: Option<&str>,
+) -> Result<Response, (StatusCode, String)> {
+    let mut file = tokio::fs::File::open(path).await.map_err(|e| {
+        (StatusCode::NOT_FOUND, format!("output file {} is not available: {}", path.display(), e))
+    })?;
+    let len = file
+        .metadata()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .len();
+
+    let builder = Response::builder()
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_TYPE, content_type)
+        .header(
+            header::CONTENT_DISPOSITION,
+            HeaderValue::from_str(&content_disposition(file_name))
+                .unwrap_or_else(|_| HeaderValue::from_static("attachment")),
+        );
+
+    let (builder, body) = match parse_range(range, len) {
+        RangeRequest::Unsatisfiable => {
+            let response = builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty());
+            return response.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+        }
+        RangeRequest::Full => (
+            builder.status(StatusCode::OK).header(header::CONTENT_LENGTH, len),
+            Body::from_stream(ReaderStream::new(file)),
+        ),
+        RangeRequest::Partial { start, end } => {
+            file.seek(std::io::SeekFrom::Start(start))
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            let count = end - start + 1;
+            (
+                builder
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .header(header::CONTENT_LENGTH, count)
+                    .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)),
+                Body::from_stream(ReaderStream::new(file.take(count))),
+            )
+        }
+    };
+    builder
+        .body(body)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_byte_ranges() {
+        use RangeRequest::*;
+        let len = 1000;
+        assert_eq!(parse_range(None, len), Full);
+        assert_eq!(parse_range(Some("bytes=0-99"), len), Partial { start: 0, end: 99 });
+        assert_eq!(parse_range(Some("bytes=900-"), len), Partial { start: 900, end: 999 });
+        assert_eq!(parse_range(Some("bytes=990-5000"), len), Partial { start: 990, end: 999 });
+        assert_eq!(parse_range(Some("bytes=-100"), len), Partial { start: 900, end: 999 });
+        assert_eq!(parse_range(Some("bytes=-5000"), len), Partial { start: 0, end: 999 });
+        assert_eq!(parse_range(Some("bytes=1000-"), len), Unsatisfiable);
+        assert_eq!(parse_range(Some("bytes=0-"), 0), Unsatisfiable);
+        // Ignored rather than rejected.
+        assert_eq!(parse_range(Some("bytes=0-1,5-6"), len), Full);
+        assert_eq!(parse_range(Some("items=0-1"), len), Full);
+        assert_eq!(parse_range(Some("bytes=50-10"), len), Full);
+    }
+
+    #[tokio::test]
+    async fn streams_whole_file_or_requested_range() {
+        let dir = std::env::temp_dir().join(format!("rdm-file-server-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.mp4");
+        let data: Vec<u8> = (0..=255u8).cycle().take(4096).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let full = serve_file(&path, "video/mp4", "clip é.mp4", None).await.unwrap();
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(full.headers()[header::CONTENT_LENGTH], "4096");
+        assert_eq!(
+            full.headers()[header::CONTENT_DISPOSITION],
+            "attachment; filename=\"clip _.mp4\"; filename*=UTF-8''clip%20%C3%A9.mp4"
+        );
+        let body = axum::body::to_bytes(full.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), data.as_slice());
+
+        let part = serve_file(&path, "video/mp4", "clip.mp4", Some("bytes=100-199")).await.unwrap();
+        assert_eq!(part.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(part.headers()[header::CONTENT_RANGE], "bytes 100-199/4096");
+        let body = axum::body::to_bytes(part.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.as_ref(), &data[100..200]);
+
+        let past = serve_file(&path, "video/mp4", "clip.mp4", Some("bytes=5000-")).await.unwrap();
+        assert_eq!(past.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(past.headers()[header::CONTENT_RANGE], "bytes */4096");
+
+        let missing = serve_file(&dir.join("gone.mp4"), "video/mp4", "gone.mp4", None).await;
+        assert_eq!(missing.unwrap_err().0, StatusCode::NOT_FOUND);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
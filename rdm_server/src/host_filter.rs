@@ -0,0 +1,199 @@
+//! Host filter — keeps rdmd from being used to reach internal services.
+//!
+//! Any local client may ask rdmd to fetch a URL, so without a check it can
+//! be pointed at cloud metadata (`http://169.254.169.254/`), the router's
+//! admin page or other services on the loopback interface. Before a download
+//! starts the URL's host is resolved and every address it resolves to is
+//! checked, so a public-looking name pointing at `127.0.0.1` is caught too:
+//!
+//! 1. Hosts in `RDM_BLOCKED_HOSTS` are always rejected.
+//! 2. Hosts in `RDM_ALLOWED_HOSTS` skip the address check.
+//! 3. Otherwise loopback, link-local, private, shared (CGNAT), unspecified,
+//!    broadcast and multicast addresses are rejected.
+//!
+//! Both lists are comma-separated; an entry matches the host itself and its
+//! subdomains, and may also be an IP address.
+//!
+//! [`check`](HostFilter::check) rejects a bad URL up front, with a reason
+//! for the response. The filter is also the downloads' connect guard, so
+//! the same rules apply to the addresses the HTTP client actually connects
+//! to, to every redirect, and to mirror, audio and DASH segment URLs.
+
+use std::net::IpAddr;
+
+use rdm_core::downloader::connect_guard::ConnectGuard;
+use url::Url;
+
+#[derive(Debug, Clone, Default)]
+pub struct HostFilter {
+    allowed: Vec<String>,
+    blocked: Vec<String>,
+}
+
+impl HostFilter {
+    pub fn new(allowed: Vec<String>, blocked: Vec<String>) -> Self {
+        let normalize = |list: Vec<String>| {
+            list.into_iter()
+                .map(|h| h.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect()
+        };
+        Self { allowed: normalize(allowed), blocked: normalize(blocked) }
+    }
+
+    /// Lists from `RDM_ALLOWED_HOSTS` and `RDM_BLOCKED_HOSTS`.
+    pub fn from_env() -> Self {
+        let list = |var: &str| -> Vec<String> {
+            std::env::var(var)
+                .map(|v| v.split(',').map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+        Self::new(list("RDM_ALLOWED_HOSTS"), list("RDM_BLOCKED_HOSTS"))
+    }
+
+    /// `Ok` if `url` may be downloaded, otherwise a human-readable reason.
+    pub async fn check(&self, url: &str) -> Result<(), String> {
+        let parsed = Url::parse(url).map_err(|e| format!("invalid URL {:?}: {}", url, e))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(format!("scheme {:?} is not allowed", parsed.scheme()));
+        }
+        let host = match parsed.host() {
+            Some(url::Host::Domain(d)) => d.to_ascii_lowercase(),
+            Some(url::Host::Ipv4(ip)) => ip.to_string(),
+            Some(url::Host::Ipv6(ip)) => ip.to_string(),
+            None => return Err(format!("URL {:?} has no host", url)),
+        };
+
+        self.check_host(&host)?;
+
+        let port = parsed.port_or_known_default().unwrap_or(80);
+        let addrs: Vec<IpAddr> = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| format!("could not resolve {}: {}", host, e))?
+            .map(|a| a.ip())
+            .collect();
+
+        for ip in addrs {
+            self.check_addr(&host, ip)?;
+        }
+        Ok(())
+    }
+}
+
+impl ConnectGuard for HostFilter {
+    fn check_host(&self, host: &str) -> Result<(), String> {
+        if matches_list(&self.blocked, host) {
+            return Err(format!("host {} is blocked", host));
+        }
+        Ok(())
+    }
+
+    fn check_addr(&self, host: &str, ip: IpAddr) -> Result<(), String> {
+        if matches_list(&self.blocked, &ip.to_string()) {
+            return Err(format!("host {} resolves to blocked address {}", host, ip));
+        }
+        if !matches_list(&self.allowed, host) && is_internal(&ip) {
+            return Err(format!(
+                "host {} resolves to internal address {}; add it to RDM_ALLOWED_HOSTS to permit it",
+                host, ip
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `host` equals an entry or is a subdomain of one.
+fn matches_list(list: &[String], host: &str) -> bool {
+    list.iter().any(|entry| {
+        host == entry
+            || host
+                .strip_suffix(entry.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+/// Addresses that reach the local machine or network rather than the
+/// public internet.
+fn is_internal(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_multicast()
+                // 100.64.0.0/10, carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b))
+                // 0.0.0.0/8, "this network"
+                || a == 0
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_internal(&IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // fc00::/7, unique local
+                || (first & 0xfe00) == 0xfc00
+                // fe80::/10, link-local
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rejects_internal_addresses_by_default() {
+        let filter = HostFilter::default();
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://127.0.0.1:8080/",
+            "http://10.0.0.5/file",
+            "http://192.168.1.1/",
+            "http://[::1]/",
+            "http://[::ffff:127.0.0.1]/",
+            "http://[fd00::1]/",
+            "http://localhost/",
+        ] {
+            assert!(filter.check(url).await.is_err(), "{} should be rejected", url);
+        }
+        assert!(filter.check("ftp://8.8.8.8/").await.is_err());
+        assert!(filter.check("http://8.8.8.8/").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn allow_and_block_lists() {
+        let filter = HostFilter::new(
+            vec!["127.0.0.1".into(), "localhost".into()],
+            vec!["example.com".into(), "8.8.4.4".into()],
+        );
+        assert!(filter.check("http://127.0.0.1:9000/").await.is_ok());
+        assert!(filter.check("http://localhost/").await.is_ok());
+        assert!(filter.check("http://10.0.0.5/").await.is_err());
+
+        // Blocked names are rejected before any lookup, subdomains included.
+        let err = filter.check("https://cdn.example.com/a.mp4").await.unwrap_err();
+        assert!(err.contains("blocked"), "{}", err);
+        assert!(!matches_list(&filter.blocked, "notexample.com"));
+        assert!(filter.check("http://8.8.4.4/").await.is_err());
+    }
+
+    #[test]
+    fn guards_the_addresses_a_download_connects_to() {
+        let filter = HostFilter::new(vec!["localhost".into()], vec!["8.8.4.4".into()]);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        // The name passes; what it resolves to at connect time decides.
+        assert!(filter.check_host("rebind.example").is_ok());
+        assert!(filter.check_addr("rebind.example", ip("127.0.0.1")).is_err());
+        assert!(filter.check_addr("rebind.example", ip("93.184.216.34")).is_ok());
+        assert!(filter.check_addr("rebind.example", ip("8.8.4.4")).is_err());
+        assert!(filter.check_addr("localhost", ip("127.0.0.1")).is_ok());
+    }
+}
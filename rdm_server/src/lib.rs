@@ -1,5 +1,12 @@
+#[cfg(all(feature = "network-watch", target_os = "linux"))]
+pub mod network_watch;
+pub mod dashboard;
+pub mod file_server;
+pub mod host_filter;
 pub mod path_sanitizer;
+pub mod payload;
 pub mod server;
 pub mod sse_observer;
 pub mod types;
+pub mod url_key;
 pub mod video_tracker;
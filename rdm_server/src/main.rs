@@ -76,17 +76,151 @@ async fn main() {
     let host = args.host.unwrap_or(std::env::var("RDM_HOST").unwrap_or("127.0.0.1".to_string())) ;
     let port = args.port.unwrap_or(std::env::var("RDM_PORT").unwrap_or("8597".to_string()));
     let connections = args.connections.unwrap_or(std::env::var("RDM_CONN_SIZE").unwrap_or("8".to_string()).parse().unwrap());
-    let addr = format!("{}:{}", host, port);
 
     let state = AppState::with_connections(connections);
+    #[cfg(all(feature = "network-watch", target_os = "linux"))]
+    rdm_server::network_watch::spawn(std::sync::Arc::clone(&state));
     let app = rdm_server::server::router(state);
 
-    let listener = tokio::net::TcpListener::bind(&addr)
-        .await
-        .expect("failed to bind address");
+    #[cfg(unix)]
+    if let Ok(socket_path) = std::env::var("RDM_SOCKET") {
+        serve_unix(app, std::path::PathBuf::from(socket_path)).await;
+        return;
+    }
+
+    let listener = match bind_tcp(&host, &port).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let addr = listener.local_addr().expect("bound listener has an address");
 
     log::info!("rdmd listening on http://{}  (set RDM_PORT to override)", addr);
     axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
         .await
         .expect("server error");
 }
+
+/// Resolve `host` (an IPv4/IPv6 literal, optionally bracketed, or a hostname)
+/// and bind the first address that accepts a listener.
+async fn bind_tcp(host: &str, port: &str) -> Result<tokio::net::TcpListener, String> {
+    let port: u16 = port
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid port {:?}", port))?;
+    let host = host.trim().trim_start_matches('[').trim_end_matches(']');
+
+    let candidates: Vec<std::net::SocketAddr> = match host.parse::<std::net::IpAddr>() {
+        Ok(ip) => vec![std::net::SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("could not resolve {:?}: {}", host, e))?
+            .collect(),
+    };
+
+    let mut errors = Vec::new();
+    for addr in &candidates {
+        match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => {
+                log::info!("bound {} (resolved from {:?})", addr, host);
+                return Ok(listener);
+            }
+            Err(e) => {
+                log::warn!("bind {} failed: {}", addr, e);
+                errors.push(format!("{}: {}", addr, e));
+            }
+        }
+    }
+
+    Err(format!(
+        "failed to bind {:?} port {} on any address [{}]",
+        host,
+        port,
+        errors.join("; ")
+    ))
+}
+
+/// Serve on a Unix domain socket instead of TCP (`RDM_SOCKET`).
+///
+/// The socket is created owner-only (0600) so only the current user's
+/// processes can reach rdmd, and removed again on shutdown.
+#[cfg(unix)]
+async fn serve_unix(app: axum::Router, socket_path: std::path::PathBuf) {
+    use std::os::unix::fs::PermissionsExt;
+
+    // A stale socket from an unclean exit would make bind() fail.
+    if socket_path.exists() {
+        let _ = std::fs::remove_file(&socket_path);
+    }
+
+    let listener = tokio::net::UnixListener::bind(&socket_path)
+        .expect("failed to bind unix socket");
+    if let Err(e) = std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600)) {
+        log::warn!("could not restrict permissions on {:?}: {}", socket_path, e);
+    }
+
+    log::info!("rdmd listening on unix:{}  (unset RDM_SOCKET for TCP)", socket_path.display());
+    let result = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await;
+
+    let _ = std::fs::remove_file(&socket_path);
+    result.expect("server error");
+}
+
+/// Resolves on Ctrl-C (and SIGTERM on Unix) so the server can shut down cleanly.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    log::info!("rdmd shutting down");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn binds_ipv4_ipv6_and_hostnames() {
+        for (host, ip) in [("127.0.0.1", "127.0.0.1"), ("::1", "::1"), ("[::1]", "::1"), (" 127.0.0.1 ", "127.0.0.1")] {
+            let listener = bind_tcp(host, "0").await.unwrap_or_else(|e| panic!("{host}: {e}"));
+            assert_eq!(listener.local_addr().unwrap().ip().to_string(), ip, "{host}");
+        }
+        let listener = bind_tcp("localhost", "0").await.unwrap();
+        assert!(listener.local_addr().unwrap().ip().is_loopback());
+    }
+
+    #[tokio::test]
+    async fn binding_errors_name_the_host() {
+        let err = bind_tcp("127.0.0.1", "http").await.unwrap_err();
+        assert!(err.contains("invalid port"), "{err}");
+        let err = bind_tcp("no-such-host.invalid", "0").await.unwrap_err();
+        assert!(err.contains("could not resolve \"no-such-host.invalid\""), "{err}");
+
+        // Taken by another listener on every candidate.
+        let taken = bind_tcp("127.0.0.1", "0").await.unwrap();
+        let port = taken.local_addr().unwrap().port().to_string();
+        let err = bind_tcp("127.0.0.1", &port).await.unwrap_err();
+        assert!(err.starts_with(&format!("failed to bind \"127.0.0.1\" port {}", port)), "{err}");
+    }
+}
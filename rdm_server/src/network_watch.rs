@@ -0,0 +1,76 @@
+//! Metered-network watcher (cargo feature `network-watch`, Linux only).
+//!
+//! Polls NetworkManager's global `Metered` property over D-Bus (through
+//! `busctl`) and calls [`AppState::pause_all`] when the connection turns
+//! metered, then [`AppState::resume_all`] once it no longer is. While
+//! NetworkManager cannot be reached the watcher logs once and keeps
+//! polling, leaving downloads as they are; `POST /pause-all` and
+//! `POST /resume-all` remain available either way.
+
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::server::AppState;
+
+/// How often the metered state is re-read.
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Start the watcher as a background task.
+pub fn spawn(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut was_metered = false;
+        let mut reachable = true;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let metered = match tokio::task::spawn_blocking(read_metered).await {
+                Ok(Some(metered)) => metered,
+                _ => {
+                    if reachable {
+                        log::warn!("[network-watch] NetworkManager not reachable; retrying every {:?}", POLL_INTERVAL);
+                        reachable = false;
+                    }
+                    continue;
+                }
+            };
+            if !reachable {
+                log::info!("[network-watch] NetworkManager reachable again");
+                reachable = true;
+            }
+            if metered && !was_metered {
+                log::info!("[network-watch] connection is metered, pausing downloads");
+                state.pause_all().await;
+            } else if !metered && was_metered {
+                log::info!("[network-watch] connection no longer metered, resuming downloads");
+                state.resume_all().await;
+            }
+            was_metered = metered;
+        }
+    });
+}
+
+/// Read `org.freedesktop.NetworkManager.Metered`. Its value is an `NMMetered`
+/// enum: 1 (yes) and 3 (guessed yes) count as metered.
+fn read_metered() -> Option<bool> {
+    let output = Command::new("busctl")
+        .args([
+            "get-property",
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            "org.freedesktop.NetworkManager",
+            "Metered",
+        ])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Output looks like `u 4`.
+    let value: u32 = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .last()?
+        .parse()
+        .ok()?;
+    Some(matches!(value, 1 | 3))
+}
@@ -0,0 +1,157 @@
+//! `ValidatedJson` — a drop-in for axum's `Json` extractor on extension
+//! payloads.
+//!
+//! axum rejects a body that fails to deserialize with a bare `422`, which
+//! makes extension bugs hard to track down. This extractor logs the raw body
+//! and answers `400` with a JSON description of what was wrong, a body not
+//! sent as `application/json` included:
+//!
+//! ```json
+//! { "error": "missing field `url`", "field": "url", "expected": "required field" }
+//! ```
+
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Longest slice of a rejected body that gets logged.
+const MAX_LOGGED_BODY: usize = 2048;
+
+/// Deserialized JSON request body; see the module docs.
+pub struct ValidatedJson<T>(pub T);
+
+/// `400 Bad Request` body returned for a malformed payload.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct PayloadRejection {
+    pub error: String,
+    /// Dotted path of the offending field, when one can be pinned down.
+    pub field: Option<String>,
+    /// What the field should have been (`"required field"`, `"a string"`, …).
+    pub expected: Option<String>,
+}
+
+impl IntoResponse for PayloadRejection {
+    fn into_response(self) -> Response {
+        (StatusCode::BAD_REQUEST, Json(self)).into_response()
+    }
+}
+
+impl<T, S> FromRequest<S> for ValidatedJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = PayloadRejection;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let path = req.uri().path().to_string();
+        let content_type = req.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default();
+        if !is_json_content_type(content_type) {
+            log::warn!("[{}] payload sent as {:?}, not JSON", path, content_type);
+            return Err(PayloadRejection {
+                error: format!("expected a JSON body, got content type {:?}", content_type),
+                field: None,
+                expected: Some("application/json".to_string()),
+            });
+        }
+        let body = Bytes::from_request(req, state).await.map_err(|e| PayloadRejection {
+            error: e.body_text(),
+            field: None,
+            expected: None,
+        })?;
+
+        parse_payload(&body).map(ValidatedJson).inspect_err(|rejection| {
+            let raw = String::from_utf8_lossy(&body[..body.len().min(MAX_LOGGED_BODY)]);
+            log::warn!("[{}] malformed payload: {} — body: {}", path, rejection.error, raw);
+        })
+    }
+}
+
+/// `application/json` or an `application/*+json` type, parameters aside.
+fn is_json_content_type(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    essence == "application/json" || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+/// Deserialize `body`, describing the first problem on failure.
+pub fn parse_payload<T: DeserializeOwned>(body: &[u8]) -> Result<T, PayloadRejection> {
+    let de = &mut serde_json::Deserializer::from_slice(body);
+    serde_path_to_error::deserialize(de).map_err(describe)
+}
+
+fn describe(err: serde_path_to_error::Error<serde_json::Error>) -> PayloadRejection {
+    let path = err.path().to_string();
+    let inner = err.into_inner();
+    let message = inner.to_string();
+    // serde_json appends " at line X column Y"; the position adds nothing here.
+    let error = match message.rfind(" at line ") {
+        Some(i) => message[..i].to_string(),
+        None => message,
+    };
+
+    if !inner.is_data() {
+        return PayloadRejection { error, field: None, expected: Some("valid JSON".to_string()) };
+    }
+
+    // A missing field is reported against its parent, so append its name.
+    if let Some(name) = error
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next())
+    {
+        let field = if path == "." { name.to_string() } else { format!("{}.{}", path, name) };
+        return PayloadRejection {
+            field: Some(field),
+            expected: Some("required field".to_string()),
+            error,
+        };
+    }
+
+    let expected = error.split_once(", expected ").map(|(_, e)| e.to_string());
+    let field = (path != ".").then_some(path);
+    PayloadRejection { error, field, expected }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    struct Payload {
+        id: String,
+        #[serde(default)]
+        count: u32,
+    }
+
+    #[test]
+    fn describes_missing_and_mistyped_fields() {
+        let missing = parse_payload::<Payload>(br#"{"count": 1}"#).unwrap_err();
+        assert_eq!(missing.field.as_deref(), Some("id"));
+        assert_eq!(missing.expected.as_deref(), Some("required field"));
+
+        let mistyped = parse_payload::<Payload>(br#"{"id": "a", "count": "x"}"#).unwrap_err();
+        assert_eq!(mistyped.field.as_deref(), Some("count"));
+        assert_eq!(mistyped.expected.as_deref(), Some("u32"));
+
+        let garbage = parse_payload::<Payload>(b"{not json").unwrap_err();
+        assert_eq!(garbage.field, None);
+        assert_eq!(garbage.expected.as_deref(), Some("valid JSON"));
+
+        assert!(parse_payload::<Payload>(br#"{"id": "a"}"#).is_ok());
+    }
+
+    #[test]
+    fn accepts_only_json_content_types() {
+        for accepted in ["application/json", "Application/JSON; charset=utf-8", "application/vnd.rdm+json"] {
+            assert!(is_json_content_type(accepted), "{accepted}");
+        }
+        for rejected in ["", "text/plain", "application/x-www-form-urlencoded", "text/json+x"] {
+            assert!(!is_json_content_type(rejected), "{rejected}");
+        }
+    }
+}
@@ -3,26 +3,43 @@ use std::convert::Infallible;
 use std::io::Write;
 use std::path::PathBuf;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use axum::extract::{Path, State};
-use axum::http::{Method, StatusCode};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
 use axum::response::sse::{Event, Sse};
+use axum::response::Response;
 use axum::routing::{delete, get, post};
 use axum::{Json, Router};
 use tokio::sync::{watch, Mutex as TokioMutex, RwLock};
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
+use rdm_core::downloader::dash_manifest::is_dash_manifest;
 use rdm_core::downloader::http_downloader::HttpDownloader;
+use rdm_core::downloader::naming::{naming_strategy, NamingStrategy, SafeNamingStrategy};
+use rdm_core::downloader::rate_limiter::{parse_rate, SharedRateLimiter};
+use rdm_core::downloader::strategy::common_options::CommonOptions;
+use rdm_core::downloader::strategy::dash_download_strategy::DashDownloadStrategy;
+use rdm_core::downloader::strategy::download_strategy::DownloadStrategy;
 use rdm_core::downloader::strategy::multipart_download_strategy::MultipartDownloadStrategy;
+use rdm_core::progress::file_observer::FileProgressObserver;
+use rdm_core::progress::log_observer::{prune_logs, DownloadLogObserver};
 use rdm_core::progress::snapshot::ProgressSnapshot;
-use crate::path_sanitizer::safe_output_path;
+use rdm_core::types::types::{ChecksumAlgo, DownloadError, DownloadSummary, ProbeHints, ProbeResult, Segment, StreamType};
+use crate::dashboard::{dashboard_from_env, dashboard_handler};
+use crate::file_server;
+use crate::path_sanitizer::{download_dir, prepare_output_path, validate_output_path};
+use crate::payload::ValidatedJson;
 use crate::sse_observer::SseProgressObserver;
 use crate::types::{
-    DownloadRequest, DownloadResponse, MediaData, SyncConfig, TabUpdateData,
-    VideoListItem, VidRequest,
+    ConnectionsRequest, DownloadListItem, DownloadListResponse, DownloadRequest, DownloadResponse,
+    DownloadsQuery, EnabledRequest, HeadersRequest, HealthResponse, MediaData, RenameRequest, SyncConfig,
+    TabUpdateData, VideoListItem, VidRequest,
 };
+use crate::host_filter::HostFilter;
+use crate::url_key::{self, volatile_params_from_env};
 use crate::video_tracker::VideoTracker;
 
 // ---------------------------------------------------------------------------
@@ -30,10 +47,14 @@ use crate::video_tracker::VideoTracker;
 // ---------------------------------------------------------------------------
 
 /// Status of an active or completed download.
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DownloadStatus {
+    /// Registered but the download task has not started yet.
+    Queued,
     Running,
+    /// Held by `pause_all()`; continues on `resume_all()`.
+    Paused,
     Complete,
     Failed,
     Cancelled,
@@ -47,9 +68,26 @@ pub struct ActiveDownload {
     /// Tokio Mutex because `HttpDownloader::download()` takes `&mut self`
     /// and must be awaited — `tokio::sync::Mutex` is `Send` across `.await`.
     pub downloader:  Arc<TokioMutex<HttpDownloader>>,
+    /// The downloader's strategy, for inspecting segments without waiting
+    /// on `downloader`'s lock (held for the whole download).
+    pub strategy:    Arc<dyn DownloadStrategy>,
     pub status:      DownloadStatus,
     /// Receiver for the latest `ProgressSnapshot`; clone to subscribe from SSE handlers.
     pub progress_rx: watch::Receiver<ProgressSnapshot>,
+    /// Publishes to `progress_rx` for the current attempt; a retry replaces
+    /// it with its `next_attempt()` so SSE clients keep their subscription.
+    pub progress_observer: SseProgressObserver,
+    /// Open GET /progress streams of this download; see `SubscriberGuard`.
+    pub subscribers: Arc<AtomicUsize>,
+    /// Set once the download completes successfully.
+    pub summary:     Option<DownloadSummary>,
+    /// The request the download was started from, for rebuilding it on retry.
+    pub source:      VideoListItem,
+    /// When the download was registered, in seconds since the Unix epoch.
+    pub created_at:  u64,
+    /// Limit on the whole run, from `DownloadRequest::deadline_secs`; a
+    /// retry gets the same limit.
+    pub deadline:    Option<Duration>,
 }
 
 // ---------------------------------------------------------------------------
@@ -63,23 +101,336 @@ pub struct AppState {
     pub downloads: Arc<RwLock<HashMap<String, ActiveDownload>>>,
 
     pub connections: usize,
+
+    /// Global monitoring kill-switch, reported to the extension as
+    /// `SyncConfig.enabled`. Toggled via POST /enabled.
+    pub enabled: AtomicBool,
+
+    /// When the server started; reported as uptime by GET /health.
+    pub started_at: Instant,
+
+    /// Speed cap split fairly between running downloads, from
+    /// `RDM_GLOBAL_MAX_SPEED`. `None` means unlimited.
+    pub limiter: Option<SharedRateLimiter>,
+
+    /// Digest reported in each download's summary, from `RDM_CHECKSUM`.
+    pub checksum: Option<ChecksumAlgo>,
+
+    /// Gap between SSE keep-alive comments on idle progress streams, from
+    /// `RDM_SSE_KEEPALIVE_SECS`.
+    pub sse_keepalive: Duration,
+
+    /// Most GET /progress streams one download may have open at once, from
+    /// `RDM_MAX_SSE_SUBSCRIBERS`.
+    pub max_sse_subscribers: usize,
+
+    /// Query parameters ignored when deriving a detected video's id, from
+    /// `RDM_VOLATILE_QUERY_PARAMS`.
+    pub volatile_params: Vec<String>,
+
+    /// Which hosts downloads may be fetched from, from `RDM_ALLOWED_HOSTS`
+    /// and `RDM_BLOCKED_HOSTS`.
+    pub host_filter: HostFilter,
+
+    /// Directory each download's progress is mirrored to as `<id>.json`,
+    /// from `RDM_PROGRESS_DIR`. `None` disables the files.
+    pub progress_dir: Option<PathBuf>,
+
+    /// Largest file a download may produce, from `RDM_MAX_FILE_SIZE`.
+    /// `None` means unlimited.
+    pub max_file_size: Option<u64>,
+
+    /// Directory each download logs its lifecycle to as `<id>.log`, from
+    /// `RDM_DOWNLOAD_LOG_DIR`. `None` disables the logs.
+    pub download_log_dir: Option<PathBuf>,
+
+    /// Serve the web dashboard at `/` and `/ui`; on with `RDM_DASHBOARD=1`.
+    pub dashboard: bool,
+
+    /// Names the files rdmd picks a path for itself, from `RDM_NAMING`.
+    pub naming: Box<dyn NamingStrategy>,
+
+    /// Browser origins allowed to read responses, from `RDM_CORS_ORIGINS`.
+    /// Empty means any browser extension's; see [`cors_origin_allowed`].
+    pub cors_origins: Vec<String>,
+}
+
+/// `RDM_CORS_ORIGINS`: comma-separated origins such as
+/// `chrome-extension://<id>`. Unset or empty allows extensions only.
+fn cors_origins_from_env() -> Vec<String> {
+    std::env::var("RDM_CORS_ORIGINS")
+        .map(|v| {
+            v.split(',')
+                .map(|o| o.trim().trim_end_matches('/').to_string())
+                .filter(|o| !o.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Whether a page at `origin` may read rdmd's responses — among them
+/// downloaded files. Only the configured origins, or without any, browser
+/// extensions: a web page the user happens to visit must not be able to.
+fn cors_origin_allowed(origin: &[u8], configured: &[String]) -> bool {
+    let Ok(origin) = std::str::from_utf8(origin) else {
+        return false;
+    };
+    if configured.is_empty() {
+        ["chrome-extension://", "moz-extension://"]
+            .iter()
+            .any(|scheme| origin.strip_prefix(scheme).is_some_and(|id| !id.is_empty()))
+    } else {
+        configured.iter().any(|allowed| allowed == origin)
+    }
+}
+
+/// `RDM_GLOBAL_MAX_SPEED` (bytes/s, `K`/`M`/`G` suffixes accepted) as a
+/// shared limiter. An unparsable value is logged and ignored.
+fn global_limiter_from_env() -> Option<SharedRateLimiter> {
+    let value = std::env::var("RDM_GLOBAL_MAX_SPEED").ok()?;
+    match parse_rate(&value) {
+        Ok(rate) => {
+            log::info!("[limiter] global max speed {} bytes/s shared across downloads", rate);
+            Some(SharedRateLimiter::new(rate))
+        }
+        Err(e) => {
+            log::warn!("[limiter] ignoring RDM_GLOBAL_MAX_SPEED: {}", e);
+            None
+        }
+    }
+}
+
+/// `RDM_MAX_FILE_SIZE` (bytes, `K`/`M`/`G` suffixes accepted). An
+/// unparsable value is logged and ignored.
+fn max_file_size_from_env() -> Option<u64> {
+    let value = std::env::var("RDM_MAX_FILE_SIZE").ok()?;
+    parse_rate(&value)
+        .inspect(|limit| log::info!("[download] refusing files over {} bytes", limit))
+        .inspect_err(|e| log::warn!("[download] ignoring RDM_MAX_FILE_SIZE: {}", e))
+        .ok()
+}
+
+/// `RDM_CHECKSUM` (`md5`, `sha256` or `sha512`). An unknown value is logged and
+/// ignored.
+fn checksum_from_env() -> Option<ChecksumAlgo> {
+    let value = std::env::var("RDM_CHECKSUM").ok()?;
+    value
+        .parse()
+        .inspect_err(|e| log::warn!("[checksum] ignoring RDM_CHECKSUM: {}", e))
+        .ok()
+}
+
+/// `RDM_MAX_TRACKED_VIDEOS` (at least 1), falling back to
+/// `video_tracker::DEFAULT_CAPACITY` when unset or invalid.
+fn tracker_capacity_from_env() -> usize {
+    let Ok(value) = std::env::var("RDM_MAX_TRACKED_VIDEOS") else {
+        return crate::video_tracker::DEFAULT_CAPACITY;
+    };
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            log::warn!("[media] ignoring RDM_MAX_TRACKED_VIDEOS `{}`; expected a count > 0", value);
+            crate::video_tracker::DEFAULT_CAPACITY
+        }
+    }
+}
+
+/// Keep-alive interval used when `RDM_SSE_KEEPALIVE_SECS` is unset.
+const DEFAULT_SSE_KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// `RDM_SSE_KEEPALIVE_SECS` (whole seconds, at least 1). Anything else is
+/// logged and the default is used.
+fn sse_keepalive_from_env() -> Duration {
+    let Ok(value) = std::env::var("RDM_SSE_KEEPALIVE_SECS") else {
+        return DEFAULT_SSE_KEEPALIVE;
+    };
+    match value.trim().parse::<u64>() {
+        Ok(secs) if secs > 0 => Duration::from_secs(secs),
+        _ => {
+            log::warn!("[sse] ignoring RDM_SSE_KEEPALIVE_SECS `{}`; expected whole seconds > 0", value);
+            DEFAULT_SSE_KEEPALIVE
+        }
+    }
+}
+
+/// Subscriber limit used when `RDM_MAX_SSE_SUBSCRIBERS` is unset.
+const DEFAULT_MAX_SSE_SUBSCRIBERS: usize = 32;
+
+/// `RDM_MAX_SSE_SUBSCRIBERS` (a count, at least 1). Anything else is logged
+/// and the default is used.
+fn max_sse_subscribers_from_env() -> usize {
+    let Ok(value) = std::env::var("RDM_MAX_SSE_SUBSCRIBERS") else {
+        return DEFAULT_MAX_SSE_SUBSCRIBERS;
+    };
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            log::warn!("[sse] ignoring RDM_MAX_SSE_SUBSCRIBERS `{}`; expected a count > 0", value);
+            DEFAULT_MAX_SSE_SUBSCRIBERS
+        }
+    }
+}
+
+/// A file name for download `id`: ASCII letters, digits, `-` and `_` are
+/// kept and every other byte becomes `%XX`, so distinct ids never share a
+/// file and none can name a path outside the directory.
+fn file_stem_for_id(id: &str) -> String {
+    id.bytes()
+        .map(|b| match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// `RDM_PROGRESS_DIR`, created if missing. A directory that cannot be
+/// created is logged and progress files are disabled.
+fn progress_dir_from_env() -> Option<PathBuf> {
+    let dir = PathBuf::from(std::env::var_os("RDM_PROGRESS_DIR")?);
+    match std::fs::create_dir_all(&dir) {
+        Ok(()) => {
+            log::info!("[progress] writing progress files to {}", dir.display());
+            Some(dir)
+        }
+        Err(e) => {
+            log::warn!("[progress] ignoring RDM_PROGRESS_DIR {}: {}", dir.display(), e);
+            None
+        }
+    }
+}
+
+/// `RDM_NAMING` (`safe`, `raw` or `template`, which takes its template
+/// from `RDM_OUTPUT_TEMPLATE`), naming files in the download directory. An
+/// invalid choice is logged and `safe` is used.
+fn naming_from_env() -> Box<dyn NamingStrategy> {
+    let dir = download_dir();
+    let Ok(name) = std::env::var("RDM_NAMING") else {
+        return Box::new(SafeNamingStrategy::new(dir));
+    };
+    let template = std::env::var("RDM_OUTPUT_TEMPLATE").ok();
+    naming_strategy(&name, dir.clone(), template.as_deref())
+        .inspect_err(|e| log::warn!("[path] ignoring RDM_NAMING: {}", e))
+        .unwrap_or_else(|_| Box::new(SafeNamingStrategy::new(dir)))
+}
+
+/// Per-download logs older than this are removed when rdmd starts.
+const DOWNLOAD_LOG_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// `RDM_DOWNLOAD_LOG_DIR`, created if missing and cleared of logs older
+/// than a week. A directory that cannot be created is logged and the
+/// per-download logs are disabled.
+fn download_log_dir_from_env() -> Option<PathBuf> {
+    let dir = PathBuf::from(std::env::var_os("RDM_DOWNLOAD_LOG_DIR")?);
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        log::warn!("[download-log] ignoring RDM_DOWNLOAD_LOG_DIR {}: {}", dir.display(), e);
+        return None;
+    }
+    match prune_logs(&dir, DOWNLOAD_LOG_MAX_AGE) {
+        Ok(0) => {}
+        Ok(removed) => log::info!("[download-log] removed {} log(s) older than a week", removed),
+        Err(e) => log::warn!("[download-log] could not prune {}: {}", dir.display(), e),
+    }
+    log::info!("[download-log] writing per-download logs to {}", dir.display());
+    Some(dir)
 }
 
 impl AppState {
     pub fn new() -> Arc<Self> {
-        Arc::new(Self {
-            video_tracker: Arc::new(RwLock::new(VideoTracker::new())),
-            downloads:     Arc::new(RwLock::new(HashMap::new())),
-            connections:   8,
-        })
+        Arc::new(Self::from_env())
     }
 
     pub fn with_connections(connections: usize) -> Arc<Self> {
-        Arc::new(Self {
-            video_tracker: Arc::new(RwLock::new(VideoTracker::new())),
+        Arc::new(Self { connections, ..Self::from_env() })
+    }
+
+    /// The state `new()` wraps: defaults, overridden by `RDM_*` variables.
+    fn from_env() -> Self {
+        Self {
+            video_tracker: Arc::new(RwLock::new(VideoTracker::with_capacity(tracker_capacity_from_env()))),
             downloads:     Arc::new(RwLock::new(HashMap::new())),
-            connections,
-        })
+            connections:   8,
+            enabled:       AtomicBool::new(true),
+            started_at:    Instant::now(),
+            limiter:       global_limiter_from_env(),
+            checksum:      checksum_from_env(),
+            sse_keepalive: sse_keepalive_from_env(),
+            max_sse_subscribers: max_sse_subscribers_from_env(),
+            volatile_params: volatile_params_from_env(),
+            host_filter:   HostFilter::from_env(),
+            progress_dir:  progress_dir_from_env(),
+            max_file_size: max_file_size_from_env(),
+            download_log_dir: download_log_dir_from_env(),
+            dashboard:     dashboard_from_env(),
+            naming:        naming_from_env(),
+            cors_origins:  cors_origins_from_env(),
+        }
+    }
+
+    /// Where the progress file of download `id` lives, if enabled. The id
+    /// comes from the client, so it is encoded into a plain file name (see
+    /// [`file_stem_for_id`]).
+    fn progress_file(&self, id: &str) -> Option<PathBuf> {
+        let dir = self.progress_dir.as_ref()?;
+        Some(dir.join(format!("{}.json", file_stem_for_id(id))))
+    }
+
+    /// Where the log of download `id` lives, if enabled; named like
+    /// `progress_file`.
+    fn download_log_file(&self, id: &str) -> Option<PathBuf> {
+        let dir = self.download_log_dir.as_ref()?;
+        Some(dir.join(format!("{}.log", file_stem_for_id(id))))
+    }
+
+    /// Hold every running download in place. Returns how many were paused.
+    pub async fn pause_all(&self) -> usize {
+        let paused = self.move_all(DownloadStatus::Running, DownloadStatus::Paused).await;
+        log::info!("[pause-all] paused {} download(s)", paused);
+        paused
+    }
+
+    /// Resume every download paused by `pause_all()`. Returns how many resumed.
+    pub async fn resume_all(&self) -> usize {
+        let resumed = self.move_all(DownloadStatus::Paused, DownloadStatus::Running).await;
+        log::info!("[resume-all] resumed {} download(s)", resumed);
+        resumed
+    }
+
+    /// Holds (for `Paused`) or resumes (for `Running`) every download in
+    /// status `from` and records `to` for each that took it. The strategies
+    /// are called without the map locked, so the rest of the server is not
+    /// kept waiting on them.
+    async fn move_all(&self, from: DownloadStatus, to: DownloadStatus) -> usize {
+        let targets: Vec<(String, Arc<dyn DownloadStrategy>)> = self
+            .downloads
+            .read()
+            .await
+            .values()
+            .filter(|dl| dl.status == from)
+            .map(|dl| (dl.id.clone(), Arc::clone(&dl.strategy)))
+            .collect();
+        let mut moved = Vec::new();
+        for (id, strategy) in targets {
+            let result = match to {
+                DownloadStatus::Paused => strategy.hold().await,
+                _ => strategy.resume().await,
+            };
+            match result {
+                Ok(()) => moved.push((id, strategy)),
+                Err(e) => log::warn!("[pause-all] id={} could not move to {:?}: {:?}", id, to, e),
+            }
+        }
+        // A download that was retried or finished meanwhile keeps its status.
+        let mut downloads = self.downloads.write().await;
+        moved
+            .into_iter()
+            .filter(|(id, strategy)| match downloads.get_mut(id) {
+                Some(dl) if dl.status == from && Arc::ptr_eq(&dl.strategy, strategy) => {
+                    dl.status = to;
+                    true
+                }
+                _ => false,
+            })
+            .count()
     }
 }
 
@@ -88,11 +439,21 @@ impl AppState {
 // ---------------------------------------------------------------------------
 
 pub fn router(state: Arc<AppState>) -> Router {
-    // Allow requests from any chrome-extension:// origin (and localhost for dev).
+    // Only the extension (or RDM_CORS_ORIGINS) may read responses from a
+    // browser.
+    let origins = state.cors_origins.clone();
     let cors = CorsLayer::new()
         .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
         .allow_headers(Any)
-        .allow_origin(Any);
+        .allow_origin(AllowOrigin::predicate(move |origin, _| cors_origin_allowed(origin.as_bytes(), &origins)));
+
+    let dashboard = if state.dashboard {
+        Router::new()
+            .route("/",   get(dashboard_handler))
+            .route("/ui", get(dashboard_handler))
+    } else {
+        Router::new()
+    };
 
     Router::new()
         // ── Extension-facing endpoints (XDM-compatible) ─────────────────────
@@ -102,14 +463,29 @@ pub fn router(state: Arc<AppState>) -> Router {
         .route("/tab-update", post(tab_update_handler))
         .route("/vid",        post(vid_handler))
         .route("/clear",      post(clear_handler))
+        .route("/enabled",    post(enabled_handler))
         // ── Internal / REST endpoints ────────────────────────────────────────
         .route("/status/{id}",   get(status_handler))
         .route("/progress/{id}", get(progress_handler))
         .route("/cancel/{id}",   post(cancel_handler))
+        .route("/pause-all",     post(pause_all_handler))
+        .route("/resume-all",    post(resume_all_handler))
+        .route("/downloads",             get(downloads_handler))
+        .route("/downloads/{id}/rename", post(rename_handler))
+        .route("/downloads/{id}/retry",  post(retry_handler))
+        .route("/downloads/{id}/connections", post(connections_handler))
+        .route("/downloads/{id}/headers",     post(headers_handler))
+        .route("/downloads/{id}/segments", get(segments_handler))
+        .route("/downloads/{id}/file",     get(file_handler))
         .route("/videos",      get(videos_handler))
+        .route("/videos/clear-idle", post(clear_idle_handler))
+        .route("/videos/{id}", get(get_video_handler))
         .route("/videos/{id}", post(add_video_handler))
         .route("/videos/{id}", delete(remove_video_handler))
-        .route("/echo/{msg}",get(echo_handler))
+        .route("/health",      get(health_handler))
+        .route("/echo/{msg}",  get(echo_handler))
+        // ── Web dashboard ────────────────────────────────────────────────────
+        .merge(dashboard)
         .layer(cors)
         .with_state(state)
 }
@@ -120,7 +496,9 @@ pub fn router(state: Arc<AppState>) -> Router {
 
 async fn sync_config(state: &Arc<AppState>) -> SyncConfig {
     let tracker = state.video_tracker.read().await;
-    SyncConfig::default_with_videos(tracker.get_list())
+    let mut config = SyncConfig::default_with_videos(tracker.get_list());
+    config.enabled = state.enabled.load(Ordering::Relaxed);
+    config
 }
 
 // ---------------------------------------------------------------------------
@@ -140,7 +518,7 @@ async fn sync_handler(State(state): State<Arc<AppState>>) -> Json<SyncConfig> {
 /// Logs the video to the console and stores it in the VideoTracker.
 async fn media_handler(
     State(state): State<Arc<AppState>>,
-    Json(data): Json<MediaData>,
+    ValidatedJson(data): ValidatedJson<MediaData>,
 ) -> Json<SyncConfig> {
     // Derive a human-readable title: prefer tab title, fall back to URL.
     let title = data
@@ -170,8 +548,9 @@ async fn media_handler(
         data.tab_url.as_deref().unwrap_or("-"),
     );
 
-    // Build a VideoListItem and store it.
-    let id = uuid_from_url(&data.url);
+    // Build a VideoListItem and store it. Re-requests of the same media
+    // that differ only by cache-busting params map to the existing entry.
+    let id = url_key::video_id(&data.url, &state.volatile_params);
 
     // Extract Referer from request headers if present.
     let referer = data.request_headers
@@ -195,11 +574,13 @@ async fn media_handler(
         user_agent:       data.user_agent.clone(),
         tab_url:          data.tab_url.clone(),
         referer,
+        capture_range:    data.capture_range,
     };
 
+    let active = active_download_ids(&state).await;
     {
         let mut tracker = state.video_tracker.write().await;
-        tracker.add_or_update(item);
+        tracker.add_or_update(item, |id| active.contains(id));
     }
 
     let list = {
@@ -224,8 +605,8 @@ async fn media_handler(
 /// to GET /progress/{id} for real-time progress updates.
 async fn download_handler(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<DownloadRequest>,
-) -> Json<DownloadResponse> {
+    ValidatedJson(req): ValidatedJson<DownloadRequest>,
+) -> Result<Json<DownloadResponse>, (StatusCode, String)> {
     log::info!(
         "[download] id=\"{}\"  url=\"{}\"  title=\"{}\"  output_path=\"{}\"",
         req.id,
@@ -234,7 +615,39 @@ async fn download_handler(
         req.output_path,
     );
 
+    if req.deadline_secs == Some(0) {
+        log::warn!("[download] rejected id={}: zero deadline", req.id);
+        return Err((StatusCode::BAD_REQUEST, "deadlineSecs must be at least 1".to_string()));
+    }
+
+    if let Err(reason) = state.host_filter.check(&req.url).await {
+        log::warn!("[download] refused id={}: {}", req.id, reason);
+        return Err((StatusCode::FORBIDDEN, reason));
+    }
+
+    let output_path = if req.output_path.trim().is_empty() {
+        let probe = hinted_probe(&req.url, req.content_type.clone(), req.attachment_name.clone());
+        let path = state.naming.resolve(&probe, &req.url, &req.title);
+        log::info!("[download] id={} named {:?}", req.id, path);
+        path.to_string_lossy().into_owned()
+    } else {
+        req.output_path
+    };
+
+    // Create a missing directory now: finding out in postprocess would throw
+    // away the whole transfer.
+    if let Err(reason) = prepare_output_path(&output_path) {
+        log::warn!("[download] rejected id={}: {}", req.id, reason);
+        return Err((StatusCode::BAD_REQUEST, reason));
+    }
+
     let id = req.id.clone();
+    let hints = ProbeHints {
+        file_size:       req.file_size,
+        resumable:       req.resumable,
+        content_type:    req.content_type,
+        attachment_name: req.attachment_name,
+    };
 
     // Build a VideoListItem from the DownloadRequest so we can reuse spawn_download.
     let item = VideoListItem {
@@ -250,21 +663,23 @@ async fn download_handler(
         user_agent:       req.user_agent,
         tab_url:          None,
         referer:          req.referer,
+        capture_range:    req.capture_range,
     };
 
-    spawn_download_to_path(item, req.output_path, Arc::clone(&state));
+    let deadline = req.deadline_secs.map(Duration::from_secs);
+    spawn_download_to_path(item, output_path, Some(hints), deadline, Arc::clone(&state));
 
-    Json(DownloadResponse {
+    Ok(Json(DownloadResponse {
         id,
         status: "queued".to_string(),
-    })
+    }))
 }
 
 /// POST /tab-update
 /// Tab title changed on a watched URL — update matching video entries.
 async fn tab_update_handler(
     State(state): State<Arc<AppState>>,
-    Json(data): Json<TabUpdateData>,
+    ValidatedJson(data): ValidatedJson<TabUpdateData>,
 ) -> Json<SyncConfig> {
     log::debug!(
         "[tab-update] tab_url=\"{}\"  title=\"{}\"",
@@ -285,7 +700,7 @@ async fn tab_update_handler(
 /// user can choose a save location before the download starts.
 async fn vid_handler(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<VidRequest>,
+    ValidatedJson(req): ValidatedJson<VidRequest>,
 ) -> Json<SyncConfig> {
     let result = {
         let tracker = state.video_tracker.read().await;
@@ -391,112 +806,268 @@ fn find_ui_binary() -> PathBuf {
 /// Spawn a download task for the given `VideoListItem`, saving to `output_path`.
 /// The task runs in the background; the server response is not blocked.
 /// The `state` is used to register and update the download's status.
-fn spawn_download_to_path(item: VideoListItem, output_path_str: String, state: Arc<AppState>) {
+/// `hints` are what the client already knows about the file; see
+/// `MultipartDownloadStrategyBuilder::with_probe_hints`. `deadline` limits
+/// the whole run; see `HttpDownloader::set_deadline`.
+fn spawn_download_to_path(
+    item: VideoListItem,
+    output_path_str: String,
+    hints: Option<ProbeHints>,
+    deadline: Option<Duration>,
+    state: Arc<AppState>,
+) {
     let output_path = PathBuf::from(&output_path_str);
     log::info!("[download] output_path={:?}", output_path);
 
-    // Convert request headers: HashMap<String, serde_json::Value (array)>
-    // → HashMap<String, Vec<String>> as expected by the builder.
-    let req_headers = json_headers_to_vec(&item.request_headers);
+    let strategy = build_strategy(&item, &output_path, hints, &state);
+    let mut downloader = HttpDownloader::new(Arc::clone(&strategy));
+    if let Some(deadline) = deadline {
+        downloader.set_deadline(deadline);
+    }
 
-    // Build the strategy via the builder.
-    let builder = MultipartDownloadStrategy::builder(item.url.clone(), output_path.clone())
-        .with_headers(req_headers)
-        .with_connection_size(state.connections);
+    // Create the SSE observer and register it with the downloader.
+    let (sse_observer, progress_watch_rx) = SseProgressObserver::new(item.id.clone());
+    downloader.add_observer(Box::new(sse_observer.clone()));
+    if let Some(path) = state.progress_file(&item.id) {
+        downloader.add_observer(Box::new(FileProgressObserver::new(path).with_download_id(item.id.clone())));
+    }
+    if let Some(path) = state.download_log_file(&item.id) {
+        downloader.add_observer(Box::new(DownloadLogObserver::new(path).with_strategy(Arc::clone(&strategy))));
+    }
 
-    // Set cookies if present.
-    let builder = if !item.cookie.is_empty() {
-        builder.with_cookies(item.cookie.clone())
-    } else {
-        builder
+    let download_id = item.id.clone();
+    let dl = ActiveDownload {
+        id:          download_id.clone(),
+        url:         item.url.clone(),
+        output_path,
+        downloader:  Arc::new(TokioMutex::new(downloader)),
+        strategy,
+        status:      DownloadStatus::Queued,
+        progress_rx: progress_watch_rx,
+        progress_observer: sse_observer,
+        subscribers: Arc::new(AtomicUsize::new(0)),
+        summary:     None,
+        source:      item,
+        created_at:  unix_now(),
+        deadline,
     };
 
-    // Inject User-Agent as an explicit header if provided and not already set.
-    let builder = if let Some(ua) = &item.user_agent {
-        builder.add_header("User-Agent", ua.clone())
-    } else {
-        builder
-    };
+    // Register the download in the shared map, then run it; a single task so
+    // the entry is always in place when the run looks it up.
+    tokio::spawn(async move {
+        state.downloads.write().await.insert(download_id.clone(), dl);
+        run_download(download_id, state).await;
+    });
+}
 
-    // Inject Referer as an explicit header if provided and not already set.
-    let builder = if let Some(referer) = &item.referer {
-        builder.add_header("Referer", referer.clone())
-    } else {
-        builder
-    };
+/// The Content-Type prefix a download detected as `info` must keep:
+/// `video/` or `audio/` for media, `None` for anything else.
+fn expected_media_prefix(info: &str) -> Option<&'static str> {
+    let mime = info.trim().to_ascii_lowercase();
+    ["video/", "audio/"].into_iter().find(|prefix| mime.starts_with(prefix))
+}
 
-    let strategy = builder.build();
-    let mut downloader = HttpDownloader::new(Arc::new(strategy));
+/// Build the strategy for `item` — DASH for manifests, multipart otherwise.
+/// `hints` only apply to the latter.
+fn build_strategy(
+    item: &VideoListItem,
+    output_path: &std::path::Path,
+    hints: Option<ProbeHints>,
+    state: &AppState,
+) -> Arc<dyn DownloadStrategy> {
+    let req_headers = forwarded_headers(item);
+
+    // DASH manifests get their own strategy; everything else is a plain
+    // (possibly multi-connection) HTTP download.
+    if is_dash_manifest(&item.url, Some(&item.info)) {
+        log::info!("[download] detected DASH manifest, url={}", item.url);
+        let mut builder = DashDownloadStrategy::builder(item.url.clone(), output_path.to_path_buf())
+            .with_headers(req_headers)
+            .with_connection_size(state.connections)
+            .with_connect_guard(Arc::new(state.host_filter.clone()));
+        if !item.cookie.is_empty() {
+            builder = builder.with_cookies(item.cookie.clone());
+        }
+        if let Some(limiter) = &state.limiter {
+            builder = builder.with_shared_limiter(limiter.clone());
+        }
+        if let Some(algo) = state.checksum {
+            builder = builder.with_compute_checksum(algo);
+        }
+        if let Some(limit) = state.max_file_size {
+            builder = builder.with_max_file_size(limit);
+        }
+        if let Some((start, end)) = item.capture_range {
+            log::warn!("[download] ignoring captured range {}-{} of a DASH manifest", start, end);
+        }
+        Arc::new(builder.build())
+    } else {
+        // Build the strategy via the builder.
+        let builder = MultipartDownloadStrategy::builder(item.url.clone(), output_path.to_path_buf())
+            .with_headers(req_headers)
+            .with_connection_size(state.connections)
+            .with_connect_guard(Arc::new(state.host_filter.clone()));
+
+        // Set cookies if present.
+        let builder = if !item.cookie.is_empty() {
+            builder.with_cookies(item.cookie.clone())
+        } else {
+            builder
+        };
 
-    // Create the SSE observer and register it with the downloader.
-    let (sse_observer, progress_watch_rx) = SseProgressObserver::new();
-    downloader.add_observer(Box::new(sse_observer));
+        // Share the global speed cap, if any, with the other downloads.
+        let builder = if let Some(limiter) = &state.limiter {
+            builder.with_shared_limiter(limiter.clone())
+        } else {
+            builder
+        };
 
-    // Register the download in the shared map before spawning.
-    let download_id = item.id.clone();
-    let download_url = item.url.clone();
-    {
-        let state_clone = Arc::clone(&state);
-        let dl = ActiveDownload {
-            id:          download_id.clone(),
-            url:         download_url.clone(),
-            output_path: output_path.clone(),
-            downloader:  Arc::new(TokioMutex::new(downloader)),
-            status:      DownloadStatus::Running,
-            progress_rx: progress_watch_rx,
+        let builder = if let Some(algo) = state.checksum {
+            builder.with_compute_checksum(algo)
+        } else {
+            builder
         };
-        tokio::spawn(async move {
-            state_clone.downloads.write().await.insert(dl.id.clone(), dl);
+
+        // Replay the captured method; a non-GET one downloads as a single
+        // non-resumable segment.
+        let method = item.method.as_deref().and_then(|m| {
+            Method::from_bytes(m.trim().to_ascii_uppercase().as_bytes())
+                .inspect_err(|_| log::warn!("[download] ignoring unknown method {:?}", m))
+                .ok()
         });
-    }
+        let builder = match method {
+            Some(method) => builder.with_method(method),
+            None => builder,
+        };
 
-    // Spawn the download task.
-    let state_for_done = Arc::clone(&state);
-    let id_for_done    = download_id.clone();
-    let url_for_log    = download_url.clone();
-    tokio::spawn(async move {
-        // Obtain an exclusive handle to the downloader from the shared map.
-        let downloader_arc = {
-            state_for_done
-                .downloads
-                .read()
-                .await
-                .get(&id_for_done)
-                .map(|dl| Arc::clone(&dl.downloader))
+        let builder = match hints {
+            Some(hints) => builder.with_probe_hints(hints),
+            None => builder,
         };
 
-        let Some(downloader_arc) = downloader_arc else {
-            log::error!("[download] download entry missing for id={}", id_for_done);
-            return;
+        let builder = match state.max_file_size {
+            Some(limit) => builder.with_max_file_size(limit),
+            None => builder,
         };
 
-        let result = downloader_arc.lock().await.download().await;
-        let new_status = match &result {
-            Ok(()) => {
-                log::info!("[download] complete  url=\"{}\"  path={:?}", url_for_log, output_path);
-                DownloadStatus::Complete
+        // A capture defined by its range fetches only those bytes.
+        let builder = match item.capture_range {
+            Some((start, end)) => builder.with_byte_range(start, end),
+            None => builder,
+        };
+
+        // Detected as media: an HTML answer now is an error page.
+        let builder = match expected_media_prefix(&item.info) {
+            Some(prefix) => builder.with_expect_content_type(prefix),
+            None => builder,
+        };
+
+        Arc::new(builder.build())
+    }
+}
+
+/// Run the registered download `id` to completion and record the outcome in
+/// its entry.
+async fn run_download(id: String, state: Arc<AppState>) {
+    // Obtain an exclusive handle to the downloader from the shared map and
+    // mark it Running under the same lock, so a concurrent rename sees a
+    // consistent status.
+    let entry = {
+        state
+            .downloads
+            .write()
+            .await
+            .get_mut(&id)
+            .map(|dl| {
+                dl.status = DownloadStatus::Running;
+                (Arc::clone(&dl.downloader), dl.url.clone(), dl.output_path.clone())
+            })
+    };
+
+    let Some((downloader_arc, url, output_path)) = entry else {
+        log::error!("[download] download entry missing for id={}", id);
+        return;
+    };
+
+    let result = downloader_arc.lock().await.download().await;
+    let (new_status, summary) = match result {
+        Ok(summary) => {
+            log::info!(
+                "[download] complete  url=\"{}\"  path={:?}  bytes={}  duration={:.2}s  segments={}",
+                url, summary.path, summary.bytes, summary.duration.as_secs_f64(), summary.segments,
+            );
+            // Nothing left to monitor; a failed download keeps its file so
+            // the error can still be read.
+            if let Some(path) = state.progress_file(&id) {
+                let _ = tokio::fs::remove_file(path).await;
             }
-            Err(e) => {
-                log::error!("[download] failed  url=\"{}\"  err={:?}", url_for_log, e);
-                DownloadStatus::Failed
+            (DownloadStatus::Complete, Some(summary))
+        }
+        Err(e) => {
+            log::error!("[download] failed  url=\"{}\"  path={:?}  err={:?}", url, output_path, e);
+            // A cancelled download has no error worth reading.
+            if let (DownloadError::Cancelled, Some(path)) = (&e, state.progress_file(&id)) {
+                let _ = tokio::fs::remove_file(path).await;
             }
-        };
-        if let Some(entry) = state_for_done.downloads.write().await.get_mut(&id_for_done) {
+            (DownloadStatus::Failed, None)
+        }
+    };
+    // A retry may have replaced the downloader meanwhile; its outcome is
+    // not ours to record.
+    if let Some(entry) = state.downloads.write().await.get_mut(&id) {
+        if Arc::ptr_eq(&entry.downloader, &downloader_arc) {
             entry.status = new_status;
+            entry.summary = summary;
         }
-    });
+    }
 }
 
 /// Spawn a download task for the given `VideoListItem`.
-/// Auto-derives the output path from the item title and mime type.
+/// Auto-derives the output path from the item title and mime type with the
+/// configured naming strategy.
 /// Kept for potential future use (e.g. headless mode).
 #[allow(dead_code)]
 fn spawn_download(item: VideoListItem, state: Arc<AppState>) {
-    let mime = if item.info.is_empty() { None } else { Some(item.info.as_str()) };
-    let output_path = safe_output_path(&item.text, &item.url, mime);
+    let probe = hinted_probe(&item.url, (!item.info.is_empty()).then(|| item.info.clone()), None);
+    let output_path = state.naming.resolve(&probe, &item.url, &item.text);
+    if let Some(parent) = output_path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            log::warn!("[path] could not create download dir {:?}: {}", parent, e);
+        }
+    }
     log::info!("[vid] output_path={:?}", output_path);
     let output_path_str = output_path.to_string_lossy().to_string();
-    spawn_download_to_path(item, output_path_str, state);
+    spawn_download_to_path(item, output_path_str, None, None, state);
+}
+
+/// What is known of `url`'s response before it is probed, for the naming
+/// strategy to name a file by.
+fn hinted_probe(url: &str, content_type: Option<String>, attachment_name: Option<String>) -> ProbeResult {
+    ProbeResult {
+        resumable: false,
+        resource_size: None,
+        final_uri: url.to_string(),
+        attachment_name,
+        content_type,
+        last_modified: None,
+        etag: None,
+        set_cookies: Vec::new(),
+        not_modified: false,
+    }
+}
+
+/// The headers a download of `item` sends: the captured request headers
+/// (see `json_headers_to_vec`), with its User-Agent and Referer, when
+/// given, replacing any captured ones.
+fn forwarded_headers(item: &VideoListItem) -> HashMap<String, Vec<String>> {
+    let mut headers = json_headers_to_vec(&item.request_headers);
+    if let Some(ua) = &item.user_agent {
+        headers.insert("User-Agent".to_string(), vec![ua.clone()]);
+    }
+    if let Some(referer) = &item.referer {
+        headers.insert("Referer".to_string(), vec![referer.clone()]);
+    }
+    headers
 }
 
 fn json_headers_to_vec(
@@ -522,7 +1093,8 @@ fn json_headers_to_vec(
             | "accept-encoding"
             // Managed by segment_grabber — rdm sets its own Range header per segment;
             // a browser-captured Range would create a duplicate and cause the
-            // server to return the wrong byte range (or the full file).
+            // server to return the wrong byte range (or the full file). A
+            // range that defines the resource arrives as `capture_range`.
             | "range"
             // Body-related — not relevant for rdm's GET replay
             | "content-length"
@@ -564,6 +1136,41 @@ async fn clear_handler(State(state): State<Arc<AppState>>) -> Json<SyncConfig> {
     Json(sync_config(&state).await)
 }
 
+/// POST /videos/clear-idle
+/// Clear every detected video except those with a queued, running or paused download.
+async fn clear_idle_handler(State(state): State<Arc<AppState>>) -> Json<SyncConfig> {
+    let active = active_download_ids(&state).await;
+    let removed = {
+        let mut tracker = state.video_tracker.write().await;
+        tracker.retain(|item| active.contains(&item.id))
+    };
+    log::info!("[clear-idle] removed {} video(s), kept {} downloading", removed, active.len());
+    Json(sync_config(&state).await)
+}
+
+/// Ids of videos with a queued, running or paused download.
+async fn active_download_ids(state: &AppState) -> std::collections::HashSet<String> {
+    let downloads = state.downloads.read().await;
+    downloads
+        .values()
+        .filter(|dl| matches!(dl.status, DownloadStatus::Queued | DownloadStatus::Running | DownloadStatus::Paused))
+        .map(|dl| dl.id.clone())
+        .collect()
+}
+
+/// POST /enabled
+/// Global kill-switch — pauses or resumes the extension's monitoring.
+/// The extension picks up the new value from `SyncConfig.enabled` on its
+/// next /sync.
+async fn enabled_handler(
+    State(state): State<Arc<AppState>>,
+    ValidatedJson(req): ValidatedJson<EnabledRequest>,
+) -> Json<SyncConfig> {
+    state.enabled.store(req.enabled, Ordering::Relaxed);
+    log::info!("[enabled] monitoring {}", if req.enabled { "enabled" } else { "disabled" });
+    Json(sync_config(&state).await)
+}
+
 // ---------------------------------------------------------------------------
 // Internal REST handlers
 // ---------------------------------------------------------------------------
@@ -580,51 +1187,407 @@ async fn status_handler(
             "url":         dl.url,
             "output_path": dl.output_path.to_string_lossy(),
             "status":      dl.status,
+            "summary":     dl.summary,
         }))
     } else {
         Json(serde_json::json!({ "id": id, "status": "not_found" }))
     }
 }
 
+/// GET /downloads?status=&since=&limit=&offset=
+/// Downloads newest first, optionally only those with `status` and those
+/// created at or after `since` (Unix seconds), paged by `limit`/`offset`.
+/// `total` counts every match, not just the returned page.
+async fn downloads_handler(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DownloadsQuery>,
+) -> Json<DownloadListResponse> {
+    let items: Vec<DownloadListItem> = {
+        let downloads = state.downloads.read().await;
+        downloads
+            .values()
+            .map(|dl| DownloadListItem {
+                id:          dl.id.clone(),
+                url:         dl.url.clone(),
+                output_path: dl.output_path.to_string_lossy().to_string(),
+                status:      dl.status,
+                created_at:  dl.created_at,
+            })
+            .collect()
+    };
+    Json(page_downloads(items, &query))
+}
+
+/// Filter, sort (newest first, then by id) and page a snapshot of the
+/// download list.
+fn page_downloads(mut items: Vec<DownloadListItem>, query: &DownloadsQuery) -> DownloadListResponse {
+    items.retain(|item| {
+        query.status.is_none_or(|status| item.status == status)
+            && query.since.is_none_or(|since| item.created_at >= since)
+    });
+    items.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.id.cmp(&b.id)));
+    let total = items.len();
+    let offset = query.offset.unwrap_or(0);
+    let downloads = items
+        .into_iter()
+        .skip(offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    DownloadListResponse { total, offset, downloads }
+}
+
+/// Seconds since the Unix epoch.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 /// POST /cancel/:id
 async fn cancel_handler(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> Json<serde_json::Value> {
-    let mut downloads = state.downloads.write().await;
-    if let Some(dl) = downloads.get_mut(&id) {
-        match dl.downloader.lock().await.stop().await {
-            Ok(()) => {
+    // Stop through the strategy: `run_download` holds the downloader's lock
+    // for the whole download, and the map lock must not be held across it.
+    let strategy = match state.downloads.read().await.get(&id) {
+        Some(dl) => Arc::clone(&dl.strategy),
+        None => return Json(serde_json::json!({ "id": id, "status": "not_found" })),
+    };
+    match strategy.stop().await {
+        Ok(()) => {
+            if let Some(dl) = state.downloads.write().await.get_mut(&id) {
                 dl.status = DownloadStatus::Cancelled;
-                log::info!("[cancel] id={} cancelled", id);
-                Json(serde_json::json!({ "id": id, "status": "cancelled" }))
             }
-            Err(e) => {
-                log::warn!("[cancel] id={} stop error: {:?}", id, e);
-                Json(serde_json::json!({ "id": id, "status": "error", "detail": format!("{:?}", e) }))
+            log::info!("[cancel] id={} cancelled", id);
+            Json(serde_json::json!({ "id": id, "status": "cancelled" }))
+        }
+        Err(e) => {
+            log::warn!("[cancel] id={} stop error: {:?}", id, e);
+            Json(serde_json::json!({ "id": id, "status": "error", "detail": format!("{:?}", e) }))
+        }
+    }
+}
+
+/// POST /pause-all
+/// Pause every running download, e.g. from a network-change script.
+async fn pause_all_handler(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let paused = state.pause_all().await;
+    Json(serde_json::json!({ "paused": paused }))
+}
+
+/// POST /resume-all
+/// Resume every download paused by /pause-all.
+async fn resume_all_handler(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let resumed = state.resume_all().await;
+    Json(serde_json::json!({ "resumed": resumed }))
+}
+
+/// POST /downloads/:id/retry
+/// Start a `Failed` or `Cancelled` download over with a freshly built
+/// strategy (and so a fresh temp dir — nothing of the old attempt is kept).
+/// The entry keeps its progress channel, so SSE clients still subscribed to
+/// it see the new attempt. Returns 409 for any other status, 404 for unknown
+/// ids and 403 if the host filter now refuses the URL.
+async fn retry_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    // The host may resolve differently now than on the first attempt.
+    let url = {
+        let downloads = state.downloads.read().await;
+        let dl = downloads
+            .get(&id)
+            .ok_or((StatusCode::NOT_FOUND, format!("no download with id {}", id)))?;
+        dl.source.url.clone()
+    };
+    if let Err(reason) = state.host_filter.check(&url).await {
+        log::warn!("[retry] refused id={}: {}", id, reason);
+        return Err((StatusCode::FORBIDDEN, reason));
+    }
+
+    {
+        let mut downloads = state.downloads.write().await;
+        let dl = downloads
+            .get_mut(&id)
+            .ok_or((StatusCode::NOT_FOUND, format!("no download with id {}", id)))?;
+
+        if !matches!(dl.status, DownloadStatus::Failed | DownloadStatus::Cancelled) {
+            return Err((StatusCode::CONFLICT, format!("download {} has not failed or been cancelled", id)));
+        }
+
+        // Probe afresh: the failure may have been a stale hint.
+        let strategy = build_strategy(&dl.source, &dl.output_path, None, &state);
+        let mut downloader = HttpDownloader::new(Arc::clone(&strategy));
+        if let Some(deadline) = dl.deadline {
+            downloader.set_deadline(deadline);
+        }
+        // The cancelled attempt may still be winding down; its events must
+        // not reach the subscribers as this attempt's.
+        dl.progress_observer = dl.progress_observer.next_attempt();
+        downloader.add_observer(Box::new(dl.progress_observer.clone()));
+        if let Some(path) = state.progress_file(&id) {
+            downloader.add_observer(Box::new(FileProgressObserver::new(path).with_download_id(id.clone())));
+        }
+        if let Some(path) = state.download_log_file(&id) {
+            downloader.add_observer(Box::new(DownloadLogObserver::new(path).with_strategy(Arc::clone(&strategy))));
+        }
+
+        dl.downloader = Arc::new(TokioMutex::new(downloader));
+        dl.strategy = strategy;
+        dl.status = DownloadStatus::Queued;
+        dl.summary = None;
+    }
+
+    log::info!("[retry] id={} queued again", id);
+    tokio::spawn(run_download(id.clone(), Arc::clone(&state)));
+
+    Ok(Json(serde_json::json!({ "id": id, "status": "queued" })))
+}
+
+/// POST /downloads/:id/rename
+/// Change the output path of a download that is still queued.
+/// Returns 409 once the download has started, 404 for unknown ids.
+async fn rename_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<RenameRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let new_path = validate_output_path(&req.output_path)
+        .map_err(|reason| (StatusCode::BAD_REQUEST, reason))?;
+
+    let mut downloads = state.downloads.write().await;
+    let dl = downloads
+        .get_mut(&id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no download with id {}", id)))?;
+
+    if !matches!(dl.status, DownloadStatus::Queued) {
+        return Err((StatusCode::CONFLICT, format!("download {} has already started", id)));
+    }
+
+    // The download task holds this lock for the whole download, so failing
+    // to take it also means the download is underway.
+    let renamed = match dl.downloader.try_lock() {
+        Ok(downloader) => downloader.set_output_path(req.output_path.clone()),
+        Err(_) => return Err((StatusCode::CONFLICT, format!("download {} has already started", id))),
+    };
+    renamed.map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+
+    log::info!("[rename] id={} output_path={:?}", id, new_path);
+    dl.output_path = new_path;
+
+    Ok(Json(serde_json::json!({
+        "id":          dl.id,
+        "output_path": dl.output_path.to_string_lossy(),
+        "status":      dl.status,
+    })))
+}
+
+/// POST /downloads/:id/connections
+/// Change how many connections a still-queued download will use. Segments
+/// are planned when the download starts, so this returns 409 once it has;
+/// 404 for unknown ids, 400 for zero.
+async fn connections_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<ConnectionsRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if req.connections == 0 {
+        return Err((StatusCode::BAD_REQUEST, "connections must be at least 1".to_string()));
+    }
+
+    let downloads = state.downloads.read().await;
+    let dl = downloads
+        .get(&id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no download with id {}", id)))?;
+
+    if !matches!(dl.status, DownloadStatus::Queued) {
+        return Err((StatusCode::CONFLICT, format!("download {} has already started", id)));
+    }
+
+    // As in `rename_handler`: the download task holds this lock throughout.
+    let changed = match dl.downloader.try_lock() {
+        Ok(downloader) => downloader.set_connection_size(req.connections),
+        Err(_) => return Err((StatusCode::CONFLICT, format!("download {} has already started", id))),
+    };
+    changed.map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+
+    log::info!("[connections] id={} connections={}", id, req.connections);
+
+    Ok(Json(serde_json::json!({
+        "id":          dl.id,
+        "connections": req.connections,
+        "status":      dl.status,
+    })))
+}
+
+/// POST /downloads/:id/headers
+/// Replace the request headers and/or cookie of a still-queued download,
+/// for when the captured ones expired while it waited; a retry keeps them.
+/// Returns 409 once the download has started (cancel and retry it
+/// instead), 404 for unknown ids, 400 for an empty body.
+async fn headers_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    ValidatedJson(req): ValidatedJson<HeadersRequest>,
+) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    if req.headers.is_none() && req.cookie.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "expected headers or cookie".to_string()));
+    }
+
+    let mut downloads = state.downloads.write().await;
+    let dl = downloads
+        .get_mut(&id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no download with id {}", id)))?;
+
+    if !matches!(dl.status, DownloadStatus::Queued) {
+        return Err((StatusCode::CONFLICT, format!("download {} has already started", id)));
+    }
+
+    let mut source = dl.source.clone();
+    if let Some(headers) = req.headers {
+        source.request_headers = headers;
+    }
+    if let Some(cookie) = req.cookie {
+        source.cookie = cookie;
+    }
+    let headers = forwarded_headers(&source);
+    let cookies = Some(source.cookie.clone()).filter(|c| !c.is_empty());
+
+    // As in `rename_handler`: the download task holds this lock throughout.
+    let updated = match dl.downloader.try_lock() {
+        Ok(downloader) => downloader.set_request_headers(headers.clone(), cookies.clone()),
+        Err(_) => return Err((StatusCode::CONFLICT, format!("download {} has already started", id))),
+    };
+    updated.map_err(|e| (StatusCode::CONFLICT, e.to_string()))?;
+    dl.source = source;
+
+    let mut names: Vec<&String> = headers.keys().collect();
+    names.sort();
+    log::info!("[headers] id={} headers={:?} cookie={}", id, names, cookies.is_some());
+
+    // Header values and cookies may carry credentials, so only their
+    // presence is echoed back.
+    Ok(Json(serde_json::json!({
+        "id":      dl.id,
+        "headers": names,
+        "cookie":  cookies.is_some(),
+        "status":  dl.status,
+    })))
+}
+
+/// GET /downloads/:id/segments
+/// The download's segment plan, sorted by stream and offset. While running,
+/// `downloaded` comes from the latest progress snapshot.
+async fn segments_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Segment>>, (StatusCode, String)> {
+    let (strategy, progress) = {
+        let downloads = state.downloads.read().await;
+        let dl = downloads
+            .get(&id)
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("download {} not found", id)))?;
+        let progress = dl.progress_rx.borrow().clone();
+        (Arc::clone(&dl.strategy), progress)
+    };
+
+    let mut segments = strategy.segments_snapshot().await;
+    for segment in &mut segments {
+        if let Some(live) = progress.segments.iter().find(|p| p.segment_id == segment.id) {
+            segment.downloaded = segment.downloaded.max(live.bytes_downloaded as i64);
+        }
+    }
+    segments.sort_by_key(|s| (s.stream_type == StreamType::Secondary, s.offset));
+    Ok(Json(segments))
+}
+
+/// GET /downloads/:id/file
+/// Stream a completed download's output file, honouring a single `Range`
+/// (see `file_server`). 409 while the download isn't complete, 404 for
+/// unknown ids or when the file has since been moved or deleted.
+async fn file_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let (path, strategy) = {
+        let downloads = state.downloads.read().await;
+        let dl = downloads
+            .get(&id)
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("download {} not found", id)))?;
+        match (&dl.status, &dl.summary) {
+            (DownloadStatus::Complete, Some(summary)) => (PathBuf::from(&summary.path), Arc::clone(&dl.strategy)),
+            (status, _) => {
+                let status = format!("{:?}", status).to_lowercase();
+                return Err((StatusCode::CONFLICT, format!("download {} is {}, not complete", id, status)));
             }
         }
-    } else {
-        Json(serde_json::json!({ "id": id, "status": "not_found" }))
+    };
+
+    // A compressed file no longer has the type or name the server sent.
+    let download_state = strategy.state_snapshot();
+    let original = download_state.compressed_size.is_none();
+    let content_type = download_state
+        .content_type
+        .filter(|_| original)
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+    let file_name = download_state
+        .attachment_name
+        .filter(|_| original)
+        .or_else(|| path.file_name().map(|n| n.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "download".to_string());
+
+    let range = headers.get(header::RANGE).and_then(|v| v.to_str().ok());
+    file_server::serve_file(&path, &content_type, &file_name, range).await
+}
+
+/// One open GET /progress stream, counted in `ActiveDownload::subscribers`
+/// for as long as it lives: the stream owns it, so the count goes down
+/// however the stream ends, including a client that disconnects.
+struct SubscriberGuard(Arc<AtomicUsize>);
+
+impl SubscriberGuard {
+    /// Count one more subscriber, unless `limit` are already open.
+    fn acquire(count: &Arc<AtomicUsize>, limit: usize) -> Option<Self> {
+        count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < limit).then_some(n + 1))
+            .ok()
+            .map(|_| Self(Arc::clone(count)))
+    }
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
     }
 }
 
 /// GET /progress/:id — Server-Sent Events stream of download progress.
 ///
 /// Waits for each change on the `watch` channel (true push) and emits it as
-/// a JSON `ProgressSnapshot` event.  Closes the stream once `done == true`.
+/// a JSON `ProgressSnapshot` in a named `progress`, `done` or `error` event
+/// (see `sse_event_name`).  Closes the stream once `done == true`.  Idle
+/// streams get a comment every `AppState::sse_keepalive`.  `429` once the
+/// download already has `AppState::max_sse_subscribers` streams open.
 async fn progress_handler(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
 ) -> Result<Sse<impl futures::Stream<Item = Result<Event, Infallible>>>, StatusCode> {
     // Clone the watch receiver for this SSE client.
-    let mut rx = {
+    let (mut rx, guard) = {
         let downloads = state.downloads.read().await;
         let dl = downloads.get(&id).ok_or(StatusCode::NOT_FOUND)?;
-        dl.progress_rx.clone()
+        let guard = SubscriberGuard::acquire(&dl.subscribers, state.max_sse_subscribers).ok_or_else(|| {
+            log::warn!("[sse] id={} refusing subscriber: {} already open", id, state.max_sse_subscribers);
+            StatusCode::TOO_MANY_REQUESTS
+        })?;
+        (dl.progress_rx.clone(), guard)
     };
 
     let stream = async_stream::stream! {
+        let _guard = guard;
         loop {
             // Wait until a new snapshot is published.
             if rx.changed().await.is_err() {
@@ -634,20 +1597,33 @@ async fn progress_handler(
             let snap = rx.borrow_and_update().clone();
             let is_done = snap.done;
             let json = serde_json::to_string(&snap).unwrap_or_default();
-            yield Ok::<_, Infallible>(Event::default().data(json));
+            yield Ok::<_, Infallible>(Event::default().event(sse_event_name(&snap)).data(json));
             if is_done {
                 break;
             }
         }
     };
 
+    // The default keep-alive is a bare `:` comment line, which clients
+    // (including the UI's `subscribe_progress`) skip without parsing.
     Ok(Sse::new(stream).keep_alive(
-        axum::response::sse::KeepAlive::new()
-            .interval(Duration::from_secs(15))
-            .text("keep-alive"),
+        axum::response::sse::KeepAlive::new().interval(state.sse_keepalive),
     ))
 }
 
+/// SSE event name for a snapshot: `error` for a failed download, `done` for
+/// the final snapshot, `progress` otherwise. The payload keeps its `done`
+/// flag for clients that ignore event names.
+fn sse_event_name(snap: &ProgressSnapshot) -> &'static str {
+    if snap.error.is_some() {
+        "error"
+    } else if snap.done {
+        "done"
+    } else {
+        "progress"
+    }
+}
+
 /// GET /videos
 async fn videos_handler(
     State(state): State<Arc<AppState>>,
@@ -656,15 +1632,30 @@ async fn videos_handler(
     Json(tracker.get_list())
 }
 
+/// GET /videos/:id
+/// One detected video with its full detail (headers, cookies, …); 404 if
+/// it isn't in the list.
+async fn get_video_handler(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<VideoListItem>, (StatusCode, String)> {
+    let tracker = state.video_tracker.read().await;
+    tracker
+        .get_video(&id)
+        .map(Json)
+        .map_err(|e| (StatusCode::NOT_FOUND, e))
+}
+
 /// POST /videos/:id
 async fn add_video_handler(
     State(state): State<Arc<AppState>>,
     Path(id): Path<String>,
-    Json(item): Json<VideoListItem>,
+    ValidatedJson(item): ValidatedJson<VideoListItem>,
 ) -> Json<serde_json::Value> {
     log::info!("video added: id={}", id);
+    let active = active_download_ids(&state).await;
     let mut tracker = state.video_tracker.write().await;
-    tracker.add_or_update(item);
+    tracker.add_or_update(item, |id| active.contains(id));
     Json(serde_json::json!({ "status": "ok" }))
 }
 
@@ -679,26 +1670,36 @@ async fn remove_video_handler(
     Json(serde_json::json!({ "status": "ok" }))
 }
 
-async fn echo_handler(
-    State(_state): State<Arc<AppState>>,
-    Path(msg): Path<String>,
-)  {
-    log::info!("echo {}",msg );
+/// GET /health
+/// Liveness check for the UI and load balancers.
+async fn health_handler(State(state): State<Arc<AppState>>) -> Json<HealthResponse> {
+    let active_downloads = state
+        .downloads
+        .read()
+        .await
+        .values()
+        .filter(|dl| matches!(dl.status, DownloadStatus::Queued | DownloadStatus::Running | DownloadStatus::Paused))
+        .count();
+
+    Json(HealthResponse {
+        status: "ok",
+        version: env!("CARGO_PKG_VERSION"),
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        active_downloads,
+    })
+}
+
+/// GET /echo/:msg
+/// Debug helper — logs and echoes the message back.
+async fn echo_handler(Path(msg): Path<String>) -> Json<serde_json::Value> {
+    log::info!("echo {}", msg);
+    Json(serde_json::json!({ "echo": msg }))
 }
 
 // ---------------------------------------------------------------------------
 // Utilities
 // ---------------------------------------------------------------------------
 
-/// Derive a stable ID from a URL (simple truncated hash).
-fn uuid_from_url(url: &str) -> String {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    let mut h = DefaultHasher::new();
-    url.hash(&mut h);
-    format!("{:016x}", h.finish())
-}
-
 /// Extract the last path segment from a URL as a filename fallback.
 #[allow(dead_code)]
 fn filename_from_url(url: &str) -> String {
@@ -707,3 +1708,724 @@ fn filename_from_url(url: &str) -> String {
         .unwrap_or("download")
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str, status: DownloadStatus, created_at: u64) -> DownloadListItem {
+        DownloadListItem {
+            id: id.to_string(),
+            url: format!("https://example.com/{}", id),
+            output_path: format!("/tmp/{}", id),
+            status,
+            created_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn only_extension_origins_may_read_responses() {
+        use tower::ServiceExt;
+
+        let allowed_origin = |state: Arc<AppState>, origin: &'static str| async move {
+            let request = axum::http::Request::get("/health")
+                .header(header::ORIGIN, origin)
+                .body(axum::body::Body::empty())
+                .unwrap();
+            let response = router(state).oneshot(request).await.unwrap();
+            response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).cloned()
+        };
+
+        let state = AppState::with_connections(1);
+        let extension = "chrome-extension://abcdefghijklmnop";
+        assert_eq!(allowed_origin(Arc::clone(&state), extension).await.unwrap(), extension);
+        assert!(allowed_origin(Arc::clone(&state), "moz-extension://1234").await.is_some());
+        assert!(allowed_origin(Arc::clone(&state), "https://evil.example").await.is_none());
+        assert!(allowed_origin(Arc::clone(&state), "http://localhost:3000").await.is_none());
+
+        let mut state = AppState::with_connections(1);
+        Arc::get_mut(&mut state).unwrap().cors_origins = vec![extension.to_string()];
+        assert!(allowed_origin(Arc::clone(&state), extension).await.is_some());
+        assert!(allowed_origin(state, "chrome-extension://other").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn dashboard_is_opt_in_and_pages_the_download_list() {
+        use tower::ServiceExt;
+
+        let get = |state: Arc<AppState>, uri: &'static str| async move {
+            let request = axum::http::Request::get(uri).body(axum::body::Body::empty()).unwrap();
+            let response = router(state).oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        let mut state = AppState::with_connections(1);
+        assert_eq!(get(Arc::clone(&state), "/").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(get(Arc::clone(&state), "/ui").await.0, StatusCode::NOT_FOUND);
+
+        Arc::get_mut(&mut state).unwrap().dashboard = true;
+        let (status, page) = get(Arc::clone(&state), "/ui").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(page.contains("const PAGE_SIZE = 50;"));
+
+        // The page's request for its second page, one download per page.
+        for (id, created_at) in [("a", 1), ("b", 3), ("c", 2)] {
+            let mut dl = queued_download(id);
+            dl.created_at = created_at;
+            state.downloads.write().await.insert(id.to_string(), dl);
+        }
+        let (status, list) = get(state, "/downloads?limit=1&offset=1").await;
+        assert_eq!(status, StatusCode::OK);
+        let list: serde_json::Value = serde_json::from_str(&list).unwrap();
+        assert_eq!((list["total"].as_u64(), list["offset"].as_u64()), (Some(3), Some(1)));
+        let ids: Vec<_> = list["downloads"].as_array().unwrap().iter().map(|d| d["id"].clone()).collect();
+        assert_eq!(ids, [serde_json::json!("c")]);
+    }
+
+    #[test]
+    fn captured_range_header_is_stripped() {
+        let headers = HashMap::from([
+            ("Range".to_string(), serde_json::json!(["bytes=0-1023"])),
+            ("Referer".to_string(), serde_json::json!(["https://example.com/"])),
+        ]);
+        let forwarded = json_headers_to_vec(&headers);
+        assert_eq!(forwarded.keys().collect::<Vec<_>>(), ["Referer"]);
+    }
+
+    /// A queued download registered under `id`, for handlers that only look
+    /// at the map.
+    fn queued_download(id: &str) -> ActiveDownload {
+        let source: VideoListItem = serde_json::from_value(serde_json::json!({
+            "id": id, "text": id, "info": "", "tabId": "1", "url": "https://example.com/a.mp4",
+        }))
+        .unwrap();
+        let output_path = std::env::temp_dir().join(format!("rdm-test-{}.mp4", id));
+        let strategy: Arc<dyn DownloadStrategy> =
+            Arc::new(MultipartDownloadStrategy::new(source.url.clone(), output_path.clone()));
+        let (progress_observer, progress_rx) = SseProgressObserver::new(id.to_string());
+        ActiveDownload {
+            id: id.to_string(),
+            url: source.url.clone(),
+            output_path,
+            downloader: Arc::new(TokioMutex::new(HttpDownloader::new(Arc::clone(&strategy)))),
+            strategy,
+            status: DownloadStatus::Queued,
+            progress_rx,
+            progress_observer,
+            subscribers: Arc::new(AtomicUsize::new(0)),
+            summary: None,
+            source,
+            created_at: 0,
+            deadline: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_streams_over_the_limit_are_refused() {
+        let mut state = AppState::with_connections(1);
+        Arc::get_mut(&mut state).unwrap().max_sse_subscribers = 2;
+        state.downloads.write().await.insert("v".to_string(), queued_download("v"));
+        let subscribe = || progress_handler(State(Arc::clone(&state)), Path("v".to_string()));
+
+        let first = subscribe().await.ok().unwrap();
+        let _second = subscribe().await.ok().unwrap();
+        assert_eq!(subscribe().await.err(), Some(StatusCode::TOO_MANY_REQUESTS));
+
+        // A stream that ends frees its place.
+        drop(first);
+        assert!(subscribe().await.is_ok());
+        assert_eq!(state.downloads.read().await["v"].subscribers.load(Ordering::Acquire), 1);
+    }
+
+    #[tokio::test]
+    async fn cancel_does_not_wait_for_the_running_download() {
+        let state = AppState::with_connections(1);
+        let mut dl = queued_download("c");
+        dl.status = DownloadStatus::Running;
+        let downloader = Arc::clone(&dl.downloader);
+        state.downloads.write().await.insert("c".to_string(), dl);
+
+        // `run_download` holds this for as long as the download runs.
+        let _running = downloader.lock().await;
+        let Json(reply) = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            cancel_handler(State(Arc::clone(&state)), Path("c".to_string())),
+        )
+        .await
+        .expect("cancel should not wait on the downloader lock");
+        assert_eq!(reply["status"], "cancelled");
+        assert!(matches!(state.downloads.read().await["c"].status, DownloadStatus::Cancelled));
+    }
+
+    #[tokio::test]
+    async fn a_retry_does_not_show_the_cancelled_attempts_late_events() {
+        // Accepts connections but never answers, so the retry stays running.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/a.mp4", listener.local_addr().unwrap());
+        let mut state = AppState::with_connections(1);
+        Arc::get_mut(&mut state).unwrap().host_filter = HostFilter::new(vec!["127.0.0.1".into()], vec![]);
+        let mut dl = queued_download("r");
+        dl.source.url = url.clone();
+        dl.url = url;
+        dl.status = DownloadStatus::Running;
+        let first_attempt = dl.progress_observer.clone();
+        let mut progress = dl.progress_rx.clone();
+        state.downloads.write().await.insert("r".to_string(), dl);
+
+        let Json(reply) = cancel_handler(State(Arc::clone(&state)), Path("r".to_string())).await;
+        assert_eq!(reply["status"], "cancelled");
+        let Json(reply) = retry_handler(State(Arc::clone(&state)), Path("r".to_string())).await.unwrap();
+        assert_eq!(reply["status"], "queued");
+        progress.borrow_and_update();
+
+        // The first attempt only now gets round to reporting its end.
+        rdm_core::progress::observer::ProgressObserver::on_error(&first_attempt, "cancelled").await;
+        assert!(!progress.has_changed().unwrap());
+        assert_eq!((progress.borrow().done, progress.borrow().error.clone()), (false, None));
+
+        let _ = cancel_handler(State(Arc::clone(&state)), Path("r".to_string())).await;
+    }
+
+    #[tokio::test]
+    async fn a_download_without_an_output_path_is_named_by_the_naming_strategy() {
+        use tower::ServiceExt;
+
+        let dir = std::env::temp_dir().join(format!("rdm-naming-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/watch?v=1", listener.local_addr().unwrap());
+        let mut state = AppState::with_connections(1);
+        let app = Arc::get_mut(&mut state).unwrap();
+        app.host_filter = HostFilter::new(vec!["127.0.0.1".into()], vec![]);
+        app.naming = Box::new(SafeNamingStrategy::new(dir.clone()));
+        let request = axum::http::Request::post("/download")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(
+                serde_json::json!({
+                    "id": "n", "url": url, "title": "My Show: Episode 1", "contentType": "video/mp4",
+                })
+                .to_string(),
+            ))
+            .unwrap();
+
+        let response = router(Arc::clone(&state)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // The download is registered by the task that runs it.
+        let path = loop {
+            if let Some(dl) = state.downloads.read().await.get("n") {
+                break dl.output_path.clone();
+            }
+            tokio::task::yield_now().await;
+        };
+        assert_eq!(path, dir.join("My_Show_Episode_1.mp4"));
+        assert!(dir.is_dir(), "the download directory was not created");
+
+        let _ = cancel_handler(State(Arc::clone(&state)), Path("n".to_string())).await;
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn an_expired_media_link_fails_instead_of_saving_the_error_page() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tower::ServiceExt;
+
+        // Whatever is asked for, the link has expired.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v.mp4", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0u8; 4096];
+                    let _ = socket.read(&mut request).await;
+                    let page = "<html>Link expired</html>";
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        page.len(), page
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("rdm-expired-{}", std::process::id()));
+        let output = dir.join("v.mp4");
+        let mut state = AppState::with_connections(2);
+        Arc::get_mut(&mut state).unwrap().host_filter = HostFilter::new(vec!["127.0.0.1".into()], vec![]);
+        // As the extension sends it: detected as video, so the probe is skipped.
+        let request = axum::http::Request::post("/download")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(
+                serde_json::json!({
+                    "id": "x", "url": url, "title": "v", "outputPath": output, "info": "video/mp4",
+                    "fileSize": 4 * 1024 * 1024, "resumable": true, "contentType": "video/mp4",
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = router(Arc::clone(&state)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut progress = loop {
+            if let Some(dl) = state.downloads.read().await.get("x") {
+                break dl.progress_rx.clone();
+            }
+            tokio::task::yield_now().await;
+        };
+        let error = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if let Some(error) = progress.borrow_and_update().error.clone() {
+                    break error;
+                }
+                progress.changed().await.unwrap();
+            }
+        })
+        .await
+        .expect("the download did not fail");
+        assert!(error.contains("unexpected content type"), "{error}");
+        assert!(!output.exists(), "the error page was saved");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn rest_endpoints_reject_bad_bodies_like_the_extension_endpoints() {
+        use tower::ServiceExt;
+
+        let state = AppState::with_connections(1);
+        state.downloads.write().await.insert("q".to_string(), queued_download("q"));
+        let post = |path: &'static str, content_type: &'static str, body: &'static str| {
+            let state = Arc::clone(&state);
+            async move {
+                let request = axum::http::Request::post(path)
+                    .header("content-type", content_type)
+                    .body(axum::body::Body::from(body))
+                    .unwrap();
+                let response = router(state).oneshot(request).await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&body).unwrap_or_default())
+            }
+        };
+
+        for path in ["/downloads/q/rename", "/downloads/q/connections", "/enabled", "/videos/v"] {
+            let (status, body) = post(path, "application/json", "{}").await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{path}");
+            assert_eq!(body["expected"], "required field", "{path}: {body}");
+        }
+        let (status, body) = post("/downloads/q/connections", "application/json", r#"{"connections": "four"}"#).await;
+        assert_eq!((status, body["field"].as_str()), (StatusCode::BAD_REQUEST, Some("connections")));
+        let (status, body) = post("/downloads/q/headers", "text/plain", r#"{"cookie": "a=b"}"#).await;
+        assert_eq!((status, body["expected"].as_str()), (StatusCode::BAD_REQUEST, Some("application/json")));
+
+        let (status, _) = post("/downloads/q/connections", "application/json; charset=utf-8", r#"{"connections": 4}"#).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test]
+    fn progress_and_log_files_of_distinct_ids_do_not_collide() {
+        let dir = std::env::temp_dir().join("rdm-id-files");
+        let mut state = AppState::with_connections(1);
+        let state_mut = Arc::get_mut(&mut state).unwrap();
+        state_mut.progress_dir = Some(dir.clone());
+        state_mut.download_log_dir = Some(dir.clone());
+
+        let ids = ["a/b", "a_b", "a:b", "a%2Fb", "..", "a b", "ä"];
+        let progress: std::collections::HashSet<PathBuf> = ids.iter().map(|id| state.progress_file(id).unwrap()).collect();
+        let logs: std::collections::HashSet<PathBuf> = ids.iter().map(|id| state.download_log_file(id).unwrap()).collect();
+        assert_eq!((progress.len(), logs.len()), (ids.len(), ids.len()));
+        for path in progress.iter().chain(&logs) {
+            assert_eq!(path.parent(), Some(dir.as_path()), "{}", path.display());
+        }
+        assert_eq!(state.progress_file("video-1_hd").unwrap(), dir.join("video-1_hd.json"));
+        assert_eq!(state.progress_file("a/b").unwrap(), dir.join("a%2Fb.json"));
+    }
+
+    #[tokio::test]
+    async fn a_cancelled_download_removes_its_progress_file() {
+        use tower::ServiceExt;
+
+        // Accepts connections and never answers, so the download stays running.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v.mp4", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        let dir = std::env::temp_dir().join(format!("rdm-cancel-progress-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut state = AppState::with_connections(2);
+        let state_mut = Arc::get_mut(&mut state).unwrap();
+        state_mut.host_filter = HostFilter::new(vec!["127.0.0.1".into()], vec![]);
+        state_mut.progress_dir = Some(dir.clone());
+        let request = axum::http::Request::post("/download")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(
+                serde_json::json!({
+                    "id": "c/1", "url": url, "title": "v", "outputPath": dir.join("v.mp4"),
+                    "fileSize": 4 * 1024 * 1024, "resumable": true, "contentType": "video/mp4",
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = router(Arc::clone(&state)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let downloader = loop {
+            if let Some(dl) = state.downloads.read().await.get("c/1") {
+                break Arc::clone(&dl.downloader);
+            }
+            tokio::task::yield_now().await;
+        };
+
+        let Json(cancelled) = cancel_handler(State(Arc::clone(&state)), Path("c/1".to_string())).await;
+        assert_eq!(cancelled["status"], "cancelled");
+        // The download has returned, its observers told of the cancel.
+        let _ = downloader.lock().await;
+        let progress_file = state.progress_file("c/1").unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while progress_file.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the progress file of a cancelled download was kept");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn a_zero_deadline_is_rejected() {
+        use tower::ServiceExt;
+
+        let state = AppState::with_connections(1);
+        let request = axum::http::Request::post("/download")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(
+                serde_json::json!({
+                    "id": "d", "url": "https://example.com/a.mp4", "title": "a",
+                    "outputPath": std::env::temp_dir().join("rdm-zero-deadline.mp4"), "deadlineSecs": 0,
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = router(Arc::clone(&state)).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(state.downloads.read().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn pause_all_holds_running_downloads_until_resume_all() {
+        use tower::ServiceExt;
+
+        let state = AppState::with_connections(1);
+        let running = Arc::new(MultipartDownloadStrategy::new("https://example.com/r".into(), "/tmp/r".into()));
+        {
+            let mut downloads = state.downloads.write().await;
+            let mut dl = queued_download("r");
+            dl.strategy = running.clone();
+            dl.status = DownloadStatus::Running;
+            downloads.insert("r".to_string(), dl);
+            let mut done = queued_download("d");
+            done.status = DownloadStatus::Complete;
+            downloads.insert("d".to_string(), done);
+        }
+        let post = |path: &'static str| {
+            let state = Arc::clone(&state);
+            async move {
+                let request = axum::http::Request::post(path).body(axum::body::Body::empty()).unwrap();
+                let response = router(state).oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+            }
+        };
+        let status = |id: &'static str| {
+            let state = Arc::clone(&state);
+            async move { state.downloads.read().await[id].status }
+        };
+
+        assert_eq!(post("/pause-all").await, serde_json::json!({ "paused": 1 }));
+        assert_eq!((status("r").await, status("d").await), (DownloadStatus::Paused, DownloadStatus::Complete));
+        assert!(running.pause_token().is_paused());
+        assert!(!running.cancel_token().is_cancelled(), "a paused download must be able to go on");
+        assert_eq!(post("/pause-all").await, serde_json::json!({ "paused": 0 }));
+
+        assert_eq!(post("/resume-all").await, serde_json::json!({ "resumed": 1 }));
+        assert_eq!(status("r").await, DownloadStatus::Running);
+        assert!(!running.pause_token().is_paused());
+        assert_eq!(post("/resume-all").await, serde_json::json!({ "resumed": 0 }));
+    }
+
+    #[tokio::test]
+    async fn queued_downloads_take_new_headers() {
+        let state = AppState::with_connections(1);
+        let mut dl = queued_download("h");
+        dl.source.user_agent = Some("captured-agent".to_string());
+        state.downloads.write().await.insert("h".to_string(), dl);
+        let update = |body: serde_json::Value| {
+            headers_handler(State(Arc::clone(&state)), Path("h".to_string()), ValidatedJson(serde_json::from_value(body).unwrap()))
+        };
+
+        let Json(summary) = update(serde_json::json!({
+            "headers": { "Authorization": ["Bearer fresh"], "Cookie": ["dropped"] },
+            "cookie": "session=new",
+        }))
+        .await
+        .unwrap();
+        assert_eq!(summary["headers"], serde_json::json!(["Authorization", "User-Agent"]));
+        assert_eq!(summary["cookie"], true);
+
+        {
+            let downloads = state.downloads.read().await;
+            let sent = downloads["h"].strategy.state_snapshot();
+            assert_eq!(sent.headers["Authorization"], ["Bearer fresh"]);
+            assert_eq!(sent.headers["User-Agent"], ["captured-agent"]);
+            assert_eq!(sent.cookies.as_deref(), Some("session=new"));
+            // A retry rebuilds the download from the updated source.
+            assert_eq!(downloads["h"].source.cookie, "session=new");
+        }
+
+        assert_eq!(update(serde_json::json!({})).await.unwrap_err().0, StatusCode::BAD_REQUEST);
+        state.downloads.write().await.get_mut("h").unwrap().status = DownloadStatus::Running;
+        let err = update(serde_json::json!({ "cookie": "" })).await.unwrap_err();
+        assert_eq!(err.0, StatusCode::CONFLICT);
+    }
+
+    /// Send `request` through the router; the status and the JSON body
+    /// (`Null` when it isn't JSON).
+    async fn send(state: &Arc<AppState>, request: axum::http::Request<axum::body::Body>) -> (StatusCode, serde_json::Value) {
+        use tower::ServiceExt;
+
+        let response = router(Arc::clone(state)).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    fn get_request(uri: &str) -> axum::http::Request<axum::body::Body> {
+        axum::http::Request::get(uri).body(axum::body::Body::empty()).unwrap()
+    }
+
+    fn post_request(uri: &str, body: serde_json::Value) -> axum::http::Request<axum::body::Body> {
+        axum::http::Request::post(uri)
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    /// A detected video as the extension reports it.
+    fn video(id: &str) -> VideoListItem {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "text": id, "info": "", "tabId": "1", "url": format!("https://example.com/{}.mp4", id),
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn sync_reports_the_enabled_switch() {
+        let state = AppState::with_connections(1);
+        assert_eq!(send(&state, get_request("/sync")).await.1["enabled"], true);
+
+        let (status, config) = send(&state, post_request("/enabled", serde_json::json!({ "enabled": false }))).await;
+        assert_eq!((status, config["enabled"].as_bool()), (StatusCode::OK, Some(false)));
+        assert_eq!(send(&state, get_request("/sync")).await.1["enabled"], false);
+
+        send(&state, post_request("/enabled", serde_json::json!({ "enabled": true }))).await;
+        assert_eq!(send(&state, get_request("/sync")).await.1["enabled"], true);
+    }
+
+    #[tokio::test]
+    async fn health_counts_unfinished_downloads() {
+        let state = AppState::with_connections(1);
+        {
+            let mut downloads = state.downloads.write().await;
+            for (id, status) in [
+                ("q", DownloadStatus::Queued),
+                ("r", DownloadStatus::Running),
+                ("p", DownloadStatus::Paused),
+                ("c", DownloadStatus::Complete),
+                ("f", DownloadStatus::Failed),
+            ] {
+                let mut dl = queued_download(id);
+                dl.status = status;
+                downloads.insert(id.to_string(), dl);
+            }
+        }
+
+        let (status, health) = send(&state, get_request("/health")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health["status"], "ok");
+        assert_eq!(health["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(health["active_downloads"], 3);
+        assert!(health["uptime_secs"].is_u64());
+
+        let (status, echo) = send(&state, get_request("/echo/hello")).await;
+        assert_eq!((status, echo), (StatusCode::OK, serde_json::json!({ "echo": "hello" })));
+    }
+
+    #[tokio::test]
+    async fn clear_idle_keeps_only_videos_with_an_unfinished_download() {
+        let state = AppState::with_connections(1);
+        {
+            let mut tracker = state.video_tracker.write().await;
+            for id in ["running", "paused", "complete", "detected"] {
+                tracker.add_or_update(video(id), |_| false);
+            }
+            let mut downloads = state.downloads.write().await;
+            for (id, status) in [
+                ("running", DownloadStatus::Running),
+                ("paused", DownloadStatus::Paused),
+                ("complete", DownloadStatus::Complete),
+            ] {
+                let mut dl = queued_download(id);
+                dl.status = status;
+                downloads.insert(id.to_string(), dl);
+            }
+        }
+
+        let (status, config) = send(&state, post_request("/videos/clear-idle", serde_json::json!({}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let mut kept: Vec<_> = config["videoList"].as_array().unwrap().iter().map(|v| v["id"].clone()).collect();
+        kept.sort_by_key(|id| id.to_string());
+        assert_eq!(kept, [serde_json::json!("paused"), serde_json::json!("running")]);
+        assert_eq!(state.video_tracker.read().await.get_list().len(), 2);
+        assert_eq!(state.downloads.read().await.len(), 3, "downloads are not touched");
+    }
+
+    #[tokio::test]
+    async fn segments_are_sorted_by_offset_with_live_progress() {
+        let state = AppState::with_connections(1);
+        let strategy = Arc::new(MultipartDownloadStrategy::new("https://example.com/s".into(), "/tmp/s".into()));
+        {
+            let mut segments = strategy.segments().write().await;
+            for (id, offset) in [("c", 200), ("a", 0), ("b", 100)] {
+                let mut segment = Segment::new(id.to_string(), offset, 100);
+                segment.downloaded = 10;
+                segments.insert(id.to_string(), segment);
+            }
+        }
+        let mut dl = queued_download("s");
+        dl.strategy = strategy;
+        dl.status = DownloadStatus::Running;
+        let observer = dl.progress_observer.clone();
+        state.downloads.write().await.insert("s".to_string(), dl);
+        let live = rdm_core::progress::snapshot::SegmentSnapshot {
+            segment_id: "b".to_string(),
+            bytes_downloaded: 60,
+            total_bytes: 100,
+            speed: 0.0,
+            eta_secs: 0.0,
+            retry_count: 0,
+        };
+        let snapshot = ProgressSnapshot { segments: vec![live], ..ProgressSnapshot::empty() };
+        rdm_core::progress::observer::ProgressObserver::on_progress(&observer, &snapshot).await;
+
+        let (status, segments) = send(&state, get_request("/downloads/s/segments")).await;
+        assert_eq!(status, StatusCode::OK);
+        let plan: Vec<_> = segments
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| (s["id"].as_str().unwrap().to_string(), s["offset"].as_i64().unwrap(), s["downloaded"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(plan, [("a".to_string(), 0, 10), ("b".to_string(), 100, 60), ("c".to_string(), 200, 10)]);
+
+        assert_eq!(send(&state, get_request("/downloads/missing/segments")).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn progress_is_streamed_as_named_events() {
+        use futures::StreamExt;
+        use rdm_core::progress::observer::ProgressObserver;
+        use tower::ServiceExt;
+
+        let state = AppState::with_connections(1);
+        let subscribe = |id: &'static str| {
+            let state = Arc::clone(&state);
+            async move {
+                let response = router(state).oneshot(get_request(&format!("/progress/{}", id))).await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                response.into_body().into_data_stream()
+            }
+        };
+        async fn next_event(stream: &mut axum::body::BodyDataStream) -> String {
+            let frame = tokio::time::timeout(Duration::from_secs(5), stream.next()).await.expect("no event");
+            String::from_utf8(frame.unwrap().unwrap().to_vec()).unwrap()
+        }
+
+        let dl = queued_download("ok");
+        let observer = dl.progress_observer.clone();
+        state.downloads.write().await.insert("ok".to_string(), dl);
+        let mut events = subscribe("ok").await;
+
+        observer.on_progress(&ProgressSnapshot { total_bytes_downloaded: 5, ..ProgressSnapshot::empty() }).await;
+        let event = next_event(&mut events).await;
+        assert!(event.starts_with("event: progress\ndata: {"), "{event}");
+        assert!(event.contains("\"done\":false"), "{event}");
+        observer.on_complete(&ProgressSnapshot { done: true, ..ProgressSnapshot::empty() }).await;
+        let event = next_event(&mut events).await;
+        assert!(event.starts_with("event: done\ndata: {"), "{event}");
+        assert!(event.contains("\"done\":true"), "the payload keeps its done flag: {event}");
+        assert!(events.next().await.is_none(), "the stream ends after done");
+
+        let dl = queued_download("err");
+        let observer = dl.progress_observer.clone();
+        state.downloads.write().await.insert("err".to_string(), dl);
+        let mut events = subscribe("err").await;
+        observer.on_error("connection refused").await;
+        let event = next_event(&mut events).await;
+        assert!(event.starts_with("event: error\ndata: {"), "{event}");
+        assert!(event.contains("connection refused") && event.contains("\"done\":true"), "{event}");
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn a_single_video_is_fetched_by_id() {
+        let state = AppState::with_connections(1);
+        let mut item = video("v1");
+        item.cookie = "session=1".to_string();
+        state.video_tracker.write().await.add_or_update(item, |_| false);
+
+        let (status, video) = send(&state, get_request("/videos/v1")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((video["id"].as_str(), video["url"].as_str()), (Some("v1"), Some("https://example.com/v1.mp4")));
+        assert_eq!(video["cookie"], "session=1");
+
+        assert_eq!(send(&state, get_request("/videos/v2")).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn media_downloads_expect_their_media_type() {
+        assert_eq!(expected_media_prefix("video/mp4"), Some("video/"));
+        assert_eq!(expected_media_prefix(" Audio/MPEG; codecs=mp3"), Some("audio/"));
+        assert_eq!(expected_media_prefix("application/octet-stream"), None);
+        assert_eq!(expected_media_prefix(""), None);
+    }
+
+    #[test]
+    fn pages_filtered_downloads_newest_first() {
+        let items = vec![
+            item("a", DownloadStatus::Complete, 100),
+            item("b", DownloadStatus::Failed, 200),
+            item("c", DownloadStatus::Complete, 300),
+            item("d", DownloadStatus::Complete, 400),
+        ];
+
+        let all = page_downloads(items.clone(), &DownloadsQuery::default());
+        assert_eq!(all.total, 4);
+        let ids: Vec<_> = all.downloads.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["d", "c", "b", "a"]);
+
+        let query = DownloadsQuery {
+            status: Some(DownloadStatus::Complete),
+            since: Some(200),
+            limit: Some(1),
+            offset: Some(1),
+        };
+        let page = page_downloads(items, &query);
+        assert_eq!(page.total, 2);
+        assert_eq!(page.offset, 1);
+        let ids: Vec<_> = page.downloads.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["c"]);
+    }
+}
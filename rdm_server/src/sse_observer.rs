@@ -1,3 +1,6 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use tokio::sync::watch;
 use rdm_core::progress::observer::ProgressObserver;
@@ -8,38 +11,134 @@ use rdm_core::progress::snapshot::ProgressSnapshot;
 ///
 /// Multiple SSE clients can each hold a clone of the `watch::Receiver` and
 /// receive every update in true push fashion — no polling required.
+///
+/// A retried download registers [`next_attempt`](Self::next_attempt) with
+/// its new downloader and keeps the existing subscribers; the previous
+/// attempt's observer goes quiet, so its late events (a cancelled attempt
+/// still winding down) never show up as the new attempt's progress.
+///
+/// Every published snapshot carries the observer's `download_id`.
+#[derive(Clone)]
 pub struct SseProgressObserver {
-    tx: watch::Sender<ProgressSnapshot>,
+    tx: Arc<watch::Sender<ProgressSnapshot>>,
+    download_id: String,
+    /// The current attempt, shared by every attempt's observer.
+    current: Arc<AtomicU64>,
+    /// The attempt this observer reports for.
+    attempt: u64,
 }
 
 impl SseProgressObserver {
     /// Creates a new observer and returns both the observer (to be registered
     /// with `ProgressNotifier`) and a `watch::Receiver` that can be cloned
-    /// and handed to SSE handler tasks.
-    pub fn new() -> (Self, watch::Receiver<ProgressSnapshot>) {
-        let (tx, rx) = watch::channel(ProgressSnapshot::empty());
-        (Self { tx }, rx)
+    /// and handed to SSE handler tasks. Snapshots are stamped with
+    /// `download_id`.
+    pub fn new(download_id: impl Into<String>) -> (Self, watch::Receiver<ProgressSnapshot>) {
+        let download_id = download_id.into();
+        let (tx, rx) = watch::channel(ProgressSnapshot { download_id: download_id.clone(), ..ProgressSnapshot::empty() });
+        (Self { tx: Arc::new(tx), download_id, current: Arc::new(AtomicU64::new(0)), attempt: 0 }, rx)
+    }
+
+    /// Applies `update` to the published snapshot, unless a newer attempt
+    /// has started. The check runs under the channel's lock, so it cannot
+    /// interleave with `next_attempt`.
+    fn update(&self, update: impl FnOnce(&mut ProgressSnapshot)) {
+        self.tx.send_if_modified(|snap| {
+            if self.current.load(Ordering::Acquire) != self.attempt {
+                return false;
+            }
+            update(snap);
+            snap.download_id = self.download_id.clone();
+            true
+        });
+    }
+
+    fn publish(&self, snapshot: &ProgressSnapshot) {
+        self.update(|snap| *snap = snapshot.clone());
+    }
+
+    /// The observer for a new attempt at the download: publishes an empty
+    /// snapshot, clearing `done` and `error`, and silences this observer and
+    /// its clones.
+    pub fn next_attempt(&self) -> Self {
+        let mut next = self.clone();
+        self.tx.send_modify(|snap| {
+            next.attempt = self.current.fetch_add(1, Ordering::AcqRel) + 1;
+            *snap = ProgressSnapshot { download_id: self.download_id.clone(), ..ProgressSnapshot::empty() };
+        });
+        next
     }
 }
 
 #[async_trait]
 impl ProgressObserver for SseProgressObserver {
     async fn on_progress(&self, snapshot: &ProgressSnapshot) {
-        // send() only fails if all receivers are dropped; we can safely ignore that.
-        let _ = self.tx.send(snapshot.clone());
+        self.publish(snapshot);
+    }
+
+    async fn on_phase(&self, phase: &str) {
+        self.update(|snap| snap.phase = Some(phase.to_string()));
+    }
+
+    async fn on_warning(&self, message: &str) {
+        // Show it right away; the next snapshot carries it as well.
+        self.update(|snap| snap.warnings.push(message.to_string()));
     }
 
     async fn on_complete(&self, snapshot: &ProgressSnapshot) {
-        let _ = self.tx.send(snapshot.clone());
+        self.publish(snapshot);
     }
 
     async fn on_error(&self, error: &str) {
-        let mut snap = self.tx.borrow().clone();
-        snap.done = true;
-        // Embed the error string in the message field isn't ideal, but
-        // ProgressSnapshot doesn't have an error field yet.  We mark done=true
-        // so the SSE stream closes, and log the error server-side.
         log::error!("[SseProgressObserver] download error: {}", error);
-        let _ = self.tx.send(snap);
+        self.update(|snap| {
+            // done=true as well, so clients that only look at `done` still stop.
+            snap.done = true;
+            snap.error = Some(error.to_string());
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn every_snapshot_carries_the_download_id() {
+        let (observer, rx) = SseProgressObserver::new("abc123");
+        assert_eq!(rx.borrow().download_id, "abc123");
+
+        let mut snapshot = ProgressSnapshot::empty();
+        snapshot.total_bytes_downloaded = 40;
+        observer.on_progress(&snapshot).await;
+        assert_eq!((rx.borrow().download_id.as_str(), rx.borrow().total_bytes_downloaded), ("abc123", 40));
+
+        observer.on_error("connection reset").await;
+        assert_eq!(rx.borrow().download_id, "abc123");
+        let observer = observer.next_attempt();
+        assert_eq!((rx.borrow().download_id.as_str(), rx.borrow().error.as_deref()), ("abc123", None));
+        observer.on_progress(&snapshot).await;
+        assert_eq!(rx.borrow().download_id, "abc123");
+    }
+
+    #[tokio::test]
+    async fn a_previous_attempt_no_longer_publishes() {
+        let (first, mut rx) = SseProgressObserver::new("retried");
+        let late = first.clone();
+        let second = first.next_attempt();
+        rx.borrow_and_update();
+
+        // The first attempt, still winding down, reports its cancellation.
+        late.on_phase("Cleaning up…").await;
+        late.on_warning("segment 2 aborted").await;
+        late.on_error("cancelled").await;
+        assert!(!rx.has_changed().unwrap());
+        let shown = rx.borrow().clone();
+        assert_eq!((shown.phase, shown.warnings.len(), shown.error, shown.done), (None, 0, None, false));
+
+        let mut snapshot = ProgressSnapshot::empty();
+        snapshot.total_bytes_downloaded = 7;
+        second.on_progress(&snapshot).await;
+        assert_eq!(rx.borrow_and_update().total_bytes_downloaded, 7);
     }
 }
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use crate::server::DownloadStatus;
+
 // ---------------------------------------------------------------------------
 // Inbound — browser extension payloads
 // ---------------------------------------------------------------------------
@@ -46,8 +48,9 @@ pub struct DownloadRequest {
     pub url: String,
     /// Human-readable title (used in UI and for the default filename).
     pub title: String,
-    /// Full absolute path where the file should be saved.
-    #[serde(rename = "outputPath")]
+    /// Full absolute path where the file should be saved. Empty or missing
+    /// to have rdmd name the file in its download directory.
+    #[serde(default, rename = "outputPath")]
     pub output_path: String,
     /// Cookie string, if any.
     #[serde(default)]
@@ -63,6 +66,26 @@ pub struct DownloadRequest {
     /// Content-Type / mime info string.
     #[serde(default)]
     pub info: String,
+    /// Size of the file, if already known from the detected response.
+    /// Together with `resumable` this lets the download skip its probe.
+    #[serde(default, rename = "fileSize")]
+    pub file_size: Option<u64>,
+    /// Whether the server accepts range requests, if already known.
+    #[serde(default)]
+    pub resumable: Option<bool>,
+    /// Content-Type of the detected response.
+    #[serde(default, rename = "contentType")]
+    pub content_type: Option<String>,
+    /// Filename from the detected response's Content-Disposition.
+    #[serde(default, rename = "attachmentName")]
+    pub attachment_name: Option<String>,
+    /// Fail the download once it has run this many seconds, probe and
+    /// assembly included.
+    #[serde(default, rename = "deadlineSecs")]
+    pub deadline_secs: Option<u64>,
+    /// See `VideoListItem::capture_range`.
+    #[serde(default, rename = "captureRange")]
+    pub capture_range: Option<(u64, u64)>,
 }
 
 /// Response returned by POST /download once the download has been queued.
@@ -73,6 +96,76 @@ pub struct DownloadResponse {
     pub status: String,
 }
 
+/// Response body of GET /health.
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+    pub version: &'static str,
+    pub uptime_secs: u64,
+    /// Downloads that are queued, running or paused.
+    pub active_downloads: usize,
+}
+
+/// Query string for GET /downloads; every parameter is optional.
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadsQuery {
+    /// Only downloads with this status.
+    pub status: Option<DownloadStatus>,
+    /// Only downloads created at or after this time, in Unix seconds.
+    pub since: Option<u64>,
+    /// At most this many entries.
+    pub limit: Option<usize>,
+    /// Skip this many matching entries first.
+    pub offset: Option<usize>,
+}
+
+/// One entry of GET /downloads.
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadListItem {
+    pub id: String,
+    pub url: String,
+    pub output_path: String,
+    pub status: DownloadStatus,
+    /// Unix seconds.
+    pub created_at: u64,
+}
+
+/// Response body of GET /downloads.
+#[derive(Debug, Serialize)]
+pub struct DownloadListResponse {
+    /// Number of downloads matching the filters, across all pages.
+    pub total: usize,
+    pub offset: usize,
+    pub downloads: Vec<DownloadListItem>,
+}
+
+/// Payload for POST /downloads/{id}/rename.
+#[derive(Debug, Deserialize)]
+pub struct RenameRequest {
+    /// New absolute output path; must pass the same guard as POST /download.
+    pub output_path: String,
+}
+
+/// Payload for POST /downloads/{id}/headers. A field left out keeps its
+/// current value.
+#[derive(Debug, Deserialize)]
+pub struct HeadersRequest {
+    /// Request headers to send instead of the captured ones, in the same
+    /// shape as `VideoListItem::request_headers`.
+    #[serde(default)]
+    pub headers: Option<HashMap<String, serde_json::Value>>,
+    /// Cookie string to send instead of the captured one; empty sends none.
+    #[serde(default)]
+    pub cookie: Option<String>,
+}
+
+/// Payload for POST /downloads/{id}/connections.
+#[derive(Debug, Deserialize)]
+pub struct ConnectionsRequest {
+    /// New maximum number of concurrent connections; at least 1.
+    pub connections: usize,
+}
+
 /// Payload POSTed by the extension on /media (detected streaming media).
 #[derive(Debug, Deserialize)]
 pub struct MediaData {
@@ -92,6 +185,11 @@ pub struct MediaData {
     pub tab_url: Option<String>,
     #[serde(rename = "tabId")]
     pub tab_id: Option<String>,
+    /// Inclusive byte range the browser requested, when the capture is a
+    /// sub-resource defined by it (e.g. a DASH init segment); see
+    /// `VideoListItem::capture_range`.
+    #[serde(default, rename = "captureRange")]
+    pub capture_range: Option<(u64, u64)>,
 }
 
 /// Payload POSTed by the extension on /tab-update.
@@ -109,6 +207,12 @@ pub struct VidRequest {
     pub vid: String,
 }
 
+/// Payload POSTed on /enabled to toggle monitoring globally.
+#[derive(Debug, Deserialize)]
+pub struct EnabledRequest {
+    pub enabled: bool,
+}
+
 // ---------------------------------------------------------------------------
 // Outbound — video list item
 // ---------------------------------------------------------------------------
@@ -148,6 +252,12 @@ pub struct VideoListItem {
     pub tab_url: Option<String>,
     /// Referer header value, if present in request headers.
     pub referer: Option<String>,
+    /// Inclusive byte range that defines the resource, when the extension
+    /// captured a ranged sub-resource request. The captured `Range` header
+    /// itself is always stripped; with this set the download fetches just
+    /// these bytes as one non-resumable segment instead of the whole file.
+    #[serde(default, rename = "captureRange", skip_serializing_if = "Option::is_none")]
+    pub capture_range: Option<(u64, u64)>,
 }
 
 // ---------------------------------------------------------------------------
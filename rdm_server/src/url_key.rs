@@ -0,0 +1,106 @@
+//! URL key — derives the id a detected video is tracked under.
+//!
+//! Players often re-request the same media with a cache-busting query
+//! parameter (`?t=1712345678`), which would otherwise show up as a new entry
+//! in the popup every time. The id is therefore a hash of a normalized URL:
+//! 1. The host is lowercased (done by the URL parser).
+//! 2. The fragment is dropped.
+//! 3. Volatile query parameters (see [`DEFAULT_VOLATILE_PARAMS`]) are removed.
+//!
+//! Only the id is derived from the normalized form; the download itself uses
+//! the URL exactly as the browser sent it.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use url::Url;
+
+/// Query parameters stripped when `RDM_VOLATILE_QUERY_PARAMS` is unset.
+pub const DEFAULT_VOLATILE_PARAMS: &[&str] =
+    &["_", "t", "ts", "timestamp", "cb", "cachebust", "nocache", "rnd", "rand"];
+
+/// `RDM_VOLATILE_QUERY_PARAMS` (comma-separated names), or the defaults.
+/// An empty value disables stripping.
+pub fn volatile_params_from_env() -> Vec<String> {
+    match std::env::var("RDM_VOLATILE_QUERY_PARAMS") {
+        Ok(value) => value
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(_) => DEFAULT_VOLATILE_PARAMS.iter().map(|p| p.to_string()).collect(),
+    }
+}
+
+/// Normalize `url` for identity purposes. Parameter names are matched
+/// case-sensitively; the remaining ones keep their order. Input that does
+/// not parse as a URL is returned unchanged.
+pub fn normalize_url(url: &str, volatile: &[String]) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    parsed.set_fragment(None);
+
+    if parsed.query().is_some() {
+        let kept: Vec<(String, String)> = parsed
+            .query_pairs()
+            .filter(|(name, _)| !volatile.iter().any(|v| v == name))
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        if kept.is_empty() {
+            parsed.set_query(None);
+        } else {
+            parsed.query_pairs_mut().clear().extend_pairs(kept);
+        }
+    }
+    parsed.into()
+}
+
+/// Stable id for a detected video (truncated hash of the normalized URL).
+pub fn video_id(url: &str, volatile: &[String]) -> String {
+    let mut h = DefaultHasher::new();
+    normalize_url(url, volatile).hash(&mut h);
+    format!("{:016x}", h.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> Vec<String> {
+        DEFAULT_VOLATILE_PARAMS.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn cache_busting_param_maps_to_same_id() {
+        let v = defaults();
+        assert_eq!(
+            video_id("https://cdn.example.com/v.mp4?t=123", &v),
+            video_id("https://cdn.example.com/v.mp4?t=456", &v),
+        );
+        assert_eq!(
+            video_id("https://cdn.example.com/v.mp4?q=hd&t=123", &v),
+            video_id("https://cdn.example.com/v.mp4?q=hd", &v),
+        );
+        assert_ne!(
+            video_id("https://cdn.example.com/v.mp4?q=hd", &v),
+            video_id("https://cdn.example.com/v.mp4?q=sd", &v),
+        );
+    }
+
+    #[test]
+    fn normalizes_host_and_fragment() {
+        let v = defaults();
+        assert_eq!(
+            normalize_url("https://CDN.Example.com/Path/v.mp4?t=1&id=7#start", &v),
+            "https://cdn.example.com/Path/v.mp4?id=7",
+        );
+        // An empty list keeps every parameter.
+        assert_eq!(
+            normalize_url("https://cdn.example.com/v.mp4?t=1", &[]),
+            "https://cdn.example.com/v.mp4?t=1",
+        );
+        assert_eq!(normalize_url("not a url", &v), "not a url");
+    }
+}
@@ -2,19 +2,77 @@ use crate::types::VideoListItem;
 use log::{error, info};
 use std::collections::HashMap;
 
+/// Default for [`VideoTracker::with_capacity`].
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// Videos detected by the extension, capped at `capacity` entries.
+///
+/// Every `add_or_update` marks the video as most recently used; once the cap
+/// is exceeded the least recently used ones are evicted, skipping any the
+/// caller reports as pinned (those with a live download).
 pub struct VideoTracker {
     videos: HashMap<String, VideoListItem>,
+    /// Value of `clock` when each video was last added or updated.
+    last_used: HashMap<String, u64>,
+    clock: u64,
+    capacity: usize,
+}
+
+impl Default for VideoTracker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl VideoTracker {
     pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+
+    /// `capacity` is raised to 1 if zero.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
             videos: HashMap::new(),
+            last_used: HashMap::new(),
+            clock: 0,
+            capacity: capacity.max(1),
         }
     }
 
-    pub fn add_or_update(&mut self, item: VideoListItem) {
-        self.videos.insert(item.id.clone(), item);
+    /// Insert `item`, or merge it into the entry with the same id (see
+    /// [`merge_item`]), then evict least recently used videos until
+    /// the tracker is back within capacity. Videos for which `pinned` returns
+    /// true are never evicted, so the tracker may stay over capacity while
+    /// they are. Returns the evicted ids.
+    pub fn add_or_update(
+        &mut self,
+        item: VideoListItem,
+        pinned: impl Fn(&str) -> bool,
+    ) -> Vec<String> {
+        self.clock += 1;
+        self.last_used.insert(item.id.clone(), self.clock);
+        let id = item.id.clone();
+        match self.videos.get_mut(&id) {
+            Some(existing) => merge_item(existing, item),
+            None => {
+                self.videos.insert(id.clone(), item);
+            }
+        }
+
+        let mut evicted = Vec::new();
+        while self.videos.len() > self.capacity {
+            let oldest = self
+                .last_used
+                .iter()
+                .filter(|(k, _)| **k != id && !pinned(k))
+                .min_by_key(|(_, used)| **used)
+                .map(|(k, _)| k.clone());
+            let Some(oldest) = oldest else { break };
+            self.remove(&oldest);
+            info!("VideoTracker::add_or_update: evicted id={}", oldest);
+            evicted.push(oldest);
+        }
+        evicted
     }
 
     /// Look up a video by `id` and return a clone of its data.
@@ -34,6 +92,7 @@ impl VideoTracker {
 
     pub fn clear(&mut self) {
         self.videos.clear();
+        self.last_used.clear();
     }
 
     pub fn get_list(&self) -> Vec<VideoListItem> {
@@ -41,9 +100,19 @@ impl VideoTracker {
     }
 
     pub fn remove(&mut self, id: &str) -> Option<VideoListItem> {
+        self.last_used.remove(id);
         self.videos.remove(id)
     }
 
+    /// Keep only the videos for which `keep` returns true.
+    /// Returns how many were removed.
+    pub fn retain(&mut self, mut keep: impl FnMut(&VideoListItem) -> bool) -> usize {
+        let before = self.videos.len();
+        self.videos.retain(|_, item| keep(item));
+        self.last_used.retain(|id, _| self.videos.contains_key(id));
+        before - self.videos.len()
+    }
+
     /// Update the `text` (title) of any video whose `tab_id` matches the
     /// given tab URL.  Called when the extension reports a tab-title change.
     pub fn update_title_for_tab(&mut self, tab_url: &str, new_title: &str) {
@@ -58,3 +127,118 @@ impl VideoTracker {
         }
     }
 }
+
+/// Fold a re-detection of the same video into `existing`.
+///
+/// The extension often sees a video several times with partial data (the
+/// first request without cookies, a later one with them), so nothing
+/// already known is thrown away: header maps are unioned with `newer`
+/// winning on conflicts, and the cookie, title and optional fields are only
+/// replaced by non-empty values. A title equal to the URL is the fallback
+/// `media_handler` uses when the extension had none, so it never replaces
+/// a real one.
+fn merge_item(existing: &mut VideoListItem, newer: VideoListItem) {
+    if !newer.text.is_empty() && (newer.text != newer.url || existing.text.is_empty()) {
+        existing.text = newer.text;
+    }
+    if !newer.info.is_empty() {
+        existing.info = newer.info;
+    }
+    if !newer.tab_id.is_empty() {
+        existing.tab_id = newer.tab_id;
+    }
+    existing.url = newer.url;
+    if !newer.cookie.is_empty() {
+        existing.cookie = newer.cookie;
+    }
+    existing.request_headers.extend(newer.request_headers);
+    existing.response_headers.extend(newer.response_headers);
+    existing.method = newer.method.or(existing.method.take());
+    existing.user_agent = newer.user_agent.or(existing.user_agent.take());
+    existing.tab_url = newer.tab_url.or(existing.tab_url.take());
+    existing.referer = newer.referer.or(existing.referer.take());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: &str) -> VideoListItem {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "text": id,
+            "info": "video/mp4",
+            "tabId": "tab",
+            "url": format!("https://example.com/{}.mp4", id),
+            "method": null,
+            "userAgent": null,
+        }))
+        .unwrap()
+    }
+
+    fn ids(tracker: &VideoTracker) -> Vec<String> {
+        let mut ids: Vec<String> = tracker.get_list().into_iter().map(|v| v.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn evicts_least_recently_used_past_capacity() {
+        let mut tracker = VideoTracker::with_capacity(3);
+        for id in ["a", "b", "c"] {
+            assert!(tracker.add_or_update(item(id), |_| false).is_empty());
+        }
+        assert_eq!(tracker.add_or_update(item("d"), |_| false), vec!["a"]);
+        assert_eq!(ids(&tracker), vec!["b", "c", "d"]);
+
+        // Updating "b" makes "c" the oldest.
+        tracker.add_or_update(item("b"), |_| false);
+        assert_eq!(tracker.add_or_update(item("e"), |_| false), vec!["c"]);
+        assert_eq!(ids(&tracker), vec!["b", "d", "e"]);
+    }
+
+    #[test]
+    fn never_evicts_pinned_videos() {
+        let mut tracker = VideoTracker::with_capacity(2);
+        tracker.add_or_update(item("a"), |_| false);
+        tracker.add_or_update(item("b"), |_| false);
+
+        assert_eq!(tracker.add_or_update(item("c"), |id| id == "a"), vec!["b"]);
+        assert_eq!(ids(&tracker), vec!["a", "c"]);
+
+        // Everything else pinned: stay over capacity rather than drop one.
+        assert!(tracker.add_or_update(item("d"), |id| id != "d").is_empty());
+        assert_eq!(ids(&tracker), vec!["a", "c", "d"]);
+    }
+
+    #[test]
+    fn merges_partial_redetections() {
+        let mut tracker = VideoTracker::with_capacity(10);
+
+        let mut first = item("a");
+        first.text = "Some Video".to_string();
+        first.request_headers.insert("Accept".into(), serde_json::json!(["*/*"]));
+        first.request_headers.insert("Range".into(), serde_json::json!(["bytes=0-"]));
+        first.user_agent = Some("UA/1".into());
+        tracker.add_or_update(first, |_| false);
+
+        let mut second = item("a");
+        second.text = second.url.clone();
+        second.cookie = "session=abc".to_string();
+        second.request_headers.insert("Range".into(), serde_json::json!(["bytes=100-"]));
+        second.response_headers.insert("Content-Length".into(), serde_json::json!(["1000"]));
+        tracker.add_or_update(second, |_| false);
+
+        let merged = tracker.get_video("a").unwrap();
+        assert_eq!(merged.cookie, "session=abc");
+        assert_eq!(merged.text, "Some Video");
+        assert_eq!(merged.request_headers["Accept"], serde_json::json!(["*/*"]));
+        assert_eq!(merged.request_headers["Range"], serde_json::json!(["bytes=100-"]));
+        assert_eq!(merged.response_headers["Content-Length"], serde_json::json!(["1000"]));
+        assert_eq!(merged.user_agent.as_deref(), Some("UA/1"));
+
+        // A later request without cookies keeps the one already known.
+        tracker.add_or_update(item("a"), |_| false);
+        assert_eq!(tracker.get_video("a").unwrap().cookie, "session=abc");
+    }
+}
@@ -0,0 +1,320 @@
+//! Test servers shared by the workspace's tests: a wiremock responder for
+//! ranged requests, and raw-TCP HTTP/1.1 servers for the misbehaviour
+//! wiremock can't act out (slow, stalled or reset transfers, chunked bodies,
+//! a single connection).
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use wiremock::{Request, Respond, ResponseTemplate};
+
+/// Answers `Range: bytes=a-b` with that slice of `body`, anything else with
+/// all of it.
+pub struct RangeSlice {
+    pub body: Vec<u8>,
+}
+
+impl Respond for RangeSlice {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let range = request
+            .headers
+            .get("Range")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|r| r.strip_prefix("bytes="))
+            .and_then(|r| r.split_once('-'))
+            .and_then(|(a, b)| Some((a.parse::<usize>().ok()?, b.parse::<usize>().ok()?)));
+        match range {
+            Some((start, end)) => ResponseTemplate::new(206)
+                .set_body_bytes(self.body[start..=end].to_vec())
+                .insert_header("Content-Range", format!("bytes {}-{}/{}", start, end, self.body.len())),
+            None => ResponseTemplate::new(200).set_body_bytes(self.body.clone()),
+        }
+    }
+}
+
+/// Reads a request head off `socket`, up to the blank line, lowercased.
+/// `None` if the client hangs up first.
+async fn read_head(socket: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        match socket.read(&mut buf).await {
+            Ok(0) | Err(_) => return None,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+        }
+    }
+    Some(String::from_utf8_lossy(&head).to_ascii_lowercase())
+}
+
+/// The value of header `name` in a lowercased request `head`, or `""`.
+fn request_header(head: &str, name: &str) -> String {
+    let prefix = format!("{}: ", name);
+    head.lines()
+        .find_map(|l| l.strip_prefix(prefix.as_str()))
+        .unwrap_or("")
+        .trim()
+        .to_string()
+}
+
+/// Where the `Range` of `head` starts; 0 without one.
+fn range_start(head: &str) -> usize {
+    request_header(head, "range")
+        .trim_start_matches("bytes=")
+        .split('-')
+        .next()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0)
+}
+
+/// The `bytes=a-b` bounds of the `Range` of `head`; all of a `len`-byte
+/// body without one.
+fn range_bounds(head: &str, len: usize) -> (usize, usize) {
+    request_header(head, "range")
+        .strip_prefix("bytes=")
+        .and_then(|r| r.split_once('-'))
+        .map(|(s, e)| (s.parse::<usize>().unwrap(), e.parse::<usize>().unwrap()))
+        .unwrap_or((0, len - 1))
+}
+
+/// The head of a `206` for bytes `start..=end` of a `len`-byte body, on a
+/// connection closed afterwards.
+fn partial_content_head(start: usize, end: usize, len: usize) -> String {
+    format!(
+        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nConnection: close\r\n\r\n",
+        end - start + 1,
+        start,
+        end,
+        len
+    )
+}
+
+/// Serves `body` over plain HTTP/1.1, one ranged request per connection, in
+/// 16 KB chunks: one every 200 ms for ranges starting at 0 and one every
+/// 25 ms for any other range, so the first segment crawls.
+pub async fn start_throttled_range_server(body: Vec<u8>) -> String {
+    let body = Arc::new(body);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let body = Arc::clone(&body);
+            tokio::spawn(async move {
+                let Some(head) = read_head(&mut socket).await else { return };
+                let (start, end) = range_bounds(&head, body.len());
+                let pause = if start == 0 { 200 } else { 25 };
+                if socket.write_all(partial_content_head(start, end, body.len()).as_bytes()).await.is_err() {
+                    return;
+                }
+                for (i, chunk) in body[start..=end].chunks(16 * 1024).enumerate() {
+                    if i > 0 {
+                        tokio::time::sleep(Duration::from_millis(pause)).await;
+                    }
+                    if socket.write_all(chunk).await.is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+    });
+    format!("http://{}/file.bin", addr)
+}
+
+/// Serves ranges of `body` over plain HTTP/1.1 to one client at a time,
+/// answering `503` to a request that arrives while another is open. The
+/// first ranged request for more than one byte is cut off halfway through.
+/// Returns the URL, the Range headers received, and the most requests seen
+/// open at once.
+pub async fn start_single_connection_server(body: Vec<u8>) -> (String, Arc<Mutex<Vec<String>>>, Arc<AtomicUsize>) {
+    let body = Arc::new(body);
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let open = Arc::new(AtomicUsize::new(0));
+    let most_open = Arc::new(AtomicUsize::new(0));
+    let cut_off = Arc::new(AtomicBool::new(false));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (ranges_seen, most_open_seen) = (Arc::clone(&ranges), Arc::clone(&most_open));
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let (body, ranges, open, most_open, cut_off) =
+                (Arc::clone(&body), Arc::clone(&ranges), Arc::clone(&open), Arc::clone(&most_open), Arc::clone(&cut_off));
+            tokio::spawn(async move {
+                let Some(head) = read_head(&mut socket).await else { return };
+                let now_open = open.fetch_add(1, Ordering::SeqCst) + 1;
+                most_open.fetch_max(now_open, Ordering::SeqCst);
+                if now_open > 1 {
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .await;
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    return;
+                }
+
+                let range = request_header(&head, "range");
+                let (start, end) = range_bounds(&head, body.len());
+                if !range.is_empty() {
+                    ranges.lock().unwrap().push(range);
+                }
+                let slice = &body[start..=end];
+                let sent = if end > start && !cut_off.swap(true, Ordering::SeqCst) { &slice[..slice.len() / 2] } else { slice };
+                let _ = socket.write_all(partial_content_head(start, end, body.len()).as_bytes()).await;
+                let _ = socket.write_all(sent).await;
+                open.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    });
+    (format!("http://{}/file.bin", addr), ranges_seen, most_open_seen)
+}
+
+/// Raw TCP server whose first response sends half of `body` and then stalls
+/// with the connection held open; later requests are answered from the
+/// requested `Range` start. Returns the base URL and the request log.
+pub async fn start_stalling_server(body: Vec<u8>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let ranges_for_server = Arc::clone(&ranges);
+
+    tokio::spawn(async move {
+        let mut first = true;
+        loop {
+            let Ok((mut socket, _)) = listener.accept().await else { return };
+            let head = read_head(&mut socket).await.unwrap_or_default();
+            ranges_for_server.lock().unwrap().push(request_header(&head, "range"));
+
+            let start = range_start(&head);
+            let slice = &body[start..];
+            let response = format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                slice.len(),
+                start,
+                body.len() - 1,
+                body.len()
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+
+            if first {
+                first = false;
+                let _ = socket.write_all(&slice[..slice.len() / 2]).await;
+                let _ = socket.flush().await;
+                // Keep the connection open without sending anything else.
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    drop(socket);
+                });
+            } else {
+                let _ = socket.write_all(slice).await;
+            }
+        }
+    });
+
+    (format!("http://{}", addr), ranges)
+}
+
+/// Raw TCP server that keeps connections alive and logs each request with
+/// the number of the connection it came on. The first request for a `Range`
+/// gets half of `body` and then a reset; the rest are answered in full from
+/// the requested start (or with `body` whole, without a `Range`).
+pub async fn start_resetting_server(body: Vec<u8>) -> (String, Arc<Mutex<Vec<(usize, String)>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let requests_for_server = Arc::clone(&requests);
+    let body = Arc::new(body);
+    let reset_done = Arc::new(AtomicBool::new(false));
+
+    tokio::spawn(async move {
+        for connection in 0.. {
+            let Ok((mut socket, _)) = listener.accept().await else { return };
+            let (requests, body, reset_done) = (Arc::clone(&requests_for_server), Arc::clone(&body), Arc::clone(&reset_done));
+            tokio::spawn(async move {
+                let mut head = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let Ok(n) = socket.read(&mut buf).await else { return };
+                    if n == 0 {
+                        return;
+                    }
+                    head.extend_from_slice(&buf[..n]);
+                    let Some(end) = head.windows(4).position(|w| w == b"\r\n\r\n") else { continue };
+                    let request = String::from_utf8_lossy(&head[..end]).to_lowercase();
+                    head.drain(..end + 4);
+                    let range = request_header(&request, "range");
+                    let start = range_start(&request);
+                    requests.lock().unwrap().push((connection, request));
+
+                    let slice = &body[start..];
+                    let status = if range.is_empty() { "200 OK" } else { "206 Partial Content" };
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        status,
+                        slice.len(),
+                        start,
+                        body.len() - 1,
+                        body.len()
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    if !range.is_empty() && !reset_done.swap(true, Ordering::SeqCst) {
+                        let _ = socket.write_all(&slice[..slice.len() / 2]).await;
+                        let _ = socket.flush().await;
+                        // SO_LINGER 0 turns the close into a RST; nothing is
+                        // left to flush, so the drop doesn't block.
+                        #[allow(deprecated)]
+                        socket.set_linger(Some(Duration::ZERO)).unwrap();
+                        return;
+                    }
+                    let _ = socket.write_all(slice).await;
+                }
+            });
+        }
+    });
+
+    (format!("http://{}", addr), requests)
+}
+
+/// Serves `body` to every request as a chunked `200` with neither
+/// `Content-Length` nor range support.
+pub async fn start_chunked_server(body: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/stream.bin", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let body = body.clone();
+            tokio::spawn(async move {
+                if read_head(&mut socket).await.is_none() {
+                    return;
+                }
+                let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\n\
+                    Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+                    .to_vec();
+                for chunk in body.chunks(16 * 1024) {
+                    response.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+                    response.extend_from_slice(chunk);
+                    response.extend_from_slice(b"\r\n");
+                }
+                response.extend_from_slice(b"0\r\n\r\n");
+                let _ = socket.write_all(&response).await;
+            });
+        }
+    });
+    url
+}
+
+/// Accepts connections and records how each one opens, then drops it.
+pub async fn start_preface_recorder() -> (String, Arc<Mutex<Vec<Vec<u8>>>>) {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+    let recorded = Arc::clone(&seen);
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let mut start = vec![0u8; 14];
+            if socket.read_exact(&mut start).await.is_ok() {
+                recorded.lock().unwrap().push(start);
+            }
+        }
+    });
+    (url, seen)
+}
@@ -26,6 +26,63 @@ pub struct VideoItem {
     #[serde(rename = "tabUrl")]
     pub tab_url: Option<String>,
     pub referer: Option<String>,
+    /// Inclusive byte range the capture is limited to, if any.
+    #[serde(default, rename = "captureRange")]
+    pub capture_range: Option<(u64, u64)>,
+}
+
+impl VideoItem {
+    /// A header of the response the browser saw, matched case-insensitively.
+    fn response_header(&self, name: &str) -> Option<&str> {
+        let value = self
+            .response_headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))?
+            .1;
+        match value {
+            serde_json::Value::String(s) => Some(s),
+            serde_json::Value::Array(values) => values.first()?.as_str(),
+            _ => None,
+        }
+    }
+
+    /// Total size from `Content-Range` (`bytes a-b/total`), else
+    /// `Content-Length` — unless the body was content-encoded, when that is
+    /// the compressed size.
+    pub fn known_size(&self) -> Option<u64> {
+        match self.response_header("content-range") {
+            Some(range) => range.rsplit('/').next()?.trim().parse().ok(),
+            None if self.response_header("content-encoding").is_some() => None,
+            None => self.response_header("content-length")?.trim().parse().ok(),
+        }
+    }
+
+    /// Whether the server takes range requests, if the response said so.
+    pub fn known_resumable(&self) -> Option<bool> {
+        if self.response_header("content-range").is_some() {
+            return Some(true);
+        }
+        match self.response_header("accept-ranges")?.trim() {
+            v if v.eq_ignore_ascii_case("bytes") => Some(true),
+            v if v.eq_ignore_ascii_case("none") => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn known_content_type(&self) -> Option<String> {
+        self.response_header("content-type").map(str::to_string)
+    }
+
+    /// The `filename` of a `Content-Disposition: attachment` response.
+    pub fn known_attachment_name(&self) -> Option<String> {
+        let disposition = self.response_header("content-disposition")?;
+        let name = disposition
+            .split(';')
+            .filter_map(|part| part.trim().strip_prefix("filename="))
+            .next()?
+            .trim_matches('"');
+        (!name.is_empty()).then(|| name.to_string())
+    }
 }
 
 /// Request payload for POST /download.
@@ -45,6 +102,17 @@ pub struct DownloadRequest {
     pub referer: Option<String>,
     #[serde(default)]
     pub info: String,
+    /// What the detected response already told us; lets rdmd skip its probe.
+    #[serde(rename = "fileSize", skip_serializing_if = "Option::is_none")]
+    pub file_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resumable: Option<bool>,
+    #[serde(rename = "contentType", skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(rename = "attachmentName", skip_serializing_if = "Option::is_none")]
+    pub attachment_name: Option<String>,
+    #[serde(rename = "captureRange", skip_serializing_if = "Option::is_none")]
+    pub capture_range: Option<(u64, u64)>,
 }
 
 /// Response from POST /download.
@@ -62,6 +130,23 @@ pub struct ProgressSnapshot {
     pub speed: f64,
     pub eta_secs: f64,
     pub done: bool,
+    /// Recent `(elapsed_secs, bytes_per_sec)` samples for the speed graph.
+    #[serde(default)]
+    pub speed_samples: Vec<(f64, f64)>,
+    #[serde(default)]
+    pub total_segments: usize,
+    #[serde(default)]
+    pub completed_segments: usize,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Non-fatal problems, e.g. a size mismatch, to show alongside progress.
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// What the download is doing before its first byte, e.g. `Probing…`.
+    #[serde(default)]
+    pub phase: Option<String>,
+    #[serde(default)]
+    pub download_id: String,
 }
 
 // ---------------------------------------------------------------------------
@@ -70,10 +155,24 @@ pub struct ProgressSnapshot {
 
 const SERVER_BASE: &str = "http://127.0.0.1:8597";
 
+/// HTTP client for talking to rdmd. When `RDM_SOCKET` is set (as it is for
+/// a UI spawned by an rdmd listening on a Unix socket), requests go over that
+/// socket and the host in `SERVER_BASE` is ignored.
+fn client() -> reqwest::Client {
+    #[cfg(unix)]
+    if let Ok(socket) = std::env::var("RDM_SOCKET") {
+        match reqwest::Client::builder().unix_socket(socket).build() {
+            Ok(client) => return client,
+            Err(e) => eprintln!("[api] unix socket client failed, falling back to TCP: {}", e),
+        }
+    }
+    reqwest::Client::new()
+}
+
 /// Trigger a download by calling POST /download on rdmd.
 /// Returns the download ID on success.
 pub async fn trigger_download(req: &DownloadRequest) -> Result<DownloadResponse, String> {
-    let client = reqwest::Client::new();
+    let client = client();
     let resp = client
         .post(format!("{}/download", SERVER_BASE))
         .json(req)
@@ -92,7 +191,7 @@ pub async fn trigger_download(req: &DownloadRequest) -> Result<DownloadResponse,
 
 /// Cancel an active download by calling POST /cancel/{id}.
 pub async fn cancel_download(id: &str) -> Result<(), String> {
-    let client = reqwest::Client::new();
+    let client = client();
     client
         .post(format!("{}/cancel/{}", SERVER_BASE, id))
         .send()
@@ -103,14 +202,15 @@ pub async fn cancel_download(id: &str) -> Result<(), String> {
 
 /// Subscribe to progress updates via SSE (GET /progress/{id}).
 /// Calls `on_snapshot` with each new `ProgressSnapshot` until the download
-/// is done or the connection drops.
+/// is done or the connection drops. A failed download (`error` event) is
+/// returned as `Err` with the server's message.
 pub async fn subscribe_progress<F>(id: &str, mut on_snapshot: F) -> Result<(), String>
 where
     F: FnMut(ProgressSnapshot),
 {
     use futures::StreamExt;
 
-    let client = reqwest::Client::new();
+    let client = client();
     let resp = client
         .get(format!("{}/progress/{}", SERVER_BASE, id))
         .send()
@@ -119,22 +219,36 @@ where
 
     let mut stream = resp.bytes_stream();
     let mut buf = String::new();
+    let mut event_name = String::new();
 
     while let Some(chunk_result) = stream.next().await {
         let chunk = chunk_result.map_err(|e| format!("SSE stream error: {}", e))?;
         buf.push_str(&String::from_utf8_lossy(&chunk));
 
-        // SSE lines are separated by \n; data lines start with "data:"
+        // SSE lines are separated by \n. rdmd names each event (`progress`,
+        // `done` or `error`) in an "event:" line ahead of its "data:" line.
         loop {
             if let Some(newline_pos) = buf.find('\n') {
                 let line = buf[..newline_pos].trim().to_string();
                 buf = buf[newline_pos + 1..].to_string();
 
-                if let Some(json_str) = line.strip_prefix("data:") {
+                if line.is_empty() {
+                    event_name.clear();
+                } else if line.starts_with(':') {
+                    // Comment line — rdmd's keep-alive. Nothing to parse.
+                    continue;
+                } else if let Some(name) = line.strip_prefix("event:") {
+                    event_name = name.trim().to_string();
+                } else if let Some(json_str) = line.strip_prefix("data:") {
                     let json_str = json_str.trim();
                     if let Ok(snap) = serde_json::from_str::<ProgressSnapshot>(json_str) {
-                        let done = snap.done;
+                        let error = snap.error.clone();
+                        // An unnamed event falls back to the payload's `done` flag.
+                        let done = event_name == "done" || snap.done;
                         on_snapshot(snap);
+                        if event_name == "error" || error.is_some() {
+                            return Err(error.unwrap_or_else(|| "download failed".to_string()));
+                        }
                         if done {
                             return Ok(());
                         }
@@ -1,4 +1,5 @@
 use dioxus::prelude::*;
+use rdm_core::progress::snapshot::{format_speed, SpeedUnit};
 
 use crate::api::{
     cancel_download, subscribe_progress, trigger_download, DownloadRequest, ProgressSnapshot,
@@ -153,6 +154,11 @@ fn FilePickerView(video: VideoItem, mut view: Signal<View>) -> Element {
                                 user_agent:      video_for_download.user_agent.clone(),
                                 referer:         video_for_download.referer.clone(),
                                 info:            video_for_download.info.clone(),
+                                file_size:       video_for_download.known_size(),
+                                resumable:       video_for_download.known_resumable(),
+                                content_type:    video_for_download.known_content_type(),
+                                attachment_name: video_for_download.known_attachment_name(),
+                                capture_range:   video_for_download.capture_range,
                             };
 
                             spawn(async move {
@@ -187,6 +193,13 @@ fn ProgressView(download_id: String, title: String) -> Element {
         speed: 0.0,
         eta_secs: 0.0,
         done: false,
+        speed_samples: Vec::new(),
+        total_segments: 0,
+        completed_segments: 0,
+        error: None,
+        warnings: Vec::new(),
+        phase: None,
+        download_id: download_id.clone(),
     });
     let mut error_msg = use_signal(|| String::new());
 
@@ -206,10 +219,17 @@ fn ProgressView(download_id: String, title: String) -> Element {
     } else {
         0.0
     };
-    let speed_mb      = snap.speed / (1024.0 * 1024.0);
+    let speed_str     = format_speed(snap.speed, speed_unit());
     let downloaded_mb = snap.total_bytes_downloaded as f64 / (1024.0 * 1024.0);
     let total_mb      = snap.total_bytes as f64 / (1024.0 * 1024.0);
     let is_done       = snap.done;
+    let parts_done    = snap.completed_segments;
+    let parts_total   = snap.total_segments;
+    let header_title  = if is_done {
+        "Download Complete".to_string()
+    } else {
+        snap.phase.clone().unwrap_or_else(|| "Downloading…".to_string())
+    };
 
     let eta_str = if is_done {
         "Complete".to_string()
@@ -220,6 +240,7 @@ fn ProgressView(download_id: String, title: String) -> Element {
     };
 
     let bar_width = format!("{:.2}%", pct);
+    let sparkline = sparkline_points(&snap.speed_samples, SPARK_WIDTH, SPARK_HEIGHT);
 
     rsx! {
         div { class: "view",
@@ -231,9 +252,7 @@ fn ProgressView(download_id: String, title: String) -> Element {
                     if is_done { "✓" } else { "↓" }
                 }
                 div { class: "header-text",
-                    div { class: "header-title",
-                        if is_done { "Download Complete" } else { "Downloading…" }
-                    }
+                    div { class: "header-title", "{header_title}" }
                     div { class: "header-subtitle", "{title}" }
                 }
             }
@@ -265,13 +284,34 @@ fn ProgressView(download_id: String, title: String) -> Element {
                 div { class: "stat-card",
                     div { class: "stat-label", "Speed" }
                     div { class: "stat-value",
-                        if is_done { "—" } else { "{speed_mb:.2} MB/s" }
+                        if is_done { "—" } else { "{speed_str}" }
                     }
                 }
                 div { class: "stat-card",
                     div { class: "stat-label", "ETA" }
                     div { class: "stat-value", "{eta_str}" }
                 }
+                if parts_total > 1 {
+                    div { class: "stat-card",
+                        div { class: "stat-label", "Parts" }
+                        div { class: "stat-value", "{parts_done} of {parts_total} done" }
+                    }
+                }
+            }
+
+            // ── Speed sparkline ──────────────────────────────────────────────
+            if !is_done && snap.speed_samples.len() > 1 {
+                svg {
+                    class: "sparkline",
+                    view_box: "0 0 {SPARK_WIDTH} {SPARK_HEIGHT}",
+                    preserve_aspect_ratio: "none",
+                    polyline { class: "sparkline-line", points: "{sparkline}" }
+                }
+            }
+
+            // ── Warnings ─────────────────────────────────────────────────────
+            for warning in snap.warnings.iter() {
+                div { class: "warning-banner", style: "margin-top: 14px;", "Warning: {warning}" }
             }
 
             // ── Error ────────────────────────────────────────────────────────
@@ -376,6 +416,39 @@ fn ext_from_url(url: &str) -> Option<&'static str> {
     }
 }
 
+/// Logical size of the speed sparkline's SVG viewBox.
+const SPARK_WIDTH: f64 = 440.0;
+const SPARK_HEIGHT: f64 = 36.0;
+
+/// Map `(elapsed_secs, bytes_per_sec)` samples onto an SVG `points` string
+/// scaled to fit a `width` × `height` viewBox (y grows downward).
+fn sparkline_points(samples: &[(f64, f64)], width: f64, height: f64) -> String {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return String::new();
+    };
+    let span = (last.0 - first.0).max(f64::EPSILON);
+    let peak = samples.iter().map(|(_, v)| *v).fold(0.0_f64, f64::max).max(1.0);
+
+    samples
+        .iter()
+        .map(|(t, v)| {
+            let x = (t - first.0) / span * width;
+            let y = height - (v / peak) * height;
+            format!("{:.1},{:.1}", x, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The unit from `RDM_SPEED_UNIT` (`binary`, `decimal` or `bits`, inherited
+/// from rdmd), binary by default.
+fn speed_unit() -> SpeedUnit {
+    static UNIT: std::sync::OnceLock<SpeedUnit> = std::sync::OnceLock::new();
+    *UNIT.get_or_init(|| {
+        std::env::var("RDM_SPEED_UNIT").ok().and_then(|unit| unit.parse().ok()).unwrap_or_default()
+    })
+}
+
 fn format_eta(secs: f64) -> String {
     let s = secs as u64;
     if s >= 3600 {